@@ -1,7 +1,10 @@
 use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 
 use clap::Parser;
-use gm_quic::QuicClient;
+use gm_quic::{
+    qbase::{error::AppError, varint::VarInt},
+    QuicClient,
+};
 use rustls::pki_types::{pem::PemObject, CertificateDer};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
@@ -66,7 +69,7 @@ async fn run(args: Arguments) -> Result<(), Box<dyn std::error::Error>> {
         .with_alpns([b"hq-29".as_ref()].iter().map(|s| s.to_vec()))
         .build();
 
-    let quic_conn = client.connect(args.domain, args.addr).unwrap();
+    let quic_conn = client.connect(args.domain, args.addr).await.unwrap();
     let mut counter = 0;
     loop {
         let mut input = String::new();
@@ -84,7 +87,10 @@ async fn run(args: Arguments) -> Result<(), Box<dyn std::error::Error>> {
         }
 
         if content == "exit" || content == "quit" {
-            quic_conn.close("Client close the connection");
+            quic_conn.close(AppError::new(
+                VarInt::from_u32(0),
+                "Client close the connection",
+            ));
             break;
         }
 
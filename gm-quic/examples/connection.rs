@@ -30,9 +30,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .without_cert()
         .build();
 
-    let _conn = client
-        .connect("localhost", "127.0.0.1:5000".parse().unwrap())
-        .unwrap();
+    let _conn = client.connect("localhost", "127.0.0.1:5000").await.unwrap();
     tokio::time::sleep(std::time::Duration::from_secs(100000)).await;
     Ok(())
 }
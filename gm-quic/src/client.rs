@@ -1,39 +1,63 @@
 use std::{
+    future::Future,
     io::{self},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
     path::Path,
+    pin::pin,
     sync::Arc,
+    time::Duration,
 };
 
 use qbase::{
     cid::ConnectionId,
+    error::{Error, ErrorKind},
     param::{ClientParameters, CommonParameters},
     sid::{handy::ConsistentConcurrency, ControlConcurrency},
-    token::{ArcTokenRegistry, TokenSink},
+    token::{ArcTokenRegistry, MemoryTokenSink, TokenSink},
+};
+use qcongestion::{CongestionAlgorithm, INITIAL_RTT};
+use qconnection::{
+    conn::ArcConnection,
+    path::Pathway,
+    spawn::{Runtime, TokioRuntime},
+    usc::{bind_udp_socket, bind_udp_socket_with, SocketFactory},
 };
-use qconnection::{conn::ArcConnection, path::Pathway};
 use rustls::{
     client::{ResolvesClientCert, WantsClientCert},
     pki_types::{CertificateDer, PrivateKeyDer},
     ClientConfig as TlsClientConfig, ConfigBuilder, WantsVerifier,
 };
 
-use crate::{create_new_usc, get_or_create_usc, util, ConnKey, QuicConnection, CONNECTIONS};
+use crate::{
+    create_new_usc, get_or_create_usc, util, ConnKey, QuicConnection, Registration, CONNECTIONS,
+};
 
 type TlsClientConfigBuilder<T> = ConfigBuilder<TlsClientConfig, T>;
 
+/// How long the IPv6 connection attempt goes first, before the IPv4 attempt starts racing with it, see
+/// [`QuicClient::connect`].
+///
+/// It's the recommended value of the "Connection Attempt Delay" in
+/// [Section 5](https://www.rfc-editor.org/rfc/rfc8305.html#section-5) of Happy Eyeballs.
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
 /// A quic client that can initiates connections to servers.
 pub struct QuicClient {
     bind_addresseses: Vec<SocketAddr>,
     reuse_udp_sockets: bool,
     _reuse_connection: bool, // TODO
-    _enable_happy_eyepballs: bool,
+    enable_happy_eyeballs: bool,
     _prefer_versions: Vec<u32>,
     parameters: ClientParameters,
     remembered: Option<CommonParameters>,
     tls_config: Arc<TlsClientConfig>,
     streams_controller: Box<dyn Fn(u64, u64) -> Box<dyn ControlConcurrency> + Send + Sync>,
-    token_sink: Option<Arc<dyn TokenSink>>,
+    token_sink: Arc<dyn TokenSink>,
+    socket_factory: SocketFactory,
+    initial_rtt: Duration,
+    congestion_algorithm: CongestionAlgorithm,
+    max_send_udp_payload: Option<usize>,
+    runtime: Arc<dyn Runtime>,
 }
 
 impl QuicClient {
@@ -62,12 +86,17 @@ impl QuicClient {
             bind_addresses: vec![],
             reuse_udp_sockets: false,
             reuse_connection: true,
-            enable_happy_eyepballs: false,
+            enable_happy_eyeballs: false,
             prefer_versions: vec![1],
             parameters: ClientParameters::default(),
             tls_config: TlsClientConfig::builder_with_protocol_versions(&[&rustls::version::TLS13]),
             streams_controller: Box::new(|bi, uni| Box::new(ConsistentConcurrency::new(bi, uni))),
             token_sink: None,
+            socket_factory: Arc::new(bind_udp_socket),
+            initial_rtt: INITIAL_RTT,
+            congestion_algorithm: CongestionAlgorithm::Bbr,
+            max_send_udp_payload: None,
+            runtime: Arc::new(TokioRuntime),
         }
     }
 
@@ -79,7 +108,7 @@ impl QuicClient {
             bind_addresses: vec![],
             reuse_udp_sockets: false,
             reuse_connection: true,
-            enable_happy_eyepballs: false,
+            enable_happy_eyeballs: false,
             prefer_versions: vec![1],
             parameters: ClientParameters::default(),
             tls_config: TlsClientConfig::builder_with_provider(provider)
@@ -87,6 +116,11 @@ impl QuicClient {
                 .unwrap(),
             streams_controller: Box::new(|bi, uni| Box::new(ConsistentConcurrency::new(bi, uni))),
             token_sink: None,
+            socket_factory: Arc::new(bind_udp_socket),
+            initial_rtt: INITIAL_RTT,
+            congestion_algorithm: CongestionAlgorithm::Bbr,
+            max_send_udp_payload: None,
+            runtime: Arc::new(TokioRuntime),
         }
     }
 
@@ -98,12 +132,17 @@ impl QuicClient {
             bind_addresses: vec![],
             reuse_udp_sockets: false,
             reuse_connection: true,
-            enable_happy_eyepballs: false,
+            enable_happy_eyeballs: false,
             prefer_versions: vec![1],
             parameters: ClientParameters::default(),
             tls_config,
             streams_controller: Box::new(|bi, uni| Box::new(ConsistentConcurrency::new(bi, uni))),
             token_sink: None,
+            socket_factory: Arc::new(bind_udp_socket),
+            initial_rtt: INITIAL_RTT,
+            congestion_algorithm: CongestionAlgorithm::Bbr,
+            max_send_udp_payload: None,
+            runtime: Arc::new(TokioRuntime),
         }
     }
 
@@ -130,12 +169,24 @@ impl QuicClient {
     ///
     /// `server_name` is the name of the server, it will be included in the `ClientHello` message.
     ///
-    /// `server_addr` is the address of the server, packets will be sent to this address.
+    /// `server_addrs` are the addresses of the server, such as the resolved addresses of `server_name`, packets will be
+    /// sent to one of them. The addresses whose family the client can not bind a socket for are skipped, if the client
+    /// does not bind any address, both IPv6 and IPv4 can be used.
     ///
     /// Note that the returned connection may not yet be connected to the server, but you can use it to do anything you
     /// want, such as sending data, receiving data... operations will be pending until the connection is connected or
     /// failed to connect.
     ///
+    /// ### Happy eyeballs
+    ///
+    /// If [happy eyeballs] is enabled, and both an IPv6 and an IPv4 address can be used, the client races the handshakes
+    /// toward the first IPv6 address and the first IPv4 address. The IPv6 attempt starts first, the IPv4 attempt starts
+    /// [`CONNECTION_ATTEMPT_DELAY`] later, or once the IPv6 attempt fails. The connection that completes its handshake
+    /// first is returned, the other one is closed. If both attempts fail, the error of the IPv6 attempt is returned. In
+    /// this case, the returned connection has been connected to the server.
+    ///
+    /// Otherwise, the client only connects to the first address that can be used, without waiting for the handshake.
+    ///
     /// ### (WIP)Reuse connection
     ///
     /// If `reuse connection` is enabled, the client will try to reuse the connection that has already connected to the
@@ -160,13 +211,49 @@ impl QuicClient {
     ///
     /// Note that although `reuse udp sockets` is not enabled, the socket bound by the client may still be reused, because
     /// this option can only determine the behavior of this client when initiates a new connection.
-    pub fn connect(
+    pub async fn connect(
         &self,
         server_name: impl Into<String>,
-        server_addr: SocketAddr,
+        server_addrs: impl ToSocketAddrs,
     ) -> io::Result<Arc<QuicConnection>> {
         let server_name = server_name.into();
+        let server_addrs = server_addrs
+            .to_socket_addrs()?
+            .filter(|addr| {
+                self.bind_addresseses.is_empty()
+                    || self
+                        .bind_addresseses
+                        .iter()
+                        .any(|bind_addr| bind_addr.is_ipv4() == addr.is_ipv4())
+            })
+            .collect::<Vec<_>>();
+        let v6_addr = server_addrs.iter().find(|addr| addr.is_ipv6());
+        let v4_addr = server_addrs.iter().find(|addr| addr.is_ipv4());
+
+        match (v6_addr, v4_addr) {
+            (Some(&v6_addr), Some(&v4_addr)) if self.enable_happy_eyeballs => {
+                happy_eyeballs(
+                    self.attempt(server_name.clone(), v6_addr),
+                    || self.attempt(server_name, v4_addr),
+                    self.runtime.sleep(CONNECTION_ATTEMPT_DELAY),
+                )
+                .await
+            }
+            _ => match server_addrs.first() {
+                Some(&server_addr) => self.new_connection(server_name, server_addr),
+                None => Err(io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    "No available address",
+                )),
+            },
+        }
+    }
 
+    fn new_connection(
+        &self,
+        server_name: String,
+        server_addr: SocketAddr,
+    ) -> io::Result<Arc<QuicConnection>> {
         let usc_creator = if self.reuse_udp_sockets {
             get_or_create_usc
         } else {
@@ -175,9 +262,11 @@ impl QuicClient {
 
         let usc = if self.bind_addresseses.is_empty() {
             if server_addr.is_ipv4() {
-                (usc_creator)(&SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0))
+                let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+                (usc_creator)(&addr, &self.socket_factory, self.runtime.as_ref())
             } else {
-                (usc_creator)(&SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0))
+                let addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0);
+                (usc_creator)(&addr, &self.socket_factory, self.runtime.as_ref())
             }
         } else {
             // similar to std::net::UdpSocket::bind
@@ -189,7 +278,7 @@ impl QuicClient {
                 .iter()
                 .filter(|addr| addr.is_ipv4() == server_addr.is_ipv4())
                 .find_map(|suite_addr| {
-                    match (usc_creator)(suite_addr) {
+                    match (usc_creator)(suite_addr, &self.socket_factory, self.runtime.as_ref()) {
                         Ok(usc) => return Some(usc),
                         Err(err) => last_error = Some(err),
                     }
@@ -216,10 +305,8 @@ impl QuicClient {
             self.parameters.initial_max_streams_uni().into_inner(),
         );
 
-        let token_registry = match &self.token_sink {
-            Some(sink) => ArcTokenRegistry::with_sink(server_name.clone(), sink.clone()),
-            None => ArcTokenRegistry::default_sink(server_name.clone()),
-        };
+        let token_registry =
+            ArcTokenRegistry::with_sink(server_name.clone(), self.token_sink.clone());
 
         let tls_config = self.tls_config.clone();
         let key = ConnKey::Client(initial_scid);
@@ -231,14 +318,93 @@ impl QuicClient {
             streams_ctrl,
             tls_config,
             token_registry,
+            self.initial_rtt,
+            self.congestion_algorithm,
+            self.max_send_udp_payload,
+            self.runtime.clone(),
         );
         inner.add_initial_path(pathway, usc);
 
         CONNECTIONS.insert(key.clone(), inner.clone());
-        let conn = QuicConnection { key, inner };
+        let conn = QuicConnection {
+            _registration: Arc::new(Registration(key)),
+            inner,
+        };
 
         Ok(Arc::new(conn))
     }
+
+    async fn attempt(
+        &self,
+        server_name: String,
+        server_addr: SocketAddr,
+    ) -> io::Result<Arc<QuicConnection>> {
+        let conn = self.new_connection(server_name, server_addr)?;
+        let attempt = Attempt(Some(conn.clone()));
+        conn.established()
+            .await
+            .map_err(|error| io::Error::new(io::ErrorKind::ConnectionAborted, error))?;
+        Ok(attempt.adopt())
+    }
+}
+
+/// 一次连接尝试，若在握手完成前被放弃，比如在竞速中落败，则关闭其连接
+struct Attempt(Option<Arc<QuicConnection>>);
+
+impl Attempt {
+    fn adopt(mut self) -> Arc<QuicConnection> {
+        self.0.take().expect("the connection has not been adopted")
+    }
+}
+
+impl Drop for Attempt {
+    fn drop(&mut self) {
+        if let Some(conn) = self.0.take() {
+            conn.close(Error::with_default_fty(
+                ErrorKind::None,
+                "abandoned by happy eyeballs",
+            ));
+        }
+    }
+}
+
+/// Race the connection attempts toward the IPv6 and the IPv4 address of the server, see [`QuicClient::connect`].
+///
+/// The `v4` attempt is started once the `delay` completes, or once the `v6` attempt fails. The first successful attempt wins, and the
+/// other attempt is dropped. If both attempts fail, the error of the `v6` attempt is returned.
+async fn happy_eyeballs<T, E, V6, V4>(
+    v6: V6,
+    v4: impl FnOnce() -> V4,
+    delay: impl Future<Output = ()>,
+) -> Result<T, E>
+where
+    V6: Future<Output = Result<T, E>>,
+    V4: Future<Output = Result<T, E>>,
+{
+    let mut v6 = pin!(v6);
+    // IPv6先行，超时或失败后才开始IPv4的尝试
+    let v6_error = tokio::select! {
+        result = &mut v6 => match result {
+            Ok(t) => return Ok(t),
+            Err(error) => Some(error),
+        },
+        _ = delay => None,
+    };
+
+    let mut v4 = pin!(v4());
+    if let Some(v6_error) = v6_error {
+        return v4.await.map_err(|_| v6_error);
+    }
+    tokio::select! {
+        result = &mut v6 => match result {
+            Ok(t) => Ok(t),
+            Err(v6_error) => v4.await.map_err(|_| v6_error),
+        },
+        result = &mut v4 => match result {
+            Ok(t) => Ok(t),
+            Err(_) => v6.await,
+        },
+    }
 }
 
 /// A builder for [`QuicClient`].
@@ -246,12 +412,17 @@ pub struct QuicClientBuilder<T> {
     bind_addresses: Vec<SocketAddr>,
     reuse_udp_sockets: bool,
     reuse_connection: bool,
-    enable_happy_eyepballs: bool,
+    enable_happy_eyeballs: bool,
     prefer_versions: Vec<u32>,
     parameters: ClientParameters,
     tls_config: T,
     streams_controller: Box<dyn Fn(u64, u64) -> Box<dyn ControlConcurrency> + Send + Sync>,
     token_sink: Option<Arc<dyn TokenSink>>,
+    socket_factory: SocketFactory,
+    initial_rtt: Duration,
+    congestion_algorithm: CongestionAlgorithm,
+    max_send_udp_payload: Option<usize>,
+    runtime: Arc<dyn Runtime>,
 }
 
 impl<T> QuicClientBuilder<T> {
@@ -294,6 +465,16 @@ impl<T> QuicClientBuilder<T> {
         self
     }
 
+    /// Enable [Happy Eyeballs](https://www.rfc-editor.org/rfc/rfc8305.html).
+    ///
+    /// If you enable this option, when the server has both IPv6 and IPv4 addresses, the client races the handshakes
+    /// toward them with a head-start for IPv6, rather than only trying one of them, read [`QuicClient::connect`] for
+    /// more information.
+    pub fn enable_happy_eyeballs(mut self) -> Self {
+        self.enable_happy_eyeballs = true;
+        self
+    }
+
     /// (WIP)Specify the quic versions that the client prefers.
     ///
     /// If you call this multiple times, only the last call will take effect.
@@ -315,6 +496,35 @@ impl<T> QuicClientBuilder<T> {
         self
     }
 
+    /// Specify the maximum number of bidirectional streams the server is allowed to open concurrently.
+    ///
+    /// It's the `initial_max_streams_bidi` [transport parameter], a shortcut for setting it in the parameters
+    /// provided by [`with_parameters`], which overrides this if called later.
+    ///
+    /// If you call this multiple times, only the last `streams` will be used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `streams` exceeds [`MAX_STREAMS_LIMIT`].
+    ///
+    /// [transport parameter]: https://www.rfc-editor.org/rfc/rfc9000.html#name-transport-parameter-definit
+    /// [`with_parameters`]: Self::with_parameters
+    /// [`MAX_STREAMS_LIMIT`]: qbase::sid::MAX_STREAMS_LIMIT
+    pub fn max_concurrent_bidi_streams(mut self, streams: u64) -> Self {
+        self.parameters.set_initial_max_streams_bidi(streams);
+        self
+    }
+
+    /// Specify the maximum number of unidirectional streams the server is allowed to open concurrently.
+    ///
+    /// See [`max_concurrent_bidi_streams`] for more details.
+    ///
+    /// [`max_concurrent_bidi_streams`]: Self::max_concurrent_bidi_streams
+    pub fn max_concurrent_uni_streams(mut self, streams: u64) -> Self {
+        self.parameters.set_initial_max_streams_uni(streams);
+        self
+    }
+
     /// Specify the streams controller for the client.
     ///
     /// The streams controller is used to control the concurrency of data streams. `controller` is a closure that accept
@@ -338,11 +548,116 @@ impl<T> QuicClientBuilder<T> {
     /// tokens to prove it self to the server when it reconnects to the server. read [address verification] in quic rfc
     /// for more information.
     ///
+    /// By default, the tokens are kept in memory by a [`MemoryTokenSink`], shared by the connections initiated by the
+    /// same client.
+    ///
     /// [address verification](https://www.rfc-editor.org/rfc/rfc9000.html#name-address-validation)
     pub fn with_token_sink(mut self, sink: Arc<dyn TokenSink>) -> Self {
         self.token_sink = Some(sink);
         self
     }
+
+    /// Specify how to bind the sockets that the client uses to initiate connections.
+    ///
+    /// By default, the client binds the os udp sockets. The `socket_factory` will be called with the address to bind
+    /// each time the client needs a new socket, read [`QuicClient::connect`] for when the client binds a new socket.
+    /// Provide your own [`AsyncUdpSocket`] implementation to use other transports, such as io_uring based sockets, or
+    /// in-process sockets for testing.
+    ///
+    /// If you call this multiple times, only the last `socket_factory` will be used.
+    ///
+    /// [`AsyncUdpSocket`]: qudp::AsyncUdpSocket
+    pub fn with_socket_factory(
+        mut self,
+        socket_factory: impl Fn(SocketAddr) -> io::Result<Arc<dyn qudp::AsyncUdpSocket>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.socket_factory = Arc::new(socket_factory);
+        self
+    }
+
+    /// Apply the socket options to the os udp sockets that the client binds, such as the DSCP for QoS marking, and the
+    /// sizes of the socket buffers.
+    ///
+    /// The DSCP only occupies the higher 6 bits of the TOS byte, the ECN codepoint of each datagram is kept.
+    ///
+    /// This replaces the socket factory with the one that binds the os udp sockets with the `options`, so it overrides
+    /// the previous [`with_socket_factory`] call, and vice versa.
+    ///
+    /// [`with_socket_factory`]: Self::with_socket_factory
+    pub fn with_socket_options(mut self, options: qudp::SocketOptions) -> Self {
+        self.socket_factory = bind_udp_socket_with(options);
+        self
+    }
+
+    /// Specify the RTT assumed for the new connections before any RTT sample is taken.
+    ///
+    /// The loss detection and the PTO use it until the first RTT sample of the path. If the RTT of the network is known
+    /// to be much smaller, for example in a LAN, a smaller `initial_rtt` makes the lost handshake packets retransmitted
+    /// sooner.
+    ///
+    /// If you call this multiple times, only the last `initial_rtt` will be used. By default, it's [`INITIAL_RTT`].
+    pub fn initial_rtt(mut self, initial_rtt: Duration) -> Self {
+        self.initial_rtt = initial_rtt;
+        self
+    }
+
+    /// Specify the congestion control algorithm of the paths of the new connections.
+    ///
+    /// If you call this multiple times, only the last `algorithm` will be used. By default, it's
+    /// [`CongestionAlgorithm::Bbr`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`io::ErrorKind::InvalidInput`] error if the beta of [`CongestionAlgorithm::NewRenoWithBeta`] is
+    /// not in (0, 1).
+    pub fn with_congestion_algorithm(mut self, algorithm: CongestionAlgorithm) -> io::Result<Self> {
+        if let CongestionAlgorithm::NewRenoWithBeta(beta) = algorithm {
+            if !(beta > 0.0 && beta < 1.0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("the loss reduction factor must be in (0, 1), but got {beta}"),
+                ));
+            }
+        }
+        self.congestion_algorithm = algorithm;
+        Ok(self)
+    }
+
+    /// Cap the size of the UDP datagrams sent on each path.
+    ///
+    /// Some networks drop large UDP datagrams, the datagrams will not be larger than `size`, even if the peer advertised,
+    /// or the path discovered, a larger one. It's useful for the conservative deployments behind such middleboxes.
+    ///
+    /// If you call this multiple times, only the last `size` will be used. By default, only the MTU of the path limits
+    /// the size of the datagrams.
+    ///
+    /// # Panics
+    ///
+    /// Values below 1200 are invalid, QUIC requires to send datagrams of at least 1200 bytes, see
+    /// [Section 14](https://www.rfc-editor.org/rfc/rfc9000.html#name-datagram-size) of
+    /// [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html).
+    pub fn max_send_udp_payload(mut self, size: usize) -> Self {
+        assert!(
+            size >= 1200,
+            "Values below 1200 are invalid for max_send_udp_payload"
+        );
+        self.max_send_udp_payload = Some(size);
+        self
+    }
+
+    /// Specify the async runtime to run the background tasks and the timers of the connections.
+    ///
+    /// By default, the tasks are spawned on the current tokio runtime, and timed by the tokio timers. Provide your own
+    /// [`Runtime`] implementation to run them elsewhere, such as on a dedicated runtime, or to instrument them.
+    ///
+    /// If you call this multiple times, only the last `runtime` will be used.
+    pub fn with_runtime(mut self, runtime: impl Runtime + 'static) -> Self {
+        self.runtime = Arc::new(runtime);
+        self
+    }
 }
 
 impl QuicClientBuilder<TlsClientConfigBuilder<WantsVerifier>> {
@@ -357,12 +672,17 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsVerifier>> {
             bind_addresses: self.bind_addresses,
             reuse_udp_sockets: self.reuse_udp_sockets,
             reuse_connection: self.reuse_connection,
-            enable_happy_eyepballs: self.enable_happy_eyepballs,
+            enable_happy_eyeballs: self.enable_happy_eyeballs,
             prefer_versions: self.prefer_versions,
             parameters: self.parameters,
             tls_config: self.tls_config.with_root_certificates(root_store),
             streams_controller: self.streams_controller,
             token_sink: self.token_sink,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
         }
     }
 
@@ -377,12 +697,17 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsVerifier>> {
             bind_addresses: self.bind_addresses,
             reuse_udp_sockets: self.reuse_udp_sockets,
             reuse_connection: self.reuse_connection,
-            enable_happy_eyepballs: self.enable_happy_eyepballs,
+            enable_happy_eyeballs: self.enable_happy_eyeballs,
             prefer_versions: self.prefer_versions,
             parameters: self.parameters,
             tls_config: self.tls_config.with_webpki_verifier(verifier),
             streams_controller: self.streams_controller,
             token_sink: self.token_sink,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
         }
     }
 }
@@ -401,7 +726,7 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
             bind_addresses: self.bind_addresses,
             reuse_udp_sockets: self.reuse_udp_sockets,
             reuse_connection: self.reuse_connection,
-            enable_happy_eyepballs: self.enable_happy_eyepballs,
+            enable_happy_eyeballs: self.enable_happy_eyeballs,
             prefer_versions: self.prefer_versions,
             parameters: self.parameters,
             tls_config: self
@@ -410,6 +735,11 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
                 .expect("The private key was wrong encoded or failed validation"),
             streams_controller: self.streams_controller,
             token_sink: self.token_sink,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
         }
     }
 
@@ -433,12 +763,17 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
             bind_addresses: self.bind_addresses,
             reuse_udp_sockets: self.reuse_udp_sockets,
             reuse_connection: self.reuse_connection,
-            enable_happy_eyepballs: self.enable_happy_eyepballs,
+            enable_happy_eyeballs: self.enable_happy_eyeballs,
             prefer_versions: self.prefer_versions,
             parameters: self.parameters,
             tls_config: self.tls_config.with_no_client_auth(),
             streams_controller: self.streams_controller,
             token_sink: self.token_sink,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
         }
     }
 
@@ -451,12 +786,17 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
             bind_addresses: self.bind_addresses,
             reuse_udp_sockets: self.reuse_udp_sockets,
             reuse_connection: self.reuse_connection,
-            enable_happy_eyepballs: self.enable_happy_eyepballs,
+            enable_happy_eyeballs: self.enable_happy_eyeballs,
             prefer_versions: self.prefer_versions,
             parameters: self.parameters,
             tls_config: self.tls_config.with_client_cert_resolver(cert_resolver),
             streams_controller: self.streams_controller,
             token_sink: self.token_sink,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
         }
     }
 }
@@ -494,14 +834,135 @@ impl QuicClientBuilder<TlsClientConfig> {
             bind_addresseses: self.bind_addresses,
             reuse_udp_sockets: self.reuse_udp_sockets,
             _reuse_connection: self.reuse_connection,
-            _enable_happy_eyepballs: self.enable_happy_eyepballs,
+            enable_happy_eyeballs: self.enable_happy_eyeballs,
             _prefer_versions: self.prefer_versions,
             parameters: self.parameters,
             // TODO: 要能加载上次连接的parameters
             remembered: None,
             tls_config: Arc::new(self.tls_config),
             streams_controller: self.streams_controller,
-            token_sink: self.token_sink,
+            token_sink: self
+                .token_sink
+                .unwrap_or_else(|| Arc::new(MemoryTokenSink::default())),
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    async fn stub_attempt(
+        delay: Duration,
+        result: Result<&'static str, &'static str>,
+    ) -> Result<&'static str, &'static str> {
+        tokio::time::sleep(delay).await;
+        result
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_prefers_v6() {
+        let winner = happy_eyeballs(
+            stub_attempt(Duration::from_millis(10), Ok("v6")),
+            || stub_attempt(Duration::ZERO, Ok("v4")),
+            tokio::time::sleep(Duration::from_millis(100)),
+        )
+        .await;
+        assert_eq!(winner, Ok("v6"));
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_v4_wins_after_delay() {
+        let start = Instant::now();
+        let winner = happy_eyeballs(
+            stub_attempt(Duration::from_secs(5), Ok("v6")),
+            || stub_attempt(Duration::ZERO, Ok("v4")),
+            tokio::time::sleep(Duration::from_millis(50)),
+        )
+        .await;
+        assert_eq!(winner, Ok("v4"));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50) && elapsed < Duration::from_secs(5));
+
+        // IPv6失败时，立即开始IPv4的尝试，不必等待
+        let start = Instant::now();
+        let winner = happy_eyeballs(
+            stub_attempt(Duration::ZERO, Err("v6")),
+            || stub_attempt(Duration::ZERO, Ok("v4")),
+            tokio::time::sleep(Duration::from_secs(5)),
+        )
+        .await;
+        assert_eq!(winner, Ok("v4"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_both_fail() {
+        let error = happy_eyeballs(
+            stub_attempt(Duration::from_millis(100), Err("v6")),
+            || stub_attempt(Duration::ZERO, Err("v4")),
+            tokio::time::sleep(Duration::from_millis(10)),
+        )
+        .await;
+        assert_eq!(error, Err("v6"));
+
+        let error = happy_eyeballs(
+            stub_attempt(Duration::from_millis(10), Err("v6")),
+            || stub_attempt(Duration::from_millis(100), Err("v4")),
+            tokio::time::sleep(Duration::from_millis(50)),
+        )
+        .await;
+        assert_eq!(error, Err("v6"));
+    }
+
+    #[test]
+    fn invalid_loss_reduction_factor() {
+        let builder = || {
+            QuicClient::builder_with_crypto_provieder(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+        };
+        for beta in [0.0, 1.0, -0.5, f64::NAN] {
+            let error = builder()
+                .with_congestion_algorithm(CongestionAlgorithm::NewRenoWithBeta(beta))
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(builder()
+            .with_congestion_algorithm(CongestionAlgorithm::NewRenoWithBeta(0.7))
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn connect_with_stored_token() {
+        let sink = Arc::new(MemoryTokenSink::default());
+        sink.sink("localhost", vec![1, 2, 3]);
+
+        let client = QuicClient::builder_with_crypto_provieder(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .without_cert()
+        .with_token_sink(sink.clone())
+        .build();
+        let server_addr: SocketAddr = "127.0.0.1:4433".parse().unwrap();
+
+        // 连接时取出令牌，放入Initial包中，令牌不会被另一个连接重复使用
+        let _conn = client.connect("localhost", server_addr).await.unwrap();
+        assert!(sink.fetch_token("localhost").is_empty());
+
+        sink.sink("localhost", vec![4, 5, 6]);
+        sink.sink("example.com", vec![7, 8, 9]);
+        let _conn = client.connect("localhost", server_addr).await.unwrap();
+        assert!(sink.fetch_token("localhost").is_empty());
+        assert_eq!(sink.fetch_token("example.com"), vec![7, 8, 9]);
+    }
+}
@@ -1,4 +1,12 @@
-use std::{io, net::SocketAddr, sync::LazyLock};
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock,
+    },
+    time::Duration,
+};
 
 use bytes::BytesMut;
 use dashmap::DashMap;
@@ -9,9 +17,11 @@ use qbase::{
 };
 use qconnection::{
     conn::{ArcConnection, StreamReader, StreamWriter},
+    error::HandshakeError,
     path::Pathway,
     router::Router,
-    usc::{ArcUsc, UscRegistry},
+    spawn::Runtime,
+    usc::{ArcUsc, SocketFactory, UscRegistry},
 };
 
 pub mod client;
@@ -23,6 +33,7 @@ pub use qbase;
 pub use qcongestion;
 pub use qconnection;
 pub use qrecovery;
+pub use qudp;
 pub use qunreliable;
 pub use rustls;
 pub use server::QuicServer;
@@ -31,15 +42,35 @@ pub use server::QuicServer;
 /// 包括被动接收的连接和主动发起的连接
 static CONNECTIONS: LazyLock<DashMap<ConnKey, ArcConnection>> = LazyLock::new(DashMap::new);
 
+/// CONNECTIONS中被动接收的连接数，避免每次接受新连接时都遍历CONNECTIONS
+static SERVER_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum ConnKey {
     Client(ConnectionId),
     Server(ConnectionId),
 }
 
+/// 连接在CONNECTIONS中的注册，所有克隆的[`QuicConnection`]共享，最后一个被Drop时才注销
 #[derive(Debug)]
+struct Registration(ConnKey);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if CONNECTIONS.remove(&self.0).is_some() && matches!(self.0, ConnKey::Server(_)) {
+            SERVER_CONNECTIONS.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+/// A QUIC connection.
+///
+/// Cloning a `QuicConnection` is cheap, the clones refer to the same connection. They can be
+/// moved to different tasks, and open, accept streams or close the connection concurrently.
+/// The connection is unregistered from the global connection set once all the clones are dropped.
+#[derive(Debug, Clone)]
 pub struct QuicConnection {
-    key: ConnKey,
+    _registration: Arc<Registration>,
     inner: ArcConnection,
 }
 
@@ -56,12 +87,44 @@ impl QuicConnection {
         self.inner.accept_uni_stream().await
     }
 
+    /// Same as [`ArcConnection::available_bidi_streams`]
+    #[inline]
+    pub fn available_bidi_streams(&self) -> u64 {
+        self.inner.available_bidi_streams()
+    }
+
+    /// Same as [`ArcConnection::available_uni_streams`]
+    #[inline]
+    pub fn available_uni_streams(&self) -> u64 {
+        self.inner.available_uni_streams()
+    }
+
     /// Gracefully closes the connection.
     ///
     /// Same as [`ArcConnection::close`]
     #[inline]
-    pub fn close(&self, msg: impl Into<std::borrow::Cow<'static, str>>) {
-        self.inner.close(msg)
+    pub fn close(&self, error: impl Into<qbase::error::Error>) {
+        self.inner.close(error)
+    }
+
+    /// Returns the reason why the connection is closed, or `None` if it is still alive.
+    ///
+    /// Same as [`ArcConnection::close_reason`]
+    #[inline]
+    pub fn close_reason(&self) -> Option<qbase::error::Error> {
+        self.inner.close_reason()
+    }
+
+    /// Same as [`ArcConnection::closed`]
+    #[inline]
+    pub async fn closed(&self) -> qbase::error::Error {
+        self.inner.closed().await
+    }
+
+    /// Same as [`ArcConnection::current_spin_bit`]
+    #[inline]
+    pub fn current_spin_bit(&self) -> Option<qbase::packet::SpinBit> {
+        self.inner.current_spin_bit()
     }
 
     #[inline]
@@ -69,16 +132,76 @@ impl QuicConnection {
         self.inner.datagram_reader()
     }
 
+    /// Same as [`ArcConnection::datagram_stats`]
+    #[inline]
+    pub fn datagram_stats(&self) -> Option<qunreliable::DatagramStats> {
+        self.inner.datagram_stats()
+    }
+
     #[inline]
     pub async fn datagram_writer(&self) -> io::Result<Option<qunreliable::UnreliableWriter>> {
         self.inner.datagram_writer().await
     }
 
+    /// Same as [`ArcConnection::flush`]
+    #[inline]
+    pub fn flush(&self) {
+        self.inner.flush()
+    }
+
+    /// Same as [`ArcConnection::idle_timeout`]
+    #[inline]
+    pub fn idle_timeout(&self) -> Duration {
+        self.inner.idle_timeout()
+    }
+
     #[inline]
     pub fn is_active(&self) -> bool {
         self.inner.is_active()
     }
 
+    /// Same as [`ArcConnection::is_handshake_complete`]
+    #[inline]
+    pub fn is_handshake_complete(&self) -> bool {
+        self.inner.is_handshake_complete()
+    }
+
+    /// Same as [`ArcConnection::established`]
+    #[inline]
+    pub async fn established(&self) -> Result<(), HandshakeError> {
+        self.inner.established().await
+    }
+
+    /// Same as [`ArcConnection::is_handshake_confirmed`]
+    #[inline]
+    pub fn is_handshake_confirmed(&self) -> bool {
+        self.inner.is_handshake_confirmed()
+    }
+
+    /// Same as [`ArcConnection::is_peer_validated`]
+    #[inline]
+    pub fn is_peer_validated(&self) -> bool {
+        self.inner.is_peer_validated()
+    }
+
+    /// Same as [`ArcConnection::keep_alive`]
+    #[inline]
+    pub fn keep_alive(&self, policy: qconnection::conn::keep_alive::KeepAlive) {
+        self.inner.keep_alive(policy)
+    }
+
+    /// Same as [`ArcConnection::local_cids`]
+    #[inline]
+    pub fn local_cids(&self) -> Vec<(u64, ConnectionId, qbase::token::ResetToken)> {
+        self.inner.local_cids()
+    }
+
+    /// Same as [`ArcConnection::negotiated_cipher_suite`]
+    #[inline]
+    pub fn negotiated_cipher_suite(&self) -> Option<rustls::SupportedCipherSuite> {
+        self.inner.negotiated_cipher_suite()
+    }
+
     #[inline]
     pub async fn open_bi_stream(
         &self,
@@ -90,11 +213,51 @@ impl QuicConnection {
     pub async fn open_uni_stream(&self) -> io::Result<Option<(StreamId, StreamWriter)>> {
         self.inner.open_uni_stream().await
     }
-}
 
-impl Drop for QuicConnection {
-    fn drop(&mut self) {
-        CONNECTIONS.remove(&self.key);
+    /// Same as [`ArcConnection::path_stats`]
+    #[inline]
+    pub fn path_stats(&self) -> Vec<qconnection::path::PathStats> {
+        self.inner.path_stats()
+    }
+
+    /// Same as [`ArcConnection::remote_cids`]
+    #[inline]
+    pub fn remote_cids(&self) -> Vec<(u64, ConnectionId, qbase::token::ResetToken)> {
+        self.inner.remote_cids()
+    }
+
+    /// Same as [`ArcConnection::send_buffered_bytes`]
+    #[inline]
+    pub fn send_buffered_bytes(&self) -> u64 {
+        self.inner.send_buffered_bytes()
+    }
+
+    /// Same as [`ArcConnection::set_stream_scheduler`]
+    #[inline]
+    pub fn set_stream_scheduler(&self, scheduler: Box<dyn qrecovery::streams::StreamScheduler>) {
+        self.inner.set_stream_scheduler(scheduler)
+    }
+
+    /// Same as [`ArcConnection::spin_flips`]
+    #[inline]
+    pub fn spin_flips(
+        &self,
+    ) -> Option<futures::channel::mpsc::UnboundedReceiver<qbase::packet::SpinBit>> {
+        self.inner.spin_flips()
+    }
+
+    /// Same as [`ArcConnection::stats`]
+    #[inline]
+    pub fn stats(&self) -> Option<qconnection::conn::raw::ConnectionStats> {
+        self.inner.stats()
+    }
+
+    /// Returns the QUIC version that the connection uses.
+    ///
+    /// Same as [`ArcConnection::version`]
+    #[inline]
+    pub fn version(&self) -> u32 {
+        self.inner.version()
     }
 }
 
@@ -118,21 +281,34 @@ async fn usc_recv_task(usc: ArcUsc) {
                 remote: hdr.src,
             };
 
+            // 仅低2位是ECN，其余是DSCP
+            let ecn = hdr.ecn.map(|tos| tos & 0b11);
             let reader = PacketReader::new(data, 8);
-            for pkt in reader.flatten() {
+            for mut pkt in reader.flatten() {
+                if let Packet::Data(packet) = &mut pkt {
+                    packet.ecn = ecn;
+                }
                 accpet_packet(pkt, pathway, &usc);
             }
         }
     }
 }
 
-fn get_or_create_usc(bind_addr: &SocketAddr) -> io::Result<ArcUsc> {
-    let usc = UscRegistry::get_or_create_usc(*bind_addr, usc_recv_task)?;
+fn get_or_create_usc(
+    bind_addr: &SocketAddr,
+    factory: &SocketFactory,
+    runtime: &dyn Runtime,
+) -> io::Result<ArcUsc> {
+    let usc = UscRegistry::get_or_create_usc(*bind_addr, factory.as_ref(), runtime, usc_recv_task)?;
     Ok(usc)
 }
 
-fn create_new_usc(bind_addr: &SocketAddr) -> io::Result<ArcUsc> {
-    let usc = UscRegistry::create_new_usc(*bind_addr, usc_recv_task)?;
+fn create_new_usc(
+    bind_addr: &SocketAddr,
+    factory: &SocketFactory,
+    runtime: &dyn Runtime,
+) -> io::Result<ArcUsc> {
+    let usc = UscRegistry::create_new_usc(*bind_addr, factory.as_ref(), runtime, usc_recv_task)?;
     Ok(usc)
 }
 
@@ -153,7 +329,8 @@ fn accpet_packet(packet: Packet, pathway: Pathway, usc: &ArcUsc) {
             }
         }
         Packet::Retry(retry) => {
-            let key = ConnKey::Server(*retry.get_dcid());
+            // Retry包只会发给客户端
+            let key = ConnKey::Client(*retry.get_dcid());
             if let Some(conn) = CONNECTIONS.get(&key) {
                 conn.recv_retry_packet(&retry);
                 conn.update_path_recv_time(pathway);
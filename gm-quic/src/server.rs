@@ -1,25 +1,39 @@
 use std::{
     io::{self},
     net::{SocketAddr, ToSocketAddrs},
-    sync::{Arc, LazyLock, RwLock, Weak},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock, RwLock, Weak,
+    },
+    time::Duration,
 };
 
 use dashmap::DashMap;
 use qbase::{
     cid::ConnectionId,
+    error::{Error, ErrorKind},
     packet::{header::GetScid, long, DataHeader, DataPacket, InitialHeader, RetryHeader},
     param::ServerParameters,
     sid::{handy::ConsistentConcurrency, ControlConcurrency},
     token::{ArcTokenRegistry, TokenProvider},
 };
-use qconnection::{conn::ArcConnection, path::Pathway, router::Router, usc::ArcUsc};
+use qcongestion::{CongestionAlgorithm, INITIAL_RTT};
+use qconnection::{
+    conn::ArcConnection,
+    path::{Pathway, DEFAULT_ANTI_FACTOR},
+    router::Router,
+    spawn::{self, Runtime, TokioRuntime},
+    usc::{bind_udp_socket, bind_udp_socket_with, ArcUsc, SocketFactory},
+};
 use rustls::{
     pki_types::{CertificateDer, PrivateKeyDer},
     server::{danger::ClientCertVerifier, NoClientAuth, ResolvesServerCert, WantsServerCert},
     ConfigBuilder, ServerConfig as TlsServerConfig, WantsVerifier,
 };
 
-use crate::{get_or_create_usc, util, ConnKey, QuicConnection, CONNECTIONS};
+use crate::{
+    get_or_create_usc, util, ConnKey, QuicConnection, Registration, CONNECTIONS, SERVER_CONNECTIONS,
+};
 
 type TlsServerConfigBuilder<T> = ConfigBuilder<TlsServerConfig, T>;
 type QuicListner = Arc<util::Channel<(Arc<QuicConnection>, Pathway)>>;
@@ -64,6 +78,15 @@ pub struct QuicServer {
     streams_controller:
         Box<dyn Fn(u64, u64) -> Box<dyn ControlConcurrency> + Send + Sync + 'static>,
     token_provider: Option<Arc<dyn TokenProvider>>,
+    max_connections: Option<usize>,
+    max_half_open_connections: Option<usize>,
+    handshake_timeout: Option<Duration>,
+    half_open_connections: Arc<AtomicUsize>,
+    initial_rtt: Duration,
+    congestion_algorithm: CongestionAlgorithm,
+    max_send_udp_payload: Option<usize>,
+    runtime: Arc<dyn Runtime>,
+    anti_amplification_factor: usize,
 }
 
 impl QuicServer {
@@ -77,6 +100,15 @@ impl QuicServer {
             tls_config: TlsServerConfig::builder_with_protocol_versions(&[&rustls::version::TLS13]),
             streams_controller: Box::new(|bi, uni| Box::new(ConsistentConcurrency::new(bi, uni))),
             token_provider: None,
+            max_connections: None,
+            max_half_open_connections: None,
+            handshake_timeout: None,
+            socket_factory: Arc::new(bind_udp_socket),
+            initial_rtt: INITIAL_RTT,
+            congestion_algorithm: CongestionAlgorithm::Bbr,
+            max_send_udp_payload: None,
+            runtime: Arc::new(TokioRuntime),
+            anti_amplification_factor: DEFAULT_ANTI_FACTOR,
         }
     }
 
@@ -92,6 +124,15 @@ impl QuicServer {
             tls_config,
             streams_controller: Box::new(|bi, uni| Box::new(ConsistentConcurrency::new(bi, uni))),
             token_provider: None,
+            max_connections: None,
+            max_half_open_connections: None,
+            handshake_timeout: None,
+            socket_factory: Arc::new(bind_udp_socket),
+            initial_rtt: INITIAL_RTT,
+            congestion_algorithm: CongestionAlgorithm::Bbr,
+            max_send_udp_payload: None,
+            runtime: Arc::new(TokioRuntime),
+            anti_amplification_factor: DEFAULT_ANTI_FACTOR,
         }
     }
 
@@ -109,6 +150,15 @@ impl QuicServer {
                 .unwrap(),
             streams_controller: Box::new(|bi, uni| Box::new(ConsistentConcurrency::new(bi, uni))),
             token_provider: None,
+            max_connections: None,
+            max_half_open_connections: None,
+            handshake_timeout: None,
+            socket_factory: Arc::new(bind_udp_socket),
+            initial_rtt: INITIAL_RTT,
+            congestion_algorithm: CongestionAlgorithm::Bbr,
+            max_send_udp_payload: None,
+            runtime: Arc::new(TokioRuntime),
+            anti_amplification_factor: DEFAULT_ANTI_FACTOR,
         }
     }
 
@@ -165,6 +215,24 @@ impl QuicServer {
             _ => return,
         };
 
+        // 未完成握手的连接数已达上限，同样忽略新的连接请求，直到有握手完成或超时
+        let Some(half_open) = server.try_reserve_half_open_connection() else {
+            log::warn!(
+                "refuse connection from {}: too many half-open connections",
+                pathway.dst_addr()
+            );
+            return;
+        };
+
+        // 连接数已达上限，忽略新的连接请求，直到有连接被释放
+        if !server.try_reserve_connection() {
+            log::warn!(
+                "refuse connection from {}: too many connections",
+                pathway.dst_addr()
+            );
+            return;
+        }
+
         let streams_ctrl = (server.streams_controller)(
             server.parameters.initial_max_streams_bidi().into_inner(),
             server.parameters.initial_max_streams_uni().into_inner(),
@@ -186,15 +254,29 @@ impl QuicServer {
             streams_ctrl,
             tls_config,
             token_registry,
+            server.initial_rtt,
+            server.congestion_algorithm,
+            server.anti_amplification_factor,
+            server.max_send_udp_payload,
+            server.runtime.clone(),
         );
         inner.add_initial_path(pathway, usc.clone());
+        if half_open.is_some() || server.handshake_timeout.is_some() {
+            server.runtime.spawn(Box::pin(Self::watch_handshake(
+                inner.clone(),
+                half_open,
+                server.handshake_timeout,
+                server.runtime.clone(),
+            )));
+        }
         let conn = Arc::new(QuicConnection {
-            key: ConnKey::Server(initial_scid),
+            _registration: Arc::new(Registration(ConnKey::Server(initial_scid))),
             inner: inner.clone(), // emm...
         });
 
+        // 先注册再交给listener，若listener已关闭，连接被丢弃时会从CONNECTIONS中移除并释放名额
+        CONNECTIONS.insert(ConnKey::Server(initial_scid), inner);
         if server.listener.send((conn, pathway.filp())).is_ok() {
-            CONNECTIONS.insert(ConnKey::Server(initial_scid), inner);
             _ = Router::try_to_route_packet_from(packet, pathway, usc);
         }
     }
@@ -210,6 +292,63 @@ impl QuicServer {
         }
     }
 
+    /// Take a place for a new connection, return `false` if the number of live connections accepted by the
+    /// server has reached the limit.
+    ///
+    /// The place is released when the [`QuicConnection`] is dropped.
+    fn try_reserve_connection(&self) -> bool {
+        SERVER_CONNECTIONS
+            .fetch_update(
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                |live_connections| match self.max_connections {
+                    Some(max_connections) if live_connections >= max_connections => None,
+                    _ => Some(live_connections + 1),
+                },
+            )
+            .is_ok()
+    }
+
+    /// Take a place for a new half-open connection, return `None` if the number of half-open connections has reached
+    /// the limit, or `Some(None)` if there is no limit.
+    ///
+    /// The place is released when the returned [`HalfOpen`] is dropped.
+    fn try_reserve_half_open_connection(&self) -> Option<Option<HalfOpen>> {
+        let Some(max_half_open_connections) = self.max_half_open_connections else {
+            return Some(None);
+        };
+        self.half_open_connections
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |half_open| {
+                (half_open < max_half_open_connections).then_some(half_open + 1)
+            })
+            .ok()?;
+        Some(Some(HalfOpen(self.half_open_connections.clone())))
+    }
+
+    /// Wait for the handshake of the connection, release the place of the half-open connection once the handshake is
+    /// complete or the connection is closed, and close the connection if the handshake is timed out on the `runtime`.
+    async fn watch_handshake(
+        conn: ArcConnection,
+        _half_open: Option<HalfOpen>,
+        handshake_timeout: Option<Duration>,
+        runtime: Arc<dyn Runtime>,
+    ) {
+        let Some(handshake_timeout) = handshake_timeout else {
+            _ = conn.established().await;
+            return;
+        };
+        if spawn::timeout(runtime.as_ref(), handshake_timeout, conn.established())
+            .await
+            .is_err()
+        {
+            let reason = "handshake timeout";
+            conn.close(Error::with_default_fty(
+                ErrorKind::ConnectionRefused,
+                reason,
+            ));
+        }
+    }
+
     fn initial_server_keys(&self, dcid: ConnectionId) -> rustls::quic::Keys {
         let suite = self
             .tls_config
@@ -239,6 +378,15 @@ impl QuicServer {
     }
 }
 
+/// A place of the half-open connection, released when it's dropped.
+struct HalfOpen(Arc<AtomicUsize>);
+
+impl Drop for HalfOpen {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
 #[derive(Debug)]
 struct Host {
     cert_chain: Vec<rustls::pki_types::CertificateDer<'static>>,
@@ -255,6 +403,15 @@ pub struct QuicServerBuilder<T> {
     streams_controller:
         Box<dyn Fn(u64, u64) -> Box<dyn ControlConcurrency> + Send + Sync + 'static>,
     token_provider: Option<Arc<dyn TokenProvider>>,
+    max_connections: Option<usize>,
+    max_half_open_connections: Option<usize>,
+    handshake_timeout: Option<Duration>,
+    socket_factory: SocketFactory,
+    initial_rtt: Duration,
+    congestion_algorithm: CongestionAlgorithm,
+    max_send_udp_payload: Option<usize>,
+    runtime: Arc<dyn Runtime>,
+    anti_amplification_factor: usize,
 }
 
 /// The builder for the quic server with SNI enabled.
@@ -268,6 +425,15 @@ pub struct QuicServerSniBuilder<T> {
     streams_controller:
         Box<dyn Fn(u64, u64) -> Box<dyn ControlConcurrency> + Send + Sync + 'static>,
     token_provider: Option<Arc<dyn TokenProvider>>,
+    max_connections: Option<usize>,
+    max_half_open_connections: Option<usize>,
+    handshake_timeout: Option<Duration>,
+    socket_factory: SocketFactory,
+    initial_rtt: Duration,
+    congestion_algorithm: CongestionAlgorithm,
+    max_send_udp_payload: Option<usize>,
+    runtime: Arc<dyn Runtime>,
+    anti_amplification_factor: usize,
 }
 
 impl<T> QuicServerBuilder<T> {
@@ -349,6 +515,174 @@ impl<T> QuicServerBuilder<T> {
         self.passive_listening = true;
         self
     }
+
+    /// Limit the number of connections that the server can hold at the same time.
+    ///
+    /// When there are already `max_connections` connections accepted by the server and not dropped yet, the server will
+    /// ignore the new connection attempts(the [Initial packet]s from unknown clients), until some connections are
+    /// dropped. The client whose attempt is ignored will retry by itself, so the limit works as a backpressure.
+    ///
+    /// Note that the connections that have been queued in the server but not yet returned by [`QuicServer::accept`] are
+    /// also counted, so if the application stops calling [`QuicServer::accept`], no more connections will be accepted
+    /// once the limit is reached.
+    ///
+    /// If you call this multiple times, only the last `max_connections` will be used. By default, there is no limit.
+    ///
+    /// [Initial packet](https://www.rfc-editor.org/rfc/rfc9000.html#name-initial-packet)
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Limit the number of half-open connections, whose handshake is not complete yet, that the server can hold at the
+    /// same time.
+    ///
+    /// It bounds the resources spent on the incomplete handshakes, for example when the server is flooded by the
+    /// [Initial packet]s from the spoofed addresses. Like [`QuicServerBuilder::max_connections`], the new connection
+    /// attempts beyond the limit are ignored, until some handshakes are complete, the connections are closed, or the
+    /// half-open connections are timed out by [`QuicServerBuilder::handshake_timeout`].
+    ///
+    /// If you call this multiple times, only the last `max_half_open_connections` will be used. By default, there is no
+    /// limit.
+    ///
+    /// [Initial packet](https://www.rfc-editor.org/rfc/rfc9000.html#name-initial-packet)
+    pub fn max_half_open_connections(mut self, max_half_open_connections: usize) -> Self {
+        self.max_half_open_connections = Some(max_half_open_connections);
+        self
+    }
+
+    /// Close the connections whose handshake is not complete within `handshake_timeout` after they are accepted.
+    ///
+    /// The half-open connections usually idle out after the idle timeout, which is much longer than a handshake should
+    /// take. A shorter `handshake_timeout` frees the resources, and the place in the
+    /// [`QuicServerBuilder::max_half_open_connections`] limit, sooner.
+    ///
+    /// If you call this multiple times, only the last `handshake_timeout` will be used. By default, there is no timeout
+    /// other than the idle timeout.
+    pub fn handshake_timeout(mut self, handshake_timeout: Duration) -> Self {
+        self.handshake_timeout = Some(handshake_timeout);
+        self
+    }
+
+    /// Specify the RTT assumed for the new connections before any RTT sample is taken.
+    ///
+    /// The loss detection and the PTO use it until the first RTT sample of the path. If the RTT of the network is known
+    /// to be much smaller, for example in a LAN, a smaller `initial_rtt` makes the lost handshake packets retransmitted
+    /// sooner.
+    ///
+    /// If you call this multiple times, only the last `initial_rtt` will be used. By default, it's [`INITIAL_RTT`].
+    ///
+    /// See [Section 6.2.2](https://www.rfc-editor.org/rfc/rfc9002.html#name-handshakes-and-new-paths) of
+    /// [RFC 9002](https://www.rfc-editor.org/rfc/rfc9002.html).
+    pub fn initial_rtt(mut self, initial_rtt: Duration) -> Self {
+        self.initial_rtt = initial_rtt;
+        self
+    }
+
+    /// Specify the congestion control algorithm of the paths of the new connections.
+    ///
+    /// If you call this multiple times, only the last `algorithm` will be used. By default, it's
+    /// [`CongestionAlgorithm::Bbr`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`io::ErrorKind::InvalidInput`] error if the beta of [`CongestionAlgorithm::NewRenoWithBeta`] is
+    /// not in (0, 1).
+    pub fn with_congestion_algorithm(mut self, algorithm: CongestionAlgorithm) -> io::Result<Self> {
+        if let CongestionAlgorithm::NewRenoWithBeta(beta) = algorithm {
+            if !(beta > 0.0 && beta < 1.0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("the loss reduction factor must be in (0, 1), but got {beta}"),
+                ));
+            }
+        }
+        self.congestion_algorithm = algorithm;
+        Ok(self)
+    }
+
+    /// Specify the anti-amplification factor for the paths not validated yet.
+    ///
+    /// Before the address of the client is validated, the server can only send `factor` times the amount of data
+    /// received from that address. A smaller factor is more conservative against the amplification attacks, but may
+    /// make the handshake take more round trips. It's useful for testing and interop scenarios.
+    ///
+    /// If you call this multiple times, only the last `factor` will be used. By default, it's [`DEFAULT_ANTI_FACTOR`],
+    /// which is three times as RFC required.
+    ///
+    /// See [Section 8](https://www.rfc-editor.org/rfc/rfc9000.html#name-address-validation) of
+    /// [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html).
+    pub fn anti_amplification_factor(mut self, factor: usize) -> Self {
+        self.anti_amplification_factor = factor;
+        self
+    }
+
+    /// Cap the size of the UDP datagrams sent on each path.
+    ///
+    /// Some networks drop large UDP datagrams, the datagrams will not be larger than `size`, even if the peer advertised,
+    /// or the path discovered, a larger one. It's useful for the conservative deployments behind such middleboxes.
+    ///
+    /// If you call this multiple times, only the last `size` will be used. By default, only the MTU of the path limits
+    /// the size of the datagrams.
+    ///
+    /// # Panics
+    ///
+    /// Values below 1200 are invalid, QUIC requires to send datagrams of at least 1200 bytes, see
+    /// [Section 14](https://www.rfc-editor.org/rfc/rfc9000.html#name-datagram-size) of
+    /// [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html).
+    pub fn max_send_udp_payload(mut self, size: usize) -> Self {
+        assert!(
+            size >= 1200,
+            "Values below 1200 are invalid for max_send_udp_payload"
+        );
+        self.max_send_udp_payload = Some(size);
+        self
+    }
+
+    /// Specify the async runtime to run the background tasks and the timers of the connections.
+    ///
+    /// By default, the tasks are spawned on the current tokio runtime, and timed by the tokio timers. Provide your own
+    /// [`Runtime`] implementation to run them elsewhere, such as on a dedicated runtime, or to instrument them.
+    ///
+    /// If you call this multiple times, only the last `runtime` will be used.
+    pub fn with_runtime(mut self, runtime: impl Runtime + 'static) -> Self {
+        self.runtime = Arc::new(runtime);
+        self
+    }
+
+    /// Specify how to bind the sockets that the server listens to.
+    ///
+    /// By default, the server binds the os udp sockets. The `socket_factory` will be called with each address passed
+    /// to [`QuicServerBuilder::listen`] that has not been bound yet. Provide your own [`AsyncUdpSocket`] implementation
+    /// to use other transports, such as io_uring based sockets, or in-process sockets for testing.
+    ///
+    /// If you call this multiple times, only the last `socket_factory` will be used.
+    ///
+    /// [`AsyncUdpSocket`]: qudp::AsyncUdpSocket
+    pub fn with_socket_factory(
+        mut self,
+        socket_factory: impl Fn(SocketAddr) -> io::Result<Arc<dyn qudp::AsyncUdpSocket>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.socket_factory = Arc::new(socket_factory);
+        self
+    }
+
+    /// Apply the socket options to the os udp sockets that the server binds, such as the DSCP for QoS marking, and the
+    /// sizes of the socket buffers.
+    ///
+    /// The DSCP only occupies the higher 6 bits of the TOS byte, the ECN codepoint of each datagram is kept.
+    ///
+    /// This replaces the socket factory with the one that binds the os udp sockets with the `options`, so it overrides
+    /// the previous [`with_socket_factory`] call, and vice versa.
+    ///
+    /// [`with_socket_factory`]: Self::with_socket_factory
+    pub fn with_socket_options(mut self, options: qudp::SocketOptions) -> Self {
+        self.socket_factory = bind_udp_socket_with(options);
+        self
+    }
 }
 
 impl QuicServerBuilder<TlsServerConfigBuilder<WantsVerifier>> {
@@ -367,6 +701,15 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsVerifier>> {
                 .with_client_cert_verifier(client_cert_verifier),
             streams_controller: self.streams_controller,
             token_provider: self.token_provider,
+            max_connections: self.max_connections,
+            max_half_open_connections: self.max_half_open_connections,
+            handshake_timeout: self.handshake_timeout,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
+            anti_amplification_factor: self.anti_amplification_factor,
         }
     }
 
@@ -384,6 +727,15 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsVerifier>> {
                 .with_client_cert_verifier(Arc::new(NoClientAuth)),
             streams_controller: self.streams_controller,
             token_provider: self.token_provider,
+            max_connections: self.max_connections,
+            max_half_open_connections: self.max_half_open_connections,
+            handshake_timeout: self.handshake_timeout,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
+            anti_amplification_factor: self.anti_amplification_factor,
         }
     }
 }
@@ -410,6 +762,15 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsServerCert>> {
                 .expect("The private key was wrong encoded or failed validation"),
             streams_controller: self.streams_controller,
             token_provider: self.token_provider,
+            max_connections: self.max_connections,
+            max_half_open_connections: self.max_half_open_connections,
+            handshake_timeout: self.handshake_timeout,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
+            anti_amplification_factor: self.anti_amplification_factor,
         }
     }
 
@@ -448,6 +809,15 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsServerCert>> {
                 .expect("The private key was wrong encoded or failed validation"),
             streams_controller: self.streams_controller,
             token_provider: self.token_provider,
+            max_connections: self.max_connections,
+            max_half_open_connections: self.max_half_open_connections,
+            handshake_timeout: self.handshake_timeout,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
+            anti_amplification_factor: self.anti_amplification_factor,
         }
     }
 
@@ -473,6 +843,15 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsServerCert>> {
                 .expect("The private key was wrong encoded or failed validation"),
             streams_controller: self.streams_controller,
             token_provider: self.token_provider,
+            max_connections: self.max_connections,
+            max_half_open_connections: self.max_half_open_connections,
+            handshake_timeout: self.handshake_timeout,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
+            anti_amplification_factor: self.anti_amplification_factor,
         })
     }
 
@@ -490,6 +869,15 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsServerCert>> {
             hosts,
             streams_controller: self.streams_controller,
             token_provider: self.token_provider,
+            max_connections: self.max_connections,
+            max_half_open_connections: self.max_half_open_connections,
+            handshake_timeout: self.handshake_timeout,
+            socket_factory: self.socket_factory,
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
+            anti_amplification_factor: self.anti_amplification_factor,
         }
     }
 }
@@ -545,6 +933,10 @@ impl QuicServerBuilder<TlsServerConfig> {
     ///
     /// If you call this multiple times, all the `alpn_protocol` will be used.
     ///
+    /// The order is the server's preference: the first protocol in this list that the client
+    /// offers is selected, regardless of the order in the client's offer. If none of them is
+    /// offered, the handshake fails with the `no_application_protocol` alert.
+    ///
     /// If you never call this method, we will not do ALPN with the client.
     ///
     /// [alpn-protocol-ids](https://www.iana.org/assignments/tls-extensiontype-values/tls-extensiontype-values.xhtml#alpn-protocol-ids)
@@ -582,7 +974,9 @@ impl QuicServerBuilder<TlsServerConfig> {
         let uscs = addresses
             .to_socket_addrs()?
             .filter_map(|address| {
-                let arc_usc = get_or_create_usc(&address).map_err(|e| log::error!("{e}"));
+                let arc_usc =
+                    get_or_create_usc(&address, &self.socket_factory, self.runtime.as_ref())
+                        .map_err(|e| log::error!("{e}"));
                 Some((address, arc_usc.ok()?))
             })
             .collect::<DashMap<_, _>>();
@@ -602,6 +996,15 @@ impl QuicServerBuilder<TlsServerConfig> {
             tls_config: Arc::new(self.tls_config),
             streams_controller: self.streams_controller,
             token_provider: self.token_provider,
+            max_connections: self.max_connections,
+            max_half_open_connections: self.max_half_open_connections,
+            handshake_timeout: self.handshake_timeout,
+            half_open_connections: Default::default(),
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
+            anti_amplification_factor: self.anti_amplification_factor,
         });
         quic_server.listen()?;
         Ok(quic_server)
@@ -613,6 +1016,8 @@ impl QuicServerSniBuilder<TlsServerConfig> {
     ///
     /// If you call this multiple times, all the `alpn_protocol` will be used.
     ///
+    /// Same as [`QuicServerBuilder::with_alpns`], the order is the server's preference.
+    ///
     /// If you never call this method, we will not do ALPN negotiation with the client.
     pub fn with_alpns(mut self, alpn: impl IntoIterator<Item = Vec<u8>>) -> Self {
         self.tls_config.alpn_protocols.extend(alpn);
@@ -648,7 +1053,9 @@ impl QuicServerSniBuilder<TlsServerConfig> {
         let uscs = addresses
             .to_socket_addrs()?
             .filter_map(|address| {
-                let arc_usc = get_or_create_usc(&address).map_err(|e| log::error!("{e}"));
+                let arc_usc =
+                    get_or_create_usc(&address, &self.socket_factory, self.runtime.as_ref())
+                        .map_err(|e| log::error!("{e}"));
                 Some((address, arc_usc.ok()?))
             })
             .collect::<DashMap<_, _>>();
@@ -667,8 +1074,142 @@ impl QuicServerSniBuilder<TlsServerConfig> {
             tls_config: Arc::new(self.tls_config),
             streams_controller: self.streams_controller,
             token_provider: self.token_provider,
+            max_connections: self.max_connections,
+            max_half_open_connections: self.max_half_open_connections,
+            handshake_timeout: self.handshake_timeout,
+            half_open_connections: Default::default(),
+            initial_rtt: self.initial_rtt,
+            congestion_algorithm: self.congestion_algorithm,
+            max_send_udp_payload: self.max_send_udp_payload,
+            runtime: self.runtime,
+            anti_amplification_factor: self.anti_amplification_factor,
         });
         quic_server.listen()?;
         Ok(quic_server)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use qbase::packet::header::long::io::LongHeaderBuilder;
+
+    use super::*;
+    use crate::util::TryRecvError;
+
+    fn client_initial() -> DataPacket {
+        let dcid = ConnectionId::random_gen(8);
+        let scid = ConnectionId::random_gen(8);
+        let header = LongHeaderBuilder::with_cid(dcid, scid).initial(vec![]);
+        DataPacket {
+            header: DataHeader::Long(long::DataHeader::Initial(header)),
+            bytes: BytesMut::zeroed(1200),
+            offset: 64,
+            ecn: None,
+        }
+    }
+
+    fn negotiate_alpn(
+        server_alpns: &[&[u8]],
+        client_alpns: &[&[u8]],
+    ) -> Result<Option<Vec<u8>>, rustls::Error> {
+        let server_tls = QuicServer::builder()
+            .without_cert_verifier()
+            .with_single_cert_files(
+                "examples/keychain/quic.test.net/quic-test-net-ECC.crt",
+                "examples/keychain/quic.test.net/quic-test-net-ECC.key",
+            )
+            .unwrap()
+            .with_alpns(server_alpns.iter().map(|alpn| alpn.to_vec()))
+            .tls_config;
+        let mut client_tls = rustls::ClientConfig::builder()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        client_tls.alpn_protocols = client_alpns.iter().map(|alpn| alpn.to_vec()).collect();
+
+        let version = rustls::quic::Version::V1;
+        let server_name = "quic.test.net".try_into().unwrap();
+        let mut client =
+            rustls::quic::ClientConnection::new(Arc::new(client_tls), version, server_name, vec![])
+                .unwrap();
+        let mut server =
+            rustls::quic::ServerConnection::new(Arc::new(server_tls), version, vec![]).unwrap();
+
+        // 服务端在处理ClientHello时即选定ALPN
+        let mut client_hello = Vec::new();
+        client.write_hs(&mut client_hello);
+        server.read_hs(&client_hello)?;
+        Ok(server.alpn_protocol().map(|alpn| alpn.to_vec()))
+    }
+
+    #[test]
+    fn alpn_server_preference() {
+        let selected = negotiate_alpn(&[b"h3", b"h2"], &[b"h2", b"h3"]).unwrap();
+        assert_eq!(selected.as_deref(), Some(b"h3".as_ref()));
+
+        let selected = negotiate_alpn(&[b"h2", b"h3"], &[b"h3", b"h2"]).unwrap();
+        assert_eq!(selected.as_deref(), Some(b"h2".as_ref()));
+
+        assert_eq!(
+            negotiate_alpn(&[b"h3"], &[b"hq-29"]),
+            Err(rustls::Error::NoApplicationProtocol)
+        );
+    }
+
+    #[tokio::test]
+    async fn max_connections() {
+        let server = QuicServer::builder()
+            .without_cert_verifier()
+            .with_single_cert_files(
+                "examples/keychain/quic.test.net/quic-test-net-ECC.crt",
+                "examples/keychain/quic.test.net/quic-test-net-ECC.key",
+            )
+            .unwrap()
+            .enable_passive_listening()
+            .max_connections(1)
+            .listen(&[] as &[SocketAddr])
+            .unwrap();
+
+        let socket_factory: SocketFactory = Arc::new(bind_udp_socket);
+        let usc = get_or_create_usc(
+            &"127.0.0.1:0".parse().unwrap(),
+            &socket_factory,
+            &TokioRuntime,
+        )
+        .unwrap();
+        let pathway = Pathway::Direct {
+            local: usc.local_addr(),
+            remote: "127.0.0.1:1".parse().unwrap(),
+        };
+
+        QuicServer::try_to_accept_conn_from(client_initial(), pathway, &usc);
+        let (first, _) = server.listener.try_recv().unwrap();
+        assert!(CONNECTIONS.contains_key(&first._registration.0));
+
+        // the second concurrent connection attempt will be refused
+        QuicServer::try_to_accept_conn_from(client_initial(), pathway, &usc);
+        assert!(matches!(
+            server.listener.try_recv(),
+            Err(TryRecvError::Empty)
+        ));
+        assert!(!CONNECTIONS
+            .iter()
+            .any(|entry| entry.key() != &first._registration.0
+                && matches!(entry.key(), ConnKey::Server(_))));
+
+        // capacity frees up after all the clones of the first connection are dropped
+        let cloned = QuicConnection::clone(&first);
+        drop(first);
+        assert!(CONNECTIONS.contains_key(&cloned._registration.0));
+        QuicServer::try_to_accept_conn_from(client_initial(), pathway, &usc);
+        assert!(matches!(
+            server.listener.try_recv(),
+            Err(TryRecvError::Empty)
+        ));
+        drop(cloned);
+        QuicServer::try_to_accept_conn_from(client_initial(), pathway, &usc);
+        let (third, _) = server.listener.try_recv().unwrap();
+        assert!(CONNECTIONS.contains_key(&third._registration.0));
+        drop(third);
+    }
+}
@@ -0,0 +1,71 @@
+//! Open streams concurrently from the clones of the same [`QuicConnection`], over in-process sockets.
+mod common;
+
+use std::{collections::HashSet, io, net::SocketAddr, sync::Arc, time::Duration};
+
+use common::{client_builder, server_builder, MockNetwork};
+use gm_quic::{
+    qbase::{
+        error::{Error, ErrorKind},
+        param::{ClientParameters, ServerParameters},
+    },
+    QuicConnection,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const STREAMS_PER_CLONE: u64 = 2;
+
+async fn open_streams(conn: QuicConnection, tag: u8) -> io::Result<()> {
+    for _ in 0..STREAMS_PER_CLONE {
+        let (_sid, mut writer) = conn.open_uni_stream().await?.unwrap();
+        writer.write_all(&[tag]).await?;
+        writer.shutdown().await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn open_streams_from_clones() {
+    let network = Arc::new(MockNetwork::default());
+    let server_addr: SocketAddr = "10.0.0.1:4433".parse().unwrap();
+
+    let mut server_params = ServerParameters::default();
+    server_params.set_initial_max_streams_uni(2 * STREAMS_PER_CLONE);
+    server_params.set_initial_max_data((1u32 << 10).into());
+    server_params.set_initial_max_stream_data_uni((1u32 << 10).into());
+    let server = server_builder(&network, server_params)
+        .listen(server_addr)
+        .unwrap();
+    let client = client_builder(&network, ClientParameters::default(), "10.0.0.2:0").build();
+
+    let exchange = async {
+        let client_conn = client.connect("quic.test.net", server_addr).await.unwrap();
+        let first = tokio::spawn(open_streams(QuicConnection::clone(&client_conn), 1));
+        let second = tokio::spawn(open_streams(QuicConnection::clone(&client_conn), 2));
+
+        let (server_conn, _pathway) = server.accept().await?;
+        let mut sids = HashSet::new();
+        let mut tags = Vec::new();
+        for _ in 0..2 * STREAMS_PER_CLONE {
+            let (sid, mut reader) = server_conn.accept_uni_stream().await?;
+            assert!(sids.insert(sid));
+            let mut tag = Vec::new();
+            reader.read_to_end(&mut tag).await?;
+            tags.extend(tag);
+        }
+        first.await.unwrap()?;
+        second.await.unwrap()?;
+        tags.sort();
+        assert_eq!(tags, [1, 1, 2, 2]);
+
+        // 任一克隆关闭连接，其余克隆都能观察到
+        let cloned = QuicConnection::clone(&client_conn);
+        cloned.close(Error::with_default_fty(ErrorKind::None, "done"));
+        assert!(client_conn.close_reason().is_some());
+        io::Result::Ok(())
+    };
+    tokio::time::timeout(Duration::from_secs(10), exchange)
+        .await
+        .expect("the streams opened from the clones should be accepted")
+        .unwrap();
+}
@@ -0,0 +1,275 @@
+//! The in-process sockets and the helpers shared by the integration tests.
+//!
+//! The server is global, so each test using these helpers lives in its own test binary.
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, IoSlice, IoSliceMut},
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::task::AtomicWaker;
+use gm_quic::{
+    client::QuicClientBuilder,
+    qbase::param::{ClientParameters, ServerParameters},
+    qudp::{AsyncUdpSocket, PacketHeader},
+    server::QuicServerBuilder,
+    QuicClient, QuicServer,
+};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, ServerConfig, SignatureScheme,
+};
+
+/// Return the builder of the server with the certificate of quic.test.net, its sockets are bound on the `network`.
+pub fn server_builder(
+    network: &Arc<MockNetwork>,
+    parameters: ServerParameters,
+) -> QuicServerBuilder<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    QuicServer::builder_with_crypto_provieder(provider)
+        .with_parameters(parameters)
+        .with_socket_factory({
+            let network = network.clone();
+            move |addr| network.bind(addr)
+        })
+        .without_cert_verifier()
+        .with_single_cert_files(
+            "examples/keychain/quic.test.net/quic-test-net-ECC.crt",
+            "examples/keychain/quic.test.net/quic-test-net-ECC.key",
+        )
+        .unwrap()
+}
+
+/// Return the builder of the client that skips the verification of the server certificate, its sockets are bound to
+/// `bind_addrs` on the `network`.
+#[allow(dead_code)] // 自定义TLS配置的测试不需要它
+pub fn client_builder(
+    network: &Arc<MockNetwork>,
+    parameters: ClientParameters,
+    bind_addrs: impl ToSocketAddrs,
+) -> QuicClientBuilder<ClientConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    client_builder_with_tls(
+        network,
+        skip_server_verification(provider),
+        parameters,
+        bind_addrs,
+    )
+}
+
+/// Like [`client_builder`], but with the given TLS configuration.
+pub fn client_builder_with_tls(
+    network: &Arc<MockNetwork>,
+    tls_config: ClientConfig,
+    parameters: ClientParameters,
+    bind_addrs: impl ToSocketAddrs,
+) -> QuicClientBuilder<ClientConfig> {
+    QuicClient::builder_with_tls(tls_config)
+        .with_parameters(parameters)
+        .with_socket_factory({
+            let network = network.clone();
+            move |addr| network.bind(addr)
+        })
+        .bind(bind_addrs)
+        .unwrap()
+}
+
+/// Return the TLS configuration of the client that only supports TLS 1.3 with the `provider`, and skips the
+/// verification of the server certificate.
+#[allow(dead_code)] // 验证证书的测试不需要它
+pub fn skip_server_verification(provider: Arc<CryptoProvider>) -> ClientConfig {
+    ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(SkipServerVerification(provider)))
+        .with_no_client_auth()
+}
+
+#[derive(Debug, Default)]
+struct Inbox {
+    datagrams: Mutex<VecDeque<(PacketHeader, Vec<u8>)>>,
+    waker: AtomicWaker,
+}
+
+/// A network in the process, delivers the datagrams between the [`MockSocket`]s immediately.
+///
+/// The datagrams to an address can be dropped on purpose to simulate the packet loss.
+#[derive(Debug, Default)]
+pub struct MockNetwork {
+    inboxes: Mutex<HashMap<SocketAddr, Arc<Inbox>>>,
+    next_port: Mutex<u16>,
+    // 每个地址还要丢弃的数据报数目
+    drops: Mutex<HashMap<SocketAddr, usize>>,
+    // 发往每个地址的数据报，包括被丢弃的
+    sent: Mutex<HashMap<SocketAddr, Vec<Vec<u8>>>>,
+    // 断开的地址，其收发的数据报都被丢弃
+    disconnected: Mutex<HashSet<SocketAddr>>,
+}
+
+impl MockNetwork {
+    pub fn bind(self: &Arc<Self>, mut addr: SocketAddr) -> io::Result<Arc<dyn AsyncUdpSocket>> {
+        if addr.port() == 0 {
+            let mut next_port = self.next_port.lock().unwrap();
+            *next_port = (*next_port).max(40000) + 1;
+            addr.set_port(*next_port);
+        }
+        let inbox = Arc::new(Inbox::default());
+        let mut inboxes = self.inboxes.lock().unwrap();
+        if inboxes.contains_key(&addr) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        inboxes.insert(addr, inbox.clone());
+        Ok(Arc::new(MockSocket {
+            addr,
+            inbox,
+            network: self.clone(),
+        }))
+    }
+
+    /// Drop the next `count` datagrams sent to `addr`.
+    #[allow(dead_code)] // 只有模拟丢包的测试需要
+    pub fn drop_next_datagrams_to(&self, addr: SocketAddr, count: usize) {
+        *self.drops.lock().unwrap().entry(addr).or_default() += count;
+    }
+
+    /// Return all the datagrams sent to `addr` in order, including the dropped ones.
+    #[allow(dead_code)] // 只有模拟丢包的测试需要
+    pub fn datagrams_sent_to(&self, addr: SocketAddr) -> Vec<Vec<u8>> {
+        let sent = self.sent.lock().unwrap();
+        sent.get(&addr).cloned().unwrap_or_default()
+    }
+
+    /// Drop all the datagrams sent from and to `addr` from now on, as if the host had gone.
+    #[allow(dead_code)] // 只有模拟主机消失的测试需要
+    pub fn disconnect(&self, addr: SocketAddr) {
+        self.disconnected.lock().unwrap().insert(addr);
+    }
+
+    // 记录数据报，返回其是否应被丢弃
+    fn on_send(&self, dst: SocketAddr, datagram: &[u8]) -> bool {
+        let mut sent = self.sent.lock().unwrap();
+        sent.entry(dst).or_default().push(datagram.to_vec());
+        match self.drops.lock().unwrap().get_mut(&dst) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MockSocket {
+    addr: SocketAddr,
+    inbox: Arc<Inbox>,
+    network: Arc<MockNetwork>,
+}
+
+impl AsyncUdpSocket for MockSocket {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+
+    fn poll_send(
+        &self,
+        bufs: &[IoSlice<'_>],
+        hdr: &PacketHeader,
+        _cx: &mut Context,
+    ) -> Poll<io::Result<usize>> {
+        let disconnected = self.network.disconnected.lock().unwrap();
+        if disconnected.contains(&self.addr) || disconnected.contains(&hdr.dst) {
+            return Poll::Ready(Ok(bufs.len()));
+        }
+        drop(disconnected);
+        // like udp, the datagrams sent to an unknown address are lost
+        if let Some(inbox) = self.network.inboxes.lock().unwrap().get(&hdr.dst) {
+            let mut datagrams = inbox.datagrams.lock().unwrap();
+            for buf in bufs {
+                if self.network.on_send(hdr.dst, buf) {
+                    continue;
+                }
+                let hdr = PacketHeader {
+                    src: self.addr,
+                    dst: hdr.dst,
+                    seg_size: buf.len() as u16,
+                    gso: false,
+                    ..*hdr
+                };
+                datagrams.push_back((hdr, buf.to_vec()));
+            }
+            inbox.waker.wake();
+        }
+        Poll::Ready(Ok(bufs.len()))
+    }
+
+    fn poll_recv(
+        &self,
+        bufs: &mut [IoSliceMut<'_>],
+        hdrs: &mut [PacketHeader],
+        cx: &mut Context,
+    ) -> Poll<io::Result<usize>> {
+        self.inbox.waker.register(cx.waker());
+        let mut datagrams = self.inbox.datagrams.lock().unwrap();
+        let mut received = 0;
+        for (buf, hdr) in bufs.iter_mut().zip(hdrs.iter_mut()) {
+            let Some((datagram_hdr, datagram)) = datagrams.pop_front() else {
+                break;
+            };
+            buf[..datagram.len()].copy_from_slice(&datagram);
+            *hdr = datagram_hdr;
+            received += 1;
+        }
+        if received == 0 {
+            return Poll::Pending;
+        }
+        Poll::Ready(Ok(received))
+    }
+}
+
+/// The certificate in the examples has expired, and the verification is not what most tests care about.
+#[derive(Debug)]
+#[allow(dead_code)] // 验证证书的测试不需要它
+struct SkipServerVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.0.signature_verification_algorithms;
+        verify_tls12_signature(message, cert, dss, algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.0.signature_verification_algorithms;
+        verify_tls13_signature(message, cert, dss, algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,81 @@
+//! Transfer the data in both directions of a bidirectional stream at the same time, with the small
+//! flow control windows, over in-process sockets.
+//!
+//! The [`Reader`] and the [`Writer`] of a stream are driven by different tasks, the backpressure on
+//! one direction must not block the other.
+//!
+//! [`Reader`]: gm_quic::qrecovery::recv::Reader
+//! [`Writer`]: gm_quic::qrecovery::send::Writer
+mod common;
+
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use common::{client_builder, server_builder, MockNetwork};
+use gm_quic::qbase::param::{ClientParameters, ServerParameters};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const DATA_SIZE: usize = 256 * 1024;
+const STREAM_WINDOW: u32 = 4 * 1024;
+const CONN_WINDOW: u32 = 16 * 1024;
+
+fn data(tag: u8) -> Vec<u8> {
+    (0..DATA_SIZE).map(|i| (i % 251) as u8 ^ tag).collect()
+}
+
+async fn write_all(mut writer: impl AsyncWrite + Unpin, data: Vec<u8>) -> io::Result<()> {
+    // 分成小块写入，与对向的读取交替进行
+    for chunk in data.chunks(1000) {
+        writer.write_all(chunk).await?;
+    }
+    writer.shutdown().await
+}
+
+async fn read_to_end(mut reader: impl AsyncRead + Unpin) -> io::Result<Vec<u8>> {
+    let mut received = Vec::with_capacity(DATA_SIZE);
+    reader.read_to_end(&mut received).await?;
+    Ok(received)
+}
+
+#[tokio::test]
+async fn full_duplex_bidi_stream() {
+    let network = Arc::new(MockNetwork::default());
+    let server_addr: SocketAddr = "10.0.0.1:4433".parse().unwrap();
+
+    let mut server_params = ServerParameters::default();
+    server_params.set_initial_max_streams_bidi(1);
+    server_params.set_initial_max_data(CONN_WINDOW.into());
+    server_params.set_initial_max_stream_data_bidi_local(STREAM_WINDOW.into());
+    server_params.set_initial_max_stream_data_bidi_remote(STREAM_WINDOW.into());
+    let server = server_builder(&network, server_params)
+        .listen(server_addr)
+        .unwrap();
+
+    let mut client_params = ClientParameters::default();
+    client_params.set_initial_max_data(CONN_WINDOW.into());
+    client_params.set_initial_max_stream_data_bidi_local(STREAM_WINDOW.into());
+    client_params.set_initial_max_stream_data_bidi_remote(STREAM_WINDOW.into());
+    let client = client_builder(&network, client_params, "10.0.0.2:0").build();
+
+    let exchange = async {
+        let client_conn = client.connect("quic.test.net", server_addr).await.unwrap();
+        let (_sid, (reader, writer)) = client_conn.open_bi_stream().await?.unwrap();
+        // 客户端与服务端的读写各自在独立的任务中同时进行
+        let client_write = tokio::spawn(write_all(writer, data(0)));
+        let client_read = tokio::spawn(read_to_end(reader));
+
+        let (server_conn, _pathway) = server.accept().await?;
+        let (_sid, (server_reader, server_writer)) = server_conn.accept_bi_stream().await?.unwrap();
+        let server_write = tokio::spawn(write_all(server_writer, data(0xff)));
+        let server_read = tokio::spawn(read_to_end(server_reader));
+
+        client_write.await.unwrap()?;
+        server_write.await.unwrap()?;
+        assert!(server_read.await.unwrap()? == data(0));
+        assert!(client_read.await.unwrap()? == data(0xff));
+        io::Result::Ok(())
+    };
+    tokio::time::timeout(Duration::from_secs(10), exchange)
+        .await
+        .expect("the full-duplex transfer should not deadlock")
+        .unwrap();
+}
@@ -0,0 +1,97 @@
+//! The [`QuicServer`] refuses the new handshakes beyond the limit of the half-open connections, until the stalled
+//! handshake is timed out, over in-process sockets.
+mod common;
+
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{client_builder, server_builder, MockNetwork};
+use futures::{channel::oneshot, future::BoxFuture, FutureExt};
+use gm_quic::{
+    qbase::param::{ClientParameters, ServerParameters},
+    qconnection::spawn::{Runtime, TokioRuntime},
+};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Run the tasks on tokio like the default runtime, but the handshake timeout of the first connection only expires when
+/// the test says so, rather than by the wall clock.
+struct HandshakeTimer {
+    expired: Mutex<Option<oneshot::Receiver<()>>>,
+}
+
+impl Runtime for HandshakeTimer {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        TokioRuntime.spawn(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        if duration == HANDSHAKE_TIMEOUT {
+            if let Some(expired) = self.expired.lock().unwrap().take() {
+                return Box::pin(expired.map(|_| ()));
+            }
+        }
+        TokioRuntime.sleep(duration)
+    }
+}
+
+#[tokio::test]
+async fn refuse_beyond_half_open_limit() {
+    let network = Arc::new(MockNetwork::default());
+    let server_addr: SocketAddr = "10.0.0.1:4433".parse().unwrap();
+
+    let mut server_params = ServerParameters::default();
+    server_params.set_initial_max_streams_bidi(1);
+    let (expire_handshake, handshake_expired) = oneshot::channel();
+    let server = server_builder(&network, server_params)
+        .with_runtime(HandshakeTimer {
+            expired: Mutex::new(Some(handshake_expired)),
+        })
+        .max_half_open_connections(1)
+        .handshake_timeout(HANDSHAKE_TIMEOUT)
+        .listen(server_addr)
+        .unwrap();
+
+    let new_client =
+        |addr: &str| client_builder(&network, ClientParameters::default(), addr).build();
+
+    // 发往该客户端的数据报全部丢失，其握手停滞，一直占用半开连接的名额
+    let stalled_addr: SocketAddr = "10.0.0.2:5000".parse().unwrap();
+    network.drop_next_datagrams_to(stalled_addr, usize::MAX);
+    let stalled_client = new_client("10.0.0.2:5000");
+    let _stalled_conn = stalled_client
+        .connect("quic.test.net", server_addr)
+        .await
+        .unwrap();
+    let (stalled_server_conn, _pathway) = server.accept().await.unwrap();
+    // 该客户端随即消失，不再重传Initial，否则其新的连接请求会与下面的客户端争抢名额
+    network.disconnect(stalled_addr);
+
+    // 名额已满，新的握手被拒绝；握手超时由测试控制，无论等待多久停滞的握手都不会超时
+    let client = new_client("10.0.0.3:5000");
+    let client_conn = client.connect("quic.test.net", server_addr).await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), server.accept())
+            .await
+            .is_err()
+    );
+    assert!(stalled_server_conn.close_reason().is_none());
+
+    // 停滞的握手超时后被关闭，释放名额，客户端重传的Initial被接受
+    expire_handshake.send(()).unwrap();
+    let exchange = async {
+        let (server_conn, _pathway) = server.accept().await?;
+        client_conn.established().await.unwrap();
+        server_conn.established().await.unwrap();
+        io::Result::Ok(())
+    };
+    tokio::time::timeout(Duration::from_secs(10), exchange)
+        .await
+        .expect("the handshake should be accepted once the stalled one is timed out")
+        .unwrap();
+    assert!(stalled_server_conn.close_reason().is_some());
+}
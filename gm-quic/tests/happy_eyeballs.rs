@@ -0,0 +1,56 @@
+//! The [`QuicClient`] races the handshakes toward the IPv6 and the IPv4 address of the [`QuicServer`] when happy eyeballs
+//! is enabled, and adopts the IPv4 connection once the IPv6 attempt stalls, over in-process sockets.
+//!
+//! [`QuicClient`]: gm_quic::QuicClient
+//! [`QuicServer`]: gm_quic::QuicServer
+mod common;
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use common::{client_builder, server_builder, MockNetwork};
+use gm_quic::{
+    client::CONNECTION_ATTEMPT_DELAY,
+    qbase::param::{ClientParameters, ServerParameters},
+    qconnection::path::Pathway,
+};
+
+#[tokio::test]
+async fn v4_wins_when_v6_stalls() {
+    let network = Arc::new(MockNetwork::default());
+    let v6_server_addr: SocketAddr = "[fd00::1]:4433".parse().unwrap();
+    let v4_server_addr: SocketAddr = "10.0.0.1:4433".parse().unwrap();
+    let server_addrs = [v6_server_addr, v4_server_addr];
+    let server = server_builder(&network, ServerParameters::default())
+        .listen(&server_addrs[..])
+        .unwrap();
+
+    let client_addrs: [SocketAddr; 2] = [
+        "[fd00::2]:0".parse().unwrap(),
+        "10.0.0.2:0".parse().unwrap(),
+    ];
+    let client = client_builder(&network, ClientParameters::default(), &client_addrs[..])
+        .enable_happy_eyeballs()
+        .build();
+
+    // 发往服务端IPv6地址的数据报全部丢失，IPv6的握手停滞
+    network.disconnect(v6_server_addr);
+    let start = Instant::now();
+    let connect = client.connect("quic.test.net", &server_addrs[..]);
+    let client_conn = tokio::time::timeout(Duration::from_secs(10), connect)
+        .await
+        .expect("the IPv4 attempt should win the race")
+        .unwrap();
+    // IPv4的尝试在IPv6先行一段时间之后才开始，返回的连接已经完成了握手
+    assert!(start.elapsed() >= CONNECTION_ATTEMPT_DELAY);
+    assert!(client_conn.is_handshake_complete());
+
+    let (server_conn, pathway) = server.accept().await.unwrap();
+    assert!(
+        matches!(pathway, Pathway::Direct { local, remote } if local.is_ipv4() && remote.is_ipv4())
+    );
+    server_conn.established().await.unwrap();
+}
@@ -0,0 +1,60 @@
+//! The handshake between the [`QuicClient`] and the [`QuicServer`] recovers from the loss of the first
+//! Initial packet of the client, over in-process sockets.
+mod common;
+
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use common::{client_builder, server_builder, MockNetwork};
+use gm_quic::qbase::param::{ClientParameters, ServerParameters};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[tokio::test]
+async fn retransmit_lost_initial() {
+    let network = Arc::new(MockNetwork::default());
+    let server_addr: SocketAddr = "10.0.0.1:4433".parse().unwrap();
+
+    let mut server_params = ServerParameters::default();
+    server_params.set_initial_max_streams_bidi(1);
+    server_params.set_initial_max_data((1u32 << 10).into());
+    server_params.set_initial_max_stream_data_bidi_remote((1u32 << 10).into());
+    let server = server_builder(&network, server_params)
+        .listen(server_addr)
+        .unwrap();
+    let client = client_builder(&network, ClientParameters::default(), "10.0.0.2:0").build();
+
+    // 客户端携带ClientHello的首个Initial数据报丢失
+    network.drop_next_datagrams_to(server_addr, 1);
+
+    let exchange = async {
+        let client_conn = client.connect("quic.test.net", server_addr).await.unwrap();
+        let (_sid, (mut reader, mut writer)) = client_conn.open_bi_stream().await?.unwrap();
+        writer.write_all(b"ping").await?;
+        writer.shutdown().await?;
+
+        let (server_conn, _pathway) = server.accept().await?;
+        let (_sid, (mut server_reader, mut server_writer)) =
+            server_conn.accept_bi_stream().await?.unwrap();
+        let mut request = Vec::new();
+        server_reader.read_to_end(&mut request).await?;
+        assert_eq!(request, b"ping");
+        server_writer.shutdown().await?;
+
+        let mut response = Vec::new();
+        reader.read_to_end(&mut response).await?;
+        client_conn.established().await.unwrap();
+        io::Result::Ok(())
+    };
+    tokio::time::timeout(Duration::from_secs(10), exchange)
+        .await
+        .expect("the handshake should recover from the lost Initial")
+        .unwrap();
+
+    // Initial空间的PTO超时后，ClientHello重新组帧发送，所在的数据报同样被填充至1200字节
+    let datagrams = network.datagrams_sent_to(server_addr);
+    assert!(datagrams.len() >= 2);
+    for datagram in &datagrams[..2] {
+        assert!(datagram.len() >= 1200);
+        // 长包头，类型为Initial，这些比特不受包头保护
+        assert_eq!(datagram[0] & 0xf0, 0xc0);
+    }
+}
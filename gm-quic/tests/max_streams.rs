@@ -0,0 +1,54 @@
+//! The maximum concurrent streams configured on the [`QuicClient`] builder limit the streams that the
+//! [`QuicServer`] can open, over in-process sockets.
+mod common;
+
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use common::{client_builder, server_builder, MockNetwork};
+use gm_quic::qbase::param::{ClientParameters, ServerParameters};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[tokio::test]
+async fn max_concurrent_streams() {
+    let network = Arc::new(MockNetwork::default());
+    let server_addr: SocketAddr = "10.0.0.1:4433".parse().unwrap();
+
+    let mut server_params = ServerParameters::default();
+    server_params.set_initial_max_streams_bidi(1);
+    server_params.set_initial_max_data((1u32 << 10).into());
+    server_params.set_initial_max_stream_data_bidi_remote((1u32 << 10).into());
+    let server = server_builder(&network, server_params)
+        .listen(server_addr)
+        .unwrap();
+    let client = client_builder(&network, ClientParameters::default(), "10.0.0.2:0")
+        .max_concurrent_bidi_streams(2)
+        .max_concurrent_uni_streams(3)
+        .build();
+
+    let exchange = async {
+        let client_conn = client.connect("quic.test.net", server_addr).await.unwrap();
+        let (_sid, (mut reader, mut writer)) = client_conn.open_bi_stream().await?.unwrap();
+        writer.write_all(b"ping").await?;
+        writer.shutdown().await?;
+
+        let (server_conn, _pathway) = server.accept().await?;
+        let (_sid, (mut server_reader, mut server_writer)) =
+            server_conn.accept_bi_stream().await?.unwrap();
+        let mut request = Vec::new();
+        server_reader.read_to_end(&mut request).await?;
+        assert_eq!(request, b"ping");
+        // 服务端可以打开的流数目，即客户端配置的并发流的数目
+        assert_eq!(server_conn.available_bidi_streams(), 2);
+        assert_eq!(server_conn.available_uni_streams(), 3);
+        server_writer.shutdown().await?;
+
+        let mut response = Vec::new();
+        reader.read_to_end(&mut response).await?;
+        assert!(response.is_empty());
+        io::Result::Ok(())
+    };
+    tokio::time::timeout(Duration::from_secs(10), exchange)
+        .await
+        .expect("the streams limits should be negotiated")
+        .unwrap();
+}
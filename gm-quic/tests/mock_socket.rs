@@ -0,0 +1,80 @@
+//! Run the handshake between the [`QuicClient`] and the [`QuicServer`] over in-process sockets, without
+//! touching the os network stack.
+mod common;
+
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use common::{client_builder_with_tls, server_builder, skip_server_verification, MockNetwork};
+use gm_quic::qbase::param::{ClientParameters, ServerParameters};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[tokio::test]
+async fn handshake_over_mock_socket() {
+    let network = Arc::new(MockNetwork::default());
+    let server_addr: SocketAddr = "10.0.0.1:4433".parse().unwrap();
+
+    let mut server_params = ServerParameters::default();
+    server_params.set_initial_max_streams_bidi(1);
+    server_params.set_initial_max_data((1u32 << 10).into());
+    server_params.set_initial_max_stream_data_bidi_local((1u32 << 10).into());
+    server_params.set_initial_max_stream_data_bidi_remote((1u32 << 10).into());
+    let server = server_builder(&network, server_params)
+        .listen(server_addr)
+        .unwrap();
+    assert_eq!(server.addresses(), vec![server_addr]);
+
+    let client_provider = Arc::new(rustls::crypto::CryptoProvider {
+        cipher_suites: vec![rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256],
+        ..rustls::crypto::ring::default_provider()
+    });
+    let tls_config = skip_server_verification(client_provider);
+    let mut client_params = ClientParameters::default();
+    client_params.set_initial_max_data((1u32 << 10).into());
+    client_params.set_initial_max_stream_data_bidi_local((1u32 << 10).into());
+    client_params.set_initial_max_stream_data_bidi_remote((1u32 << 10).into());
+    let client = client_builder_with_tls(&network, tls_config, client_params, "10.0.0.2:0").build();
+
+    let exchange = async {
+        let client_conn = client.connect("quic.test.net", server_addr).await.unwrap();
+        let (_sid, (mut reader, mut writer)) = client_conn.open_bi_stream().await?.unwrap();
+        // 服务端只允许打开1条双向流，且不允许单向流
+        assert_eq!(client_conn.available_bidi_streams(), 0);
+        assert_eq!(client_conn.available_uni_streams(), 0);
+        writer.write_all(b"ping").await?;
+        writer.shutdown().await?;
+
+        let (server_conn, _pathway) = server.accept().await?;
+        assert_eq!(client_conn.version(), 0x00000001);
+        assert_eq!(server_conn.version(), 0x00000001);
+        let (_sid, (mut server_reader, mut server_writer)) =
+            server_conn.accept_bi_stream().await?.unwrap();
+        let mut request = Vec::new();
+        server_reader.read_to_end(&mut request).await?;
+        assert_eq!(request, b"ping");
+        server_writer.write_all(b"pong").await?;
+        server_writer.shutdown().await?;
+
+        let mut response = Vec::new();
+        reader.read_to_end(&mut response).await?;
+        assert_eq!(response, b"pong");
+        // 服务端在握手完成时即确认，并在发送pong之前发出了HANDSHAKE_DONE
+        assert!(server_conn.is_handshake_confirmed());
+        assert!(client_conn.is_handshake_complete());
+        assert!(client_conn.is_handshake_confirmed());
+        client_conn.established().await.unwrap();
+        server_conn.established().await.unwrap();
+        // 握手完成后，服务端解除了抗放大限制；客户端则一开始就认为服务端地址已验证
+        assert!(server_conn.is_peer_validated());
+        assert!(client_conn.is_peer_validated());
+        // 客户端只提供了TLS_AES_128_GCM_SHA256
+        for conn in [&client_conn, &server_conn] {
+            let suite = conn.negotiated_cipher_suite().unwrap().suite();
+            assert_eq!(suite, rustls::CipherSuite::TLS13_AES_128_GCM_SHA256);
+        }
+        io::Result::Ok(())
+    };
+    tokio::time::timeout(Duration::from_secs(10), exchange)
+        .await
+        .expect("the handshake over the mock sockets should complete")
+        .unwrap();
+}
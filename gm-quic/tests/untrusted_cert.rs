@@ -0,0 +1,50 @@
+//! The [`QuicClient`] rejects the certificate of the [`QuicServer`] that is not trusted, and the
+//! application gets the TLS handshake error, over in-process sockets.
+mod common;
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use common::{client_builder_with_tls, server_builder, MockNetwork};
+use gm_quic::{
+    qbase::param::{ClientParameters, ServerParameters},
+    qconnection::error::HandshakeError,
+};
+
+#[tokio::test]
+async fn untrusted_server_cert() {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let network = Arc::new(MockNetwork::default());
+    let server_addr: SocketAddr = "10.0.0.1:4433".parse().unwrap();
+
+    let _server = server_builder(&network, ServerParameters::default())
+        .listen(server_addr)
+        .unwrap();
+
+    // 示例中的证书已经过期，也不被信任
+    let tls_config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
+    let client = client_builder_with_tls(
+        &network,
+        tls_config,
+        ClientParameters::default(),
+        "10.0.0.2:0",
+    )
+    .build();
+
+    let client_conn = client.connect("quic.test.net", server_addr).await.unwrap();
+    let error = tokio::time::timeout(Duration::from_secs(10), client_conn.established())
+        .await
+        .expect("the handshake should fail")
+        .unwrap_err();
+    match error {
+        HandshakeError::Tls { alert, reason } => {
+            assert_eq!(alert, rustls::AlertDescription::CertificateExpired);
+            assert!(reason.contains("certificate"), "{reason}");
+        }
+        other => panic!("unexpected handshake error: {other}"),
+    }
+    assert!(!client_conn.is_handshake_complete());
+}
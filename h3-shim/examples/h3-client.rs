@@ -101,7 +101,7 @@ pub async fn run(opt: Opt) -> Result<(), Box<dyn core::error::Error + Send + Syn
         .bind(&opt.bind[..])?
         .build();
     info!("connecting to {:?}", addr);
-    let conn = quic_client.connect(auth.host(), addr)?;
+    let conn = quic_client.connect(auth.host(), addr).await?;
 
     // create h3 client
 
@@ -6,7 +6,7 @@ use std::{
 };
 
 use futures::Stream;
-use qbase::sid::StreamId;
+use qbase::{error::AppError, sid::StreamId, varint::VarInt};
 use qconnection::conn::{StreamReader, StreamWriter};
 
 use crate::{
@@ -86,9 +86,7 @@ impl<B: bytes::Buf> h3::quic::OpenStreams<B> for QuicConnection {
 
     #[inline]
     fn close(&mut self, code: h3::error::Code, reason: &[u8]) {
-        let _ignored = code;
-        let reason = unsafe { String::from_utf8_unchecked(reason.to_vec()) };
-        self.connection.close(reason);
+        self.connection.close(h3_close_error(code.value(), reason));
     }
 }
 
@@ -179,9 +177,7 @@ impl<B: bytes::Buf> h3::quic::OpenStreams<B> for OpenStreams {
 
     #[inline]
     fn close(&mut self, code: h3::error::Code, reason: &[u8]) {
-        let _ignored = code;
-        let reason = unsafe { String::from_utf8_unchecked(reason.to_vec()) };
-        self.connection.close(reason);
+        self.connection.close(h3_close_error(code.value(), reason));
     }
 }
 
@@ -194,6 +190,16 @@ fn sid_exceed_limit_error() -> io::Error {
     )
 }
 
+/// 将h3的错误码转换为关闭连接的应用层错误，超出VarInt范围的错误码无法编码到CONNECTION_CLOSE帧中，
+/// 以H3_INTERNAL_ERROR代替
+fn h3_close_error(code: u64, reason: &[u8]) -> AppError {
+    let reason = String::from_utf8_lossy(reason).into_owned();
+    let code = VarInt::from_u64(code)
+        .or_else(|_| VarInt::from_u64(h3::error::Code::H3_INTERNAL_ERROR.value()))
+        .expect("H3_INTERNAL_ERROR is a valid varint");
+    AppError::new(code, reason)
+}
+
 #[allow(clippy::type_complexity)]
 struct OpenBiStreams(BoxStream<Result<(StreamId, (StreamReader, StreamWriter)), Error>>);
 
@@ -299,3 +305,18 @@ impl AcceptUniStreams {
             .map_ok(|r| r.map(|(sid, reader)| RecvStream::new(sid, reader)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_h3_close_error() {
+        let error = h3_close_error(h3::error::Code::H3_NO_ERROR.value(), b"bye");
+        assert_eq!(error.error_code(), VarInt::from_u32(0x100));
+        assert_eq!(error.reason(), "bye");
+
+        let error = h3_close_error(u64::MAX, b"out of range");
+        assert_eq!(error.error_code(), VarInt::from_u32(0x102));
+    }
+}
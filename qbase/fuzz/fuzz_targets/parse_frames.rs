@@ -0,0 +1,29 @@
+//! Fuzz the frame parser with arbitrary packet payloads.
+//!
+//! Run with `cargo fuzz run parse_frames` in the `qbase` directory.
+#![no_main]
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use qbase::{
+    frame::parse_frames,
+    packet::r#type::{
+        long::{self, Ver1},
+        short::OneRtt,
+        Type,
+    },
+};
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte selects the type of the packet that carries the frames
+    let Some((&selector, payload)) = data.split_first() else {
+        return;
+    };
+    let packet_type = match selector % 4 {
+        0 => Type::Long(long::Type::V1(Ver1::INITIAL)),
+        1 => Type::Long(long::Type::V1(Ver1::ZERO_RTT)),
+        2 => Type::Long(long::Type::V1(Ver1::HANDSHAKE)),
+        _ => Type::Short(OneRtt::from(selector)),
+    };
+    let _ = parse_frames(Bytes::copy_from_slice(payload), packet_type);
+});
@@ -99,9 +99,11 @@ where
         frame: &RetireConnectionIdFrame,
     ) -> Result<Option<ConnectionId>, Error> {
         let seq = frame.sequence.into_inner();
+        // Receipt of a RETIRE_CONNECTION_ID frame containing a sequence number greater than any
+        // previously sent to the peer MUST be treated as a connection error of type PROTOCOL_VIOLATION.
         if seq >= self.cid_deque.largest() {
             return Err(Error::new(
-                ErrorKind::ConnectionIdLimit,
+                ErrorKind::ProtocolViolation,
                 frame.frame_type(),
                 format!(
                     "Sequence({seq}) in RetireConnectionIdFrame exceeds the largest one({}) issued by us",
@@ -115,8 +117,12 @@ where
                 let n = self.cid_deque.iter().take_while(|v| v.is_none()).count();
                 self.cid_deque.advance(n);
 
-                // generates a new connection ID while retiring an old one.
-                self.issue_new_cid();
+                // generates a new connection ID while retiring an old one,
+                // as long as the active connection IDs are below the limit of the peer.
+                let active = self.cid_deque.iter().filter(|v| v.is_some()).count() as u64;
+                if active < self.active_cid_limit.unwrap_or(2) {
+                    self.issue_new_cid();
+                }
                 return Ok(Some(cid));
             }
         }
@@ -218,6 +224,20 @@ where
             .collect()
     }
 
+    /// Get all active connection IDs, with their sequence numbers and stateless reset tokens.
+    ///
+    /// It's useful for diagnostics. The initial source connection ID, whose sequence number is 0,
+    /// has no stateless reset token, the default one is returned.
+    pub fn active_cid_records(&self) -> Vec<(u64, ConnectionId, ResetToken)> {
+        self.0
+            .lock()
+            .unwrap()
+            .cid_deque
+            .iter_with_idx()
+            .filter_map(|(seq, v)| v.map(|(cid, token)| (seq, cid, token)))
+            .collect()
+    }
+
     /// Set the maximum number of active connection IDs.
     ///
     /// After fully obtaining the peer's connection parameters, extract the peer's
@@ -320,4 +340,78 @@ mod tests {
         let cid3 = local_cids.recv_retire_cid_frame(&retire_frame);
         assert!(cid3.is_ok());
     }
+
+    #[test]
+    fn test_active_cid_records() {
+        let initial_scid = ConnectionId::random_gen(8);
+        let issued_cids = IssuedCids::default();
+        let local_cids = ArcLocalCids::new(initial_scid, IssuedCids(issued_cids.0.clone()));
+        local_cids.set_limit(3).unwrap();
+
+        // 每个已发出的NewConnectionIdFrame都在列表中
+        let frames = issued_cids.lock_guard().clone();
+        assert_eq!(frames.len(), 2);
+        let mut expected = vec![(0, initial_scid, ResetToken::default())];
+        expected.extend(
+            frames
+                .iter()
+                .map(|frame| (frame.sequence.into_inner(), frame.id, frame.reset_token)),
+        );
+        assert_eq!(local_cids.active_cid_records(), expected);
+
+        // 被对端淘汰的连接ID不再出现，同时补发一个新的
+        let retire_frame = RetireConnectionIdFrame {
+            sequence: VarInt::from_u32(1),
+        };
+        local_cids.recv_frame(&retire_frame).unwrap();
+        let records = local_cids.active_cid_records();
+        assert_eq!(
+            records.iter().map(|(seq, ..)| *seq).collect::<Vec<_>>(),
+            [0, 2, 3]
+        );
+        let new_frame = issued_cids.lock_guard()[2];
+        assert_eq!(records[2], (3, new_frame.id, new_frame.reset_token));
+    }
+
+    #[test]
+    fn test_retire_triggers_replacement() {
+        let initial_scid = ConnectionId::random_gen(8);
+        let mut local_cids = LocalCids::new(initial_scid, IssuedCids::default());
+        local_cids.set_limit(4).unwrap();
+        assert_eq!(local_cids.issued_cids.lock_guard().len(), 3);
+
+        // 对端淘汰了一个连接ID，补发一个新的，保持4个有效的连接ID
+        let retire_frame = RetireConnectionIdFrame {
+            sequence: VarInt::from_u32(2),
+        };
+        let retired = local_cids.issued_cids.lock_guard()[1].id;
+        assert_eq!(
+            local_cids.recv_retire_cid_frame(&retire_frame),
+            Ok(Some(retired))
+        );
+        let issued = local_cids.issued_cids.lock_guard().clone();
+        assert_eq!(issued.len(), 4);
+        assert_eq!(issued[3].sequence, VarInt::from_u32(4));
+        let active = local_cids.cid_deque.iter().filter(|v| v.is_some()).count();
+        assert_eq!(active, 4);
+
+        // 重复淘汰同一个连接ID，不再补发
+        assert_eq!(local_cids.recv_retire_cid_frame(&retire_frame), Ok(None));
+        assert_eq!(local_cids.issued_cids.lock_guard().len(), 4);
+    }
+
+    #[test]
+    fn test_retire_unissued_cid() {
+        let initial_scid = ConnectionId::random_gen(8);
+        let mut local_cids = LocalCids::new(initial_scid, IssuedCids::default());
+
+        // 序号为2的连接ID尚未发出
+        let retire_frame = RetireConnectionIdFrame {
+            sequence: VarInt::from_u32(2),
+        };
+        let error = local_cids.recv_retire_cid_frame(&retire_frame).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ProtocolViolation);
+        assert_eq!(error.frame_type(), FrameType::RetireConnectionId);
+        assert_eq!(local_cids.issued_cids.lock_guard().len(), 1);
+    }
 }
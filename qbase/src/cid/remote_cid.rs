@@ -272,6 +272,22 @@ where
             .next()
             .map(|(_, cid, _)| *cid)
     }
+
+    /// Get all active connection IDs issued by the peer, with their sequence numbers and stateless
+    /// reset tokens.
+    ///
+    /// It's useful for diagnostics. The initial destination connection ID, whose sequence number
+    /// is 0, has no stateless reset token, the default one is returned.
+    pub fn active_cid_records(&self) -> Vec<(u64, ConnectionId, ResetToken)> {
+        self.0
+            .lock()
+            .unwrap()
+            .cid_deque
+            .iter()
+            .flatten()
+            .copied()
+            .collect()
+    }
 }
 
 impl<RETIRED> ReceiveFrame<NewConnectionIdFrame> for ArcRemoteCids<RETIRED>
@@ -632,4 +648,87 @@ mod tests {
             Poll::Ready(Some(r#ref)) if *r#ref == cids[5]
         ));
     }
+
+    #[test]
+    fn test_active_cid_records() {
+        let initial_dcid = ConnectionId::random_gen(8);
+        let remote_cids = ArcRemoteCids::new(initial_dcid, 8, RetiredCids::default());
+
+        let frame = NewConnectionIdFrame {
+            sequence: VarInt::from_u32(2),
+            retire_prior_to: VarInt::from_u32(1),
+            id: ConnectionId::random_gen(8),
+            reset_token: ResetToken::random_gen(),
+        };
+        remote_cids.recv_frame(&frame).unwrap();
+        // 序号0被retire_prior_to淘汰，序号1尚未收到
+        assert_eq!(
+            remote_cids.active_cid_records(),
+            [(2, frame.id, frame.reset_token)]
+        );
+    }
+
+    #[test]
+    fn test_recv_retire_prior_to() {
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let initial_dcid = ConnectionId::random_gen(8);
+        let retired_cids = RetiredCids::default();
+        let remote_cids = ArcRemoteCids::new(initial_dcid, 8, retired_cids.clone());
+        let new_cid_frame = |seq, retire_prior_to| NewConnectionIdFrame {
+            sequence: VarInt::from_u32(seq),
+            retire_prior_to: VarInt::from_u32(retire_prior_to),
+            id: ConnectionId::random_gen(8),
+            reset_token: ResetToken::random_gen(),
+        };
+
+        // 两条路径分别使用序号0、1的连接ID
+        let frame1 = new_cid_frame(1, 0);
+        remote_cids.recv_frame(&frame1).unwrap();
+        let path1 = remote_cids.apply_dcid();
+        let path2 = remote_cids.apply_dcid();
+        let borrowed = path1
+            .poll_borrow_cid(&mut cx)
+            .map(|cid| cid.map(|cid| *cid));
+        assert_eq!(borrowed, Poll::Ready(Some(initial_dcid)));
+        let borrowed = path2
+            .poll_borrow_cid(&mut cx)
+            .map(|cid| cid.map(|cid| *cid));
+        assert_eq!(borrowed, Poll::Ready(Some(frame1.id)));
+        assert!(retired_cids.lock().unwrap().is_empty());
+
+        // 对端要求淘汰序号2之前的连接ID，两条路径都换用新的连接ID
+        let frame2 = new_cid_frame(2, 2);
+        let frame3 = new_cid_frame(3, 2);
+        remote_cids.recv_frame(&frame2).unwrap();
+        remote_cids.recv_frame(&frame3).unwrap();
+        let borrowed = path1
+            .poll_borrow_cid(&mut cx)
+            .map(|cid| cid.map(|cid| *cid));
+        assert_eq!(borrowed, Poll::Ready(Some(frame2.id)));
+        let borrowed = path2
+            .poll_borrow_cid(&mut cx)
+            .map(|cid| cid.map(|cid| *cid));
+        assert_eq!(borrowed, Poll::Ready(Some(frame3.id)));
+
+        let mut retired = std::mem::take(&mut *retired_cids.lock().unwrap());
+        retired.sort_by_key(|frame| frame.sequence);
+        assert_eq!(
+            retired,
+            [0, 1].map(|seq| RetireConnectionIdFrame {
+                sequence: VarInt::from_u32(seq),
+            })
+        );
+        assert_eq!(
+            remote_cids.active_cid_records(),
+            [
+                (2, frame2.id, frame2.reset_token),
+                (3, frame3.id, frame3.reset_token)
+            ]
+        );
+
+        // 迟到的、小于retire_prior_to的NEW_CONNECTION_ID已被淘汰过，不再重复发送
+        assert_eq!(remote_cids.recv_frame(&new_cid_frame(1, 0)), Ok(None));
+        assert!(retired_cids.lock().unwrap().is_empty());
+    }
 }
@@ -3,7 +3,7 @@ use std::{borrow::Cow, fmt::Display};
 use thiserror::Error;
 
 use crate::{
-    frame::{ConnectionCloseFrame, FrameType},
+    frame::{AppCloseFrame, ConnectionCloseFrame, FrameType, QuicCloseFrame},
     varint::VarInt,
 };
 
@@ -65,6 +65,12 @@ pub enum ErrorKind {
     /// An endpoint is unlikely to receive a CONNECTION_CLOSE frame carrying this code
     /// except when the path does not support a large enough MTU.
     NoViablePath,
+    /// An endpoint detected that the version negotiation was tampered with, the Chosen Version
+    /// in the version_information transport parameter of the peer mismatches the version in use.
+    ///
+    /// See [Section 4](https://www.rfc-editor.org/rfc/rfc9368#section-4)
+    /// of [RFC 9368](https://www.rfc-editor.org/rfc/rfc9368).
+    VersionNegotiation,
     /// The cryptographic handshake failed.
     /// A range of 256 values is reserved for carrying error codes specific
     /// to the cryptographic handshake that is used.
@@ -94,6 +100,7 @@ impl Display for ErrorKind {
             ErrorKind::KeyUpdate => "Invalid packet protection update",
             ErrorKind::AeadLimitReached => "Excessive use of packet protection keys",
             ErrorKind::NoViablePath => "No viable network path exists",
+            ErrorKind::VersionNegotiation => "Error negotiating version",
             ErrorKind::Crypto(x) => return write!(f, "TLS alert code: {x}"),
         };
         write!(f, "{description}",)
@@ -131,6 +138,7 @@ impl TryFrom<VarInt> for ErrorKind {
             0x0e => ErrorKind::KeyUpdate,
             0x0f => ErrorKind::AeadLimitReached,
             0x10 => ErrorKind::NoViablePath,
+            0x11 => ErrorKind::VersionNegotiation,
             0x0100..=0x01ff => ErrorKind::Crypto((value.into_inner() & 0xff) as u8),
             other => return Err(InvalidErrorKind(other)),
         })
@@ -157,6 +165,7 @@ impl From<ErrorKind> for VarInt {
             ErrorKind::KeyUpdate => VarInt::from(0x0eu8),
             ErrorKind::AeadLimitReached => VarInt::from(0x0fu8),
             ErrorKind::NoViablePath => VarInt::from(0x10u8),
+            ErrorKind::VersionNegotiation => VarInt::from(0x11u8),
             ErrorKind::Crypto(x) => VarInt::from(0x0100u16 | x as u16),
         }
     }
@@ -164,17 +173,17 @@ impl From<ErrorKind> for VarInt {
 
 /// QUIC transport error.
 ///
-/// Its definition conforms to the usage of [`ConnectionCloseFrame`].
+/// Its definition conforms to the usage of [`QuicCloseFrame`].
 /// A value of 0 (equivalent to the mention of the PADDING frame) is used when the frame type is unknown.
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
 #[error("{kind} in {frame_type:?}, reason: {reason}")]
-pub struct Error {
+pub struct QuicError {
     kind: ErrorKind,
     frame_type: FrameType,
     reason: Cow<'static, str>,
 }
 
-impl Error {
+impl QuicError {
     /// Create a new error with the given kind, frame type, and reason.
     /// The frame type is the one that triggered this error.
     pub fn new<T: Into<Cow<'static, str>>>(
@@ -189,24 +198,123 @@ impl Error {
         }
     }
 
-    /// Create a new error with unknown frame type, and
-    /// the [`FrameType::Padding`] type will be used by default.
-    pub fn with_default_fty<T: Into<Cow<'static, str>>>(kind: ErrorKind, reason: T) -> Self {
+    /// Return the error kind.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Return the frame type that triggered this error.
+    pub fn frame_type(&self) -> FrameType {
+        self.frame_type
+    }
+
+    /// Return the reason of this error.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+/// Application error, the error code is chosen by the application protocol, such as HTTP/3.
+///
+/// Its definition conforms to the usage of [`AppCloseFrame`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Application error {error_code}, reason: {reason}")]
+pub struct AppError {
+    error_code: VarInt,
+    reason: Cow<'static, str>,
+}
+
+impl AppError {
+    /// Create a new application error with the given application error code and reason.
+    pub fn new<T: Into<Cow<'static, str>>>(error_code: VarInt, reason: T) -> Self {
         Self {
-            kind,
-            frame_type: FrameType::Padding,
+            error_code,
             reason: reason.into(),
         }
     }
 
-    /// Return the error kind.
+    /// Return the application error code.
+    pub fn error_code(&self) -> VarInt {
+        self.error_code
+    }
+
+    /// Return the reason of this error.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+/// The error that closes a QUIC connection, either at the QUIC layer or by the application.
+///
+/// They are carried by the CONNECTION_CLOSE frame of type 0x1c and 0x1d respectively,
+/// see [`ConnectionCloseFrame`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    /// Error at the QUIC layer, see [`QuicError`].
+    #[error(transparent)]
+    Quic(QuicError),
+    /// Error of the application, see [`AppError`].
+    #[error(transparent)]
+    App(AppError),
+}
+
+impl Error {
+    /// Create a new QUIC layer error with the given kind, frame type, and reason.
+    /// The frame type is the one that triggered this error.
+    pub fn new<T: Into<Cow<'static, str>>>(
+        kind: ErrorKind,
+        frame_type: FrameType,
+        reason: T,
+    ) -> Self {
+        Self::Quic(QuicError::new(kind, frame_type, reason))
+    }
+
+    /// Create a new QUIC layer error with unknown frame type, and
+    /// the [`FrameType::Padding`] type will be used by default.
+    pub fn with_default_fty<T: Into<Cow<'static, str>>>(kind: ErrorKind, reason: T) -> Self {
+        Self::Quic(QuicError::new(kind, FrameType::Padding, reason))
+    }
+
+    /// Create a new application error with the given application error code and reason.
+    pub fn app<T: Into<Cow<'static, str>>>(error_code: VarInt, reason: T) -> Self {
+        Self::App(AppError::new(error_code, reason))
+    }
+
+    /// Return the error kind, [`ErrorKind::Application`] for the application error.
     pub fn kind(&self) -> ErrorKind {
-        self.kind
+        match self {
+            Error::Quic(e) => e.kind(),
+            Error::App(_) => ErrorKind::Application,
+        }
     }
 
-    /// Return the frame type that triggered this error.
+    /// Return the frame type that triggered this error,
+    /// [`FrameType::Padding`] for the application error.
     pub fn frame_type(&self) -> FrameType {
-        self.frame_type
+        match self {
+            Error::Quic(e) => e.frame_type(),
+            Error::App(_) => FrameType::Padding,
+        }
+    }
+
+    /// Return the reason of this error.
+    pub fn reason(&self) -> &str {
+        match self {
+            Error::Quic(e) => e.reason(),
+            Error::App(e) => e.reason(),
+        }
+    }
+}
+
+impl From<QuicError> for Error {
+    fn from(e: QuicError) -> Self {
+        Self::Quic(e)
+    }
+}
+
+impl From<AppError> for Error {
+    fn from(e: AppError) -> Self {
+        Self::App(e)
     }
 }
 
@@ -218,20 +326,32 @@ impl From<Error> for std::io::Error {
 
 impl From<Error> for ConnectionCloseFrame {
     fn from(e: Error) -> Self {
-        Self {
-            error_kind: e.kind,
-            frame_type: Some(e.frame_type),
-            reason: e.reason,
+        match e {
+            Error::Quic(e) => Self::Quic(QuicCloseFrame {
+                error_kind: e.kind,
+                frame_type: e.frame_type,
+                reason: e.reason,
+            }),
+            Error::App(e) => Self::App(AppCloseFrame {
+                error_code: e.error_code,
+                reason: e.reason,
+            }),
         }
     }
 }
 
 impl From<ConnectionCloseFrame> for Error {
     fn from(value: ConnectionCloseFrame) -> Self {
-        Self {
-            kind: value.error_kind,
-            frame_type: value.frame_type.unwrap_or(FrameType::Padding),
-            reason: value.reason,
+        match value {
+            ConnectionCloseFrame::Quic(frame) => Self::Quic(QuicError {
+                kind: frame.error_kind,
+                frame_type: frame.frame_type,
+                reason: frame.reason,
+            }),
+            ConnectionCloseFrame::App(frame) => Self::App(AppError {
+                error_code: frame.error_code,
+                reason: frame.reason,
+            }),
         }
     }
 }
@@ -39,7 +39,10 @@ impl<TX> SendControler<TX> {
     }
 
     fn register_waker(&mut self, waker: Waker) {
-        self.wakers.push(waker);
+        // 发送任务在额度耗尽期间可能被反复唤醒，避免重复登记同一个waker
+        if !self.wakers.iter().any(|w| w.will_wake(&waker)) {
+            self.wakers.push(waker);
+        }
     }
 
     fn wake_all(&mut self) {
@@ -56,6 +59,16 @@ impl<TX> SendControler<TX> {
             }
         }
     }
+
+    fn reset_limit(&mut self, max_data: u64) {
+        // 0-RTT阶段按记忆的initial_max_data发送，对端新的initial_max_data可能更小，
+        // 但已经发出的数据无法撤回，超出新额度的部分只能等待对端的MaxDataFrame
+        let max_data = max_data.max(self.sent_data);
+        if max_data > self.max_data {
+            self.wake_all();
+        }
+        self.max_data = max_data;
+    }
 }
 
 /// Shared connection-level Stream Flow Control in the sending direction,
@@ -106,6 +119,13 @@ impl<TX> ArcSendControler<TX> {
         }
     }
 
+    fn reset_limit(&self, max_data: u64) {
+        let mut guard = self.0.lock().unwrap();
+        if let Ok(inner) = guard.deref_mut() {
+            inner.reset_limit(max_data);
+        }
+    }
+
     /// Return the available size of new data bytes that can be sent to peer.
     /// If some QUIC error occured, it would return the error directly.
     ///
@@ -183,6 +203,18 @@ impl<TX> Credit<'_, TX> {
             Err(_) => unreachable!(),
         }
     }
+
+    /// Register a waker to be woken up when the flow control limit is increased by the
+    /// [`MaxDataFrame`] from peer.
+    ///
+    /// Same as [`ArcSendControler::register_waker`], but it can be called while holding the
+    /// exclusive [`Credit`], when the sending task finds that the new stream data is blocked.
+    pub fn register_waker(&mut self, waker: Waker) {
+        match self.0.deref_mut() {
+            Ok(inner) => inner.register_waker(waker),
+            Err(_) => unreachable!(),
+        }
+    }
 }
 
 impl<TX> Credit<'_, TX>
@@ -195,7 +227,8 @@ where
             Ok(inner) => {
                 debug_assert!(inner.sent_data + amount as u64 <= inner.max_data);
                 inner.sent_data += amount as u64;
-                if inner.sent_data == inner.max_data {
+                // 仅在本次发送恰好用完额度时告知对端，额度为0时仍可发送不受流控的帧，不应反复发送DataBlocked帧
+                if amount > 0 && inner.sent_data == inner.max_data {
                     inner.block_tx.send_frame([DataBlockedFrame {
                         limit: VarInt::from_u64(inner.max_data).expect(
                             "max_data of flow controller is very very hard to exceed 2^62 - 1",
@@ -356,8 +389,13 @@ impl<TX: Clone> FlowController<TX> {
     /// which should be the peer's `initial_max_data` transport parameter.
     /// So once the peer's [`Parameters`](`crate::param::Parameters`) are obtained,
     /// this method should be called immediately.
+    ///
+    /// The window is replaced rather than only increased, because the window used for the 0-RTT
+    /// data is the peer's `initial_max_data` remembered from the previous connection, and the
+    /// peer may set a smaller one this time. The data already sent can not be taken back, so the
+    /// window never falls below it.
     pub fn reset_send_window(&self, snd_wnd: u64) {
-        self.sender.increase_limit(snd_wnd);
+        self.sender.reset_limit(snd_wnd);
     }
 
     /// Returns the connection-level flow controller in the sending direction.
@@ -391,3 +429,69 @@ where
         self.recver.on_new_rcvd(amount)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::task::Waker;
+
+    use super::*;
+    use crate::util::CountWaker;
+
+    #[derive(Debug, Clone, Default)]
+    struct BlockedFrames(Arc<Mutex<Vec<DataBlockedFrame>>>);
+
+    impl SendFrame<DataBlockedFrame> for BlockedFrames {
+        fn send_frame<I: IntoIterator<Item = DataBlockedFrame>>(&self, iter: I) {
+            self.0.lock().unwrap().extend(iter);
+        }
+    }
+
+    #[test]
+    fn test_max_data_unblocks() {
+        let blocked_frames = BlockedFrames::default();
+        let sender = ArcSendControler::new(10, blocked_frames.clone());
+        let count = Arc::new(CountWaker::default());
+
+        let mut credit = sender.credit().unwrap();
+        credit.post_sent(10);
+        assert_eq!(credit.available(), 0);
+        // 重复登记同一个waker，只会被唤醒一次
+        credit.register_waker(Waker::from(count.clone()));
+        credit.register_waker(Waker::from(count.clone()));
+        drop(credit);
+        assert_eq!(blocked_frames.0.lock().unwrap().len(), 1);
+
+        // 额度为0时仍可发送其他帧，不应重复通知DataBlocked
+        sender.credit().unwrap().post_sent(0);
+        assert_eq!(blocked_frames.0.lock().unwrap().len(), 1);
+
+        sender
+            .recv_frame(&MaxDataFrame {
+                max_data: VarInt::from_u32(30),
+            })
+            .unwrap();
+        assert_eq!(count.count(), 1);
+        assert_eq!(sender.credit().unwrap().available(), 20);
+    }
+
+    #[test]
+    fn test_reset_send_window() {
+        let flow_ctrl = FlowController::new(100, 100, BlockedFrames::default());
+        // 0-RTT阶段按记忆的额度发送了60字节
+        flow_ctrl.send_limit().unwrap().post_sent(60);
+
+        // 对端这次的initial_max_data更小，剩余额度随之减少
+        flow_ctrl.reset_send_window(80);
+        assert_eq!(flow_ctrl.send_limit().unwrap().available(), 20);
+
+        // 已经发出的数据无法撤回，额度不会低于已发送的量
+        flow_ctrl.reset_send_window(40);
+        assert_eq!(flow_ctrl.send_limit().unwrap().available(), 0);
+
+        let count = Arc::new(CountWaker::default());
+        flow_ctrl.sender.register_waker(Waker::from(count.clone()));
+        flow_ctrl.reset_send_window(200);
+        assert_eq!(count.count(), 1);
+        assert_eq!(flow_ctrl.send_limit().unwrap().available(), 140);
+    }
+}
@@ -11,6 +11,7 @@ mod crypto;
 mod data_blocked;
 mod datagram;
 mod handshake_done;
+mod immediate_ack;
 mod max_data;
 mod max_stream_data;
 mod max_streams;
@@ -33,13 +34,14 @@ pub mod error;
 pub mod io;
 
 pub use ack::{AckFrame, EcnCounts};
-pub use connection_close::ConnectionCloseFrame;
+pub use connection_close::{AppCloseFrame, ConnectionCloseFrame, QuicCloseFrame};
 pub use crypto::CryptoFrame;
 pub use data_blocked::DataBlockedFrame;
 pub use datagram::DatagramFrame;
 #[doc(hidden)]
 pub use error::Error;
 pub use handshake_done::HandshakeDoneFrame;
+pub use immediate_ack::ImmediateAckFrame;
 pub use max_data::MaxDataFrame;
 pub use max_stream_data::MaxStreamDataFrame;
 pub use max_streams::MaxStreamsFrame;
@@ -172,6 +174,8 @@ pub enum FrameType {
     ConnectionClose(u8),
     /// HANDSHAKE_DONE frame, see [`HandshakeDoneFrame`].
     HandshakeDone,
+    /// IMMEDIATE_ACK frame, see [`ImmediateAckFrame`].
+    ImmediateAck,
     /// DATAGRAM frame, see [`DatagramFrame`].
     Datagram(u8),
 }
@@ -223,6 +227,7 @@ impl FrameType {
                 }
             }
             FrameType::HandshakeDone => l,
+            FrameType::ImmediateAck => o | l,
             FrameType::Datagram(_) => o | l,
         }
     }
@@ -288,6 +293,7 @@ impl TryFrom<u8> for FrameType {
             // The last bit is the layer flag bit, 0 indicates application layer, 1 indicates transport layer.
             ty @ (0x1c | 0x1d) => FrameType::ConnectionClose(ty & 0x1),
             0x1e => FrameType::HandshakeDone,
+            0x1f => FrameType::ImmediateAck,
             // The last bit is the length flag bit, 0 the length field is absent and the Datagram Data
             // field extends to the end of the packet, 1 the length field is present.
             ty @ (0x30 | 0x31) => FrameType::Datagram(ty & 1),
@@ -319,6 +325,7 @@ impl From<FrameType> for u8 {
             FrameType::PathResponse => 0x1b,
             FrameType::ConnectionClose(layer) => 0x1c | layer,
             FrameType::HandshakeDone => 0x1e,
+            FrameType::ImmediateAck => 0x1f,
             FrameType::Datagram(with_len) => 0x30 | with_len,
         }
     }
@@ -395,6 +402,8 @@ pub enum Frame {
     RetireConnectionId(RetireConnectionIdFrame),
     /// HANDSHAKE_DONE frame, see [`HandshakeDoneFrame`].
     HandshakeDone(HandshakeDoneFrame),
+    /// IMMEDIATE_ACK frame, see [`ImmediateAckFrame`].
+    ImmediateAck(ImmediateAckFrame),
     /// PATH_CHALLENGE frame, see [`PathChallengeFrame`].
     Challenge(PathChallengeFrame),
     /// PATH_RESPONSE frame, see [`PathResponseFrame`].
@@ -468,6 +477,23 @@ impl Iterator for FrameReader {
     }
 }
 
+/// Parse all the frames in the payload of a packet of type `packet_type`.
+///
+/// Unlike the [`FrameReader`], all the frames are collected, and the first error is returned if any
+/// frame is malformed, or [`Error::NoFrames`] if the payload is empty.
+///
+/// The payload is untrusted input from the network. This function never panics on arbitrary
+/// input, all the lengths and varints are checked, that is also what the fuzz target of this crate
+/// tests.
+pub fn parse_frames(payload: Bytes, packet_type: Type) -> Result<Vec<Frame>, Error> {
+    if payload.is_empty() {
+        return Err(Error::NoFrames);
+    }
+    FrameReader::new(payload, packet_type)
+        .map(|result| result.map(|(frame, _is_ack_eliciting)| frame))
+        .collect()
+}
+
 impl<T: BufMut> WriteFrame<StreamCtlFrame> for T {
     fn put_frame(&mut self, frame: &StreamCtlFrame) {
         match frame {
@@ -494,3 +520,83 @@ impl<T: BufMut> WriteFrame<ReliableFrame> for T {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, RngCore};
+
+    use super::*;
+    use crate::packet::r#type::{long::Ver1, short::OneRtt, Type};
+
+    fn packet_types() -> [Type; 4] {
+        [
+            Type::Long(crate::packet::r#type::long::Type::V1(Ver1::INITIAL)),
+            Type::Long(crate::packet::r#type::long::Type::V1(Ver1::ZERO_RTT)),
+            Type::Long(crate::packet::r#type::long::Type::V1(Ver1::HANDSHAKE)),
+            Type::Short(OneRtt::from(0)),
+        ]
+    }
+
+    #[test]
+    fn test_parse_arbitrary_bytes() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50000 {
+            let len = rng.gen_range(0..64);
+            let mut payload = vec![0u8; len];
+            rng.fill_bytes(&mut payload);
+            // 帧类型偏向于合法的帧类型，更容易走到各个帧的解析中
+            if len > 0 {
+                payload[0] = rng.gen_range(0..0x32);
+            }
+            for packet_type in packet_types() {
+                let _ = parse_frames(Bytes::from(payload.clone()), packet_type);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_truncated_frames() {
+        let mut payload = vec![];
+        payload.put_frame(&PingFrame);
+        payload.put_frame(&ConnectionCloseFrame::new_app(
+            VarInt::from_u32(0x100),
+            "H3_NO_ERROR".into(),
+        ));
+        payload.put_frame(&MaxDataFrame {
+            max_data: VarInt::from_u32(0x1234),
+        });
+        // CRYPTO frame with 4 bytes data, and ACK frame with ECN counts
+        payload.extend_from_slice(&[0x06, 0x00, 0x04, 1, 2, 3, 4]);
+        payload.extend_from_slice(&[0x03, 0x10, 0x00, 0x01, 0x08, 0x03, 0x03, 1, 1, 0]);
+        let packet_type = packet_types()[3];
+        assert_eq!(
+            parse_frames(Bytes::from(payload.clone()), packet_type)
+                .unwrap()
+                .len(),
+            5
+        );
+
+        for len in 0..payload.len() {
+            let _ = parse_frames(Bytes::copy_from_slice(&payload[..len]), packet_type);
+        }
+    }
+
+    #[test]
+    fn test_parse_oversized_varints() {
+        let packet_type = packet_types()[3];
+        // the end of the CRYPTO frame exceeds 2^62 - 1
+        let crypto = [
+            0x06, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02, 0, 0,
+        ];
+        assert!(parse_frames(Bytes::copy_from_slice(&crypto), packet_type).is_err());
+        // the ACK range count is huge, but there are no ranges followed
+        let ack = [
+            0x02, 0x10, 0x00, 0xbf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00,
+        ];
+        assert!(parse_frames(Bytes::copy_from_slice(&ack), packet_type).is_err());
+        assert_eq!(
+            parse_frames(Bytes::new(), packet_type),
+            Err(Error::NoFrames)
+        );
+    }
+}
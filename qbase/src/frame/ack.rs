@@ -1,6 +1,10 @@
 use std::ops::RangeInclusive;
 
-use nom::{combinator::map, sequence::tuple};
+use nom::{
+    combinator::map,
+    error::{make_error, ErrorKind},
+    sequence::tuple,
+};
 
 use crate::varint::{be_varint, VarInt, WriteVarInt};
 
@@ -110,7 +114,7 @@ pub struct EcnCounts {
 
 impl EcnCounts {
     /// Calculates the encoding size of the [`EcnCounts`] struct.
-    fn encoding_size(&self) -> usize {
+    pub fn encoding_size(&self) -> usize {
         self.ect0.encoding_size() + self.ect1.encoding_size() + self.ce.encoding_size()
     }
 }
@@ -119,16 +123,28 @@ impl EcnCounts {
 /// [nom](https://docs.rs/nom/latest/nom/) parser style.
 pub fn ack_frame_with_flag(ecn_flag: u8) -> impl Fn(&[u8]) -> nom::IResult<&[u8], AckFrame> {
     move |input: &[u8]| {
-        let (mut input, (largest, delay, count, first_range)) =
+        let (mut remain, (largest, delay, count, first_range)) =
             tuple((be_varint, be_varint, be_varint, be_varint))(input)?;
+        // If any computed packet number is negative, an endpoint MUST generate a
+        // connection error of type FRAME_ENCODING_ERROR.
+        let negative_pn = || nom::Err::Error(make_error(input, ErrorKind::Verify));
+        let mut smallest = largest
+            .into_inner()
+            .checked_sub(first_range.into_inner())
+            .ok_or_else(negative_pn)?;
         let mut ranges = Vec::new();
-        let mut count = count.into_inner() as usize;
+        let mut count = count.into_inner();
         while count > 0 {
-            let (i, (gap, ack)) = tuple((be_varint, be_varint))(input)?;
+            let (i, (gap, ack)) = tuple((be_varint, be_varint))(remain)?;
+            smallest = smallest
+                .checked_sub(gap.into_inner() + 2)
+                .and_then(|largest| largest.checked_sub(ack.into_inner()))
+                .ok_or_else(negative_pn)?;
             ranges.push((gap, ack));
             count -= 1;
-            input = i;
+            remain = i;
         }
+        let mut input = remain;
 
         let ecn = if ecn_flag & ECN_OPT != 0 {
             let (i, ecn) = be_ecn_counts(input)?;
@@ -212,7 +228,7 @@ mod tests {
 
     #[test]
     fn test_read_ack_frame() {
-        let input = vec![0x02, 0x52, 0x34, 0x52, 0x34, 0x01, 0x52, 0x34, 3, 20];
+        let input = vec![0x02, 0x52, 0x34, 0x52, 0x34, 0x01, 0x52, 0x00, 3, 20];
         let (input, ack_frame) = flat_map(be_varint, |frame_type| {
             if frame_type.into_inner() as u8 == ACK_FRAME_TYPE {
                 ack_frame_with_flag(frame_type.into_inner() as u8)
@@ -227,13 +243,26 @@ mod tests {
             AckFrame {
                 largest: VarInt::from_u32(0x1234),
                 delay: VarInt::from_u32(0x1234),
-                first_range: VarInt::from_u32(0x1234),
+                first_range: VarInt::from_u32(0x1200),
                 ranges: vec![(VarInt::from_u32(3), VarInt::from_u32(20))],
                 ecn: None,
             }
         );
     }
 
+    #[test]
+    fn test_read_ack_frame_with_negative_pn() {
+        // first range exceeds the largest acknowledged
+        let input = [0x10, 0, 0, 0x11];
+        assert!(ack_frame_with_flag(0)(&input).is_err());
+        // the smallest packet number of the second range is negative
+        let input = [0x10, 0, 1, 0x08, 3, 4];
+        assert!(ack_frame_with_flag(0)(&input).is_err());
+        // the smallest packet number is exactly 0
+        let input = [0x10, 0, 1, 0x08, 3, 3];
+        assert!(ack_frame_with_flag(0)(&input).is_ok());
+    }
+
     #[test]
     fn test_write_ack_frame() {
         let mut buf = Vec::new();
@@ -1,7 +1,7 @@
 use std::borrow::Cow;
 
 use super::FrameType;
-use crate::{error::ErrorKind, frame::be_frame_type, varint::VarInt};
+use crate::{error::ErrorKind, varint::VarInt};
 
 /// CONNECTION_CLOSE Frame.
 ///
@@ -15,12 +15,33 @@ use crate::{error::ErrorKind, frame::be_frame_type, varint::VarInt};
 /// }
 /// ```
 ///
+/// The frame type 0x1c is used to signal errors at only the QUIC layer, see [`QuicCloseFrame`],
+/// and the frame type 0x1d is used to signal an error with the application that uses QUIC,
+/// see [`AppCloseFrame`].
+///
 /// See [connection close frames](https://www.rfc-editor.org/rfc/rfc9000.html#name-connection-close-frames)
 /// of [QUIC](https://www.rfc-editor.org/rfc/rfc9000.html) for more details.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ConnectionCloseFrame {
+pub enum ConnectionCloseFrame {
+    /// CONNECTION_CLOSE frame of type 0x1c, signals errors at the QUIC layer.
+    Quic(QuicCloseFrame),
+    /// CONNECTION_CLOSE frame of type 0x1d, signals errors of the application.
+    App(AppCloseFrame),
+}
+
+/// CONNECTION_CLOSE frame of type 0x1c, the error code is one of the [`ErrorKind`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuicCloseFrame {
     pub error_kind: ErrorKind,
-    pub frame_type: Option<FrameType>,
+    pub frame_type: FrameType,
+    pub reason: Cow<'static, str>,
+}
+
+/// CONNECTION_CLOSE frame of type 0x1d, the error code is chosen by the application,
+/// such as the HTTP/3 error codes, and there is no frame type field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppCloseFrame {
+    pub error_code: VarInt,
     pub reason: Cow<'static, str>,
 }
 
@@ -31,38 +52,78 @@ const APP_LAYER: u8 = 0;
 
 impl super::BeFrame for ConnectionCloseFrame {
     fn frame_type(&self) -> FrameType {
-        FrameType::ConnectionClose(if self.frame_type.is_some() {
-            QUIC_LAYER
-        } else {
-            APP_LAYER
+        FrameType::ConnectionClose(match self {
+            ConnectionCloseFrame::Quic(_) => QUIC_LAYER,
+            ConnectionCloseFrame::App(_) => APP_LAYER,
         })
     }
 
     fn max_encoding_size(&self) -> usize {
         // reason's length could not exceed 16KB.
-        1 + 8 + if self.frame_type.is_some() { 8 } else { 0 } + 2 + self.reason.len()
+        1 + 8 + if self.is_app_layer() { 0 } else { 8 } + 2 + self.reason().len()
     }
 
     fn encoding_size(&self) -> usize {
-        1 + VarInt::from(self.error_kind).encoding_size()
-            + self.frame_type.is_some()  as usize
+        let (error_code, frame_type_size) = match self {
+            ConnectionCloseFrame::Quic(frame) => (VarInt::from(frame.error_kind), 1),
+            ConnectionCloseFrame::App(frame) => (frame.error_code, 0),
+        };
+        1 + error_code.encoding_size()
+            + frame_type_size
             // reason's length could not exceed 16KB.
-            + VarInt::try_from(self.reason.len()).unwrap().encoding_size()
-            + self.reason.len()
+            + VarInt::try_from(self.reason().len()).unwrap().encoding_size()
+            + self.reason().len()
     }
 }
 
 impl ConnectionCloseFrame {
-    /// Create a new `ConnectionCloseFrame`.
-    pub fn new(
+    /// Create a new `ConnectionCloseFrame` that signals an error at the QUIC layer.
+    pub fn new_quic(
         error_kind: ErrorKind,
-        frame_type: Option<FrameType>,
+        frame_type: FrameType,
         reason: Cow<'static, str>,
     ) -> Self {
-        Self {
+        Self::Quic(QuicCloseFrame {
             error_kind,
             frame_type,
             reason,
+        })
+    }
+
+    /// Create a new `ConnectionCloseFrame` that signals an error of the application.
+    pub fn new_app(error_code: VarInt, reason: Cow<'static, str>) -> Self {
+        Self::App(AppCloseFrame { error_code, reason })
+    }
+
+    /// Return whether the frame is the application-specific variant(type 0x1d).
+    pub fn is_app_layer(&self) -> bool {
+        matches!(self, ConnectionCloseFrame::App(_))
+    }
+
+    /// Return the reason phrase of the frame.
+    pub fn reason(&self) -> &str {
+        match self {
+            ConnectionCloseFrame::Quic(frame) => &frame.reason,
+            ConnectionCloseFrame::App(frame) => &frame.reason,
+        }
+    }
+
+    /// Return the frame that can be sent in the Initial and Handshake packets.
+    ///
+    /// The application-specific variant would reveal the application state before the
+    /// handshake is confirmed, so it must be replaced by a CONNECTION_CLOSE frame of type 0x1c
+    /// with the APPLICATION_ERROR code and an empty reason, and the frame type field is PADDING.
+    ///
+    /// See [Immediate Close during the Handshake](https://www.rfc-editor.org/rfc/rfc9000.html#name-immediate-close-during-the-)
+    /// of [QUIC](https://www.rfc-editor.org/rfc/rfc9000.html) for more details.
+    pub fn conceal(&self) -> Self {
+        match self {
+            ConnectionCloseFrame::Quic(_) => self.clone(),
+            ConnectionCloseFrame::App(_) => Self::new_quic(
+                ErrorKind::Application,
+                FrameType::Padding,
+                Cow::Borrowed(""),
+            ),
         }
     }
 }
@@ -77,55 +138,60 @@ pub fn connection_close_frame_at_layer(
     use crate::varint::be_varint;
     move |input: &[u8]| {
         let (remain, error_code) = be_varint(input)?;
-        let kind = ErrorKind::try_from(error_code).map_err(|_e| {
-            nom::Err::Error(nom::error::make_error(input, nom::error::ErrorKind::Alt))
-        })?;
-        // The application-specific variant of CONNECTION_CLOSE (type 0x1d) does not include frame_type field.
+        // The application-specific variant of CONNECTION_CLOSE (type 0x1d) does not include frame_type field,
+        // and its error code is defined by the application, which should not be parsed as ErrorKind.
         let (remain, frame_type) = if layer == QUIC_LAYER {
-            let (remain, frame_type) = be_frame_type(remain).map_err(|_e| {
-                nom::Err::Error(nom::error::make_error(input, nom::error::ErrorKind::Alt))
-            })?;
+            // 触发错误的帧类型可能是本端不认识的扩展帧，不能因此无法得知对端关闭连接的原因，
+            // 与未知帧类型的错误一样，记为Padding
+            let (remain, frame_type) = be_varint(remain)?;
+            let frame_type = u8::try_from(frame_type.into_inner())
+                .ok()
+                .and_then(|frame_type| FrameType::try_from(frame_type).ok())
+                .unwrap_or(FrameType::Padding);
             (remain, Some(frame_type))
         } else {
             (remain, None)
         };
         let (remain, rease_length) = be_varint(remain)?;
         let (remain, reason) = take(rease_length.into_inner() as usize)(remain)?;
-        let cow = String::from_utf8_lossy(reason).into_owned();
-        Ok((
-            remain,
-            ConnectionCloseFrame {
-                error_kind: kind,
-                frame_type,
-                reason: Cow::Owned(cow),
-            },
-        ))
+        let reason = Cow::Owned(String::from_utf8_lossy(reason).into_owned());
+        let frame = match frame_type {
+            Some(frame_type) => {
+                let error_kind = ErrorKind::try_from(error_code).map_err(|_e| {
+                    nom::Err::Error(nom::error::make_error(input, nom::error::ErrorKind::Alt))
+                })?;
+                ConnectionCloseFrame::new_quic(error_kind, frame_type, reason)
+            }
+            None => ConnectionCloseFrame::new_app(error_code, reason),
+        };
+        Ok((remain, frame))
     }
 }
 
 impl<T: bytes::BufMut> super::io::WriteFrame<ConnectionCloseFrame> for T {
     fn put_frame(&mut self, frame: &ConnectionCloseFrame) {
         use crate::varint::WriteVarInt;
-        let layer = if frame.frame_type.is_some() {
-            QUIC_LAYER
-        } else {
-            APP_LAYER
-        };
-        self.put_u8(CONNECTION_CLOSE_FRAME_TYPE | layer);
-        self.put_varint(&frame.error_kind.into());
-        if let Some(frame_type) = frame.frame_type {
-            self.put_u8(frame_type.into());
+        match frame {
+            ConnectionCloseFrame::Quic(frame) => {
+                self.put_u8(CONNECTION_CLOSE_FRAME_TYPE | QUIC_LAYER);
+                self.put_varint(&frame.error_kind.into());
+                self.put_u8(frame.frame_type.into());
+            }
+            ConnectionCloseFrame::App(frame) => {
+                self.put_u8(CONNECTION_CLOSE_FRAME_TYPE | APP_LAYER);
+                self.put_varint(&frame.error_code);
+            }
         }
-        self.put_varint(&VarInt::from_u32(frame.reason.len() as u32));
+        let reason = frame.reason().as_bytes();
+        self.put_varint(&VarInt::from_u32(reason.len() as u32));
         let remaining = self.remaining_mut();
-        let reason = frame.reason.as_bytes();
         self.put_slice(&reason[..reason.len().min(remaining)]);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{error::ErrorKind, frame::io::WriteFrame};
+    use crate::{error::ErrorKind, frame::io::WriteFrame, varint::VarInt};
 
     #[test]
     fn test_read_connection_close_frame() {
@@ -154,23 +220,83 @@ mod tests {
         assert!(input.is_empty());
         assert_eq!(
             frame,
-            super::ConnectionCloseFrame {
-                error_kind: ErrorKind::Application,
-                frame_type: None,
-                reason: "wrong".into(),
-            }
+            super::ConnectionCloseFrame::new_app(VarInt::from_u32(0x0c), "wrong".into())
         );
     }
 
+    #[test]
+    fn test_app_close_frame_with_app_code() {
+        use super::{connection_close_frame_at_layer, ConnectionCloseFrame};
+
+        let frame = ConnectionCloseFrame::new_app(VarInt::from_u32(0x100), "H3_NO_ERROR".into());
+        let mut buf = Vec::<u8>::new();
+        buf.put_frame(&frame);
+        assert_eq!(buf.len(), crate::frame::BeFrame::encoding_size(&frame));
+        assert_eq!(
+            buf[0],
+            super::CONNECTION_CLOSE_FRAME_TYPE | super::APP_LAYER
+        );
+
+        // 0x100 is a Crypto error code at the QUIC layer, but not for the application
+        let (remain, parsed) = connection_close_frame_at_layer(0)(&buf[1..]).unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(parsed, frame);
+    }
+
+    #[test]
+    fn test_quic_close_frame_with_frame_type() {
+        use super::{connection_close_frame_at_layer, ConnectionCloseFrame, FrameType, QUIC_LAYER};
+
+        // 由STREAM帧引发的错误，帧类型中带有OFF、LEN、FIN标志位
+        let frame = ConnectionCloseFrame::new_quic(
+            ErrorKind::StreamState,
+            FrameType::Stream(0b110),
+            "wrong".into(),
+        );
+        let mut buf = Vec::<u8>::new();
+        buf.put_frame(&frame);
+        assert_eq!(buf[2], 0x0e);
+        let (remain, parsed) = connection_close_frame_at_layer(QUIC_LAYER)(&buf[1..]).unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(parsed, frame);
+
+        // 未知的扩展帧类型，以2字节的varint编码
+        let buf = [0x0a, 0x40, 0xaf, 0x00];
+        let (remain, parsed) = connection_close_frame_at_layer(QUIC_LAYER)(&buf).unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(
+            parsed,
+            ConnectionCloseFrame::new_quic(
+                ErrorKind::ProtocolViolation,
+                FrameType::Padding,
+                "".into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_conceal_app_close_frame() {
+        use super::{ConnectionCloseFrame, FrameType};
+
+        let frame = ConnectionCloseFrame::new_app(VarInt::from_u32(0x100), "secret".into());
+        assert_eq!(
+            frame.conceal(),
+            ConnectionCloseFrame::new_quic(ErrorKind::Application, FrameType::Padding, "".into())
+        );
+        let frame =
+            ConnectionCloseFrame::new_quic(ErrorKind::Internal, FrameType::Padding, "bug".into());
+        assert_eq!(frame.conceal(), frame);
+    }
+
     #[test]
     fn test_write_connection_close_frame() {
         use super::FrameType;
         let mut buf = Vec::<u8>::new();
-        let frame = super::ConnectionCloseFrame {
-            error_kind: ErrorKind::FlowControl,
-            frame_type: Some(FrameType::Stream(0b110)),
-            reason: "wrong".into(),
-        };
+        let frame = super::ConnectionCloseFrame::new_quic(
+            ErrorKind::FlowControl,
+            FrameType::Stream(0b110),
+            "wrong".into(),
+        );
         buf.put_frame(&frame);
         assert_eq!(
             buf,
@@ -87,7 +87,7 @@ impl CryptoFrame {
 /// [nom](https://docs.rs/nom/latest/nom/) parser style.
 pub fn be_crypto_frame(input: &[u8]) -> nom::IResult<&[u8], CryptoFrame> {
     let (remain, (offset, length)) = tuple((be_varint, be_varint))(input)?;
-    if offset.into_inner() + offset.into_inner() > VARINT_MAX {
+    if offset.into_inner() + length.into_inner() > VARINT_MAX {
         return Err(nom::Err::Error(nom::error::make_error(
             input,
             nom::error::ErrorKind::TooLarge,
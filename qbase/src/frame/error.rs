@@ -63,15 +63,14 @@ impl From<nom::Err<Error>> for Error {
 }
 
 impl nom::error::ParseError<&[u8]> for Error {
-    fn from_error_kind(_input: &[u8], _kind: NomErrorKind) -> Self {
-        debug_assert_eq!(_kind, NomErrorKind::ManyTill);
-        unreachable!("QUIC frame parser must always consume")
+    fn from_error_kind(_input: &[u8], kind: NomErrorKind) -> Self {
+        // 输入来自网络，不可信，比如在帧类型处就截断了，不能因此panic
+        Self::IncompleteType(kind.description().to_owned())
     }
 
     fn append(_input: &[u8], _kind: NomErrorKind, source: Self) -> Self {
         // 在解析帧时遇到了source错误，many_till期望通过ManyTill的错误类型告知
         // 这里，源错误更有意义，所以直接返回源错误
-        debug_assert_eq!(_kind, NomErrorKind::ManyTill);
         source
     }
 }
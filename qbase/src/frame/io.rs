@@ -41,6 +41,7 @@ fn complete_frame(
         FrameType::PathChallenge => map(be_path_challenge_frame, Frame::Challenge)(input),
         FrameType::PathResponse => map(be_path_response_frame, Frame::Response)(input),
         FrameType::HandshakeDone => Ok((input, Frame::HandshakeDone(HandshakeDoneFrame))),
+        FrameType::ImmediateAck => Ok((input, Frame::ImmediateAck(ImmediateAckFrame))),
         FrameType::NewToken => map(be_new_token_frame, Frame::NewToken)(input),
         FrameType::Ack(ecn) => map(ack_frame_with_flag(ecn), Frame::Ack)(input),
         FrameType::ResetStream => map(be_reset_stream_frame, |f| Frame::StreamCtl(f.into()))(input),
@@ -5,26 +5,36 @@ use std::sync::{
 
 use crate::{
     error::{Error, ErrorKind},
-    frame::{HandshakeDoneFrame, ReceiveFrame, SendFrame},
+    frame::{BeFrame, HandshakeDoneFrame, ReceiveFrame, SendFrame},
     sid::Role,
 };
 
-/// The completion flag for the client handshake.
+/// The completion and confirmation flags for the client handshake.
 ///
-/// The client considers the handshake complete only after
+/// The client considers the handshake complete once the TLS handshake is finished,
+/// that is, the 1-RTT keys are available, but the handshake is confirmed only after
 /// receiving the [`HandshakeDoneFrame`] from the server.
+/// See [section 4.1](https://www.rfc-editor.org/rfc/rfc9001.html#section-4.1)
+/// of [RFC 9001](https://www.rfc-editor.org/rfc/rfc9001.html).
+///
 /// In the QUIC protocol, there are no tasks that specifically
 /// require waiting for the client handshake to complete.
 /// Instead, it simply queries the handshake status.
 #[derive(Debug, Default, Clone)]
 pub struct ClientHandshake {
     has_keys: Arc<AtomicBool>,
+    complete: Arc<AtomicBool>,
     done: Arc<AtomicBool>,
 }
 
 impl ClientHandshake {
-    /// Check if the client handshake is complete.
-    pub fn is_handshake_done(&self) -> bool {
+    /// Check if the client handshake is complete, the 1-RTT keys are available.
+    pub fn is_handshake_complete(&self) -> bool {
+        self.complete.load(Ordering::Acquire)
+    }
+
+    /// Check if the client handshake is confirmed, the [`HandshakeDoneFrame`] is received.
+    pub fn is_handshake_confirmed(&self) -> bool {
         self.done.load(Ordering::Acquire)
     }
 
@@ -38,7 +48,17 @@ impl ClientHandshake {
     pub fn recv_handshake_done_frame(&self, _frame: &HandshakeDoneFrame) {
         let _has_done = self.done.swap(true, Ordering::AcqRel);
         if !_has_done {
-            log::trace!("Client handshake is done");
+            log::trace!("Client handshake is confirmed");
+        }
+        // 收到HANDSHAKE_DONE帧时，握手必然已完成
+        self.complete.store(true, Ordering::Release);
+    }
+
+    /// The TLS handshake is finished, marks the completion of the client handshake.
+    pub fn on_handshake_complete(&self) {
+        let complete = self.complete.swap(true, Ordering::AcqRel);
+        if !complete {
+            log::trace!("Client handshake is complete");
         }
     }
 
@@ -92,10 +112,19 @@ where
     }
 
     /// Check if the server handshake is complete.
-    pub fn is_handshake_done(&self) -> bool {
+    pub fn is_handshake_complete(&self) -> bool {
         self.is_done.load(Ordering::Acquire)
     }
 
+    /// Check if the server handshake is confirmed.
+    ///
+    /// The server confirms the handshake as soon as it is complete, see
+    /// [section 4.1.2](https://www.rfc-editor.org/rfc/rfc9001.html#section-4.1.2)
+    /// of [RFC 9001](https://www.rfc-editor.org/rfc/rfc9001.html).
+    pub fn is_handshake_confirmed(&self) -> bool {
+        self.is_handshake_complete()
+    }
+
     /// Check if the server is getting handshake keys.
     pub fn has_keys(&self) -> bool {
         self.has_keys.load(Ordering::Acquire)
@@ -171,11 +200,22 @@ where
         Handshake::Server(ServerHandshake::new(output))
     }
 
-    /// Check if the handshake is complete.
-    pub fn is_handshake_done(&self) -> bool {
+    /// Check if the handshake is complete, the 1-RTT keys are available.
+    pub fn is_handshake_complete(&self) -> bool {
+        match self {
+            Handshake::Client(h) => h.is_handshake_complete(),
+            Handshake::Server(h) => h.is_handshake_complete(),
+        }
+    }
+
+    /// Check if the handshake is confirmed.
+    ///
+    /// For the client, it means the [`HandshakeDoneFrame`] is received;
+    /// for the server, it is the same as [`Handshake::is_handshake_complete`].
+    pub fn is_handshake_confirmed(&self) -> bool {
         match self {
-            Handshake::Client(h) => h.is_handshake_done(),
-            Handshake::Server(h) => h.is_handshake_done(),
+            Handshake::Client(h) => h.is_handshake_confirmed(),
+            Handshake::Server(h) => h.is_handshake_confirmed(),
         }
     }
 
@@ -195,9 +235,13 @@ where
         }
     }
 
+    /// The TLS handshake is finished.
+    ///
+    /// The client marks the handshake complete, and the server marks it complete and confirmed,
+    /// then sends the [`HandshakeDoneFrame`].
     pub fn done(&self) {
         match self {
-            Handshake::Client(..) => (), /* for client, do nothing */
+            Handshake::Client(h) => h.on_handshake_complete(),
             Handshake::Server(h) => h.done(),
         }
     }
@@ -230,8 +274,9 @@ where
                 h.recv_handshake_done_frame(frame);
                 Ok(())
             }
-            _ => Err(Error::with_default_fty(
+            _ => Err(Error::new(
                 ErrorKind::ProtocolViolation,
+                frame.frame_type(),
                 "Server received a HANDSHAKE_DONE frame",
             )),
         }
@@ -245,7 +290,7 @@ mod tests {
     use super::*;
     use crate::{
         error::{Error, ErrorKind},
-        frame::{ReceiveFrame, SendFrame},
+        frame::{FrameType, ReceiveFrame, SendFrame},
         util::ArcAsyncDeque,
     };
 
@@ -261,17 +306,33 @@ mod tests {
     #[test]
     fn test_client_handshake() {
         let handshake = Handshake::<HandshakeDoneFrameTx>::new_client();
-        assert!(!handshake.is_handshake_done());
+        assert!(!handshake.is_handshake_confirmed());
 
         let ret = handshake.recv_frame(&HandshakeDoneFrame);
         assert!(ret.is_ok());
-        assert!(handshake.is_handshake_done());
+        assert!(handshake.is_handshake_confirmed());
+    }
+
+    #[test]
+    fn test_client_confirmed_after_handshake_done() {
+        let handshake = Handshake::<HandshakeDoneFrameTx>::new_client();
+        assert!(!handshake.is_handshake_complete());
+        assert!(!handshake.is_handshake_confirmed());
+
+        // TLS握手完成，得到1-RTT密钥，但尚未确认
+        handshake.done();
+        assert!(handshake.is_handshake_complete());
+        assert!(!handshake.is_handshake_confirmed());
+
+        handshake.recv_frame(&HandshakeDoneFrame).unwrap();
+        assert!(handshake.is_handshake_complete());
+        assert!(handshake.is_handshake_confirmed());
     }
 
     #[test]
     fn test_client_handshake_done() {
         let handshake = Handshake::<HandshakeDoneFrameTx>::new_client();
-        assert!(!handshake.is_handshake_done());
+        assert!(!handshake.is_handshake_confirmed());
 
         match &handshake {
             Handshake::Client(client_handshake) => {
@@ -279,31 +340,32 @@ mod tests {
             }
             Handshake::Server(..) => unreachable!(),
         }
-        assert!(handshake.is_handshake_done());
+        assert!(handshake.is_handshake_confirmed());
     }
 
     #[test]
     fn test_server_handshake() {
         let handshake = Handshake::new_server(HandshakeDoneFrameTx::default());
-        assert!(!handshake.is_handshake_done());
+        assert!(!handshake.is_handshake_confirmed());
 
         match &handshake {
             Handshake::Client(..) => unreachable!(),
             Handshake::Server(server_handshake) => server_handshake.done(),
         }
-        assert!(handshake.is_handshake_done());
+        assert!(handshake.is_handshake_confirmed());
     }
 
     #[test]
     fn test_server_recv_handshake_done_frame() {
         let handshake = Handshake::new_server(HandshakeDoneFrameTx::default());
-        assert!(!handshake.is_handshake_done());
+        assert!(!handshake.is_handshake_confirmed());
 
         let ret = handshake.recv_frame(&HandshakeDoneFrame);
         assert_eq!(
             ret,
-            Err(Error::with_default_fty(
+            Err(Error::new(
                 ErrorKind::ProtocolViolation,
+                FrameType::HandshakeDone,
                 "Server received a HANDSHAKE_DONE frame",
             ))
         );
@@ -313,7 +375,7 @@ mod tests {
     fn test_server_send_handshake_done_frame() {
         let handshake = ServerHandshake::new(HandshakeDoneFrameTx::default());
         handshake.done();
-        assert!(handshake.is_handshake_done());
+        assert!(handshake.is_handshake_confirmed());
         assert_eq!(handshake.output.len(), 1);
     }
 }
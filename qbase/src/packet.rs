@@ -62,6 +62,9 @@ pub mod encrypt;
 /// Encapsulate the crypto keys's logic for long headers and 1-RTT headers.
 pub mod keys;
 
+/// Computing and verification of the Retry Integrity Tag.
+pub mod retry;
+
 /// The sum type of all QUIC packet headers.
 #[derive(Debug, Clone)]
 #[enum_dispatch(GetDcid, GetType)]
@@ -91,6 +94,8 @@ pub struct DataPacket {
     pub bytes: BytesMut,
     // payload_offset
     pub offset: usize,
+    /// The ECN codepoint in the IP header of the datagram carrying the packet, if known.
+    pub ecn: Option<u8>,
 }
 
 impl GetType for DataPacket {
@@ -99,11 +104,33 @@ impl GetType for DataPacket {
     }
 }
 
+/// The Retry packet, which keeps the raw bytes of the whole packet.
+///
+/// The Retry packet is not encrypted, but the client must verify the Retry Integrity Tag
+/// before acting on it, which is computed over the raw bytes of the packet. The unused bits
+/// in the first byte are arbitrary, so the raw bytes can't be restored from the header.
+#[derive(Debug, Clone, Deref, DerefMut)]
+pub struct RetryPacket {
+    #[deref]
+    pub header: RetryHeader,
+    pub bytes: BytesMut,
+}
+
+impl RetryPacket {
+    /// Verify the Retry Integrity Tag with the original destination connection ID which
+    /// the client used in its first Initial packet.
+    ///
+    /// See [`retry::verify_retry_integrity`].
+    pub fn verify_integrity(&self, original_dcid: &ConnectionId) -> bool {
+        retry::verify_retry_integrity(original_dcid, &self.bytes)
+    }
+}
+
 /// The sum type of all QUIC packets.
 #[derive(Debug, Clone)]
 pub enum Packet {
     VN(VersionNegotiationHeader),
-    Retry(RetryHeader),
+    Retry(RetryPacket),
     // Data(header, bytes, payload_offset)
     Data(DataPacket),
 }
@@ -259,7 +286,11 @@ impl<'b> PacketWriter<'b> {
         let mut len_buf = &mut self.buffer[self.hdr_len..self.hdr_len + self.len_encoding];
         let (actual_pn, encoded_pn) = self.pn;
         let pkt_size = self.cursor + self.tag_len;
-        len_buf.encode_varint(&VarInt::try_from(payload_len).unwrap(), EncodeBytes::Two);
+        // Length字段包括包号、载荷以及AEAD的tag
+        len_buf.encode_varint(
+            &VarInt::try_from(payload_len + self.tag_len).unwrap(),
+            EncodeBytes::Two,
+        );
         encode_long_first_byte(&mut self.buffer[0], encoded_pn.size());
         encrypt_packet(
             pk,
@@ -270,7 +301,7 @@ impl<'b> PacketWriter<'b> {
         protect_header(
             hpk,
             &mut self.buffer[..pkt_size],
-            self.hdr_len,
+            self.hdr_len + self.len_encoding,
             encoded_pn.size(),
         );
         AssembledPacket {
@@ -500,7 +531,7 @@ mod tests {
                 b't', b'e', b's', b't', b's', b'c', b'i', b'd', // scid bytes
                 10,   // token length, no token
                 b't', b'e', b's', b't', b'_', b't', b'o', b'k', b'e', b'n', // token bytes
-                64, 16, // payload length, 2 bytes encoded varint
+                64, 32, // payload length, 2 bytes encoded varint, including the tag
                 0,  // encoded packet number
                 // crypto frame header
                 6,  // crypto frame type
@@ -515,4 +546,68 @@ mod tests {
             .as_slice()
         );
     }
+
+    #[test]
+    fn test_long_packet_round_trip() {
+        let dcid = ConnectionId::from_slice("testdcid".as_bytes());
+        let header =
+            LongHeaderBuilder::with_cid(dcid, ConnectionId::from_slice("testscid".as_bytes()))
+                .initial(vec![]);
+        let suite = rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256
+            .tls13()
+            .and_then(|suite| suite.quic_suite())
+            .unwrap();
+        let client_keys = suite.keys(&dcid, rustls::Side::Client, rustls::quic::Version::V1);
+        let server_keys = suite.keys(&dcid, rustls::Side::Server, rustls::quic::Version::V1);
+
+        let mut datagram = vec![0u8; 256];
+        let mut written = 0;
+        for pn in 0..2 {
+            let buffer = &mut datagram[written..];
+            let pn = (pn, PacketNumber::encode(pn, 0));
+            let tag_len = client_keys.local.packet.tag_len();
+            let mut writer = PacketWriter::new(&header, buffer, pn, tag_len).unwrap();
+            let frame = CryptoFrame {
+                length: VarInt::from_u32(12),
+                offset: VarInt::from_u32(0),
+            };
+            writer.dump_frame_with_data(frame, "client_hello".as_bytes());
+            let packet = writer.encrypt_long_packet(
+                client_keys.local.header.as_ref(),
+                client_keys.local.packet.as_ref(),
+            );
+            written += packet.len();
+        }
+
+        // 两个包合并在同一个数据报中，Length字段须包括tag，才能正确地分开它们
+        let mut reader = PacketReader::new(BytesMut::from(&datagram[..written]), dcid.len());
+        for expected_pn in 0..2 {
+            let Some(Ok(Packet::Data(mut packet))) = reader.next() else {
+                panic!("expect a data packet");
+            };
+            let undecoded_pn = decrypt::remove_protection_of_long_packet(
+                server_keys.remote.header.as_ref(),
+                packet.bytes.as_mut(),
+                packet.offset,
+            )
+            .unwrap()
+            .expect("header protection removed");
+            let pn = undecoded_pn.decode(0);
+            assert_eq!(pn, expected_pn);
+
+            let body_offset = packet.offset + undecoded_pn.size();
+            let body_len = decrypt::decrypt_packet(
+                server_keys.remote.packet.as_ref(),
+                pn,
+                packet.bytes.as_mut(),
+                body_offset,
+            )
+            .unwrap();
+            assert_eq!(
+                &packet.bytes[body_offset..body_offset + body_len],
+                [&[6, 0, 12][..], b"client_hello"].concat()
+            );
+        }
+        assert!(reader.next().is_none());
+    }
 }
@@ -49,13 +49,18 @@ pub fn be_packet(datagram: &mut BytesMut, dcid_len: usize) -> Result<Packet, Err
     })?;
     match header {
         Header::VN(header) => Ok(Packet::VN(header)),
-        Header::Retry(header) => Ok(Packet::Retry(header)),
+        Header::Retry(header) => {
+            // The Retry packet has no length field, it extends to the end of the datagram.
+            let bytes = datagram.split();
+            Ok(Packet::Retry(RetryPacket { header, bytes }))
+        }
         Header::Initial(header) => {
             let (bytes, offset) = be_payload(pkty, datagram, remain.len())?;
             Ok(Packet::Data(DataPacket {
                 header: DataHeader::Long(long::DataHeader::Initial(header)),
                 bytes,
                 offset,
+                ecn: None,
             }))
         }
         Header::ZeroRtt(header) => {
@@ -64,6 +69,7 @@ pub fn be_packet(datagram: &mut BytesMut, dcid_len: usize) -> Result<Packet, Err
                 header: DataHeader::Long(long::DataHeader::ZeroRtt(header)),
                 bytes,
                 offset,
+                ecn: None,
             }))
         }
         Header::Handshake(header) => {
@@ -72,6 +78,7 @@ pub fn be_packet(datagram: &mut BytesMut, dcid_len: usize) -> Result<Packet, Err
                 header: DataHeader::Long(long::DataHeader::Handshake(header)),
                 bytes,
                 offset,
+                ecn: None,
             }))
         }
         Header::OneRtt(header) => {
@@ -86,6 +93,7 @@ pub fn be_packet(datagram: &mut BytesMut, dcid_len: usize) -> Result<Packet, Err
                 header: DataHeader::Short(header),
                 bytes,
                 offset,
+                ecn: None,
             }))
         }
     }
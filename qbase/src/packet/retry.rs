@@ -0,0 +1,118 @@
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_128_GCM};
+
+use crate::cid::ConnectionId;
+
+/// The length of the Retry Integrity Tag at the end of the Retry packet.
+pub const RETRY_INTEGRITY_TAG_LEN: usize = 16;
+
+/// The secret key for QUIC version 1, see [Retry Packet Integrity](https://www.rfc-editor.org/rfc/rfc9001.html#name-retry-packet-integrity).
+const RETRY_INTEGRITY_KEY: [u8; 16] = [
+    0xbe, 0x0c, 0x69, 0x0b, 0x9f, 0x66, 0x57, 0x5a, 0x1d, 0x76, 0x6b, 0x54, 0xe3, 0x68, 0xc8, 0x4e,
+];
+
+/// The nonce for QUIC version 1, see [Retry Packet Integrity](https://www.rfc-editor.org/rfc/rfc9001.html#name-retry-packet-integrity).
+const RETRY_INTEGRITY_NONCE: [u8; 12] = [
+    0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
+];
+
+/// Compute the Retry Integrity Tag of a Retry packet.
+///
+/// The `packet` is the content of the Retry packet, excluding the tag itself. The tag is
+/// the output of AEAD_AES_128_GCM with an empty plaintext, and the Retry Pseudo-Packet,
+/// which is the `original_dcid` prefixed by its length and followed by the `packet`,
+/// as the associated data.
+///
+/// ```text
+/// Retry Pseudo-Packet {
+///   ODCID Length (8),
+///   Original Destination Connection ID (0..160),
+///   Header Form (1) = 1,
+///   Fixed Bit (1) = 1,
+///   Long Packet Type (2) = 3,
+///   Unused (4),
+///   Version (32),
+///   DCID Len (8),
+///   Destination Connection ID (0..160),
+///   SCID Len (8),
+///   Source Connection ID (0..160),
+///   Retry Token (..),
+/// }
+/// ```
+///
+/// See [Retry Packet Integrity](https://www.rfc-editor.org/rfc/rfc9001.html#name-retry-packet-integrity)
+/// of [RFC9001](https://www.rfc-editor.org/rfc/rfc9001.html) for more details.
+pub fn retry_integrity_tag(
+    original_dcid: &ConnectionId,
+    packet: &[u8],
+) -> [u8; RETRY_INTEGRITY_TAG_LEN] {
+    let mut pseudo_packet = Vec::with_capacity(1 + original_dcid.len() + packet.len());
+    pseudo_packet.push(original_dcid.len() as u8);
+    pseudo_packet.extend_from_slice(original_dcid);
+    pseudo_packet.extend_from_slice(packet);
+
+    let key = UnboundKey::new(&AES_128_GCM, &RETRY_INTEGRITY_KEY).unwrap();
+    let key = LessSafeKey::new(key);
+    let nonce = Nonce::assume_unique_for_key(RETRY_INTEGRITY_NONCE);
+    let tag = key
+        .seal_in_place_separate_tag(nonce, Aad::from(pseudo_packet), &mut [])
+        .unwrap();
+
+    let mut integrity = [0; RETRY_INTEGRITY_TAG_LEN];
+    integrity.copy_from_slice(tag.as_ref());
+    integrity
+}
+
+/// Verify the Retry Integrity Tag of a Retry packet.
+///
+/// The `packet` is the whole Retry packet, including the tag at the end. The client must
+/// discard the Retry packet that fails the verification, which may be injected by an
+/// off-path attacker or be corrupted.
+pub fn verify_retry_integrity(original_dcid: &ConnectionId, packet: &[u8]) -> bool {
+    if packet.len() < RETRY_INTEGRITY_TAG_LEN {
+        return false;
+    }
+    let (packet, integrity) = packet.split_at(packet.len() - RETRY_INTEGRITY_TAG_LEN);
+    retry_integrity_tag(original_dcid, packet) == integrity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // See [Retry](https://www.rfc-editor.org/rfc/rfc9001.html#name-retry) of RFC9001 Appendix A.4
+    const RETRY_PACKET: [u8; 36] = [
+        0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x08, 0xf0, 0x67, 0xa5, 0x50, 0x2a, 0x42, 0x62, 0xb5,
+        0x74, 0x6f, 0x6b, 0x65, 0x6e, 0x04, 0xa2, 0x65, 0xba, 0x2e, 0xff, 0x4d, 0x82, 0x90, 0x58,
+        0xfb, 0x3f, 0x0f, 0x24, 0x96, 0xba,
+    ];
+
+    fn original_dcid() -> ConnectionId {
+        ConnectionId::from_slice(&[0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08])
+    }
+
+    #[test]
+    fn test_retry_integrity_tag() {
+        let (packet, integrity) = RETRY_PACKET.split_at(RETRY_PACKET.len() - 16);
+        assert_eq!(retry_integrity_tag(&original_dcid(), packet), integrity);
+        assert!(verify_retry_integrity(&original_dcid(), &RETRY_PACKET));
+    }
+
+    #[test]
+    fn test_tampered_retry_integrity_tag() {
+        let mut tampered_tag = RETRY_PACKET;
+        tampered_tag[RETRY_PACKET.len() - 1] ^= 0x01;
+        assert!(!verify_retry_integrity(&original_dcid(), &tampered_tag));
+
+        let mut tampered_token = RETRY_PACKET;
+        tampered_token[16] ^= 0x01;
+        assert!(!verify_retry_integrity(&original_dcid(), &tampered_token));
+
+        let other_dcid = ConnectionId::from_slice(&[0x83, 0x94, 0xc8, 0xf0]);
+        assert!(!verify_retry_integrity(&other_dcid, &RETRY_PACKET));
+
+        assert!(!verify_retry_integrity(
+            &original_dcid(),
+            &RETRY_PACKET[..15]
+        ));
+    }
+}
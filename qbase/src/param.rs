@@ -3,6 +3,7 @@ use std::{
     ops::{Deref, DerefMut},
     sync::{Arc, Mutex},
     task::{Context, Poll, Waker},
+    time::Duration,
 };
 
 use bytes::BufMut;
@@ -25,6 +26,7 @@ struct Requirements {
     initial_source_connection_id: Option<ConnectionId>,
     retry_source_connection_id: Option<ConnectionId>,
     original_destination_connection_id: Option<ConnectionId>,
+    chosen_version: Option<u32>,
 }
 
 pub struct Pair {
@@ -100,6 +102,14 @@ impl Parameters {
         }
     }
 
+    fn set_chosen_version(&mut self, version: u32) {
+        if self.role == Role::Client {
+            self.client.set_chosen_version(Some(version));
+        } else {
+            self.server.set_chosen_version(Some(version));
+        }
+    }
+
     fn set_retry_scid(&mut self, cid: ConnectionId) {
         assert_eq!(self.role, Role::Server);
         self.server.set_retry_source_connection_id(cid);
@@ -122,6 +132,19 @@ impl Parameters {
         }
     }
 
+    fn idle_timeout(&self) -> Duration {
+        let local = self.local().max_idle_timeout;
+        let remote = self
+            .remote()
+            .map_or(Duration::ZERO, |remote| remote.max_idle_timeout);
+        // 为0或缺省的一方，表示不限制空闲超时，取另一方的值
+        match (local.is_zero(), remote.is_zero()) {
+            (true, _) => remote,
+            (_, true) => local,
+            _ => local.min(remote),
+        }
+    }
+
     fn has_rcvd_remote_params(&self) -> bool {
         self.state == Self::CLIENT_READY | Self::SERVER_READY
     }
@@ -136,6 +159,7 @@ impl Parameters {
             )
         })?;
         self.validate_remote_params()?;
+        self.authenticate_version()?;
         self.authenticate_cids()?;
 
         self.wake_all();
@@ -163,6 +187,10 @@ impl Parameters {
         }
     }
 
+    fn chosen_version_from_peer_need_equal(&mut self, version: u32) {
+        self.requirements.chosen_version = Some(version)
+    }
+
     fn retry_scid_from_server_need_equal(&mut self, cid: ConnectionId) {
         assert_eq!(self.role, Role::Client);
         self.requirements.retry_source_connection_id = Some(cid)
@@ -173,6 +201,22 @@ impl Parameters {
         self.requirements.original_destination_connection_id = Some(cid)
     }
 
+    /// 对端不支持version_information传输参数时不做检查；否则其Chosen Version必须是正在使用的版本，
+    /// 不一致说明版本协商被篡改，即遭受了降级攻击
+    fn authenticate_version(&self) -> Result<(), Error> {
+        let remote_version = self.remote().unwrap().chosen_version;
+        match (remote_version, self.requirements.chosen_version) {
+            (Some(remote), Some(expected)) if remote != expected => Err(Error::new(
+                ErrorKind::VersionNegotiation,
+                FrameType::Crypto,
+                format!(
+                    "chosen version from peer must be {expected:#010x}, but got {remote:#010x}"
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
     fn authenticate_cids(&self) -> Result<(), Error> {
         fn param_error(reason: &'static str) -> Error {
             Error::new(ErrorKind::TransportParameter, FrameType::Crypto, reason)
@@ -218,17 +262,37 @@ impl Parameters {
     fn validate_remote_params(&self) -> Result<(), Error> {
         let remote_params = self.remote().unwrap();
         let reason = if remote_params.max_udp_payload_size.into_inner() < 1200 {
-            Some("max_udp_payload_size from peer must be at least 1200")
+            Some(format!(
+                "max_udp_payload_size from peer must be at least 1200, but got {}",
+                remote_params.max_udp_payload_size
+            ))
         } else if remote_params.ack_delay_exponent.into_inner() > 20 {
-            Some("ack_delay_exponent from peer must be at most 20")
+            Some(format!(
+                "ack_delay_exponent from peer must be at most 20, but got {}",
+                remote_params.ack_delay_exponent
+            ))
         } else if remote_params.max_ack_delay.into_inner() > 1 << 14 {
-            Some("max_ack_delay from peer must be at most 2^14")
+            Some(format!(
+                "max_ack_delay from peer must be at most 2^14, but got {}",
+                remote_params.max_ack_delay
+            ))
         } else if remote_params.active_connection_id_limit.into_inner() < 2 {
-            Some("active_connection_id_limit from peer must be at least 2")
+            Some(format!(
+                "active_connection_id_limit from peer must be at least 2, but got {}",
+                remote_params.active_connection_id_limit
+            ))
+        } else if remote_params.chosen_version == Some(0) {
+            Some("chosen version from peer must not be 0".to_string())
         } else if remote_params.initial_max_streams_bidi.into_inner() > MAX_STREAMS_LIMIT {
-            Some("initial_max_streams_bidi from peer must be at most 2^60 - 1")
+            Some(format!(
+                "initial_max_streams_bidi from peer must be at most 2^60 - 1, but got {}",
+                remote_params.initial_max_streams_bidi
+            ))
         } else if remote_params.initial_max_streams_uni.into_inner() > MAX_STREAMS_LIMIT {
-            Some("initial_max_streams_uni from peer must be at most 2^60 - 1")
+            Some(format!(
+                "initial_max_streams_uni from peer must be at most 2^60 - 1, but got {}",
+                remote_params.initial_max_streams_uni
+            ))
         } else {
             None
         };
@@ -302,6 +366,14 @@ impl ArcParameters {
         }
     }
 
+    /// Set the Chosen Version of the local version_information transport parameter.
+    pub fn set_chosen_version(&self, version: u32) {
+        let mut guard = self.0.lock().unwrap();
+        if let Ok(params) = guard.deref_mut() {
+            params.set_chosen_version(version);
+        }
+    }
+
     pub fn set_retry_scid(&self, cid: ConnectionId) {
         let mut guard = self.0.lock().unwrap();
         if let Ok(params) = guard.deref_mut() {
@@ -330,6 +402,16 @@ impl ArcParameters {
         }
     }
 
+    /// Require the Chosen Version of the peer's version_information transport parameter, if
+    /// present, to be `version`, otherwise the connection is closed with the
+    /// [`ErrorKind::VersionNegotiation`] error.
+    pub fn chosen_version_from_peer_need_equal(&self, version: u32) {
+        let mut guard = self.0.lock().unwrap();
+        if let Ok(params) = guard.deref_mut() {
+            params.chosen_version_from_peer_need_equal(version);
+        }
+    }
+
     pub fn retry_scid_from_server_need_equal(&self, cid: ConnectionId) {
         let mut guard = self.0.lock().unwrap();
         if let Ok(params) = guard.deref_mut() {
@@ -356,6 +438,22 @@ impl ArcParameters {
         Ok(())
     }
 
+    /// Return the effective idle timeout of the connection, [`Duration::ZERO`] if it is disabled.
+    ///
+    /// It is the minimum of the max_idle_timeout transport parameters of both endpoints, the
+    /// value 0 of either side means that side does not limit it, see
+    /// [Section 10.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-10.1) of RFC 9000.
+    /// Only the local value is considered before the peer's parameters are received.
+    ///
+    /// Return [`Duration::ZERO`] once a connection error occurred.
+    pub fn idle_timeout(&self) -> Duration {
+        let guard = self.0.lock().unwrap();
+        match guard.deref() {
+            Ok(params) => params.idle_timeout(),
+            Err(_) => Duration::ZERO,
+        }
+    }
+
     pub fn has_rcvd_remote_params(&self) -> bool {
         let guard = self.0.lock().unwrap();
         match guard.deref() {
@@ -387,7 +485,8 @@ impl Future for ArcParameters {
 
 #[cfg(test)]
 mod test {
-    use super::ClientParameters;
+    use super::*;
+    use crate::varint::VarInt;
 
     #[test]
     fn test_common_parameters() {
@@ -396,4 +495,116 @@ mod test {
 
         println!("{:?}", client_params);
     }
+
+    #[test]
+    fn test_clamp_max_udp_payload_size() {
+        let mut client_params = ClientParameters::default();
+        client_params.set_max_udp_payload_size(1000);
+        assert_eq!(client_params.max_udp_payload_size(), VarInt::from_u32(1200));
+    }
+
+    #[tokio::test]
+    async fn test_too_low_max_udp_payload_size() {
+        // 绕过set_max_udp_payload_size，模拟对端通告1000
+        let mut client_params = ClientParameters::default();
+        client_params.max_udp_payload_size = VarInt::from_u32(1000);
+        let mut raw = Vec::new();
+        raw.put_client_parameters(&client_params);
+
+        let params = ArcParameters::new_server(ServerParameters::default());
+        let error = params.recv_remote_params(&raw).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TransportParameter);
+        assert_eq!(
+            error.reason(),
+            "max_udp_payload_size from peer must be at least 1200, but got 1000"
+        );
+        // 错误的参数不会被外界拿到，等待参数的任务随之结束
+        assert!(params.remote().is_none());
+        assert!(params.clone().await.is_none());
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let idle_timeout = |local: u64, remote: u64| {
+            let mut client_params = ClientParameters::default();
+            client_params.set_max_idle_timeout(Duration::from_secs(remote));
+            let mut raw = Vec::new();
+            raw.put_client_parameters(&client_params);
+
+            let mut server_params = ServerParameters::default();
+            server_params.set_max_idle_timeout(Duration::from_secs(local));
+            let params = ArcParameters::new_server(server_params);
+            params.initial_scid_from_peer_need_equal(ConnectionId::default());
+            assert_eq!(params.idle_timeout(), Duration::from_secs(local));
+            params.recv_remote_params(&raw).unwrap();
+            params.idle_timeout()
+        };
+        assert_eq!(idle_timeout(30, 10), Duration::from_secs(10));
+        assert_eq!(idle_timeout(10, 30), Duration::from_secs(10));
+        // 为0的一方不限制，取另一方的值
+        assert_eq!(idle_timeout(0, 10), Duration::from_secs(10));
+        assert_eq!(idle_timeout(30, 0), Duration::from_secs(30));
+        assert_eq!(idle_timeout(0, 0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_max_idle_timeout_in_millis() {
+        let mut client_params = ClientParameters::default();
+        client_params.set_max_idle_timeout(Duration::from_millis(1500));
+        let mut raw = Vec::new();
+        raw.put_client_parameters(&client_params);
+        // max_idle_timeout以毫秒为单位编码，1500，即varint 0x45dc
+        assert_eq!(raw[..4], [0x01, 0x02, 0x45, 0xdc]);
+
+        let params = ArcParameters::new_server(ServerParameters::default());
+        params.initial_scid_from_peer_need_equal(ConnectionId::default());
+        params.recv_remote_params(&raw).unwrap();
+        assert_eq!(
+            params.remote().unwrap().max_idle_timeout(),
+            Duration::from_millis(1500)
+        );
+    }
+
+    #[test]
+    fn test_version_information() {
+        let mut client_params = ClientParameters::default();
+        client_params.set_chosen_version(Some(0x00000001));
+        let mut raw = Vec::new();
+        raw.put_client_parameters(&client_params);
+
+        let params = ArcParameters::new_server(ServerParameters::default());
+        params.initial_scid_from_peer_need_equal(ConnectionId::default());
+        params.chosen_version_from_peer_need_equal(0x00000001);
+        params.recv_remote_params(&raw).unwrap();
+        assert_eq!(params.remote().unwrap().chosen_version(), Some(0x00000001));
+    }
+
+    #[test]
+    fn test_version_downgrade() {
+        // 模拟攻击者篡改了版本协商，对端实际选择的版本与正在使用的版本不一致
+        let mut client_params = ClientParameters::default();
+        client_params.set_chosen_version(Some(0xff00001d));
+        let mut raw = Vec::new();
+        raw.put_client_parameters(&client_params);
+
+        let params = ArcParameters::new_server(ServerParameters::default());
+        params.initial_scid_from_peer_need_equal(ConnectionId::default());
+        params.chosen_version_from_peer_need_equal(0x00000001);
+        let error = params.recv_remote_params(&raw).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::VersionNegotiation);
+        assert_eq!(
+            error.reason(),
+            "chosen version from peer must be 0x00000001, but got 0xff00001d"
+        );
+        assert!(params.remote().is_none());
+    }
+
+    #[test]
+    fn test_invalid_version_information() {
+        // Available Versions不是4字节的整数倍
+        let raw = [0x11, 0x05, 0x00, 0x00, 0x00, 0x01, 0x00];
+        let params = ArcParameters::new_server(ServerParameters::default());
+        let error = params.recv_remote_params(&raw).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TransportParameter);
+    }
 }
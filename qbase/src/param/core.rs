@@ -5,8 +5,8 @@ use getset::{CopyGetters, Setters};
 use nom::combinator::map;
 
 use super::{
-    be_parameter_id, be_preferred_address, ParameterId, PreferredAddress, WirtePreferredAddress,
-    WriteParameterId,
+    be_parameter_id, be_preferred_address, be_version_information, ParameterId, PreferredAddress,
+    WirtePreferredAddress, WriteParameterId,
 };
 use crate::{
     cid::{be_connection_id_with_len, ConnectionId, WriteConnectionId},
@@ -48,6 +48,9 @@ pub struct CommonParameters {
     // TOOD: 对此传输参数的支持
     #[getset(get_copy = "pub", set = "pub")]
     pub(super) grease_quic_bit: bool,
+    /// The Chosen Version of the version_information transport parameter, absent if `None`.
+    #[getset(get_copy = "pub", set = "pub")]
+    pub(super) chosen_version: Option<u32>,
 }
 
 impl Default for CommonParameters {
@@ -77,17 +80,23 @@ impl Default for CommonParameters {
             initial_max_streams_uni: VarInt::default(),
             initial_source_connection_id: ConnectionId::default(),
             grease_quic_bit: false,
+            chosen_version: None,
         }
     }
 }
 
 impl CommonParameters {
+    /// Set the max_udp_payload_size transport parameter.
+    ///
+    /// Values below 1200 are invalid, the peer would close the connection with the
+    /// TRANSPORT_PARAMETER_ERROR, so they are clamped up to 1200.
     pub fn set_max_udp_payload_size(&mut self, size: u32) -> &mut Self {
-        assert!(
-            size >= 1200,
-            "Values below 1200 are invalid for max_udp_payload_size"
-        );
-        self.max_udp_payload_size = VarInt::from_u32(size);
+        if size < 1200 {
+            log::warn!(
+                "Values below 1200 are invalid for max_udp_payload_size, {size} is clamped up to 1200"
+            );
+        }
+        self.max_udp_payload_size = VarInt::from_u32(size.max(1200));
         self
     }
 
@@ -143,6 +152,8 @@ pub trait WriteCommonParameters: bytes::BufMut {
 
     fn put_cid_parameter(&mut self, id: ParameterId, cid: &ConnectionId);
 
+    fn put_version_information_parameter(&mut self, id: ParameterId, chosen_version: u32);
+
     fn put_common_parameters(&mut self, params: &CommonParameters);
 }
 
@@ -158,11 +169,22 @@ impl<T: bytes::BufMut> WriteCommonParameters for T {
         self.put_connection_id(value);
     }
 
+    fn put_version_information_parameter(&mut self, id: ParameterId, chosen_version: u32) {
+        self.put_parameter_id(id);
+        self.put_varint(&VarInt::from_u32(8));
+        self.put_u32(chosen_version);
+        // 只支持Chosen Version这一个版本，Available Versions中也只有它
+        self.put_u32(chosen_version);
+    }
+
     fn put_common_parameters(&mut self, parameters: &CommonParameters) {
         self.put_varint_parameter(
             ParameterId::MaxIdleTimeout,
-            VarInt::from_u64(parameters.max_idle_timeout.as_secs())
-                .expect("max_idle timeout can not exceed 2^62 seconds"),
+            // 以毫秒为单位，见RFC 9000 Section 18.2
+            u64::try_from(parameters.max_idle_timeout.as_millis())
+                .ok()
+                .and_then(|millis| VarInt::from_u64(millis).ok())
+                .expect("max_idle timeout can not exceed 2^62 milliseconds"),
         );
         self.put_varint_parameter(
             ParameterId::MaxUdpPayloadSize,
@@ -211,6 +233,9 @@ impl<T: bytes::BufMut> WriteCommonParameters for T {
             self.put_parameter_id(ParameterId::GreaseQuicBit);
             self.put_varint(&VarInt::from_u32(0));
         }
+        if let Some(version) = parameters.chosen_version {
+            self.put_version_information_parameter(ParameterId::VersionInformation, version);
+        }
     }
 }
 
@@ -227,7 +252,7 @@ pub(super) fn be_client_parameters<'b>(
         match id {
             ParameterId::MaxIdleTimeout => {
                 (input, params.max_idle_timeout) =
-                    map(be_varint, |v| Duration::from_secs(v.into_inner()))(remain)?
+                    map(be_varint, |v| Duration::from_millis(v.into_inner()))(remain)?
             }
             ParameterId::MaxUdpPayloadSize => {
                 (input, params.max_udp_payload_size) = be_varint(remain)?
@@ -266,6 +291,11 @@ pub(super) fn be_client_parameters<'b>(
                 (input, params.max_datagram_frame_size) = be_varint(remain)?
             }
             ParameterId::GreaseQuicBit => (input, params.grease_quic_bit) = (remain, true),
+            ParameterId::VersionInformation => {
+                (input, params.chosen_version) =
+                    be_version_information(remain, len.into_inner() as usize)
+                        .map(|(remain, version)| (remain, Some(version)))?
+            }
             _ => {
                 return Err(nom::Err::Failure(nom::error::Error::new(
                     input,
@@ -337,7 +367,7 @@ pub(super) fn be_server_parameters<'b>(
             }
             ParameterId::MaxIdleTimeout => {
                 (input, params.max_idle_timeout) =
-                    map(be_varint, |v| Duration::from_secs(v.into_inner()))(remain)?
+                    map(be_varint, |v| Duration::from_millis(v.into_inner()))(remain)?
             }
             ParameterId::StatelssResetToken => {
                 (input, params.statelss_reset_token) =
@@ -389,6 +419,11 @@ pub(super) fn be_server_parameters<'b>(
                 (input, params.max_datagram_frame_size) = be_varint(remain)?
             }
             ParameterId::GreaseQuicBit => (input, params.grease_quic_bit) = (remain, true),
+            ParameterId::VersionInformation => {
+                (input, params.chosen_version) =
+                    be_version_information(remain, len.into_inner() as usize)
+                        .map(|(remain, version)| (remain, Some(version)))?
+            }
         }
     }
     Ok((input, ()))
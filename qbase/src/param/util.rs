@@ -29,6 +29,7 @@ pub enum ParameterId {
     RetrySourceConnectionId,
     MaxDatagramFrameSize,
     GreaseQuicBit,
+    VersionInformation,
 }
 
 impl From<ParameterId> for VarInt {
@@ -53,6 +54,7 @@ impl From<ParameterId> for VarInt {
             ParameterId::RetrySourceConnectionId => 0x10,
             ParameterId::MaxDatagramFrameSize => 0x20,
             ParameterId::GreaseQuicBit => 0x2a_b2,
+            ParameterId::VersionInformation => 0x11,
         })
     }
 }
@@ -79,6 +81,7 @@ pub(super) fn be_parameter_id(input: &[u8]) -> nom::IResult<&[u8], ParameterId>
         0x10 => ParameterId::RetrySourceConnectionId,
         0x20 => ParameterId::MaxDatagramFrameSize,
         0x2a_b2 => ParameterId::GreaseQuicBit,
+        0x11 => ParameterId::VersionInformation,
         _ => {
             return Err(nom::Err::Error(nom::error::Error::new(
                 input,
@@ -99,6 +102,25 @@ impl<T: bytes::BufMut> WriteParameterId for T {
     }
 }
 
+/// Parse the version_information transport parameter of `len` bytes, return the Chosen Version.
+///
+/// The Available Versions are skipped, only the version 1 is supported, there is no other
+/// version to upgrade to. See [RFC 9368](https://www.rfc-editor.org/rfc/rfc9368#section-3).
+pub(super) fn be_version_information(input: &[u8], len: usize) -> nom::IResult<&[u8], u32> {
+    use nom::{bytes::streaming::take, number::streaming::be_u32};
+
+    // Chosen Version和所有的Available Versions都是32位的版本号
+    if len < 4 || !len.is_multiple_of(4) {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::LengthValue,
+        )));
+    }
+    let (remain, versions) = take(len)(input)?;
+    let (_, chosen_version) = be_u32(versions)?;
+    Ok((remain, chosen_version))
+}
+
 #[derive(Getters, Setters, MutGetters, Debug, PartialEq, Clone, Copy)]
 pub struct PreferredAddress {
     #[getset(get_copy = "pub", set = "pub")]
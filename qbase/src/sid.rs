@@ -1,4 +1,4 @@
-use std::{fmt, ops};
+use std::{fmt, ops, time::Instant};
 
 use super::{
     frame::MaxStreamsFrame,
@@ -249,6 +249,23 @@ pub trait ControlConcurrency: fmt::Debug + Send + Sync {
     /// If None is returned, it means there is no need to increase
     /// the MAX_STREAMS for the time being.
     fn on_streams_blocked(&mut self, dir: Dir, max_streams: u64) -> Option<u64>;
+
+    /// Returns the instant at which the MAX_STREAMS limit held back by the strategy should be
+    /// granted, even if no other callback happens before it, such as the peer never sends the
+    /// StreamsBlocked frame.
+    ///
+    /// If None is returned, nothing is held back. By default, nothing is ever held back.
+    fn deadline(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Called back once the instant returned by [`ControlConcurrency::deadline`] is reached.
+    ///
+    /// It may optionally return the increased value of the held back `max_streams` for the `dir`
+    /// directional streams.
+    fn on_deadline(&mut self, _dir: Dir) -> Option<u64> {
+        None
+    }
 }
 
 pub mod handy;
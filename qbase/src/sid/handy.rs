@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use super::{ControlConcurrency, Dir};
 
 /// Consistent concurrency strategy increase limits as streams are closed,
@@ -51,3 +53,161 @@ impl ControlConcurrency for DemandConcurrency {
         Some(max_streams + 1)
     }
 }
+
+/// Limits the rate of the streams accepted from the peer, on top of another strategy.
+///
+/// A peer could open and immediately close streams rapidly to force allocation churn. Once more
+/// than `max_per_period` new streams of a direction are accepted in a `period`, such as about one
+/// RTT, the increases of the MAX_STREAMS limit issued by the `inner` strategy are withheld. The
+/// withheld limit is granted once the period elapsed, on the next callback such as the peer is
+/// blocked and sends a [`StreamsBlockedFrame`](crate::frame::StreamsBlockedFrame), or at the
+/// latest on the [deadline](ControlConcurrency::deadline) if the peer sends nothing.
+#[derive(Debug)]
+pub struct RateLimitedConcurrency<C> {
+    inner: C,
+    max_per_period: u64,
+    period: Duration,
+    // 当前周期的开始时间，及周期内接受的新流数目
+    period_start: [Instant; 2],
+    accepted: [u64; 2],
+    // 下一个未被接受的流的序号
+    next_sid: [u64; 2],
+    // 被扣留的MAX_STREAMS上限
+    withheld: [Option<u64>; 2],
+}
+
+impl<C: ControlConcurrency> RateLimitedConcurrency<C> {
+    pub fn new(inner: C, max_per_period: u64, period: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            inner,
+            max_per_period,
+            period,
+            period_start: [now, now],
+            accepted: [0, 0],
+            next_sid: [0, 0],
+            withheld: [None, None],
+        }
+    }
+
+    fn refresh_period(&mut self, idx: usize) {
+        let now = Instant::now();
+        if now.duration_since(self.period_start[idx]) >= self.period {
+            self.period_start[idx] = now;
+            self.accepted[idx] = 0;
+        }
+    }
+
+    fn limit(&mut self, idx: usize, max_streams: Option<u64>) -> Option<u64> {
+        if let Some(max_streams) = max_streams {
+            let withheld = self.withheld[idx].get_or_insert(max_streams);
+            *withheld = (*withheld).max(max_streams);
+        }
+        if self.accepted[idx] >= self.max_per_period {
+            if self.withheld[idx].is_some() {
+                log::debug!("too many streams accepted in a period, withhold the MAX_STREAMS");
+            }
+            return None;
+        }
+        self.withheld[idx].take()
+    }
+}
+
+impl<C: ControlConcurrency> ControlConcurrency for RateLimitedConcurrency<C> {
+    fn on_accept_streams(&mut self, dir: Dir, sid: u64) -> Option<u64> {
+        let idx = dir as usize;
+        self.refresh_period(idx);
+        if sid >= self.next_sid[idx] {
+            self.accepted[idx] += sid + 1 - self.next_sid[idx];
+            self.next_sid[idx] = sid + 1;
+        }
+        let max_streams = self.inner.on_accept_streams(dir, sid);
+        self.limit(idx, max_streams)
+    }
+
+    fn on_end_of_stream(&mut self, dir: Dir, sid: u64) -> Option<u64> {
+        let idx = dir as usize;
+        self.refresh_period(idx);
+        let max_streams = self.inner.on_end_of_stream(dir, sid);
+        self.limit(idx, max_streams)
+    }
+
+    fn on_streams_blocked(&mut self, dir: Dir, max_streams: u64) -> Option<u64> {
+        let idx = dir as usize;
+        self.refresh_period(idx);
+        let max_streams = self.inner.on_streams_blocked(dir, max_streams);
+        self.limit(idx, max_streams)
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        // 被扣留的上限，在其所在周期结束时下发
+        let withheld = (0..2)
+            .filter(|&idx| self.withheld[idx].is_some())
+            .map(|idx| self.period_start[idx] + self.period);
+        withheld.chain(self.inner.deadline()).min()
+    }
+
+    fn on_deadline(&mut self, dir: Dir) -> Option<u64> {
+        let idx = dir as usize;
+        self.refresh_period(idx);
+        let max_streams = self.inner.on_deadline(dir);
+        self.limit(idx, max_streams)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limited() {
+        let period = Duration::from_millis(50);
+        let inner = ConsistentConcurrency::new(4, 0);
+        let mut ctrl = RateLimitedConcurrency::new(inner, 4, period);
+
+        // 对端快速地打开又关闭流，前4条流的上限照常增加
+        for sid in 0..4 {
+            assert_eq!(ctrl.on_accept_streams(Dir::Bi, sid), None);
+            let max_streams = ctrl.on_end_of_stream(Dir::Bi, sid);
+            if sid < 3 {
+                assert_eq!(max_streams, Some(5 + sid));
+            } else {
+                // 第4条流达到了速率上限
+                assert_eq!(max_streams, None);
+            }
+        }
+        // 超出速率的流，上限的增加被扣留
+        for sid in 4..8 {
+            assert_eq!(ctrl.on_accept_streams(Dir::Bi, sid), None);
+            assert_eq!(ctrl.on_end_of_stream(Dir::Bi, sid), None);
+        }
+        assert_eq!(ctrl.on_streams_blocked(Dir::Bi, 12), None);
+        // 单向流不受影响
+        assert_eq!(ctrl.on_accept_streams(Dir::Uni, 0), None);
+        assert_eq!(ctrl.on_end_of_stream(Dir::Uni, 0), Some(1));
+
+        // 周期过后，被扣留的上限在对端受阻时下发
+        std::thread::sleep(period);
+        assert_eq!(ctrl.on_streams_blocked(Dir::Bi, 12), Some(12));
+    }
+
+    #[test]
+    fn test_withheld_until_deadline() {
+        let period = Duration::from_millis(50);
+        let inner = ConsistentConcurrency::new(1, 0);
+        let mut ctrl = RateLimitedConcurrency::new(inner, 1, period);
+        assert_eq!(ctrl.deadline(), None);
+
+        assert_eq!(ctrl.on_accept_streams(Dir::Bi, 0), None);
+        assert_eq!(ctrl.on_end_of_stream(Dir::Bi, 0), None);
+        // 上限被扣留，到周期结束时须下发
+        let deadline = ctrl.deadline().unwrap();
+        assert!(deadline > Instant::now());
+        assert_eq!(ctrl.on_deadline(Dir::Bi), None);
+
+        // 对端从不发送StreamsBlocked，到期后被扣留的上限照样下发
+        std::thread::sleep(deadline - Instant::now());
+        assert_eq!(ctrl.on_deadline(Dir::Bi), Some(2));
+        assert_eq!(ctrl.deadline(), None);
+    }
+}
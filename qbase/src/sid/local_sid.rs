@@ -60,6 +60,16 @@ where
         }
     }
 
+    fn available_streams(&self, dir: Dir) -> u64 {
+        let idx = dir as usize;
+        self.max[idx].saturating_sub(self.unallocated[idx])
+    }
+
+    fn is_allocated(&self, sid: StreamId) -> bool {
+        debug_assert_eq!(sid.role(), self.role);
+        sid.id() < self.unallocated[sid.dir() as usize]
+    }
+
     fn poll_alloc_sid(&mut self, cx: &mut Context<'_>, dir: Dir) -> Poll<Option<StreamId>> {
         let idx = dir as usize;
         let cur = &mut self.unallocated[idx];
@@ -128,6 +138,24 @@ where
         self.0.lock().unwrap().recv_max_streams_frame(frame);
     }
 
+    /// Returns how many new streams can be opened locally in the `dir` direction
+    /// without being blocked, that is, the maximum streams granted by the peer
+    /// minus the streams that have been opened.
+    ///
+    /// The number increases when the [`MaxStreamsFrame`](`crate::frame::MaxStreamsFrame`)
+    /// from peer raises the limit.
+    pub fn available_streams(&self, dir: Dir) -> u64 {
+        self.0.lock().unwrap().available_streams(dir)
+    }
+
+    /// Returns whether the local stream `sid` has been allocated, that is, the stream is opened.
+    ///
+    /// Receiving a frame from peer on a local stream that has not been opened yet is a
+    /// STREAM_STATE_ERROR.
+    pub fn is_allocated(&self, sid: StreamId) -> bool {
+        self.0.lock().unwrap().is_allocated(sid)
+    }
+
     /// Asynchronously allocate the next new [`StreamId`] in the `dir` direction.
     ///
     /// When the application layer wants to proactively open a new stream,
@@ -216,4 +244,27 @@ mod tests {
         assert_eq!(local.poll_alloc_sid(&mut cx, Dir::Uni), Poll::Pending);
         assert!(!local.0.lock().unwrap().wakers[1].is_empty());
     }
+
+    #[test]
+    fn test_available_streams() {
+        let local = ArcLocalStreamIds::new(Role::Server, 2, 1, StreamsBlockedFrameTx::default());
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(local.available_streams(Dir::Bi), 2);
+        assert_eq!(local.available_streams(Dir::Uni), 1);
+
+        assert!(local.poll_alloc_sid(&mut cx, Dir::Bi).is_ready());
+        assert_eq!(local.available_streams(Dir::Bi), 1);
+        assert!(local.poll_alloc_sid(&mut cx, Dir::Bi).is_ready());
+        assert_eq!(local.available_streams(Dir::Bi), 0);
+        assert!(local.poll_alloc_sid(&mut cx, Dir::Bi).is_pending());
+        assert_eq!(local.available_streams(Dir::Bi), 0);
+        assert_eq!(local.available_streams(Dir::Uni), 1);
+
+        local.recv_max_streams_frame(&MaxStreamsFrame::Bi(VarInt::from_u32(5)));
+        assert_eq!(local.available_streams(Dir::Bi), 3);
+        // 不增大限制的MAX_STREAMS帧被忽略
+        local.recv_max_streams_frame(&MaxStreamsFrame::Bi(VarInt::from_u32(4)));
+        assert_eq!(local.available_streams(Dir::Bi), 3);
+    }
 }
@@ -1,4 +1,8 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Instant,
+};
 
 use thiserror::Error;
 
@@ -58,6 +62,7 @@ struct RemoteStreamIds<MAX> {
     unallocated: [StreamId; 2],        // The stream ID that peer has not used
     ctrl: Box<dyn ControlConcurrency>, // The strategy to control the concurrency of streams
     max_tx: MAX,                       // The channel to send the MAX_STREAMS frame to peer
+    deadline_waker: Option<Waker>, // The task waiting for the deadline of the held back MAX_STREAMS
 }
 
 impl<MAX> RemoteStreamIds<MAX>
@@ -82,6 +87,7 @@ where
             ],
             ctrl,
             max_tx,
+            deadline_waker: None,
         }
     }
 
@@ -111,6 +117,7 @@ where
                         .expect("max_streams must be less than VARINT_MAX"),
                 )]);
             }
+            self.wake_deadline();
             Ok(AcceptSid::New(NeedCreate { start, end: sid }))
         }
     }
@@ -127,6 +134,7 @@ where
                 VarInt::from_u64(max_streams).expect("max_streams must be less than VARINT_MAX"),
             )]);
         }
+        self.wake_deadline();
     }
 
     fn recv_streams_blocked_frame(&mut self, frame: &StreamsBlockedFrame) {
@@ -141,6 +149,39 @@ where
                 VarInt::from_u64(max_streams).expect("max_streams must be less than VARINT_MAX"),
             )]);
         }
+        self.wake_deadline();
+    }
+
+    // 控制策略扣留了MAX_STREAMS上限时，唤醒等待的任务，使其在期限到达时下发
+    fn wake_deadline(&mut self) {
+        if self.ctrl.deadline().is_some() {
+            if let Some(waker) = self.deadline_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn poll_deadline(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        match self.ctrl.deadline() {
+            Some(deadline) => Poll::Ready(deadline),
+            None => {
+                self.deadline_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn on_deadline(&mut self) {
+        for dir in [Dir::Bi, Dir::Uni] {
+            if let Some(max_streams) = self.ctrl.on_deadline(dir) {
+                self.max[dir as usize] = max_streams;
+                self.max_tx.send_frame([MaxStreamsFrame::with(
+                    dir,
+                    VarInt::from_u64(max_streams)
+                        .expect("max_streams must be less than VARINT_MAX"),
+                )]);
+            }
+        }
     }
 }
 
@@ -223,6 +264,23 @@ where
     pub fn recv_streams_blocked_frame(&self, frame: &StreamsBlockedFrame) {
         self.0.lock().unwrap().recv_streams_blocked_frame(frame);
     }
+
+    /// Poll the instant at which the MAX_STREAMS held back by the [`ControlConcurrency`] should
+    /// be granted, see [`ControlConcurrency::deadline`].
+    ///
+    /// It's pending until the strategy holds back a MAX_STREAMS. Once the returned instant is
+    /// reached, [`ArcRemoteStreamIds::on_deadline`] should be called, otherwise the peer may be
+    /// blocked forever if it never sends a STREAMS_BLOCKED frame.
+    pub fn poll_deadline(&self, cx: &mut Context<'_>) -> Poll<Instant> {
+        self.0.lock().unwrap().poll_deadline(cx)
+    }
+
+    /// Called once the instant returned by [`ArcRemoteStreamIds::poll_deadline`] is reached,
+    /// the held back MAX_STREAMS frames will be sent if the strategy grants them.
+    #[inline]
+    pub fn on_deadline(&self) {
+        self.0.lock().unwrap().on_deadline();
+    }
 }
 
 impl<MAX> ReceiveFrame<StreamsBlockedFrame> for ArcRemoteStreamIds<MAX>
@@ -239,10 +297,15 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use deref_derive::Deref;
 
     use super::*;
-    use crate::{sid::handy::ConsistentConcurrency, util::ArcAsyncDeque};
+    use crate::{
+        sid::handy::{ConsistentConcurrency, RateLimitedConcurrency},
+        util::ArcAsyncDeque,
+    };
 
     #[derive(Clone, Deref, Default)]
     struct MaxStreamsFrameTx(ArcAsyncDeque<MaxStreamsFrame>);
@@ -302,4 +365,41 @@ mod tests {
         let result = remote.try_accept_sid(StreamId(65));
         assert_eq!(result, Err(ExceedLimitError(StreamId(65), 10)));
     }
+
+    #[test]
+    fn test_grant_withheld_on_deadline() {
+        let period = Duration::from_millis(50);
+        let max_tx = MaxStreamsFrameTx::default();
+        let remote = ArcRemoteStreamIds::new(
+            Role::Client,
+            1,
+            0,
+            max_tx.clone(),
+            Box::new(RateLimitedConcurrency::new(
+                ConsistentConcurrency::new(1, 0),
+                1,
+                period,
+            )),
+        );
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert!(remote.poll_deadline(&mut cx).is_pending());
+
+        // 对端打开又关闭了一条流，达到了速率上限，MAX_STREAMS被扣留
+        remote.try_accept_sid(StreamId(0)).unwrap();
+        remote.on_end_of_stream(StreamId(0));
+        assert!(max_tx.is_empty());
+        let Poll::Ready(deadline) = remote.poll_deadline(&mut cx) else {
+            panic!("the withheld MAX_STREAMS should have a deadline");
+        };
+
+        // 对端从不发送StreamsBlocked，到期后被扣留的MAX_STREAMS照样下发
+        std::thread::sleep(deadline - Instant::now());
+        remote.on_deadline();
+        assert_eq!(
+            max_tx.poll_pop(&mut cx),
+            Poll::Ready(Some(MaxStreamsFrame::with(Dir::Bi, VarInt::from_u32(2))))
+        );
+        assert!(remote.poll_deadline(&mut cx).is_pending());
+        assert_eq!(remote.try_accept_sid(StreamId(4)).map(|_| ()), Ok(()));
+    }
 }
@@ -1,4 +1,8 @@
-use std::{ops::Deref, sync::Arc};
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
 
 use bytes::BufMut;
 use nom::{bytes::complete::take, IResult};
@@ -59,6 +63,28 @@ pub trait TokenSink: Send + Sync {
     fn fetch_token(&self, server_name: &str) -> Vec<u8>;
 }
 
+/// A [`TokenSink`] keeps the tokens in memory, the tokens are lost once it's dropped.
+///
+/// Only the last token received from each server is kept. A token is taken away once it's fetched,
+/// so that it's not reused for different connection attempts, as suggested by
+/// [Section 8.1.3](https://www.rfc-editor.org/rfc/rfc9000.html#section-8.1.3) of QUIC.
+#[derive(Debug, Default)]
+pub struct MemoryTokenSink(Mutex<HashMap<String, Vec<u8>>>);
+
+impl TokenSink for MemoryTokenSink {
+    fn sink(&self, server_name: &str, token: Vec<u8>) {
+        self.0.lock().unwrap().insert(server_name.to_owned(), token);
+    }
+
+    fn fetch_token(&self, server_name: &str) -> Vec<u8> {
+        self.0
+            .lock()
+            .unwrap()
+            .remove(server_name)
+            .unwrap_or_default()
+    }
+}
+
 pub trait TokenProvider: Send + Sync {
     fn gen_new_token(&self, server_name: &str) -> Vec<u8>;
 
@@ -184,6 +210,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_memory_token_sink() {
+        use std::sync::Arc;
+
+        use super::{ArcTokenRegistry, MemoryTokenSink, TokenSink};
+        use crate::frame::{NewTokenFrame, ReceiveFrame};
+
+        let sink = Arc::new(MemoryTokenSink::default());
+        let registry = ArcTokenRegistry::with_sink("example.com".to_owned(), sink.clone());
+        registry
+            .recv_frame(&NewTokenFrame {
+                token: vec![1, 2, 3],
+            })
+            .unwrap();
+
+        assert!(sink.fetch_token("example.org").is_empty());
+        assert_eq!(sink.fetch_token("example.com"), vec![1, 2, 3]);
+        // 令牌只能使用一次
+        assert!(sink.fetch_token("example.com").is_empty());
+    }
+
     #[test]
     fn test_write_reset_token() {
         use super::WriteResetToken;
@@ -1,6 +1,11 @@
 mod async_deque;
 pub use async_deque::ArcAsyncDeque;
 
+#[cfg(any(test, feature = "test-util"))]
+mod count_waker;
+#[cfg(any(test, feature = "test-util"))]
+pub use count_waker::CountWaker;
+
 mod data;
 pub use data::{DescribeData, WriteData};
 
@@ -0,0 +1,26 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::Wake,
+};
+
+/// A waker that counts how many times it has been woken, for testing the wake-ups of the tasks.
+///
+/// Wrap it in an [`Arc`], then convert it into a [`Waker`](std::task::Waker).
+#[derive(Debug, Default)]
+pub struct CountWaker(AtomicUsize);
+
+impl CountWaker {
+    /// Return how many times the waker has been woken.
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+impl Wake for CountWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::AcqRel);
+    }
+}
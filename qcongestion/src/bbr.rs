@@ -203,7 +203,9 @@ impl Algorithm for Bbr {
         self.newly_acked_bytes = 0;
         self.newly_lost_bytes = 0;
         self.packet_delivered = 0;
-        self.last_ack_packet_sent_time = now;
+        // 新确认的包中最晚发出的，确认了恢复期开始之后发出的包，才能退出恢复期
+        self.last_ack_packet_sent_time =
+            packets.iter().map(|ack| ack.time_sent).max().unwrap_or(now);
         self.prior_bytes_in_flight = self.bytes_in_flight;
         self.ack_time = now;
 
@@ -237,6 +239,14 @@ impl Algorithm for Bbr {
         // update newly lost bytes, set BBR.packet_conservation = true
     }
 
+    fn on_ecn_ce(&mut self, sent_time: Instant, now: Instant) {
+        // 恢复期开始之前发出的包被标记了CE，属于同一次拥塞事件，不再响应
+        if self.recovery_epoch_start.is_some_and(|t| sent_time <= t) {
+            return;
+        }
+        self.enter_recovery(now);
+    }
+
     fn cwnd(&self) -> u64 {
         self.cwnd
     }
@@ -268,6 +278,19 @@ impl Bbr {
         self.set_cwnd();
     }
 
+    // 4.2.3.4 Modulating cwnd in Loss Recovery
+    // Upon entering recovery, save the cwnd and reduce it to the data in flight, then keep
+    // packet conservation for a round trip. The cwnd is restored when the recovery ends.
+    fn enter_recovery(&mut self, now: Instant) {
+        self.save_cwnd();
+        self.in_recovery = true;
+        self.recovery_epoch_start = Some(now);
+        self.packet_conservation = true;
+        self.cwnd = self
+            .bytes_in_flight
+            .max((MSS * MINIMUM_WINDOW_PACKETS) as u64);
+    }
+
     // 3.5.3.  Per-Transmit Steps
     fn on_transmit(&mut self) {
         self.handle_restart_from_idle();
@@ -375,7 +398,7 @@ mod tests {
             };
             bbr.on_sent(&mut sent, 0, start_time);
 
-            let mut ack: AckedPkt = sent.into();
+            let mut ack = AckedPkt::new(sent, start_time);
             ack.rtt = rtt;
             acks.push_back(ack);
         }
@@ -0,0 +1,66 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The source of the current time for the congestion controller.
+///
+/// The pacing, the loss detection timer and the RTT samples of [`ArcCC`] are all based on the
+/// time it returns. By default the wall time [`SystemClock`] is used, a [`ManualClock`] can be
+/// supplied instead to run the real congestion controller in a deterministic simulation.
+///
+/// [`ArcCC`]: crate::ArcCC
+pub trait Clock: Send + Sync {
+    /// Return the current time.
+    fn now(&self) -> Instant;
+}
+
+/// The wall time clock, which is [`Instant::now`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves forward when it is advanced.
+///
+/// All the clones share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock(Arc<Mutex<Instant>>);
+
+impl ManualClock {
+    /// Create a clock stopped at `start`.
+    pub fn new(start: Instant) -> Self {
+        Self(Arc::new(Mutex::new(start)))
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let start = Instant::now();
+        let clock = ManualClock::new(start);
+        let cloned = clock.clone();
+        assert_eq!(clock.now(), start);
+
+        cloned.advance(Duration::from_millis(10));
+        assert_eq!(clock.now(), start + Duration::from_millis(10));
+    }
+}
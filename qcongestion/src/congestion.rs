@@ -6,33 +6,42 @@ use std::{
     time::{Duration, Instant},
 };
 
-use qbase::{
-    frame::{AckFrame, EcnCounts},
-    handshake::Handshake,
-    sid::Role,
-    Epoch,
-};
+use qbase::{frame::AckFrame, handshake::Handshake, sid::Role, Epoch};
 use qrecovery::reliable::ArcReliableFrameDeque;
 
 use crate::{
     bbr::{self, INITIAL_CWND},
+    ecn::EcnValidator,
+    mtu::MtuDetector,
     new_reno::NewReno,
     pacing::{self, Pacer},
-    rtt::{ArcRtt, INITIAL_RTT},
-    TrackPackets,
+    rtt::ArcRtt,
+    Clock, SystemClock, TrackPackets,
 };
 
 const K_GRANULARITY: Duration = Duration::from_millis(1);
 const K_PACKET_THRESHOLD: usize = 3;
 const MAX_SENT_DELAY: Duration = Duration::from_millis(30);
+// PTO触发后，最多可发送2个不受拥塞控制限制的探测包，见RFC 9002 Section 6.2.4
+const K_MAX_PROBES: u8 = 2;
+// 每个空间最多记录的已判定丢失的包，用于识别虚假丢包
+const MAX_LOST_RECORDS: usize = 256;
 
 ///  default datagram size in bytes.
 pub const MSS: usize = 1200;
 
 /// The [`CongestionAlgorithm`] enum represents different congestion control algorithms that can be used.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CongestionAlgorithm {
     Bbr,
+    /// NewReno, reducing the congestion window by half on a congestion event.
     NewReno,
+    /// NewReno with a custom multiplicative decrease factor, aka beta, which must be in (0, 1).
+    ///
+    /// The congestion window is reduced to `beta` times on a congestion event, a larger beta is
+    /// more aggressive. [`NewReno`](CongestionAlgorithm::NewReno) uses the RFC value
+    /// [`LOSS_REDUCTION_FACTOR`](crate::LOSS_REDUCTION_FACTOR).
+    NewRenoWithBeta(f64),
 }
 
 /// Imple RFC 9002 Appendix A. Loss Recovery
@@ -45,7 +54,13 @@ pub struct CongestionController {
     // The number of times a PTO has been sent without receiving an acknowledgment.
     // Use to pto backoff
     pto_count: u32,
+    // The number of probe packets that can still be sent regardless of the congestion window.
+    probes: u8,
+    // Whether there are urgent control frames to send, which are not delayed by the pacer.
+    urgent: bool,
     max_ack_delay: Duration,
+    // 对端的ack_delay_exponent传输参数，对端AckFrame中的ACK Delay以2^ack_delay_exponent微秒为单位
+    peer_ack_delay_exponent: u8,
     // The time the most recent ack-eliciting packet was sent.
     time_of_last_ack_eliciting_packet: [Option<Instant>; Epoch::count()],
     // The largest packet number acknowledged in the packet number space so far.
@@ -55,54 +70,78 @@ pub struct CongestionController {
     loss_time: [Option<Instant>; Epoch::count()],
     // record sent packets, remove it when receive ack.
     sent_packets: [VecDeque<SentPkt>; Epoch::count()],
+    // The packet numbers recently declared lost, to detect the spurious losses.
+    lost_packets: [VecDeque<u64>; Epoch::count()],
+    // The loss and retransmission statistics of each epoch.
+    loss_stats: [LossStats; Epoch::count()],
     // pacer is used to control the burst rate
     pacer: pacing::Pacer,
     // The time the last packet was sent.
     last_sent_time: Instant,
     // Records of received packets for each epoch.
     rcvd_records: [RcvdRecords; Epoch::count()],
-    // The waker to notify when the controller is ready to send.
-    send_waker: Option<Waker>,
+    // The wakers of the tasks to notify when the controller is ready to send. Usually only the
+    // sending task of the path polls it, but any number of tasks can.
+    send_wakers: Vec<Waker>,
     // Space packet trackers
     trackers: [Box<dyn TrackPackets>; 3],
     // Handshake state
     handshake: Handshake<ArcReliableFrameDeque>,
+    // ECN validation state of the path
+    ecn: EcnValidator,
+    // MTU black hole detection of the path
+    mtu: MtuDetector,
+    // The source of the current time
+    clock: Arc<dyn Clock>,
 }
 
 impl CongestionController {
     // A.4. Initialization
     fn new(
         algorithm: CongestionAlgorithm,
+        initial_rtt: Duration,
         max_ack_delay: Duration,
         trackers: [Box<dyn TrackPackets>; 3],
         handshake: Handshake<ArcReliableFrameDeque>,
+        clock: Arc<dyn Clock>,
     ) -> Self {
         let algorithm: Box<dyn Algorithm> = match algorithm {
             CongestionAlgorithm::Bbr => Box::new(bbr::Bbr::new()),
             CongestionAlgorithm::NewReno => Box::new(NewReno::new()),
+            CongestionAlgorithm::NewRenoWithBeta(beta) => {
+                Box::new(NewReno::with_loss_reduction_factor(beta))
+            }
         };
 
-        let now = Instant::now();
+        let now = clock.now();
         CongestionController {
             algorithm,
-            rtt: ArcRtt::new(),
+            rtt: ArcRtt::new(initial_rtt, max_ack_delay),
             loss_timer: LossDetectionTimer::default(),
             max_ack_delay,
+            peer_ack_delay_exponent: 3,
             pto_count: 0,
+            probes: 0,
+            urgent: false,
             time_of_last_ack_eliciting_packet: [None, None, None],
             largest_acked_packet: [None, None, None],
             loss_time: [None, None, None],
             sent_packets: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            lost_packets: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            loss_stats: [LossStats::default(); Epoch::count()],
             rcvd_records: [
                 RcvdRecords::new(Epoch::Initial),
                 RcvdRecords::new(Epoch::Handshake),
                 RcvdRecords::new(Epoch::Data),
             ],
-            pacer: Pacer::new(INITIAL_RTT, INITIAL_CWND, MSS, now, None),
+            pacer: Pacer::new(initial_rtt, INITIAL_CWND, MSS, now, None),
             last_sent_time: now,
-            send_waker: None,
+            send_wakers: Vec::new(),
             trackers,
             handshake,
+            ecn: EcnValidator::default(),
+            mtu: MtuDetector::default(),
+            clock,
         }
     }
 
@@ -116,13 +155,18 @@ impl CongestionController {
         sent_bytes: usize,
         now: Instant,
     ) {
+        // 紧急的控制帧已随本数据包发出
+        self.urgent = false;
         let mut sent = SentPkt::new(pn, sent_bytes, now);
+        sent.ack_eliciting = ack_eliciting && in_flight;
+        sent.ect0 = self.ecn.on_pkt_sent(space);
         if in_flight {
             if ack_eliciting {
                 self.time_of_last_ack_eliciting_packet[space] = Some(now);
+                // 探测包与普通数据包一样记录，被确认时同样更新RTT和拥塞窗口
+                self.probes = self.probes.saturating_sub(1);
             }
             self.algorithm.on_sent(&mut sent, sent_bytes, now);
-            self.set_loss_timer();
         }
 
         // Ensure that the packet number is greater than the last sent packet number for the given epoch.
@@ -131,6 +175,10 @@ impl CongestionController {
         }
         self.sent_packets[space].push_back(sent);
         self.pacer.on_sent(sent_bytes as u64);
+        // 须在记录了该包之后再设置定时器，否则在途的首个ack-eliciting包不会启动PTO定时器
+        if in_flight {
+            self.set_loss_timer();
+        }
     }
 
     // A.6. On Receiving a Datagram
@@ -143,6 +191,12 @@ impl CongestionController {
         }
     }
 
+    // See [Section 19.3](https://www.rfc-editor.org/rfc/rfc9000.html#section-19.3) of QUIC
+    fn decode_ack_delay(&self, ack_frame: &AckFrame) -> Duration {
+        let scale = 1u64 << self.peer_ack_delay_exponent;
+        Duration::from_micros(ack_frame.delay.into_inner().saturating_mul(scale))
+    }
+
     // A.7. On Receiving an Acknowledgment
     pub fn on_ack_rcvd(&mut self, space: Epoch, ack_frame: &AckFrame, now: Instant) {
         let largest_acked: u64 = ack_frame.largest.into();
@@ -150,21 +204,40 @@ impl CongestionController {
         self.largest_acked_packet[space] =
             Some(largest_acked.max(self.largest_acked_packet[space].unwrap_or(0)));
 
-        let (newly_acked_packets, latest_rtt) = self.get_newly_acked_packets(space, ack_frame);
+        let (newly_acked_packets, latest_rtt) = self.get_newly_acked_packets(space, ack_frame, now);
         if newly_acked_packets.is_empty() {
             return;
         }
+        // 收到了新的确认，不再需要探测
+        self.probes = 0;
 
-        let ack_delay = Duration::from_millis(ack_frame.delay.into());
+        let ack_delay = self.decode_ack_delay(ack_frame);
         if let Some(latest_rtt) = latest_rtt {
-            let is_handshake_confirmed = self.handshake.is_handshake_done();
+            let is_handshake_confirmed = self.handshake.is_handshake_confirmed();
             self.rtt
                 .update(latest_rtt, ack_delay, is_handshake_confirmed);
         }
 
-        // Process ECN information if present.
-        if let Some(ecn) = ack_frame.ecn {
-            self.process_ecn(space, ecn)
+        // Validate ECN with the ECN counts, see RFC 9000 Section 13.4.2
+        let newly_acked_ect0 = newly_acked_packets.iter().filter(|p| p.ect0).count();
+        if self
+            .ecn
+            .on_ack_rcvd(space, newly_acked_ect0 as u64, ack_frame.ecn)
+        {
+            // 以最大的新确认包的发送时间进入拥塞恢复，see RFC 9002 Appendix B.7。
+            // 各个ACK Range内的包号是升序的，最前面的并不是包号最大的
+            let largest_acked = newly_acked_packets
+                .iter()
+                .max_by_key(|acked| acked.pn)
+                .expect("not empty");
+            self.algorithm.on_ecn_ce(largest_acked.time_sent, now);
+        }
+        for acked in &newly_acked_packets {
+            if space == Epoch::Data && self.mtu.is_probe(acked.pn) {
+                self.mtu.on_probe_acked();
+            } else {
+                self.mtu.on_pkt_acked(acked.size);
+            }
         }
 
         let lost_packets = self.remove_loss_packets(space, now);
@@ -183,10 +256,12 @@ impl CongestionController {
         &mut self,
         epoch: Epoch,
         ack_frame: &AckFrame,
+        now: Instant,
     ) -> (VecDeque<AckedPkt>, Option<Duration>) {
         let mut newly_acked_packets: VecDeque<AckedPkt> = VecDeque::new();
         let largest_acked: u64 = ack_frame.largest.into();
         let mut latest_rtt = None;
+        let mut includes_ack_eliciting = false;
         for range in ack_frame.iter() {
             for pn in range {
                 let acked: Option<AckedPkt> = self.sent_packets[epoch]
@@ -194,8 +269,10 @@ impl CongestionController {
                     .ok()
                     .map(|idx| {
                         self.rcvd_records[epoch].ack(pn, &self.trackers);
-                        self.sent_packets[epoch][idx].is_acked = true;
-                        self.sent_packets[epoch][idx].clone().into()
+                        let sent = &mut self.sent_packets[epoch][idx];
+                        sent.is_acked = true;
+                        includes_ack_eliciting |= sent.ack_eliciting;
+                        AckedPkt::new(sent.clone(), now)
                     });
                 if let Some(ack) = acked {
                     // largest is newly ackd, update latest_rtt
@@ -207,15 +284,43 @@ impl CongestionController {
             }
         }
         self.slide_sent_packets(epoch);
+        self.detect_spurious_losses(epoch, ack_frame);
+        // 仅确认了非ack-eliciting的包时，对端可能任意推迟确认，不能采样RTT，
+        // see [Section 5.1](https://www.rfc-editor.org/rfc/rfc9002.html#name-generating-rtt-samples) of RFC 9002
+        if !includes_ack_eliciting {
+            latest_rtt = None;
+        }
         (newly_acked_packets, latest_rtt)
     }
 
+    // 已判定丢失的包又被确认，说明丢包是误判的，可能是乱序阈值设置得太小
+    fn detect_spurious_losses(&mut self, epoch: Epoch, ack_frame: &AckFrame) {
+        let before = self.lost_packets[epoch].len();
+        self.lost_packets[epoch].retain(|pn| !ack_frame.iter().any(|range| range.contains(pn)));
+        self.loss_stats[epoch].spurious_losses += (before - self.lost_packets[epoch].len()) as u64;
+    }
+
     // A.8. Setting the Loss Detection Timer
     fn on_packets_lost(&mut self, packets: impl Iterator<Item = SentPkt>, epoch: Epoch) {
-        let now = Instant::now();
+        let now = self.clock.now();
         for lost in packets {
-            self.algorithm.on_congestion_event(&lost, now);
-            self.trackers[epoch].may_loss(lost.pn);
+            // PMTU探测包的丢失并不意味着拥塞，see RFC 9000 Section 14.4
+            if epoch == Epoch::Data && self.mtu.is_probe(lost.pn) {
+                self.mtu.on_probe_lost();
+            } else {
+                self.ecn.on_pkt_lost(lost.ect0);
+                self.mtu.on_pkt_lost(lost.size);
+                self.algorithm.on_congestion_event(&lost, now);
+            }
+            let frames = self.trackers[epoch].may_loss(lost.pn);
+
+            let stats = &mut self.loss_stats[epoch];
+            stats.lost_packets += 1;
+            stats.retransmitted_frames += frames as u64;
+            if self.lost_packets[epoch].len() == MAX_LOST_RECORDS {
+                self.lost_packets[epoch].pop_front();
+            }
+            self.lost_packets[epoch].push_back(lost.pn);
         }
     }
 
@@ -267,21 +372,38 @@ impl CongestionController {
             };
 
         self.pto_count += 1;
+        self.probes = K_MAX_PROBES;
         log::debug!(
             "PTO timeout, epoch: {:?}, pto_count: {}",
             pto_epoch,
             self.pto_count
         );
-        // Retransmit frames from the oldest sent packet. However
-        // these packets are not actually declared lost, so have no effect on
-        // congestion control, we just retransmit the data they carry.
-        let retransmit = self.sent_packets[pto_epoch]
-            .iter()
-            .take(self.pto_count as usize);
-
-        retransmit.for_each(|pkt| {
-            self.trackers[pto_epoch].may_loss(pkt.pn);
-        });
+        // Before the handshake is confirmed, both the Initial and the Handshake space may have
+        // unacknowledged data, probe each of them so that the probes could be coalesced, see
+        // RFC 9002 Section 6.2.4.
+        for &epoch in Epoch::iter() {
+            if epoch != pto_epoch
+                && (epoch == Epoch::Data || !self.has_ack_eliciting_in_flight(epoch))
+            {
+                continue;
+            }
+            // Retransmit frames from the oldest sent packet. However
+            // these packets are not actually declared lost, so have no effect on
+            // congestion control, we just retransmit the data they carry.
+            let retransmit = self.sent_packets[epoch]
+                .iter()
+                .filter(|pkt| !pkt.is_acked)
+                .take(self.pto_count as usize);
+
+            let frames: usize = retransmit
+                .map(|pkt| self.trackers[epoch].may_loss(pkt.pn))
+                .sum();
+            self.loss_stats[epoch].retransmitted_frames += frames as u64;
+            // 没有可重传的数据，比如客户端的防死锁探测，发送PING使探测包可被确认
+            if frames == 0 {
+                self.trackers[epoch].send_ping();
+            }
+        }
 
         self.set_loss_timer();
     }
@@ -305,7 +427,7 @@ impl CongestionController {
         let rttvar = self.rtt.rttvar();
         let mut duration = smoothed_rtt + std::cmp::max(K_GRANULARITY, rttvar * 4);
         // 握手已完成, 则应该考虑 max_ack_delay
-        if epoch == Epoch::Data && self.handshake.is_handshake_done() {
+        if epoch == Epoch::Data && self.handshake.is_handshake_confirmed() {
             duration += self.max_ack_delay
         }
         duration * 2_u32.pow(self.pto_count)
@@ -314,18 +436,18 @@ impl CongestionController {
     fn get_pto_timeout(&self) -> Option<(Instant, Epoch)> {
         let mut duration = self.get_pto_time(Epoch::Initial);
         if self.no_ack_eliciting_in_flight() {
-            return Some((Instant::now() + duration, Epoch::Initial));
+            return Some((self.clock.now() + duration, Epoch::Initial));
         }
 
         let mut pto_time = None;
         for &space in Epoch::iter() {
-            if self.time_of_last_ack_eliciting_packet[space].is_none() {
+            if !self.has_ack_eliciting_in_flight(space) {
                 continue;
             }
             if space == Epoch::Data {
                 // An endpoint MUST NOT set its PTO timer for the Application Data
                 // packet number space until the handshake is confirmed
-                if !self.handshake.is_handshake_done() {
+                if !self.handshake.is_handshake_confirmed() {
                     return pto_time;
                 }
                 duration += self.max_ack_delay * 2_u32.pow(self.pto_count);
@@ -396,21 +518,69 @@ impl CongestionController {
         }
     }
 
-    fn no_ack_eliciting_in_flight(&self) -> bool {
-        for space in Epoch::iter() {
-            if self.time_of_last_ack_eliciting_packet[*space].is_some() {
-                return false;
+    /// Return how many bytes can be sent now, or None if the sending is limited by the congestion
+    /// window or the pacer.
+    ///
+    /// The probe packets after a PTO are never blocked by the congestion controller, see
+    /// [Section 7.5](https://www.rfc-editor.org/rfc/rfc9002.html#name-probe-timeout) of RFC 9002.
+    /// Neither are the urgent control frames, see [`ArcCC::send_urgently`].
+    fn send_quota(&mut self, now: Instant) -> Option<usize> {
+        let srtt = self.rtt.smoothed_rtt();
+        let cwnd = self.algorithm.cwnd();
+        let mtu = self.mtu.mtu();
+        let rate = self.algorithm.pacing_rate();
+        let tokens = self.pacer.schedule(srtt, cwnd, mtu, now, rate);
+        if tokens >= mtu {
+            return Some(tokens);
+        }
+        if self.probes > 0 || self.urgent {
+            return Some(mtu);
+        }
+
+        let mut need_ack = false;
+        for &epoch in Epoch::iter() {
+            if self.rcvd_records[epoch]
+                .need_ack(self.max_ack_delay, now)
+                .is_some()
+            {
+                need_ack = true;
+                break;
             }
         }
-        true
+        // 1. 有 ack 要发送, 且距离上次发送时间大于 max ack dely
+        // 2. 距离上次发送时间大于 max sent delay
+        let elapsed = now.saturating_duration_since(self.last_sent_time);
+        if (need_ack && elapsed >= self.max_ack_delay) || elapsed >= MAX_SENT_DELAY {
+            return Some(tokens);
+        }
+        None
     }
 
-    fn server_completed_address_validation(&mut self) -> bool {
-        self.handshake.role() == Role::Server || self.handshake.is_handshake_done()
+    // 同一任务重复poll时只更新其waker，不同任务则都会被记下
+    fn register_sender(&mut self, waker: &Waker) {
+        match self.send_wakers.iter_mut().find(|w| w.will_wake(waker)) {
+            Some(registered) => registered.clone_from(waker),
+            None => self.send_wakers.push(waker.clone()),
+        }
+    }
+
+    fn wake_senders(&mut self) {
+        self.send_wakers.drain(..).for_each(Waker::wake);
+    }
+
+    fn has_ack_eliciting_in_flight(&self, space: Epoch) -> bool {
+        self.time_of_last_ack_eliciting_packet[space].is_some()
+            && self.sent_packets[space]
+                .iter()
+                .any(|sent| sent.ack_eliciting && !sent.is_acked)
+    }
+
+    fn no_ack_eliciting_in_flight(&self) -> bool {
+        !Epoch::iter().any(|&space| self.has_ack_eliciting_in_flight(space))
     }
 
-    fn process_ecn(&mut self, _: Epoch, _: EcnCounts) {
-        todo!()
+    fn server_completed_address_validation(&mut self) -> bool {
+        self.handshake.role() == Role::Server || self.handshake.is_handshake_confirmed()
     }
 }
 
@@ -419,72 +589,208 @@ impl CongestionController {
 pub struct ArcCC(Arc<Mutex<CongestionController>>);
 
 impl ArcCC {
+    /// Create a new congestion controller for a path.
+    ///
+    /// The `initial_rtt` is used to compute the PTO and the pacing rate until the first RTT sample
+    /// is taken, usually it's [`INITIAL_RTT`](crate::INITIAL_RTT).
     pub fn new(
         algorithm: CongestionAlgorithm,
+        initial_rtt: Duration,
+        max_ack_delay: Duration,
+        trackers: [Box<dyn TrackPackets>; 3],
+        handshake: Handshake<ArcReliableFrameDeque>,
+    ) -> Self {
+        Self::with_clock(
+            algorithm,
+            initial_rtt,
+            max_ack_delay,
+            trackers,
+            handshake,
+            Arc::new(SystemClock),
+        )
+    }
+
+    /// Create a new congestion controller for a path, whose timers and pacing are driven by the
+    /// `clock` rather than the wall time.
+    ///
+    /// It's useful to run reproducible congestion experiments, see [`ManualClock`](crate::ManualClock).
+    pub fn with_clock(
+        algorithm: CongestionAlgorithm,
+        initial_rtt: Duration,
         max_ack_delay: Duration,
         trackers: [Box<dyn TrackPackets>; 3],
         handshake: Handshake<ArcReliableFrameDeque>,
+        clock: Arc<dyn Clock>,
     ) -> Self {
         ArcCC(Arc::new(Mutex::new(CongestionController::new(
             algorithm,
+            initial_rtt,
             max_ack_delay,
             trackers,
             handshake,
+            clock,
         ))))
     }
 }
 
+impl ArcCC {
+    /// Take a snapshot of the ACK generation state of the `epoch` space.
+    ///
+    /// This is useful to debug why the ACK frames are not sent as expected.
+    pub fn ack_snapshot(&self, epoch: Epoch) -> AckSnapshot {
+        let guard = self.0.lock().unwrap();
+        guard.rcvd_records[epoch].snapshot(guard.max_ack_delay)
+    }
+
+    /// Return the loss and retransmission statistics of the `epoch` space.
+    pub fn loss_stats(&self, epoch: Epoch) -> LossStats {
+        self.0.lock().unwrap().loss_stats[epoch]
+    }
+
+    /// Return the smoothed RTT of the path, it's the initial RTT before the first RTT sample.
+    pub fn smoothed_rtt(&self) -> Duration {
+        self.0.lock().unwrap().rtt.smoothed_rtt()
+    }
+
+    /// Set the ack_delay_exponent transport parameter of the peer, the `ACK Delay` field of the
+    /// ACK frames received is decoded in units of 2^`exponent` microseconds.
+    ///
+    /// It's 3 before the transport parameters of the peer are received, as the default value.
+    pub fn set_peer_ack_delay_exponent(&self, exponent: u8) {
+        self.0.lock().unwrap().peer_ack_delay_exponent = exponent;
+    }
+
+    /// Return the congestion window of the path, in bytes.
+    pub fn cwnd(&self) -> u64 {
+        self.0.lock().unwrap().algorithm.cwnd()
+    }
+}
+
+impl ArcCC {
+    /// Return the largest datagram size that can be sent on the path now.
+    ///
+    /// It starts from [`MSS`], and falls back to [`MSS`] once an MTU black hole is detected.
+    pub fn mtu(&self) -> usize {
+        self.0.lock().unwrap().mtu.mtu()
+    }
+
+    /// Set the upper bound of the datagram size to probe on the path, usually the smaller one of
+    /// the local limit and the `max_udp_payload_size` transport parameter of the peer.
+    ///
+    /// The probe size never exceeds 1452 bytes, and no probe is sent before it's set.
+    pub fn set_max_mtu(&self, max_mtu: usize) {
+        self.0.lock().unwrap().mtu.set_max_mtu(max_mtu);
+    }
+
+    /// Return the size of the PMTU probe to send, if a probe is due.
+    ///
+    /// The probes are sent only after the handshake is confirmed, one at a time. The probe
+    /// should be sent in a datagram of its own, see [`ArcCC::on_mtu_probe_sent`].
+    pub fn mtu_probe(&self) -> Option<usize> {
+        let guard = self.0.lock().unwrap();
+        if !guard.handshake.is_handshake_confirmed() {
+            return None;
+        }
+        guard.mtu.probe_size()
+    }
+
+    /// Called after the 1-RTT packet `pn` is sent as a PMTU probe of `size` bytes.
+    ///
+    /// The packet must have been recorded by [`on_pkt_sent`](crate::CongestionControl::on_pkt_sent).
+    /// Once it's acknowledged, the [`mtu`](ArcCC::mtu) is raised to `size`, while its loss is not
+    /// taken as congestion.
+    pub fn on_mtu_probe_sent(&self, pn: u64, size: usize) {
+        self.0.lock().unwrap().mtu.on_probe_sent(pn, size);
+    }
+
+    /// Wake the sending task of the path immediately, rather than waiting for the next tick.
+    ///
+    /// The sending task will try to assemble and send the pending data right away, still
+    /// subject to the congestion control, the anti-amplification limit and the flow control.
+    pub fn wake_sending(&self) {
+        self.0.lock().unwrap().wake_senders();
+    }
+
+    /// Send the urgent control frames of the path right away, such as the PATH_RESPONSE frame.
+    ///
+    /// Unlike [`ArcCC::wake_sending`], the next datagram is not delayed by the pacer or the
+    /// congestion window. It's still subject to the anti-amplification limit, and needs a
+    /// connection ID to send on the path.
+    ///
+    /// The ACK frames that must be sent immediately, see [`AckSnapshot::immediate`], are sent
+    /// in the same way once the packet is received.
+    pub fn send_urgently(&self) {
+        let mut guard = self.0.lock().unwrap();
+        guard.urgent = true;
+        guard.wake_senders();
+    }
+
+    /// Called when an IMMEDIATE_ACK frame is received, which asks for an ACK frame without delay.
+    ///
+    /// The ACK frame of the data space is sent on the next opportunity, as if the packets are
+    /// received out of order, regardless of the `max_ack_delay`.
+    pub fn on_immediate_ack(&self) {
+        let mut guard = self.0.lock().unwrap();
+        guard.rcvd_records[Epoch::Data].need_ack = true;
+        guard.urgent = true;
+        guard.wake_senders();
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl ArcCC {
+    /// Raise the largest datagram size can be sent on the path, as if a PMTU probe of this size
+    /// is acknowledged.
+    ///
+    /// Smaller values than the current one are ignored.
+    pub fn raise_mtu(&self, mtu: usize) {
+        self.0.lock().unwrap().mtu.raise(mtu);
+    }
+}
+
+#[cfg(any(test, feature = "inflight-dump"))]
+impl ArcCC {
+    /// Return the packets in flight in the `epoch` space, for debugging the stalled connection.
+    ///
+    /// Each item is the packet number, the time the packet was sent, and whether it is
+    /// ack-eliciting.
+    pub fn inflight_packets(&self, epoch: Epoch) -> Vec<(u64, Instant, bool)> {
+        let guard = self.0.lock().unwrap();
+        guard.sent_packets[epoch]
+            .iter()
+            .filter(|sent| !sent.is_acked)
+            .map(|sent| (sent.pn, sent.time_sent, sent.ack_eliciting))
+            .collect()
+    }
+}
+
 impl super::CongestionControl for ArcCC {
     fn do_tick(&self) {
         let mut guard = self.0.lock().unwrap();
-        let now = Instant::now();
+        let now = guard.clock.now();
         if guard.loss_timer.is_timeout(now) {
             guard.on_loss_timeout(now);
         }
-        if let Some(waker) = guard.send_waker.take() {
-            waker.wake();
-        }
+        guard.wake_senders();
     }
 
     fn poll_send(&self, cx: &mut Context<'_>) -> Poll<usize> {
         let mut guard = self.0.lock().unwrap();
-        guard.send_waker = Some(cx.waker().clone());
-        let now = Instant::now();
+        guard.register_sender(cx.waker());
+        let now = guard.clock.now();
         if guard.loss_timer.is_timeout(now) {
             guard.on_loss_timeout(now);
         }
 
-        let srtt = guard.rtt.smoothed_rtt();
-        let cwnd = guard.algorithm.cwnd();
-        let mtu = MSS;
-        let rate = guard.algorithm.pacing_rate();
-        let tokens = guard.pacer.schedule(srtt, cwnd, mtu, now, rate);
-        if tokens >= mtu {
-            return Poll::Ready(tokens);
+        match guard.send_quota(now) {
+            Some(quota) => Poll::Ready(quota),
+            None => Poll::Pending,
         }
-
-        let mut need_ack = false;
-        for &epoch in Epoch::iter() {
-            if guard.rcvd_records[epoch]
-                .need_ack(guard.max_ack_delay)
-                .is_some()
-            {
-                need_ack = true;
-                break;
-            }
-        }
-        // 1. 有 ack 要发送, 且距离上次发送时间大于 max ack dely
-        // 2. 距离上次发送时间大于 max sent delay
-        let elapsed = now.saturating_duration_since(guard.last_sent_time);
-        if (need_ack && elapsed >= guard.max_ack_delay) || elapsed >= MAX_SENT_DELAY {
-            return Poll::Ready(tokens);
-        }
-        Poll::Pending
     }
 
     fn need_ack(&self, space: Epoch) -> Option<(u64, Instant)> {
         let guard = self.0.lock().unwrap();
-        guard.rcvd_records[space].need_ack(guard.max_ack_delay)
+        guard.rcvd_records[space].need_ack(guard.max_ack_delay, guard.clock.now())
     }
 
     fn on_pkt_sent(
@@ -497,7 +803,7 @@ impl super::CongestionControl for ArcCC {
         ack: Option<u64>,
     ) {
         let mut guard = self.0.lock().unwrap();
-        let now = Instant::now();
+        let now = guard.clock.now();
         guard.on_packet_sent(pn, epoch, is_ack_eliciting, in_flight, sent_bytes, now);
 
         guard.last_sent_time = now;
@@ -508,7 +814,7 @@ impl super::CongestionControl for ArcCC {
 
     fn on_ack(&self, space: Epoch, ack_frame: &AckFrame) {
         let mut guard = self.0.lock().unwrap();
-        let now = Instant::now();
+        let now = guard.clock.now();
         guard.on_ack_rcvd(space, ack_frame, now);
     }
 
@@ -517,14 +823,54 @@ impl super::CongestionControl for ArcCC {
             return;
         }
         let mut guard = self.0.lock().unwrap();
-        guard.rcvd_records[epoch].on_pkt_rcvd(pn);
-        let now = Instant::now();
+        let now = guard.clock.now();
+        guard.rcvd_records[epoch].on_pkt_rcvd(pn, now);
         guard.on_datagram_rcvd(now);
+        // 需要立即确认的数据包，不等待pacing
+        if guard.rcvd_records[epoch].need_ack {
+            guard.urgent = true;
+            guard.wake_senders();
+        }
     }
 
     fn pto_time(&self, epoch: Epoch) -> Duration {
         self.0.lock().unwrap().get_pto_time(epoch)
     }
+
+    fn begin_datagrams(&self) -> Option<u8> {
+        self.0.lock().unwrap().ecn.begin_batch()
+    }
+}
+
+/// The ACK generation state of a packet number space, returned by [`ArcCC::ack_snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AckSnapshot {
+    /// The time the ACK frame should be sent before, [`None`] if there is no ack-eliciting packet
+    /// not acknowledged yet.
+    pub time_to_sync: Option<Instant>,
+    /// Whether the ACK frame should be sent immediately, such as the Initial or Handshake packet
+    /// is received, or the packets are received out of order.
+    pub immediate: bool,
+    /// The largest packet number received but not acknowledged yet.
+    pub largest_rcvd: Option<u64>,
+    /// The ack-eliciting packets received whose ACK frame has not been confirmed by the peer.
+    pub rcvd_unreached_packets: Vec<u64>,
+    /// The largest packet number acknowledged by the last ACK frame sent.
+    pub last_synced_ack_largest: Option<u64>,
+}
+
+/// The loss and retransmission statistics of a packet number space, returned by
+/// [`ArcCC::loss_stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LossStats {
+    /// The number of packets declared lost.
+    pub lost_packets: u64,
+    /// The number of packets declared lost but acknowledged later. A high ratio of them to the
+    /// lost packets indicates that the reordering threshold is too small for the path.
+    pub spurious_losses: u64,
+    /// The number of frames handed over to be retransmitted because their packets were declared
+    /// lost.
+    pub retransmitted_frames: u64,
 }
 
 /// The [`RcvdRecords`] struct is used to maintain records of received packets for each epoch.
@@ -549,7 +895,7 @@ impl RcvdRecords {
         }
     }
 
-    fn on_pkt_rcvd(&mut self, pn: u64) {
+    fn on_pkt_rcvd(&mut self, pn: u64, now: Instant) {
         // An endpoint MUST acknowledge all ack-eliciting Initial and Handshake packets immediately
         if self.epoch == Epoch::Initial || self.epoch == Epoch::Handshake {
             self.need_ack = true;
@@ -564,14 +910,14 @@ impl RcvdRecords {
                 self.need_ack = true;
             }
             if pn >= largest {
-                self.largest_recv_time = Some((pn, Instant::now()));
+                self.largest_recv_time = Some((pn, now));
                 if pn > largest {
                     self.rcvd_queue.push_back(pn);
                     return;
                 }
             }
         } else {
-            self.largest_recv_time = Some((pn, Instant::now()));
+            self.largest_recv_time = Some((pn, now));
         };
 
         let index = self.rcvd_queue.partition_point(|&x| x < pn);
@@ -583,13 +929,12 @@ impl RcvdRecords {
 
     /// Checks whether an ACK frame needs to be sent.
     /// Returns [`Some`] if it's time to send an ACK based on the maximum delay.
-    fn need_ack(&self, max_delay: Duration) -> Option<(u64, Instant)> {
+    fn need_ack(&self, max_delay: Duration, now: Instant) -> Option<(u64, Instant)> {
         if self.need_ack {
             return self.largest_recv_time;
         }
         // All ack-eliciting 0-RTT and 1-RTT packets  MUST acknowledge within its advertised max_ack_delay
         if let Some((largest, recv_time)) = self.largest_recv_time {
-            let now = Instant::now();
             if now - recv_time >= max_delay {
                 return Some((largest, recv_time));
             }
@@ -597,6 +942,23 @@ impl RcvdRecords {
         None
     }
 
+    fn snapshot(&self, max_delay: Duration) -> AckSnapshot {
+        let time_to_sync = self.largest_recv_time.map(|(_, recv_time)| {
+            if self.need_ack {
+                recv_time
+            } else {
+                recv_time + max_delay
+            }
+        });
+        AckSnapshot {
+            time_to_sync,
+            immediate: self.need_ack,
+            largest_rcvd: self.largest_recv_time.map(|(largest, _)| largest),
+            rcvd_unreached_packets: self.rcvd_queue.iter().copied().collect(),
+            last_synced_ack_largest: self.last_ack_sent.map(|(_, largest_acked)| largest_acked),
+        }
+    }
+
     /// Called when an ACK is sent.
     /// Updates the last ACK sent information and resets the `need_ack` flag.
     fn on_ack_sent(&mut self, pn: u64, largest_acked: u64) {
@@ -632,11 +994,11 @@ pub struct AckedPkt {
     pub delivered_time: Instant,
     pub first_sent_time: Instant,
     pub is_app_limited: bool,
+    pub ect0: bool,
 }
 
-impl From<SentPkt> for AckedPkt {
-    fn from(sent: SentPkt) -> Self {
-        let now = Instant::now();
+impl AckedPkt {
+    pub(crate) fn new(sent: SentPkt, now: Instant) -> Self {
         AckedPkt {
             pn: sent.pn,
             time_sent: sent.time_sent,
@@ -646,6 +1008,7 @@ impl From<SentPkt> for AckedPkt {
             delivered_time: sent.delivered_time,
             first_sent_time: sent.first_sent_time,
             is_app_limited: sent.is_app_limited,
+            ect0: sent.ect0,
         }
     }
 }
@@ -662,6 +1025,10 @@ pub struct SentPkt {
     pub tx_in_flight: usize,
     pub lost: u64,
     pub is_acked: bool,
+    // Whether the packet is ack-eliciting and counts toward bytes in flight
+    pub ack_eliciting: bool,
+    // Whether the packet is sent with the ECT(0) marking
+    pub ect0: bool,
 }
 
 impl Default for SentPkt {
@@ -677,6 +1044,8 @@ impl Default for SentPkt {
             tx_in_flight: 0,
             lost: 0,
             is_acked: false,
+            ack_eliciting: false,
+            ect0: false,
         }
     }
 }
@@ -694,6 +1063,8 @@ impl SentPkt {
             tx_in_flight: 0,
             lost: 0,
             is_acked: false,
+            ack_eliciting: false,
+            ect0: false,
         }
     }
 }
@@ -723,6 +1094,10 @@ pub trait Algorithm: Send {
 
     fn on_congestion_event(&mut self, lost: &SentPkt, now: Instant);
 
+    /// Called when the ECN-CE count reported by the peer increases, the packets sent after
+    /// `time_sent` experienced congestion without being lost.
+    fn on_ecn_ce(&mut self, time_sent: Instant, now: Instant);
+
     fn cwnd(&self) -> u64;
 
     fn pacing_rate(&self) -> Option<u64>;
@@ -749,9 +1124,12 @@ impl LossDetectionTimer {
 
 #[cfg(test)]
 mod tests {
-    use qbase::varint::VarInt;
+    use std::sync::atomic::{self, AtomicUsize};
+
+    use qbase::{frame::EcnCounts, util::CountWaker, varint::VarInt};
 
     use super::*;
+    use crate::{ecn::EcnState, rtt::INITIAL_RTT, CongestionControl, ManualClock};
 
     #[test]
     fn test_on_packet_sent_multiple_packets() {
@@ -885,35 +1263,192 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_ack_only_non_ack_eliciting() {
+        let now = Instant::now();
+        let mut congestion_controller = create_congestion_controller_for_test();
+        let smoothed_rtt = congestion_controller.rtt.smoothed_rtt();
+
+        // 1 ~ 3 为ack-eliciting包，4 ~ 8 为仅携带ACK帧的包
+        for i in 1..=3 {
+            congestion_controller.on_packet_sent(i, Epoch::Data, true, true, 1000, now);
+        }
+        for i in 4..=8 {
+            congestion_controller.on_packet_sent(i, Epoch::Data, false, false, 50, now);
+        }
+        // ack 4 ~ 8
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(8),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(4),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ack_frame, now + Duration::from_millis(10));
+        // 不采样RTT，但被确认的包仍被移出，更早的1 ~ 3因乱序被判定丢失
+        assert_eq!(congestion_controller.rtt.smoothed_rtt(), smoothed_rtt);
+        assert!(congestion_controller.sent_packets[Epoch::Data].is_empty());
+        assert!(congestion_controller.no_ack_eliciting_in_flight());
+    }
+
+    #[test]
+    fn test_spurious_loss() {
+        let now = Instant::now();
+        let lost = Arc::new(Mutex::new(Vec::new()));
+        let mut congestion_controller = create_congestion_controller_with_lost(&lost);
+
+        for i in 1..=5 {
+            congestion_controller.on_packet_sent(i, Epoch::Data, true, true, 1000, now);
+        }
+        // ack 5，1 和 2 因乱序被判定丢失
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(5),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        let stats = congestion_controller.loss_stats[Epoch::Data];
+        assert_eq!(stats.lost_packets, 2);
+        assert_eq!(stats.spurious_losses, 0);
+        assert_eq!(stats.retransmitted_frames, 2);
+
+        // 乱序到达的ACK又确认了 1，这是一次虚假丢包
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(1),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        let stats = congestion_controller.loss_stats[Epoch::Data];
+        assert_eq!(stats.lost_packets, 2);
+        assert_eq!(stats.spurious_losses, 1);
+        // 重复的ACK不会重复计数
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        assert_eq!(
+            congestion_controller.loss_stats[Epoch::Data].spurious_losses,
+            1
+        );
+        assert_eq!(
+            congestion_controller.loss_stats[Epoch::Initial],
+            LossStats::default()
+        );
+    }
+
+    #[test]
+    fn test_ack_only_packet_accounting() {
+        let now = Instant::now();
+        let mut congestion_controller = create_congestion_controller_for_test();
+        let tokens = congestion_controller.pacer.tokens();
+
+        // 仅包含ACK帧的包不是在途的，不计入拥塞窗口，也不会设置丢包检测定时器
+        congestion_controller.on_packet_sent(0, Epoch::Data, false, false, 50, now);
+        assert!(congestion_controller.no_ack_eliciting_in_flight());
+        assert_eq!(congestion_controller.loss_timer.timeout, None);
+        // 但它同样占用了链路，消耗pacer的令牌
+        assert_eq!(congestion_controller.pacer.tokens(), tokens - 50);
+
+        // 握手确认之前，Data空间不设置PTO定时器，故用Initial空间的包
+        congestion_controller.on_packet_sent(0, Epoch::Initial, true, true, 1000, now);
+        assert!(!congestion_controller.no_ack_eliciting_in_flight());
+        assert!(congestion_controller.loss_timer.timeout.is_some());
+        assert_eq!(congestion_controller.pacer.tokens(), tokens - 1050);
+    }
+
+    #[test]
+    fn test_ecn_disabled_without_ecn_counts() {
+        let now = Instant::now();
+        let mut congestion_controller = create_congestion_controller_for_test();
+
+        assert_eq!(
+            congestion_controller.ecn.begin_batch(),
+            Some(crate::ecn::ECT0)
+        );
+        for i in 1..=3 {
+            congestion_controller.on_packet_sent(i, Epoch::Data, true, true, 1000, now);
+        }
+        assert!(congestion_controller.sent_packets[Epoch::Data]
+            .iter()
+            .all(|sent| sent.ect0));
+
+        // 对端确认了带ECT(0)标记的数据包，但没有报告ECN计数
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(3),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(2),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        assert_eq!(congestion_controller.ecn.state(), EcnState::Failed);
+        assert_eq!(congestion_controller.ecn.begin_batch(), None);
+
+        congestion_controller.on_packet_sent(4, Epoch::Data, true, true, 1000, now);
+        assert!(!congestion_controller.sent_packets[Epoch::Data][0].ect0);
+    }
+
+    #[test]
+    fn test_decode_ack_delay() {
+        use qrecovery::journal::ArcRcvdJournal;
+
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let mut congestion_controller = CongestionController::new(
+            CongestionAlgorithm::NewReno,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(SystemClock),
+        );
+        let records = ArcRcvdJournal::default();
+        records.register_pn(0);
+        let rcvd_time = Instant::now() - Duration::from_millis(40);
+
+        // 对端按其ack_delay_exponent编码ACK Delay，我方按同样的指数解码
+        for exponent in [0, 3, 10] {
+            records.set_ack_delay_exponent(exponent);
+            congestion_controller.peer_ack_delay_exponent = exponent;
+            let ack_frame = records.gen_ack_frame_util(0, rcvd_time, 1200).unwrap();
+            let ack_delay = congestion_controller.decode_ack_delay(&ack_frame);
+            let granularity = Duration::from_micros(1 << exponent);
+            assert!(ack_delay <= rcvd_time.elapsed());
+            assert!(ack_delay + granularity > Duration::from_millis(40));
+        }
+    }
+
     #[test]
     fn test_ack_record() {
         let max_ack_delay = Duration::from_millis(100);
+        let now = Instant::now();
         let mut ack_reocrd = RcvdRecords::new(Epoch::Initial);
-        ack_reocrd.on_pkt_rcvd(1);
-        assert!(ack_reocrd.need_ack(max_ack_delay).is_some());
+        ack_reocrd.on_pkt_rcvd(1, now);
+        assert!(ack_reocrd.need_ack(max_ack_delay, now).is_some());
 
-        ack_reocrd.on_pkt_rcvd(1);
+        ack_reocrd.on_pkt_rcvd(1, now);
         assert_eq!(ack_reocrd.rcvd_queue.len(), 1);
 
         ack_reocrd.on_ack_sent(1, 1);
         assert_eq!(ack_reocrd.last_ack_sent, Some((1, 1)));
-        assert!(ack_reocrd.need_ack(max_ack_delay).is_none());
+        assert!(ack_reocrd.need_ack(max_ack_delay, now).is_none());
 
-        ack_reocrd.on_pkt_rcvd(3);
+        ack_reocrd.on_pkt_rcvd(3, now);
         assert_eq!(ack_reocrd.rcvd_queue, vec![1, 3]);
 
-        ack_reocrd.on_pkt_rcvd(0);
+        ack_reocrd.on_pkt_rcvd(0, now);
         assert_eq!(ack_reocrd.rcvd_queue, vec![0, 1, 3]);
-        assert_eq!(ack_reocrd.need_ack(max_ack_delay).unwrap().0, 3);
+        assert_eq!(ack_reocrd.need_ack(max_ack_delay, now).unwrap().0, 3);
 
-        ack_reocrd.on_pkt_rcvd(5);
-        ack_reocrd.on_pkt_rcvd(7);
+        ack_reocrd.on_pkt_rcvd(5, now);
+        ack_reocrd.on_pkt_rcvd(7, now);
         assert_eq!(ack_reocrd.rcvd_queue, vec![0, 1, 3, 5, 7]);
-        assert_eq!(ack_reocrd.need_ack(max_ack_delay).unwrap().0, 7);
+        assert_eq!(ack_reocrd.need_ack(max_ack_delay, now).unwrap().0, 7);
 
         // pn 2 ack 0,1,3,5,7
         ack_reocrd.on_ack_sent(2, 7);
-        ack_reocrd.on_pkt_rcvd(9);
+        ack_reocrd.on_pkt_rcvd(9, now);
         assert_eq!(ack_reocrd.rcvd_queue, vec![0, 1, 3, 5, 7, 9]);
 
         // pn 3 ack 0,1,3,5,7,9
@@ -923,7 +1458,7 @@ mod tests {
         ack_reocrd.ack(2, &[Box::new(Mock), Box::new(Mock), Box::new(Mock)]);
         assert_eq!(ack_reocrd.rcvd_queue, vec![0, 1, 3, 5, 7, 9]);
 
-        ack_reocrd.on_pkt_rcvd(11);
+        ack_reocrd.on_pkt_rcvd(11, now);
         assert_eq!(ack_reocrd.rcvd_queue, vec![0, 1, 3, 5, 7, 9, 11]);
         // recv pn 3 ack, ret
 
@@ -931,19 +1466,652 @@ mod tests {
         assert_eq!(ack_reocrd.rcvd_queue, vec![11]);
     }
 
+    #[test]
+    fn test_pto_probes_handshake_space() {
+        let now = Instant::now();
+        let lost = Arc::new(Mutex::new(Vec::new()));
+        let mut congestion_controller = create_congestion_controller_with_lost(&lost);
+
+        congestion_controller.on_packet_sent(0, Epoch::Initial, true, true, 1200, now);
+        congestion_controller.on_packet_sent(0, Epoch::Handshake, true, true, 1000, now);
+        congestion_controller.on_packet_sent(1, Epoch::Handshake, true, true, 1000, now);
+        // Initial空间的数据都已确认，只有Handshake空间还有未确认的数据
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(0),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion_controller.on_ack_rcvd(Epoch::Initial, &ack_frame, now);
+
+        congestion_controller.on_loss_timeout(now);
+        assert_eq!(*lost.lock().unwrap(), vec![(Epoch::Handshake, 0)]);
+    }
+
+    #[test]
+    fn test_pto_probes_multiple_spaces() {
+        let now = Instant::now();
+        let lost = Arc::new(Mutex::new(Vec::new()));
+        let mut congestion_controller = create_congestion_controller_with_lost(&lost);
+
+        congestion_controller.on_packet_sent(0, Epoch::Initial, true, true, 1200, now);
+        congestion_controller.on_packet_sent(0, Epoch::Handshake, true, true, 1000, now);
+        // 握手尚未确认，不会探测1-RTT空间
+        congestion_controller.on_packet_sent(0, Epoch::Data, true, true, 1000, now);
+
+        congestion_controller.on_loss_timeout(now);
+        assert_eq!(
+            *lost.lock().unwrap(),
+            vec![(Epoch::Initial, 0), (Epoch::Handshake, 0)]
+        );
+    }
+
+    #[test]
+    fn test_pto_backoff_and_ping() {
+        let clock = ManualClock::new(Instant::now());
+        let pings = Arc::new(AtomicUsize::new(0));
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let mut congestion_controller = CongestionController::new(
+            CongestionAlgorithm::NewReno,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [
+                Box::new(PingCounter(pings.clone())),
+                Box::new(Mock),
+                Box::new(Mock),
+            ],
+            Handshake::new(qbase::sid::Role::Server, output),
+            Arc::new(clock.clone()),
+        );
+        let start = clock.now();
+        congestion_controller.on_packet_sent(0, Epoch::Initial, true, true, 1200, start);
+        let pto = congestion_controller.get_pto_time(Epoch::Initial);
+        assert_eq!(congestion_controller.loss_timer.timeout, Some(start + pto));
+
+        // 每次PTO，都没有可重传的数据，发送PING作为探测包；PTO时长随之指数退避
+        let pto_timeout = [start + pto, start + pto * 2];
+        for (pto_count, timeout) in (1..).zip(pto_timeout) {
+            assert!(!congestion_controller.loss_timer.is_timeout(timeout));
+            clock.advance(timeout + Duration::from_millis(1) - clock.now());
+            assert!(congestion_controller.loss_timer.is_timeout(clock.now()));
+            congestion_controller.on_loss_timeout(clock.now());
+            assert_eq!(congestion_controller.pto_count, pto_count);
+            assert_eq!(pings.load(atomic::Ordering::Relaxed), pto_count as usize);
+            assert_eq!(
+                congestion_controller.loss_timer.timeout,
+                Some(start + pto * 2u32.pow(pto_count))
+            );
+        }
+
+        // 新的ack-eliciting包被确认，PTO计数清零
+        congestion_controller.on_packet_sent(1, Epoch::Initial, true, true, 1200, clock.now());
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(1),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        };
+        clock.advance(Duration::from_millis(10));
+        congestion_controller.on_ack_rcvd(Epoch::Initial, &ack_frame, clock.now());
+        assert_eq!(congestion_controller.pto_count, 0);
+    }
+
+    #[test]
+    fn test_probe_bypasses_cwnd() {
+        // 在创建之前取时间，pacer不会因流逝的时间而补充额度
+        let now = Instant::now();
+        let mut congestion_controller = create_congestion_controller_for_test();
+
+        let mut pn = 0;
+        while congestion_controller.send_quota(now).is_some() {
+            congestion_controller.on_packet_sent(pn, Epoch::Initial, true, true, MSS, now);
+            pn += 1;
+            assert!(pn < 1000, "the pacer should limit the sending");
+        }
+
+        // PTO之后，即使受拥塞控制限制，也可以发送探测包
+        congestion_controller.on_loss_timeout(now);
+        for _ in 0..K_MAX_PROBES {
+            assert_eq!(congestion_controller.send_quota(now), Some(MSS));
+            congestion_controller.on_packet_sent(pn, Epoch::Initial, true, true, MSS, now);
+            pn += 1;
+        }
+        assert_eq!(congestion_controller.send_quota(now), None);
+        // 探测包与其他数据包一样被记录
+        assert_eq!(
+            congestion_controller.sent_packets[Epoch::Initial].len(),
+            pn as usize
+        );
+
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u64(pn - 1).unwrap(),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u64(pn - 1).unwrap(),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion_controller.on_ack_rcvd(Epoch::Initial, &ack_frame, now);
+        // 对探测包的确认更新了RTT，且所有数据包都已不在途
+        assert_ne!(congestion_controller.rtt.smoothed_rtt(), INITIAL_RTT);
+        assert!(congestion_controller.sent_packets[Epoch::Initial].is_empty());
+        assert!(congestion_controller.no_ack_eliciting_in_flight());
+        assert_eq!(congestion_controller.probes, 0);
+    }
+
+    #[test]
+    fn test_pacing_with_manual_clock() {
+        let clock = ManualClock::new(Instant::now());
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let cc = ArcCC::with_clock(
+            CongestionAlgorithm::NewReno,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(clock.clone()),
+        );
+        let mut cx = Context::from_waker(Waker::noop());
+
+        // 时钟不走，用完突发的令牌之后就不能再发送
+        let mut pn = 0;
+        while let Poll::Ready(quota) = cc.poll_send(&mut cx) {
+            assert!(quota >= MSS);
+            cc.on_pkt_sent(Epoch::Data, pn, true, MSS, true, None);
+            pn += 1;
+            assert!(pn < 1000, "the pacer should limit the sending");
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(cc.poll_send(&mut cx).is_pending());
+
+        // 按拥塞窗口计算的速率发放令牌，一个MSS的令牌需要的时间
+        let interval = {
+            let guard = cc.0.lock().unwrap();
+            let rate = pacing::N * guard.algorithm.cwnd() as f64 / INITIAL_RTT.as_secs_f64();
+            Duration::from_secs_f64(MSS as f64 / rate)
+        };
+        clock.advance(interval / 2);
+        assert!(cc.poll_send(&mut cx).is_pending());
+        clock.advance(interval);
+        assert!(matches!(cc.poll_send(&mut cx), Poll::Ready(quota) if quota >= MSS));
+    }
+
+    #[test]
+    fn test_urgent_bypasses_pacing() {
+        let clock = ManualClock::new(Instant::now());
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let cc = ArcCC::with_clock(
+            CongestionAlgorithm::NewReno,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(clock.clone()),
+        );
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut pn = 0;
+        while cc.poll_send(&mut cx).is_ready() {
+            cc.on_pkt_sent(Epoch::Data, pn, true, MSS, true, None);
+            pn += 1;
+        }
+
+        // 令牌耗尽，紧急的控制帧仍可立即发送一个数据报
+        cc.send_urgently();
+        assert_eq!(cc.poll_send(&mut cx), Poll::Ready(MSS));
+        cc.on_pkt_sent(Epoch::Data, pn, true, MSS, true, None);
+        assert!(cc.poll_send(&mut cx).is_pending());
+
+        // 乱序到达的数据包需要立即确认，同样不等待pacing
+        cc.on_pkt_rcvd(Epoch::Data, 0, true);
+        assert!(cc.poll_send(&mut cx).is_pending());
+        cc.on_pkt_rcvd(Epoch::Data, 2, true);
+        assert_eq!(cc.poll_send(&mut cx), Poll::Ready(MSS));
+    }
+
+    #[test]
+    fn test_immediate_ack() {
+        let clock = ManualClock::new(Instant::now());
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let cc = ArcCC::with_clock(
+            CongestionAlgorithm::NewReno,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(clock.clone()),
+        );
+
+        // 按序到达的数据包，既没有乱序也没有超过max_ack_delay，不需要立即确认
+        cc.on_pkt_rcvd(Epoch::Data, 0, true);
+        cc.on_pkt_rcvd(Epoch::Data, 1, true);
+        clock.advance(Duration::from_millis(10));
+        assert!(cc.need_ack(Epoch::Data).is_none());
+        assert!(!cc.ack_snapshot(Epoch::Data).immediate);
+
+        cc.on_immediate_ack();
+        assert_eq!(
+            cc.need_ack(Epoch::Data).map(|(largest, _)| largest),
+            Some(1)
+        );
+        assert!(cc.ack_snapshot(Epoch::Data).immediate);
+
+        // 发送ACK之后，恢复正常的延迟确认
+        cc.on_pkt_sent(Epoch::Data, 0, false, 50, false, Some(1));
+        cc.on_pkt_rcvd(Epoch::Data, 2, true);
+        assert!(cc.need_ack(Epoch::Data).is_none());
+    }
+
+    #[test]
+    fn test_ecn_ce_reduces_cwnd() {
+        let now = Instant::now();
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let mut congestion_controller = CongestionController::new(
+            CongestionAlgorithm::NewReno,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(SystemClock),
+        );
+        let cwnd = congestion_controller.algorithm.cwnd();
+        congestion_controller.ecn.begin_batch();
+        for pn in 0..3 {
+            congestion_controller.on_packet_sent(pn, Epoch::Data, true, true, MSS, now);
+        }
+
+        // 3个包都被确认，没有丢包，但其中1个被路径标记了CE
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(2),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(2),
+            ranges: vec![],
+            ecn: Some(EcnCounts {
+                ect0: VarInt::from_u32(2),
+                ect1: VarInt::from_u32(0),
+                ce: VarInt::from_u32(1),
+            }),
+        };
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        assert_eq!(congestion_controller.ecn.state(), EcnState::Capable);
+        assert_eq!(
+            congestion_controller.loss_stats[Epoch::Data].lost_packets,
+            0
+        );
+        assert_eq!(congestion_controller.algorithm.cwnd(), cwnd / 2);
+    }
+
+    #[test]
+    fn test_ecn_ce_reduces_bbr_cwnd() {
+        let now = Instant::now();
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let mut congestion_controller = CongestionController::new(
+            CongestionAlgorithm::Bbr,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(SystemClock),
+        );
+        let cwnd = congestion_controller.algorithm.cwnd();
+        let ecn_ack = |largest: u32, ect0: u32, ce: u32| AckFrame {
+            largest: VarInt::from_u32(largest),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: Some(EcnCounts {
+                ect0: VarInt::from_u32(ect0),
+                ect1: VarInt::from_u32(0),
+                ce: VarInt::from_u32(ce),
+            }),
+        };
+        congestion_controller.ecn.begin_batch();
+        for pn in 0..3 {
+            congestion_controller.on_packet_sent(pn, Epoch::Data, true, true, MSS, now);
+        }
+
+        // 0号包被标记了CE，进入恢复期，拥塞窗口降至在途的数据量
+        let ack_time = now + Duration::from_millis(10);
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ecn_ack(0, 0, 1), ack_time);
+        let reduced = congestion_controller.algorithm.cwnd();
+        assert!(reduced < cwnd);
+
+        // 同一恢复期内发出的1号包也被标记了CE，不再重复降低
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ecn_ack(1, 0, 2), ack_time);
+        assert_eq!(congestion_controller.algorithm.cwnd(), reduced);
+
+        // 恢复期开始之后发出的包被确认，恢复期结束，拥塞窗口恢复
+        congestion_controller.ecn.begin_batch();
+        let sent_time = ack_time + Duration::from_millis(10);
+        congestion_controller.on_packet_sent(3, Epoch::Data, true, true, MSS, sent_time);
+        congestion_controller.on_ack_rcvd(
+            Epoch::Data,
+            &ecn_ack(3, 1, 2),
+            sent_time + Duration::from_millis(10),
+        );
+        assert_eq!(congestion_controller.algorithm.cwnd(), cwnd);
+    }
+
+    #[test]
+    fn test_ecn_ce_by_largest_newly_acked() {
+        let now = Instant::now();
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let mut congestion_controller = CongestionController::new(
+            CongestionAlgorithm::NewReno,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(SystemClock),
+        );
+        let cwnd = congestion_controller.algorithm.cwnd();
+        congestion_controller.ecn.begin_batch();
+        for pn in 0..2 {
+            congestion_controller.on_packet_sent(pn, Epoch::Data, true, true, MSS, now);
+        }
+        let ecn_ack = |largest: u32, first_range: u32, ect0: u32, ce: u32| AckFrame {
+            largest: VarInt::from_u32(largest),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(first_range),
+            ranges: vec![],
+            ecn: Some(EcnCounts {
+                ect0: VarInt::from_u32(ect0),
+                ect1: VarInt::from_u32(0),
+                ce: VarInt::from_u32(ce),
+            }),
+        };
+
+        // 0号包被标记了CE，进入拥塞恢复
+        let recovery_start = now + Duration::from_millis(10);
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ecn_ack(0, 0, 0, 1), recovery_start);
+        assert_eq!(congestion_controller.algorithm.cwnd(), cwnd / 2);
+
+        // 1号包在拥塞恢复之前发出，2号包在之后发出，同一个ACK Range确认了它们，又有CE标记，
+        // 应按包号最大的2号包判断，再次进入拥塞恢复
+        congestion_controller.ecn.begin_batch();
+        let sent_time = recovery_start + Duration::from_millis(10);
+        congestion_controller.on_packet_sent(2, Epoch::Data, true, true, MSS, sent_time);
+        congestion_controller.on_ack_rcvd(
+            Epoch::Data,
+            &ecn_ack(2, 1, 1, 2),
+            sent_time + Duration::from_millis(10),
+        );
+        assert_eq!(congestion_controller.algorithm.cwnd(), cwnd / 4);
+    }
+
+    #[test]
+    fn test_new_reno_with_beta() {
+        let now = Instant::now();
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let mut congestion_controller = CongestionController::new(
+            CongestionAlgorithm::NewRenoWithBeta(0.7),
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(SystemClock),
+        );
+        let cwnd = congestion_controller.algorithm.cwnd();
+        for pn in 0..5 {
+            congestion_controller.on_packet_sent(pn, Epoch::Data, true, true, MSS, now);
+        }
+        // 确认了4号包，0、1号包超出了乱序阈值而判定丢失，只缩减一次拥塞窗口
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(4),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        assert_eq!(
+            congestion_controller.loss_stats[Epoch::Data].lost_packets,
+            2
+        );
+        assert_eq!(
+            congestion_controller.algorithm.cwnd(),
+            (cwnd as f64 * 0.7).round() as u64
+        );
+    }
+
+    #[test]
+    fn test_poll_send_from_two_tasks() {
+        let clock = ManualClock::new(Instant::now());
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let cc = ArcCC::with_clock(
+            CongestionAlgorithm::NewReno,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(clock),
+        );
+        let mut pn = 0;
+        while cc
+            .poll_send(&mut Context::from_waker(Waker::noop()))
+            .is_ready()
+        {
+            cc.on_pkt_sent(Epoch::Data, pn, true, MSS, true, None);
+            pn += 1;
+        }
+
+        // 两个任务都在等待发送，同一任务重复poll不会重复登记
+        let tasks = [0, 1].map(|_| Arc::new(CountWaker::default()));
+        let wakers = tasks.clone().map(Waker::from);
+        for waker in wakers.iter().chain(&wakers) {
+            assert!(cc.poll_send(&mut Context::from_waker(waker)).is_pending());
+        }
+        cc.wake_sending();
+        for task in &tasks {
+            assert_eq!(task.count(), 1);
+        }
+        // 唤醒之后需要重新poll才会再次被唤醒
+        cc.wake_sending();
+        for task in &tasks {
+            assert_eq!(task.count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_initial_rtt() {
+        let now = Instant::now();
+        let first_pto = |initial_rtt| {
+            let output = ArcReliableFrameDeque::with_capacity(10);
+            let mut congestion_controller = CongestionController::new(
+                CongestionAlgorithm::Bbr,
+                initial_rtt,
+                Duration::from_millis(100),
+                [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+                Handshake::new(qbase::sid::Role::Client, output),
+                Arc::new(SystemClock),
+            );
+            congestion_controller.on_packet_sent(0, Epoch::Initial, true, true, 1200, now);
+            let (pto_time, epoch) = congestion_controller.get_pto_timeout().unwrap();
+            assert_eq!(epoch, Epoch::Initial);
+            pto_time - now
+        };
+        // 在取得RTT样本前，PTO = initial_rtt + 4 * initial_rtt / 2
+        assert_eq!(
+            first_pto(Duration::from_millis(10)),
+            Duration::from_millis(30)
+        );
+        assert_eq!(first_pto(INITIAL_RTT), Duration::from_millis(999));
+    }
+
+    #[test]
+    fn test_mtu_blackhole() {
+        let now = Instant::now();
+        let mut congestion_controller = create_congestion_controller_for_test();
+        congestion_controller.mtu.raise(1452);
+        assert_eq!(congestion_controller.send_quota(now), Some(1452));
+
+        // 大包都丢失了，而之后发送的小包被确认
+        for pn in 0..3 {
+            congestion_controller.on_packet_sent(pn, Epoch::Data, true, true, 1452, now);
+        }
+        for pn in 3..6 {
+            congestion_controller.on_packet_sent(pn, Epoch::Data, true, true, 100, now);
+        }
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(5),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(2),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        assert!(congestion_controller.sent_packets[Epoch::Data].is_empty());
+        assert_eq!(congestion_controller.mtu.mtu(), MSS);
+    }
+
+    #[test]
+    fn test_mtu_probe() {
+        let now = Instant::now();
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        let mut congestion_controller = CongestionController::new(
+            CongestionAlgorithm::NewReno,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(SystemClock),
+        );
+        let cwnd = congestion_controller.algorithm.cwnd();
+        congestion_controller.mtu.set_max_mtu(1452);
+        let ack = |largest: u32, first_range: u32| AckFrame {
+            largest: VarInt::from_u32(largest),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(first_range),
+            ranges: vec![],
+            ecn: None,
+        };
+
+        // 探测包丢失了，之后的小包被确认，不视作拥塞
+        let size = congestion_controller.mtu.probe_size().unwrap();
+        congestion_controller.on_packet_sent(0, Epoch::Data, true, true, size, now);
+        congestion_controller.mtu.on_probe_sent(0, size);
+        for pn in 1..4 {
+            congestion_controller.on_packet_sent(pn, Epoch::Data, true, true, 100, now);
+        }
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ack(3, 2), now);
+        assert_eq!(
+            congestion_controller.loss_stats[Epoch::Data].lost_packets,
+            1
+        );
+        assert!(congestion_controller.algorithm.cwnd() >= cwnd);
+        assert_eq!(congestion_controller.mtu.mtu(), MSS);
+
+        // 再次探测，探测包被确认，MTU升至探测的大小
+        assert_eq!(congestion_controller.mtu.probe_size(), Some(size));
+        congestion_controller.on_packet_sent(4, Epoch::Data, true, true, size, now);
+        congestion_controller.mtu.on_probe_sent(4, size);
+        congestion_controller.on_ack_rcvd(Epoch::Data, &ack(4, 0), now);
+        assert_eq!(congestion_controller.mtu.mtu(), size);
+        assert_eq!(congestion_controller.send_quota(now), Some(size));
+    }
+
+    #[test]
+    fn test_need_ack_after_max_delay() {
+        let max_ack_delay = Duration::from_millis(100);
+        let now = Instant::now();
+        let mut ack_record = RcvdRecords::new(Epoch::Data);
+        ack_record.on_pkt_rcvd(0, now);
+        // 尚未到max_ack_delay，可以延迟确认
+        assert!(ack_record.need_ack(max_ack_delay, now).is_none());
+
+        // 越过了time_to_sync，必须发送ACK
+        let recv_time = now - max_ack_delay - Duration::from_millis(1);
+        ack_record.largest_recv_time = Some((0, recv_time));
+        let time_to_sync = ack_record.snapshot(max_ack_delay).time_to_sync.unwrap();
+        assert!(time_to_sync < now);
+        assert_eq!(
+            ack_record.need_ack(max_ack_delay, now),
+            Some((0, recv_time))
+        );
+    }
+
+    #[test]
+    fn test_ack_snapshot() {
+        let cc = ArcCC(Arc::new(
+            Mutex::new(create_congestion_controller_for_test()),
+        ));
+        let snapshot = cc.ack_snapshot(Epoch::Data);
+        assert_eq!(snapshot.time_to_sync, None);
+        assert!(snapshot.rcvd_unreached_packets.is_empty());
+
+        let before = Instant::now();
+        crate::CongestionControl::on_pkt_rcvd(&cc, Epoch::Data, 0, true);
+        let snapshot = cc.ack_snapshot(Epoch::Data);
+        // 1-RTT数据包可以延迟确认，但不能超过max_ack_delay
+        let time_to_sync = snapshot.time_to_sync.unwrap();
+        assert!(time_to_sync >= before + Duration::from_millis(100));
+        assert!(!snapshot.immediate);
+        assert_eq!(snapshot.largest_rcvd, Some(0));
+        assert_eq!(snapshot.rcvd_unreached_packets, vec![0]);
+        assert_eq!(snapshot.last_synced_ack_largest, None);
+
+        crate::CongestionControl::on_pkt_sent(&cc, Epoch::Data, 0, false, 20, false, Some(0));
+        let snapshot = cc.ack_snapshot(Epoch::Data);
+        assert_eq!(snapshot.time_to_sync, None);
+        assert_eq!(snapshot.last_synced_ack_largest, Some(0));
+        // 对端尚未确认携带ACK的数据包
+        assert_eq!(snapshot.rcvd_unreached_packets, vec![0]);
+    }
+
     struct Mock;
     impl TrackPackets for Mock {
-        fn may_loss(&self, _: u64) {}
+        fn may_loss(&self, _: u64) -> usize {
+            0
+        }
+        fn retire(&self, _: u64) {}
+        fn send_ping(&self) {}
+    }
+
+    struct PingCounter(Arc<AtomicUsize>);
+    impl TrackPackets for PingCounter {
+        fn may_loss(&self, _: u64) -> usize {
+            0
+        }
         fn retire(&self, _: u64) {}
+        fn send_ping(&self) {
+            self.0.fetch_add(1, atomic::Ordering::Relaxed);
+        }
+    }
+
+    struct LostRecorder(Epoch, Arc<Mutex<Vec<(Epoch, u64)>>>);
+    impl TrackPackets for LostRecorder {
+        fn may_loss(&self, pn: u64) -> usize {
+            self.1.lock().unwrap().push((self.0, pn));
+            1
+        }
+        fn retire(&self, _: u64) {}
+        fn send_ping(&self) {}
+    }
+
+    fn create_congestion_controller_with_lost(
+        lost: &Arc<Mutex<Vec<(Epoch, u64)>>>,
+    ) -> CongestionController {
+        let output = ArcReliableFrameDeque::with_capacity(10);
+        CongestionController::new(
+            CongestionAlgorithm::Bbr,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            Epoch::EPOCHS
+                .map(|epoch| Box::new(LostRecorder(epoch, lost.clone())) as Box<dyn TrackPackets>),
+            Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(SystemClock),
+        )
     }
 
     fn create_congestion_controller_for_test() -> CongestionController {
         let output = ArcReliableFrameDeque::with_capacity(10);
         CongestionController::new(
             CongestionAlgorithm::Bbr,
+            INITIAL_RTT,
             Duration::from_millis(100),
             [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
             Handshake::new(qbase::sid::Role::Client, output),
+            Arc::new(SystemClock),
         )
     }
 }
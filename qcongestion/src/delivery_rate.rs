@@ -169,7 +169,7 @@ mod tests {
 
         for _ in 0..3 {
             let sent = sents.pop().unwrap();
-            let mut acked: AckedPkt = sent.into();
+            let mut acked = AckedPkt::new(sent, recv_ack_time);
             acked.rtt = delay;
             rate.update_rate_sample(&acked, recv_ack_time);
             rate.generate_rate_sample();
@@ -0,0 +1,235 @@
+use qbase::{frame::EcnCounts, Epoch};
+
+/// The number of packets marked with ECT(0) during the testing period.
+///
+/// See [Section A.4](https://www.rfc-editor.org/rfc/rfc9000.html#name-sample-ecn-validation-algor)
+/// of [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html).
+const ECN_TESTING_PACKETS: u64 = 10;
+
+/// The ECN codepoint ECT(0) in the IP header.
+pub(crate) const ECT0: u8 = 0b10;
+
+/// The state of the ECN validation on a path.
+///
+/// See [ECN Validation](https://www.rfc-editor.org/rfc/rfc9000.html#name-ecn-validation)
+/// of [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EcnState {
+    /// Sending the first packets marked with ECT(0), waiting for the ACK frames to validate.
+    Testing,
+    /// The testing packets have been sent, stop marking until the ACK frames validate them.
+    Unknown,
+    /// The path and the peer support ECN, all outgoing packets are marked with ECT(0).
+    Capable,
+    /// The validation failed, the ECN marking is disabled on this path.
+    Failed,
+}
+
+/// Validates that the path and the peer support ECN, by checking the ECN counts reported in
+/// the ACK frames against the packets sent with the ECT(0) marking.
+#[derive(Debug)]
+pub(crate) struct EcnValidator {
+    state: EcnState,
+    // 当前这组数据报是否标记ECT(0)，仅在每组数据报组装前确定，组装期间不会改变
+    marking: bool,
+    // 测试阶段发送、丢失的标记包数目
+    testing_sent: u64,
+    testing_lost: u64,
+    // 各空间已发送的标记包数目
+    sent_ect0: [u64; Epoch::count()],
+    // 各空间对端上一次报告的ECN计数
+    counts: [EcnCounts; Epoch::count()],
+}
+
+impl Default for EcnValidator {
+    fn default() -> Self {
+        let zero = EcnCounts {
+            ect0: 0u8.into(),
+            ect1: 0u8.into(),
+            ce: 0u8.into(),
+        };
+        Self {
+            state: EcnState::Testing,
+            marking: false,
+            testing_sent: 0,
+            testing_lost: 0,
+            sent_ect0: [0; Epoch::count()],
+            counts: [zero; Epoch::count()],
+        }
+    }
+}
+
+impl EcnValidator {
+    #[cfg(test)]
+    pub(crate) fn state(&self) -> EcnState {
+        self.state
+    }
+
+    /// Begin assembling a new batch of datagrams, return the ECN codepoint to mark them.
+    ///
+    /// The packets recorded by [`EcnValidator::on_pkt_sent`] until the next call are considered
+    /// to be marked the same way, even if the validation fails in the meantime.
+    pub(crate) fn begin_batch(&mut self) -> Option<u8> {
+        if self.state == EcnState::Testing && self.testing_sent >= ECN_TESTING_PACKETS {
+            self.state = EcnState::Unknown;
+        }
+        self.marking = matches!(self.state, EcnState::Testing | EcnState::Capable);
+        self.marking.then_some(ECT0)
+    }
+
+    /// Return whether the packet sent is marked with ECT(0).
+    pub(crate) fn on_pkt_sent(&mut self, epoch: Epoch) -> bool {
+        if self.marking {
+            self.sent_ect0[epoch] += 1;
+            if self.state == EcnState::Testing {
+                self.testing_sent += 1;
+            }
+        }
+        self.marking
+    }
+
+    pub(crate) fn on_pkt_lost(&mut self, ect0: bool) {
+        if !ect0 || !matches!(self.state, EcnState::Testing | EcnState::Unknown) {
+            return;
+        }
+        self.testing_lost += 1;
+        // 测试阶段的所有标记包都丢失了，可能是路径丢弃了带ECN标记的数据包
+        if self.state == EcnState::Unknown && self.testing_lost >= self.testing_sent {
+            self.fail("all the ECN testing packets are lost");
+        }
+    }
+
+    /// Validate the ECN counts of the ACK frame that newly acknowledges `newly_acked_ect0`
+    /// packets sent with the ECT(0) marking.
+    ///
+    /// Return whether the validated ECN-CE count increased, which means the path experienced
+    /// congestion, see [Section 13.4.2](https://www.rfc-editor.org/rfc/rfc9000.html#name-ecn-validation)
+    /// of RFC 9000.
+    pub(crate) fn on_ack_rcvd(
+        &mut self,
+        epoch: Epoch,
+        newly_acked_ect0: u64,
+        ecn: Option<EcnCounts>,
+    ) -> bool {
+        if self.state == EcnState::Failed || newly_acked_ect0 == 0 {
+            return false;
+        }
+        let Some(ecn) = ecn else {
+            return self.fail("the ACK frame has no ECN counts");
+        };
+
+        let last = &self.counts[epoch];
+        if ecn.ect0 < last.ect0 || ecn.ect1 < last.ect1 || ecn.ce < last.ce {
+            return self.fail("the ECN counts decreased");
+        }
+        let increased_ect0 = ecn.ect0.into_inner() - last.ect0.into_inner();
+        let increased_ce = ecn.ce.into_inner() - last.ce.into_inner();
+        if increased_ect0 + increased_ce < newly_acked_ect0 {
+            return self.fail("the ECT(0) marks are bleached");
+        }
+        // ECT(1) is never sent, and the reported ECT(0) marks could not exceed the sent ones
+        if ecn.ect1.into_inner() > 0
+            || ecn.ect0.into_inner() + ecn.ce.into_inner() > self.sent_ect0[epoch]
+        {
+            return self.fail("the ECN counts exceed the marked packets sent");
+        }
+
+        self.counts[epoch] = ecn;
+        if matches!(self.state, EcnState::Testing | EcnState::Unknown) {
+            log::debug!("ECN validation succeeded");
+            self.state = EcnState::Capable;
+        }
+        increased_ce > 0
+    }
+
+    // 验证失败的ACK帧中的CE计数不可信，不作为拥塞信号
+    fn fail(&mut self, reason: &str) -> bool {
+        log::debug!("ECN validation failed: {reason}, disable ECN");
+        // 正在组装的这组数据报仍按已确定的标记发送，下一组起不再标记
+        self.state = EcnState::Failed;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(ect0: u32, ect1: u32, ce: u32) -> Option<EcnCounts> {
+        Some(EcnCounts {
+            ect0: ect0.into(),
+            ect1: ect1.into(),
+            ce: ce.into(),
+        })
+    }
+
+    #[test]
+    fn test_ecn_capable() {
+        let mut ecn = EcnValidator::default();
+        assert_eq!(ecn.begin_batch(), Some(ECT0));
+        for _ in 0..4 {
+            assert!(ecn.on_pkt_sent(Epoch::Data));
+        }
+        assert!(ecn.on_ack_rcvd(Epoch::Data, 3, counts(2, 0, 1)));
+        assert_eq!(ecn.state(), EcnState::Capable);
+        assert_eq!(ecn.begin_batch(), Some(ECT0));
+        // CE计数没有增加，没有发生拥塞
+        assert!(!ecn.on_ack_rcvd(Epoch::Data, 1, counts(3, 0, 1)));
+    }
+
+    #[test]
+    fn test_testing_period() {
+        let mut ecn = EcnValidator::default();
+        assert_eq!(ecn.begin_batch(), Some(ECT0));
+        for _ in 0..ECN_TESTING_PACKETS {
+            assert!(ecn.on_pkt_sent(Epoch::Initial));
+        }
+        assert_eq!(ecn.begin_batch(), None);
+        assert_eq!(ecn.state(), EcnState::Unknown);
+        assert!(!ecn.on_pkt_sent(Epoch::Initial));
+
+        for _ in 0..ECN_TESTING_PACKETS {
+            ecn.on_pkt_lost(true);
+        }
+        assert_eq!(ecn.state(), EcnState::Failed);
+    }
+
+    #[test]
+    fn test_bleached_ecn() {
+        let mut ecn = EcnValidator::default();
+        ecn.begin_batch();
+        ecn.on_pkt_sent(Epoch::Data);
+        ecn.on_pkt_sent(Epoch::Data);
+        ecn.on_ack_rcvd(Epoch::Data, 2, counts(1, 0, 0));
+        assert_eq!(ecn.state(), EcnState::Failed);
+        assert_eq!(ecn.begin_batch(), None);
+    }
+
+    #[test]
+    fn test_invalid_ecn_counts() {
+        let mut ecn = EcnValidator::default();
+        ecn.begin_batch();
+        ecn.on_pkt_sent(Epoch::Data);
+        ecn.on_ack_rcvd(Epoch::Data, 1, counts(1, 1, 0));
+        assert_eq!(ecn.state(), EcnState::Failed);
+
+        let mut ecn = EcnValidator::default();
+        ecn.begin_batch();
+        ecn.on_pkt_sent(Epoch::Data);
+        ecn.on_ack_rcvd(Epoch::Data, 1, counts(2, 0, 0));
+        assert_eq!(ecn.state(), EcnState::Failed);
+    }
+
+    #[test]
+    fn test_fail_during_batch() {
+        let mut ecn = EcnValidator::default();
+        assert_eq!(ecn.begin_batch(), Some(ECT0));
+        assert!(ecn.on_pkt_sent(Epoch::Data));
+        ecn.on_ack_rcvd(Epoch::Data, 1, None);
+        assert_eq!(ecn.state(), EcnState::Failed);
+        // the rest of the batch is still sent with ECT(0)
+        assert!(ecn.on_pkt_sent(Epoch::Data));
+        assert_eq!(ecn.begin_batch(), None);
+        assert!(!ecn.on_pkt_sent(Epoch::Data));
+    }
+}
@@ -3,13 +3,19 @@ use std::{
     time::{Duration, Instant},
 };
 
-pub use congestion::{ArcCC, CongestionAlgorithm, MSS};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use congestion::{AckSnapshot, ArcCC, CongestionAlgorithm, LossStats, MSS};
+pub use new_reno::LOSS_REDUCTION_FACTOR;
 use qbase::{frame::AckFrame, Epoch};
+pub use rtt::INITIAL_RTT;
 
 mod bbr;
+mod clock;
 mod congestion;
 mod delivery_rate;
+mod ecn;
 mod min_max;
+mod mtu;
 mod new_reno;
 mod pacing;
 mod rtt;
@@ -59,6 +65,13 @@ pub trait CongestionControl {
     /// # Returns
     /// The current PTO duration for the given epoch.
     fn pto_time(&self, epoch: Epoch) -> Duration;
+
+    /// Begins assembling a new batch of datagrams on this path, returns the ECN codepoint to
+    /// mark them, or `None` if ECN is not used, such as the ECN validation failed.
+    ///
+    /// The packets passed to [`CongestionControl::on_pkt_sent`] until the next call are
+    /// considered to be sent with this codepoint.
+    fn begin_datagrams(&self) -> Option<u8>;
 }
 
 /// The [`TrackPackets`] trait defines the interface for packet tracking
@@ -66,10 +79,19 @@ pub trait TrackPackets: Send + Sync {
     /// Indicates that a packet with the specified packet number may have been lost.
     /// # Parameters
     /// - `pn`: The packet number of the potentially lost packet.
-    fn may_loss(&self, pn: u64);
+    /// # Returns
+    /// The number of frames in the packet that are handed over to be retransmitted.
+    fn may_loss(&self, pn: u64) -> usize;
 
     /// Retires a packet record with the specified packet number in recv buffer.
     /// # Parameters
     /// - `pn`: The packet number of the packet record to retire.
     fn retire(&self, pn: u64);
+
+    /// Indicates that the probe timeout expired, but no frame could be retransmitted in the space.
+    ///
+    /// A PING frame should be sent, so that the probe packet is ack-eliciting, see
+    /// [Section 6.2.4](https://www.rfc-editor.org/rfc/rfc9002.html#name-sending-probe-packets)
+    /// of RFC 9002.
+    fn send_ping(&self);
 }
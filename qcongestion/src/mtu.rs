@@ -0,0 +1,247 @@
+use crate::MSS;
+
+/// The number of consecutive lost packets larger than [`MSS`] to declare a black hole.
+const BLACKHOLE_THRESHOLD: u32 = 3;
+
+/// The largest datagram size to probe, it fits the Ethernet MTU with the IPv6 and UDP headers.
+const MAX_PROBE_SIZE: usize = 1452;
+
+/// The number of consecutive lost probes of the same size to give up the size.
+const MAX_PROBE_LOSSES: u32 = 3;
+
+/// Stop probing once the search range is narrower than this.
+const MIN_PROBE_STEP: usize = 20;
+
+/// Discovers the MTU of a path, and detects the MTU black hole.
+///
+/// Once the handshake is confirmed, the path sends PMTU probes, 1-RTT packets of a PING frame
+/// padded to the probe size. The probe size is searched in binary between the validated MTU and
+/// an upper bound: the MTU is raised when a probe is acknowledged, and the upper bound drops
+/// below the probe size when [`MAX_PROBE_LOSSES`] probes of the size are lost in a row. The loss
+/// of a probe is not a signal of congestion.
+///
+/// After the path MTU is raised, it may drop again, for example the route changed. Then the
+/// packets larger than the new path MTU will silently vanish, while the smaller packets still
+/// get through. If a run of the large packets is all lost while a small packet is acknowledged,
+/// the effective MTU falls back to the safe minimum [`MSS`], and the search starts over.
+///
+/// See [Section 14.4](https://www.rfc-editor.org/rfc/rfc9000.html#name-sending-quic-pmtu-probes)
+/// of [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html) and
+/// [Section 4.3](https://www.rfc-editor.org/rfc/rfc8899.html#name-black-hole-detection-and-re)
+/// of [RFC 8899](https://www.rfc-editor.org/rfc/rfc8899.html).
+#[derive(Debug)]
+pub(crate) struct MtuDetector {
+    // 当前路径上允许的最大数据报大小
+    mtu: usize,
+    // 自上次大包被确认后，连续丢失的大包数目
+    lost_large: u32,
+    // 自上次大包被确认后，是否有小包被确认，证明路径本身仍是通的
+    small_acked: bool,
+    // 探测的上限，取本地配置与对端的max_udp_payload_size的较小者；为MSS时不探测
+    max_mtu: usize,
+    // 二分搜索中尚未被否定的上限
+    search_high: usize,
+    // 在途的探测包，(包号, 大小)
+    probe: Option<(u64, usize)>,
+    // 当前大小的探测包连续丢失的次数
+    probe_losses: u32,
+}
+
+impl Default for MtuDetector {
+    fn default() -> Self {
+        Self {
+            mtu: MSS,
+            lost_large: 0,
+            small_acked: false,
+            max_mtu: MSS,
+            search_high: MSS,
+            probe: None,
+            probe_losses: 0,
+        }
+    }
+}
+
+impl MtuDetector {
+    pub(crate) fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Raise the effective MTU, after a larger datagram size is validated on the path.
+    pub(crate) fn raise(&mut self, mtu: usize) {
+        if mtu > self.mtu {
+            self.mtu = mtu;
+            self.search_high = self.search_high.max(mtu);
+            self.lost_large = 0;
+            self.small_acked = false;
+        }
+    }
+
+    /// Set the upper bound of the probe size, no larger than [`MAX_PROBE_SIZE`], and start over
+    /// the search.
+    pub(crate) fn set_max_mtu(&mut self, max_mtu: usize) {
+        self.max_mtu = max_mtu.clamp(MSS, MAX_PROBE_SIZE);
+        self.search_high = self.max_mtu.max(self.mtu);
+        self.probe_losses = 0;
+    }
+
+    /// Return the size of the next probe, or None if a probe is in flight or the search is done.
+    pub(crate) fn probe_size(&self) -> Option<usize> {
+        if self.probe.is_some() || self.search_high < self.mtu + MIN_PROBE_STEP {
+            return None;
+        }
+        Some((self.mtu + self.search_high).div_ceil(2))
+    }
+
+    pub(crate) fn on_probe_sent(&mut self, pn: u64, size: usize) {
+        self.probe = Some((pn, size));
+    }
+
+    pub(crate) fn is_probe(&self, pn: u64) -> bool {
+        self.probe.is_some_and(|(probe_pn, _)| probe_pn == pn)
+    }
+
+    pub(crate) fn on_probe_acked(&mut self) {
+        if let Some((_, size)) = self.probe.take() {
+            self.probe_losses = 0;
+            self.raise(size);
+        }
+    }
+
+    pub(crate) fn on_probe_lost(&mut self) {
+        if let Some((_, size)) = self.probe.take() {
+            self.probe_losses += 1;
+            if self.probe_losses >= MAX_PROBE_LOSSES {
+                // 该大小无法通过，缩小搜索范围
+                self.search_high = size - 1;
+                self.probe_losses = 0;
+            }
+        }
+    }
+
+    pub(crate) fn on_pkt_acked(&mut self, size: usize) {
+        if size > MSS {
+            self.lost_large = 0;
+            self.small_acked = false;
+        } else {
+            self.small_acked = true;
+            self.detect();
+        }
+    }
+
+    pub(crate) fn on_pkt_lost(&mut self, size: usize) {
+        if size > MSS {
+            self.lost_large += 1;
+            self.detect();
+        }
+    }
+
+    fn detect(&mut self) {
+        if self.mtu > MSS && self.lost_large >= BLACKHOLE_THRESHOLD && self.small_acked {
+            log::debug!(
+                "MTU black hole detected, {} packets larger than {MSS} are lost, fall back to {MSS}",
+                self.lost_large
+            );
+            self.mtu = MSS;
+            self.lost_large = 0;
+            self.small_acked = false;
+            // 重新探测路径的MTU
+            self.search_high = self.max_mtu;
+            self.probe_losses = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blackhole() {
+        let mut detector = MtuDetector::default();
+        detector.raise(1452);
+        assert_eq!(detector.mtu(), 1452);
+
+        // 大包全部丢失，但小包仍被确认
+        detector.on_pkt_acked(100);
+        for _ in 0..BLACKHOLE_THRESHOLD - 1 {
+            detector.on_pkt_lost(1452);
+        }
+        assert_eq!(detector.mtu(), 1452);
+        detector.on_pkt_lost(1452);
+        assert_eq!(detector.mtu(), MSS);
+
+        // 回落之后可以重新探测
+        detector.raise(1452);
+        assert_eq!(detector.mtu(), 1452);
+    }
+
+    #[test]
+    fn test_probe_search() {
+        let mut detector = MtuDetector::default();
+        // 未设置上限时不探测
+        assert_eq!(detector.probe_size(), None);
+        detector.set_max_mtu(65527);
+        assert_eq!(detector.max_mtu, MAX_PROBE_SIZE);
+
+        // 探测包被确认，MTU升至探测的大小
+        let size = detector.probe_size().unwrap();
+        assert_eq!(size, (MSS + MAX_PROBE_SIZE).div_ceil(2));
+        detector.on_probe_sent(0, size);
+        assert_eq!(detector.probe_size(), None);
+        assert!(detector.is_probe(0));
+        detector.on_probe_acked();
+        assert_eq!(detector.mtu(), size);
+
+        // 同一大小的探测包连续丢失，放弃该大小，在更小的范围内继续探测
+        let lost_size = detector.probe_size().unwrap();
+        for pn in 1..=MAX_PROBE_LOSSES as u64 {
+            assert_eq!(detector.probe_size(), Some(lost_size));
+            detector.on_probe_sent(pn, lost_size);
+            detector.on_probe_lost();
+        }
+        assert_eq!(detector.mtu(), size);
+        let next_size = detector.probe_size().unwrap();
+        assert!(size < next_size && next_size < lost_size);
+
+        // 搜索范围足够小时，停止探测
+        detector.search_high = detector.mtu + MIN_PROBE_STEP - 1;
+        assert_eq!(detector.probe_size(), None);
+    }
+
+    #[test]
+    fn test_reprobe_after_blackhole() {
+        let mut detector = MtuDetector::default();
+        detector.set_max_mtu(MAX_PROBE_SIZE);
+        detector.raise(MAX_PROBE_SIZE);
+        assert_eq!(detector.probe_size(), None);
+
+        detector.on_pkt_acked(100);
+        for _ in 0..BLACKHOLE_THRESHOLD {
+            detector.on_pkt_lost(MAX_PROBE_SIZE);
+        }
+        assert_eq!(detector.mtu(), MSS);
+        // 回落之后重新开始探测
+        assert_eq!(
+            detector.probe_size(),
+            Some((MSS + MAX_PROBE_SIZE).div_ceil(2))
+        );
+    }
+
+    #[test]
+    fn test_no_blackhole() {
+        let mut detector = MtuDetector::default();
+        detector.raise(1452);
+        // 小包同样丢失，是拥塞而非黑洞
+        for _ in 0..BLACKHOLE_THRESHOLD {
+            detector.on_pkt_lost(1452);
+            detector.on_pkt_lost(100);
+        }
+        assert_eq!(detector.mtu(), 1452);
+
+        // 有大包被确认，之前的丢失不再累计
+        detector.on_pkt_acked(1452);
+        detector.on_pkt_acked(100);
+        detector.on_pkt_lost(1452);
+        assert_eq!(detector.mtu(), 1452);
+    }
+}
@@ -7,7 +7,10 @@ use crate::congestion::{AckedPkt, Algorithm, MSS};
 // See https://datatracker.ietf.org/doc/html/rfc6928#autoid-3
 const INIT_CWND: u64 = 10 * MSS as u64;
 const INFINITRE_SSTHRESH: u64 = u64::MAX;
-const LOSS_REDUCTION_FACTOR: f64 = 0.5;
+/// The default multiplicative decrease factor of the congestion window on a congestion event, see
+/// [kLossReductionFactor](https://www.rfc-editor.org/rfc/rfc9002.html#name-constants-of-interest-2)
+/// of RFC 9002.
+pub const LOSS_REDUCTION_FACTOR: f64 = 0.5;
 
 pub(super) struct NewReno {
     // Congestion window.
@@ -19,15 +22,27 @@ pub(super) struct NewReno {
     bytes_acked: u64,
     // The time at which the most recent loss recovery period started.
     recovery_start_time: Option<Instant>,
+    // The multiplicative decrease factor on a congestion event, aka beta.
+    loss_reduction_factor: f64,
 }
 
 impl NewReno {
     pub(super) fn new() -> Self {
+        Self::with_loss_reduction_factor(LOSS_REDUCTION_FACTOR)
+    }
+
+    /// 拥塞时，拥塞窗口缩减为原来的`beta`倍，`beta`须在(0, 1)之间，由使用者在配置时检查
+    pub(super) fn with_loss_reduction_factor(beta: f64) -> Self {
+        debug_assert!(
+            beta > 0.0 && beta < 1.0,
+            "the loss reduction factor must be in (0, 1)"
+        );
         NewReno {
             cwnd: INIT_CWND,
             ssthresh: INFINITRE_SSTHRESH,
             bytes_acked: 0,
             recovery_start_time: None,
+            loss_reduction_factor: beta,
         }
     }
 
@@ -38,6 +53,19 @@ impl NewReno {
             .unwrap_or(false)
     }
 
+    // 丢包或者ECN-CE计数增加，均视为拥塞，对恢复期开始前发送的包只响应一次
+    fn enter_recovery(&mut self, time_sent: &Instant, now: Instant) {
+        if self.in_congestion_recovery(time_sent) {
+            return;
+        }
+        self.recovery_start_time = Some(now);
+        self.cwnd = (self.cwnd as f64 * self.loss_reduction_factor) as u64;
+        self.cwnd = self.cwnd.max(2 * MSS as u64);
+
+        self.bytes_acked = (self.bytes_acked as f64 * self.loss_reduction_factor) as u64;
+        self.ssthresh = self.cwnd;
+    }
+
     fn on_per_ack(&mut self, ack: &AckedPkt) {
         if self.in_congestion_recovery(&ack.time_sent) {
             return;
@@ -74,15 +102,11 @@ impl Algorithm for NewReno {
     }
 
     fn on_congestion_event(&mut self, lost: &crate::congestion::SentPkt, now: std::time::Instant) {
-        if self.in_congestion_recovery(&lost.time_sent) {
-            return;
-        }
-        self.recovery_start_time = Some(now);
-        self.cwnd = (self.cwnd as f64 * LOSS_REDUCTION_FACTOR) as u64;
-        self.cwnd = self.cwnd.max(2 * MSS as u64);
+        self.enter_recovery(&lost.time_sent, now);
+    }
 
-        self.bytes_acked = (self.bytes_acked as f64 * LOSS_REDUCTION_FACTOR) as u64;
-        self.ssthresh = self.cwnd;
+    fn on_ecn_ce(&mut self, time_sent: std::time::Instant, now: std::time::Instant) {
+        self.enter_recovery(&time_sent, now);
     }
 
     fn cwnd(&self) -> u64 {
@@ -171,16 +195,37 @@ mod tests {
         assert_eq!(reno.recovery_start_time, Some(time_lost));
     }
 
+    #[test]
+    fn test_reno_loss_reduction_factor() {
+        let mut reno = NewReno::with_loss_reduction_factor(0.7);
+        let now = Instant::now();
+        reno.ssthresh = 20 * MSS as u64;
+        reno.on_ack(generate_acks(0, 10), now);
+        assert_eq!(reno.cwnd, 20 * MSS as u64);
+
+        let lost = SentPkt {
+            pn: 11,
+            size: MSS,
+            time_sent: now,
+            ..Default::default()
+        };
+        reno.on_congestion_event(&lost, now + std::time::Duration::from_millis(100));
+        // 缩减为70%，而不是默认的50%
+        assert_eq!(reno.cwnd, 14 * MSS as u64);
+        assert_eq!(reno.ssthresh, 14 * MSS as u64);
+    }
+
     fn generate_acks(start: usize, end: usize) -> VecDeque<AckedPkt> {
         let mut acks = VecDeque::with_capacity(end - start);
+        let now = Instant::now();
         for i in start..end {
             let sent = SentPkt {
                 pn: i as u64,
                 size: MSS,
-                time_sent: Instant::now(),
+                time_sent: now,
                 ..Default::default()
             };
-            let ack: AckedPkt = sent.into();
+            let ack = AckedPkt::new(sent, now);
             acks.push_back(ack);
         }
         acks
@@ -6,7 +6,7 @@ const MIN_BURST_SIZE: u64 = 10;
 const MAX_BURST_SIZE: u64 = 128;
 // Using a value for N that is small, but at least 1 (for example, 1.25)
 // ensures that variations in RTT do not result in underutilization of the congestion window.
-const N: f64 = 1.25;
+pub(super) const N: f64 = 1.25;
 
 pub(super) struct Pacer {
     capacity: u64,
@@ -35,6 +35,11 @@ impl Pacer {
         }
     }
 
+    #[cfg(test)]
+    pub(super) fn tokens(&self) -> u64 {
+        self.tokens
+    }
+
     pub(super) fn on_sent(&mut self, packet_size: u64) {
         self.tokens = self.tokens.saturating_sub(packet_size);
     }
@@ -3,6 +3,10 @@ use std::{
     time::{Duration, Instant},
 };
 
+/// The RTT assumed before any RTT sample is taken.
+///
+/// See [Section 6.2.2](https://www.rfc-editor.org/rfc/rfc9002.html#name-handshakes-and-new-paths)
+/// of [RFC 9002](https://www.rfc-editor.org/rfc/rfc9002.html).
 pub const INITIAL_RTT: Duration = Duration::from_millis(333);
 const GRANULARITY: Duration = Duration::from_millis(1);
 const TIME_THRESHOLD: f32 = 1.125;
@@ -19,18 +23,22 @@ pub struct Rtt {
 
 impl Default for Rtt {
     fn default() -> Self {
+        Self::new(INITIAL_RTT, Duration::from_millis(0))
+    }
+}
+
+impl Rtt {
+    fn new(initial_rtt: Duration, max_ack_delay: Duration) -> Self {
         Self {
-            max_ack_delay: Duration::from_millis(0),
+            max_ack_delay,
             first_rtt_sample: None,
             latest_rtt: Duration::from_millis(0),
-            smoothed_rtt: INITIAL_RTT,
-            rttvar: INITIAL_RTT / 2,
+            smoothed_rtt: initial_rtt,
+            rttvar: initial_rtt / 2,
             min_rtt: Duration::from_millis(0),
         }
     }
-}
 
-impl Rtt {
     fn update(
         &mut self,
         latest_rtt: Duration,
@@ -49,9 +57,12 @@ impl Rtt {
         // min_rtt ignores acknowledgment delay.
         self.min_rtt = std::cmp::min(self.min_rtt, latest_rtt);
 
-        // Limit ack_delay by max_ack_delay after handshake confirmation.
+        // Limit ack_delay by max_ack_delay after handshake confirmation,
+        // and ignore it before, see RFC 9002 Section 5.3.
         if is_handshake_confirmed {
             ack_delay = std::cmp::min(ack_delay, self.max_ack_delay);
+        } else {
+            ack_delay = Duration::ZERO;
         }
 
         // Adjust for acknowledgment delay if plausible.
@@ -82,8 +93,9 @@ pub struct ArcRtt(Arc<Mutex<Rtt>>);
 
 /// 对外只需暴露ArcRtt，Rtt成为内部实现
 impl ArcRtt {
-    pub fn new() -> Self {
-        Self(Arc::new(Mutex::new(Rtt::default())))
+    /// Create a new RTT estimator, the `initial_rtt` is used until the first RTT sample.
+    pub fn new(initial_rtt: Duration, max_ack_delay: Duration) -> Self {
+        Self(Arc::new(Mutex::new(Rtt::new(initial_rtt, max_ack_delay))))
     }
 
     pub fn update(&self, latest_rtt: Duration, ack_delay: Duration, is_handshake_confirmed: bool) {
@@ -107,4 +119,32 @@ impl ArcRtt {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    fn sampled_rtt(is_handshake_confirmed: bool) -> Duration {
+        let rtt = ArcRtt::new(INITIAL_RTT, Duration::from_millis(25));
+        rtt.update(
+            Duration::from_millis(100),
+            Duration::ZERO,
+            is_handshake_confirmed,
+        );
+        rtt.update(
+            Duration::from_millis(120),
+            Duration::from_millis(20),
+            is_handshake_confirmed,
+        );
+        rtt.smoothed_rtt()
+    }
+
+    #[test]
+    fn test_ack_delay_before_confirmed() {
+        let approx =
+            |rtt: Duration, expected: Duration| rtt.abs_diff(expected) < Duration::from_micros(1);
+        // 握手确认后，扣除ack_delay得到100ms的样本
+        assert!(approx(sampled_rtt(true), Duration::from_millis(100)));
+        // 握手确认前忽略ack_delay，样本仍为120ms
+        assert!(sampled_rtt(false) > sampled_rtt(true));
+        assert!(approx(sampled_rtt(false), Duration::from_micros(102_500)));
+    }
+}
@@ -1,5 +1,4 @@
 use std::{
-    borrow::Cow,
     fmt::Debug,
     io,
     ops::{Deref, DerefMut},
@@ -10,29 +9,35 @@ use std::{
 use closing::ClosingConnection;
 use draining::DrainingConnection;
 use futures::channel::mpsc;
+use keep_alive::KeepAlive;
 use qbase::{
     cid::{self, ConnectionId},
     error::{Error, ErrorKind},
     flow,
-    packet::{DataPacket, RetryHeader},
+    packet::{
+        r#type::long::{GetVersion, Ver1},
+        DataPacket, RetryPacket, SpinBit,
+    },
     param::{ArcParameters, ClientParameters, CommonParameters, Pair, ServerParameters},
-    sid::{Role, StreamId},
-    token::ArcTokenRegistry,
+    sid::{Dir, Role, StreamId},
+    token::{ArcTokenRegistry, ResetToken},
 };
+use qcongestion::CongestionAlgorithm;
 use qrecovery::{
     recv,
     reliable::ArcReliableFrameDeque,
     send,
     streams::{self, Ext},
 };
-use qunreliable::{UnreliableReader, UnreliableWriter};
+use qunreliable::{DatagramStats, UnreliableReader, UnreliableWriter};
 use raw::Connection;
-use tokio::task::JoinHandle;
 
 use crate::{
     conn::ConnState::{Closed, Closing, Draining, Invalid, Normal},
-    path::Pathway,
+    error::HandshakeError,
+    path::{PathStats, Pathway, DEFAULT_ANTI_FACTOR},
     router::{Router, RouterRegistry},
+    spawn::{JoinHandle, Runtime, Spawner},
     tls::ArcTlsSession,
     usc::ArcUsc,
 };
@@ -41,6 +46,7 @@ use crate::{
 
 pub mod closing;
 pub mod draining;
+pub mod keep_alive;
 pub mod raw;
 pub mod space;
 pub mod transmit;
@@ -63,7 +69,7 @@ pub type Handshake = qbase::handshake::Handshake<ArcReliableFrameDeque>;
 
 // TODO: 巨大提升空间
 enum ConnState {
-    Normal(Connection),
+    Normal(Box<Connection>),
     Closing(ClosingConnection),
     Draining(DrainingConnection),
     Closed(Error),
@@ -74,7 +80,7 @@ impl ConnState {
     fn try_enter_closing(
         &mut self,
         error: Error,
-    ) -> Option<([JoinHandle<RcvdPackets>; 4], Duration)> {
+    ) -> Option<([JoinHandle<RcvdPackets>; 4], Duration, Spawner)> {
         let conn = std::mem::replace(self, Invalid);
         let Normal(connection) = conn else {
             // has been closing/draining
@@ -89,6 +95,8 @@ impl ConnState {
         let one_rtt = connection.data.try_into().ok();
 
         let recv_packets = connection.join_handles;
+        // 连接的任务已被取消，关闭阶段的任务须脱离连接独立运行
+        let spawner = connection.spawner.clone();
 
         *self = match (hs, one_rtt) {
             (None, None) => {
@@ -102,7 +110,7 @@ impl ConnState {
                 let last_dcid = connection.cid_registry.remote.latest_dcid();
                 let closing_connection =
                     ClosingConnection::new(error, local_cids, hs, one_rtt, initial_scid, last_dcid);
-                tokio::spawn({
+                spawner.spawn_detached({
                     let pathes = connection.paths;
                     let closing_connection = closing_connection.clone();
                     async move {
@@ -116,10 +124,10 @@ impl ConnState {
             }
         };
 
-        Some((recv_packets, pto_time))
+        Some((recv_packets, pto_time, spawner))
     }
 
-    fn enter_draining(&mut self, error: Error) -> Option<Duration> {
+    fn enter_draining(&mut self, error: Error) -> Option<(Duration, Spawner)> {
         let conn = std::mem::replace(self, Invalid);
         let Normal(connection) = conn else {
             // has been closing/draining
@@ -131,7 +139,8 @@ impl ConnState {
         let local_cids = connection.cid_registry.local.active_cids();
         *self = Draining(DrainingConnection::new(local_cids, error));
 
-        connection.max_pto_duration()
+        let spawner = connection.spawner.clone();
+        connection.max_pto_duration().map(|pto| (pto, spawner))
     }
 
     fn no_vaiable_path(&mut self) {
@@ -148,16 +157,21 @@ impl ConnState {
 
     fn die(&mut self) {
         let conn = std::mem::replace(self, Invalid);
-        let local_cids = match &conn {
-            Closing(conn) => conn.local_cids(),
-            Draining(conn) => conn.local_cids(),
-            Closed(..) => return,
+        let (local_cids, error) = match &conn {
+            Closing(conn) => (conn.local_cids(), conn.error()),
+            Draining(conn) => (conn.local_cids(), conn.error()),
+            Closed(..) => {
+                *self = conn;
+                return;
+            }
             Normal(..) | Invalid => unreachable!(),
         };
 
         for cid in local_cids {
             Router::remove(cid);
         }
+        // 保留关闭的原因，以便应用层查询
+        *self = Closed(error.clone());
     }
 }
 
@@ -171,6 +185,7 @@ impl Debug for ArcConnection {
 }
 
 impl ArcConnection {
+    #[allow(clippy::too_many_arguments)]
     pub fn new_client(
         initial_scid: ConnectionId,
         server_name: String,
@@ -179,6 +194,10 @@ impl ArcConnection {
         streams_ctrl: Box<dyn qbase::sid::ControlConcurrency>,
         tls_config: Arc<rustls::ClientConfig>,
         token_registry: ArcTokenRegistry,
+        initial_rtt: Duration,
+        congestion_algorithm: CongestionAlgorithm,
+        max_send_udp_payload: Option<usize>,
+        runtime: Arc<dyn Runtime>,
     ) -> Self {
         let Ok(server_name) = server_name.try_into() else {
             panic!("server_name is not valid")
@@ -187,6 +206,8 @@ impl ArcConnection {
         let initial_dcid = ConnectionId::random_gen(8);
         let parameters = ArcParameters::new_client(parameters, remembered);
         parameters.set_initial_scid(initial_scid);
+        parameters.set_chosen_version(Ver1::INITIAL.get_version());
+        parameters.chosen_version_from_peer_need_equal(Ver1::INITIAL.get_version());
         parameters.original_dcid_from_server_need_equal(initial_dcid);
 
         let tls_session = ArcTlsSession::new_client(server_name, tls_config.clone(), &parameters);
@@ -204,6 +225,11 @@ impl ArcConnection {
             initial_keys,
             streams_ctrl,
             token_registry,
+            initial_rtt,
+            congestion_algorithm,
+            DEFAULT_ANTI_FACTOR,
+            max_send_udp_payload,
+            runtime,
         );
         connection.into()
     }
@@ -225,9 +251,16 @@ impl ArcConnection {
         streams_ctrl: Box<dyn qbase::sid::ControlConcurrency>,
         tls_config: Arc<rustls::ServerConfig>,
         token_registry: ArcTokenRegistry,
+        initial_rtt: Duration,
+        congestion_algorithm: CongestionAlgorithm,
+        anti_factor: usize,
+        max_send_udp_payload: Option<usize>,
+        runtime: Arc<dyn Runtime>,
     ) -> Self {
         let parameters = ArcParameters::new_server(parameters);
         parameters.set_initial_scid(initial_scid);
+        parameters.set_chosen_version(Ver1::INITIAL.get_version());
+        parameters.chosen_version_from_peer_need_equal(Ver1::INITIAL.get_version());
         parameters.set_original_dcid(origin_dcid);
 
         let tls_session = ArcTlsSession::new_server(tls_config.clone(), &parameters);
@@ -240,6 +273,11 @@ impl ArcConnection {
             initial_keys,
             streams_ctrl,
             token_registry,
+            initial_rtt,
+            congestion_algorithm,
+            anti_factor,
+            max_send_udp_payload,
+            runtime,
         );
         connection.into()
     }
@@ -304,6 +342,60 @@ impl ArcConnection {
         }
     }
 
+    /// Return how many new bidirectional streams can be opened before being blocked.
+    ///
+    /// It is the peer's MAX_STREAMS limit minus the bidirectional streams opened, and increases
+    /// when the MAX_STREAMS frames arrive. Return 0 once the connection is closed.
+    pub fn available_bidi_streams(&self) -> u64 {
+        self.available_streams(Dir::Bi)
+    }
+
+    /// Return how many new unidirectional streams can be opened before being blocked.
+    ///
+    /// See [`ArcConnection::available_bidi_streams`] for more details.
+    pub fn available_uni_streams(&self) -> u64 {
+        self.available_streams(Dir::Uni)
+    }
+
+    fn available_streams(&self, dir: Dir) -> u64 {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.data.streams.available_streams(dir),
+            _ => 0,
+        }
+    }
+
+    /// Install the scheduler deciding the emission order of the streams and how much data each of
+    /// them can send, the streams send their data in turn by default.
+    ///
+    /// For example, HTTP/3 can schedule the streams according to the
+    /// [extensible priorities](https://www.rfc-editor.org/rfc/rfc9218.html) on top of it, see
+    /// [`StreamScheduler`].
+    ///
+    /// It's ignored once the connection is closed.
+    ///
+    /// [`StreamScheduler`]: qrecovery::streams::StreamScheduler
+    pub fn set_stream_scheduler(&self, scheduler: Box<dyn qrecovery::streams::StreamScheduler>) {
+        if let Normal(connection) = self.0.lock().unwrap().deref() {
+            connection.data.streams.set_scheduler(scheduler);
+        }
+    }
+
+    /// Return the number of bytes buffered for sending in all the streams of the connection, the
+    /// data written by the application but not yet acknowledged by the peer.
+    ///
+    /// Applications can apply their own backpressure above the connection according to it, see
+    /// [`DataStreams::send_buffered_bytes`].
+    ///
+    /// Return 0 once the connection is closed.
+    ///
+    /// [`DataStreams::send_buffered_bytes`]: qrecovery::streams::raw::DataStreams::send_buffered_bytes
+    pub fn send_buffered_bytes(&self) -> u64 {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.data.streams.send_buffered_bytes(),
+            _ => 0,
+        }
+    }
+
     pub async fn accept_bi_stream(
         &self,
     ) -> io::Result<Option<(StreamId, (StreamReader, StreamWriter))>> {
@@ -396,18 +488,60 @@ impl ArcConnection {
     /// Closes the connection with a specified error.
     /// This function is intended for use by the application layer to signal an
     /// error and initiate the connection closure.
-    pub fn close(&self, msg: impl Into<Cow<'static, str>>) {
+    ///
+    /// The [`Error::App`] carries an application error code, such as the HTTP/3 error codes,
+    /// and is sent in a CONNECTION_CLOSE frame of type 0x1d; the [`Error::Quic`] is sent in
+    /// a CONNECTION_CLOSE frame of type 0x1c.
+    pub fn close(&self, error: impl Into<Error>) {
         let mut guard = self.0.lock().unwrap();
         if let Normal(connection) = guard.deref_mut() {
-            let msg = msg.into();
-            log::info!("Connection is closed by application: {}", msg);
-            let error = Error::with_default_fty(ErrorKind::Application, msg);
+            let error = error.into();
+            log::info!("Connection is closed by application: {}", error);
             connection.error.set_app_error(error.clone());
             drop(guard);
             self.should_enter_closing(error);
         }
     }
 
+    /// Return the reason why the connection is closed, or `None` if it is not closed yet.
+    ///
+    /// The reason may be closed by the application locally, or be received from the peer
+    /// through the CONNECTION_CLOSE frame, in which case an [`Error::App`] means that the peer
+    /// closed the connection with an application error code.
+    pub fn close_reason(&self) -> Option<Error> {
+        match self.0.lock().unwrap().deref() {
+            Normal(..) => None,
+            Closing(closing) => Some(closing.error().clone()),
+            Draining(draining) => Some(draining.error().clone()),
+            Closed(error) => Some(error.clone()),
+            Invalid => unreachable!(),
+        }
+    }
+
+    /// Wait until the connection is closed, and return the reason, see [`ArcConnection::close_reason`].
+    ///
+    /// It resolves once the connection leaves the normal state for any reason: closed by the
+    /// application, closed by the peer, idle timeout, no viable path, or a connection error. Any
+    /// number of tasks can wait at the same time, and all of them get the same reason. If the
+    /// connection has been closed, it returns immediately.
+    pub async fn closed(&self) -> Error {
+        let notify = match self.0.lock().unwrap().deref() {
+            Normal(connection) => Some(connection.notify.clone()),
+            _ => None,
+        };
+        if let Some(notify) = notify {
+            // 连接离开Normal状态时会notify_waiters，须先订阅，再检查是否已关闭
+            let notified = notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.close_reason().is_none() {
+                notified.await;
+            }
+        }
+        self.close_reason()
+            .expect("the connection must have been closed")
+    }
+
     /// This function transitioning connection to a `Closing` state and
     /// initiating a background task to manage the closing handshake. This task awaits
     /// confirmation from the peer (Connection Close Frame) within a timeout derived
@@ -420,13 +554,13 @@ impl ArcConnection {
         if !matches!(state, Normal(..)) {
             return;
         }
-        let Some((handles, pto)) = state.try_enter_closing(error) else {
+        let Some((handles, pto, spawner)) = state.try_enter_closing(error) else {
             return;
         };
 
         match state {
             Closing(closing) => {
-                tokio::spawn({
+                spawner.spawn_detached({
                     let mut closing = closing.clone();
                     async move {
                         use futures::StreamExt;
@@ -440,14 +574,15 @@ impl ArcConnection {
                         }
                     }
                 });
-                tokio::spawn({
+                spawner.spawn_detached({
                     let conn = self.clone();
+                    let spawner = spawner.clone();
                     let rcvd_ccf = closing.get_rcvd_ccf();
                     async move {
                         let start = Instant::now();
                         let time = pto * 3;
-                        match tokio::time::timeout(time, rcvd_ccf.did_recv()).await {
-                            Ok(_) => conn.draining(pto * 3 - start.elapsed()),
+                        match spawner.timeout(time, rcvd_ccf.did_recv()).await {
+                            Ok(_) => conn.draining(pto * 3 - start.elapsed(), &spawner),
                             Err(_) => conn.die(),
                         }
                     }
@@ -456,30 +591,31 @@ impl ArcConnection {
             Draining(..) => {
                 drop(guard);
                 drop(handles); // break the channels
-                self.draining(pto * 3)
+                self.draining(pto * 3, &spawner)
             }
             _ => unreachable!(),
         }
     }
 
     fn enter_draining(&self, error: Error) {
-        let Some(pto) = self.0.lock().unwrap().deref_mut().enter_draining(error) else {
+        let Some((pto, spawner)) = self.0.lock().unwrap().deref_mut().enter_draining(error) else {
             // has been closed
             return;
         };
 
-        self.draining(pto * 3);
+        self.draining(pto * 3, &spawner);
     }
 
     /// Enter draining state from raw state or closing state.
     /// Can only be called internally, and the app should not care this method.
-    fn draining(&self, remaining: Duration) {
+    fn draining(&self, remaining: Duration, spawner: &Spawner) {
         assert!(matches!(self.0.lock().unwrap().deref_mut(), Draining(..)));
 
-        tokio::spawn({
+        spawner.spawn_detached({
             let conn = self.clone();
+            let sleep = spawner.sleep(remaining);
             async move {
-                tokio::time::sleep(remaining).await;
+                sleep.await;
                 conn.die();
             }
         });
@@ -504,9 +640,14 @@ impl ArcConnection {
         }
     }
 
-    pub fn recv_retry_packet(&self, retry: &RetryHeader) {
+    pub fn recv_retry_packet(&self, retry: &RetryPacket) {
         let guard = self.0.lock().unwrap();
         if let Normal(ref connection) = *guard {
+            // 无法通过完整性校验的Retry包，可能是路径外的攻击者伪造的，直接丢弃
+            if !retry.verify_integrity(&connection.initial_dcid) {
+                log::warn!("Retry packet failed the integrity verification, discarded");
+                return;
+            }
             *connection.token.lock().unwrap() = retry.token.to_vec();
             connection
                 .cid_registry
@@ -530,14 +671,239 @@ impl ArcConnection {
         let guard = self.0.lock().unwrap();
         !matches!(&*guard, ConnState::Normal(..))
     }
+
+    /// Set the policy of sending PING frames to keep the connection alive, see [`KeepAlive`].
+    ///
+    /// Calling this again replaces the previous policy. It has no effect once the connection is
+    /// closed.
+    pub fn keep_alive(&self, policy: KeepAlive) {
+        if let Normal(connection) = self.0.lock().unwrap().deref_mut() {
+            connection.keep_alive(policy);
+        }
+    }
+
+    /// Wake the sending tasks of all paths, to send the pending data immediately.
+    ///
+    /// Normally the sending task is woken up by the periodical tick of the congestion
+    /// controller. For the latency-sensitive applications, call this method after writing a
+    /// request, then the pending stream data and frames will be assembled and sent right away,
+    /// as far as the congestion control and the flow control allow. It does nothing if the
+    /// connection is closing or closed.
+    pub fn flush(&self) {
+        if let Normal(connection) = self.0.lock().unwrap().deref() {
+            for path in connection.paths.iter() {
+                path.cc().wake_sending();
+            }
+        }
+    }
+
+    /// Return the latency spin bit that is sent in the 1-RTT packets, see [`ArcSpin`].
+    ///
+    /// The connection may have multiple paths, the spin bit of any path is returned. Return
+    /// `None` if there is no path, or the connection is closed.
+    ///
+    /// [`ArcSpin`]: crate::path::ArcSpin
+    pub fn current_spin_bit(&self) -> Option<SpinBit> {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection
+                .paths
+                .iter()
+                .next()
+                .map(|path| path.spin().current()),
+            _ => None,
+        }
+    }
+
+    /// Subscribe to the flips of the spin bit observed from the peer, on all paths.
+    ///
+    /// Each time the spin bit of the received 1-RTT packets flips, the new value is sent to the
+    /// receiver, so that the RTT could be measured externally. Return `None` if the connection is
+    /// closed.
+    pub fn spin_flips(&self) -> Option<mpsc::UnboundedReceiver<SpinBit>> {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => Some(connection.spin_observers.subscribe()),
+            _ => None,
+        }
+    }
+
+    /// Return whether the handshake is complete, that is, the 1-RTT keys are available.
+    ///
+    /// Always return `false` once the connection is closed.
+    pub fn is_handshake_complete(&self) -> bool {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.handshake.is_handshake_complete(),
+            _ => false,
+        }
+    }
+
+    /// Wait until the handshake is complete, see [`ArcConnection::is_handshake_complete`].
+    ///
+    /// If the connection is closed before that, the reason is returned as a [`HandshakeError`].
+    /// A TLS handshake failure, either locally or reported by the peer, such as the certificate is
+    /// not trusted, is returned as [`HandshakeError::Tls`] with the TLS alert and the description
+    /// of the problem.
+    pub async fn established(&self) -> Result<(), HandshakeError> {
+        let handshaked = match self.0.lock().unwrap().deref() {
+            Normal(connection) => Some(connection.tls_session.handshaked()),
+            _ => None,
+        };
+        let Some(mut handshaked) = handshaked else {
+            return Err(self.closed().await.into());
+        };
+        tokio::select! {
+            biased;
+            Ok(_) = handshaked.wait_for(|done| *done) => Ok(()),
+            error = self.closed() => Err(error.into()),
+        }
+    }
+
+    /// Return whether the handshake is confirmed.
+    ///
+    /// The client confirms the handshake when it receives the HANDSHAKE_DONE frame, and the
+    /// server confirms it as soon as the handshake is complete, see
+    /// [section 4.1.2](https://www.rfc-editor.org/rfc/rfc9001.html#section-4.1.2)
+    /// of [RFC 9001](https://www.rfc-editor.org/rfc/rfc9001.html).
+    ///
+    /// Always return `false` once the connection is closed.
+    pub fn is_handshake_confirmed(&self) -> bool {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.handshake.is_handshake_confirmed(),
+            _ => false,
+        }
+    }
+
+    /// Return whether the address of the peer is validated, that is, the anti-amplification limit
+    /// has been lifted on all paths.
+    ///
+    /// Before that, the server can only send three times the amount of data received from the
+    /// peer. The limit is lifted once the handshake is complete, a valid token is received, or the
+    /// path validation succeeds, see
+    /// [section 8](https://www.rfc-editor.org/rfc/rfc9000.html#section-8)
+    /// of [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html). The client always regards the
+    /// server address as validated.
+    ///
+    /// Always return `false` if there is no path, or once the connection is closed.
+    pub fn is_peer_validated(&self) -> bool {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => {
+                let mut paths = connection.paths.iter().peekable();
+                paths.peek().is_some() && paths.all(|path| path.is_validated())
+            }
+            _ => false,
+        }
+    }
+
+    /// Return the statistics of all the paths of the connection, such as their addresses, whether
+    /// they are validated, their RTT and congestion window, see [`PathStats`].
+    ///
+    /// Return an empty list once the connection is closed.
+    pub fn path_stats(&self) -> Vec<PathStats> {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.paths.stats(),
+            _ => vec![],
+        }
+    }
+
+    /// Return the packets sent but neither acknowledged nor declared lost in all spaces, for
+    /// debugging the stalled connection.
+    ///
+    /// Return an empty list once the connection is closed.
+    #[cfg(any(test, feature = "inflight-dump"))]
+    pub fn inflight_packets(&self) -> Vec<raw::InflightPacket> {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.inflight_packets(),
+            _ => vec![],
+        }
+    }
+
+    /// Return the active connection IDs issued by us, with their sequence numbers and stateless
+    /// reset tokens, for diagnostics.
+    ///
+    /// Return an empty list once the connection is closed.
+    pub fn local_cids(&self) -> Vec<(u64, ConnectionId, ResetToken)> {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.cid_registry.local.active_cid_records(),
+            _ => vec![],
+        }
+    }
+
+    /// Return the active connection IDs issued by the peer, with their sequence numbers and
+    /// stateless reset tokens, for diagnostics.
+    ///
+    /// Return an empty list once the connection is closed.
+    pub fn remote_cids(&self) -> Vec<(u64, ConnectionId, ResetToken)> {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.cid_registry.remote.active_cid_records(),
+            _ => vec![],
+        }
+    }
+
+    /// Return the effective idle timeout negotiated with the peer, [`Duration::ZERO`] means the
+    /// connection never idles out, see [`ArcParameters::idle_timeout`].
+    ///
+    /// Applications can choose their [`KeepAlive`] interval according to it.
+    ///
+    /// Return [`Duration::ZERO`] once the connection is closed.
+    pub fn idle_timeout(&self) -> Duration {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.params.idle_timeout(),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Return the TLS cipher suite negotiated by the handshake, its AEAD also protects the QUIC
+    /// packets, see [`ArcTlsSession::negotiated_cipher_suite`].
+    ///
+    /// Return `None` before the suite is negotiated, or once the connection is closed.
+    pub fn negotiated_cipher_suite(&self) -> Option<rustls::SupportedCipherSuite> {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.tls_session.negotiated_cipher_suite(),
+            _ => None,
+        }
+    }
+
+    /// Return the number of the datagrams sent, received and dropped on the connection, see
+    /// [`DatagramStats`].
+    ///
+    /// Return `None` once the connection is closed.
+    pub fn datagram_stats(&self) -> Option<DatagramStats> {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => connection.data.datagrams.stats(),
+            _ => None,
+        }
+    }
+
+    /// Return the statistics of the connection, such as how many bytes were sent as early data and
+    /// whether the server accepted them, see [`raw::ConnectionStats`].
+    ///
+    /// It helps the applications to decide whether 0-RTT is worth it for their traffic.
+    ///
+    /// Return `None` once the connection is closed.
+    pub fn stats(&self) -> Option<raw::ConnectionStats> {
+        match self.0.lock().unwrap().deref() {
+            Normal(connection) => Some(connection.stats()),
+            _ => None,
+        }
+    }
+
+    /// Return the QUIC version that the connection uses.
+    ///
+    /// Version negotiation is not supported yet, both the client and the server always use
+    /// QUIC version 1, the version in the long headers of the packets sent.
+    pub fn version(&self) -> u32 {
+        Ver1::INITIAL.get_version()
+    }
 }
 
 impl From<Connection> for ArcConnection {
     fn from(normal_conn: Connection) -> Self {
         let conn_error = normal_conn.error.clone();
-        let connection = ArcConnection(Arc::new(Mutex::new(ConnState::Normal(normal_conn))));
+        let spawner = normal_conn.spawner.clone();
+        let connection = ArcConnection(Arc::new(Mutex::new(ConnState::Normal(Box::new(
+            normal_conn,
+        )))));
 
-        tokio::spawn({
+        spawner.spawn_detached({
             let conn = connection.clone();
             async move {
                 let (err, kind) = conn_error.did_error_occur().await;
@@ -557,4 +923,407 @@ impl From<Connection> for ArcConnection {
     }
 }
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use bytes::BytesMut;
+    use qbase::{
+        frame::{
+            ConnectionCloseFrame, FrameType, MaxStreamsFrame, PingFrame, ReceiveFrame,
+            StreamCtlFrame,
+        },
+        packet::{
+            header::long::io::LongHeaderBuilder, retry::retry_integrity_tag, MarshalFrame, Packet,
+            PacketNumber, PacketReader, PacketWriter,
+        },
+        param::{ClientParameters, ServerParameters, WriteServerParameters},
+        sid::handy::ConsistentConcurrency,
+        token::ArcTokenRegistry,
+        varint::VarInt,
+        Epoch,
+    };
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::{
+        spawn::TokioRuntime,
+        usc::{bind_udp_socket, UscRegistry},
+    };
+
+    fn client() -> ArcConnection {
+        client_on(Arc::new(TokioRuntime))
+    }
+
+    fn client_on(runtime: Arc<dyn Runtime>) -> ArcConnection {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let tls_config = rustls::ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        ArcConnection::new_client(
+            ConnectionId::random_gen(8),
+            "localhost".to_string(),
+            ClientParameters::default(),
+            None,
+            Box::new(ConsistentConcurrency::new(0, 0)),
+            Arc::new(tls_config),
+            ArcTokenRegistry::default_sink("localhost".to_string()),
+            qcongestion::INITIAL_RTT,
+            CongestionAlgorithm::Bbr,
+            None,
+            runtime,
+        )
+    }
+
+    /// Add a path on a new local socket to the connection, the packets received on the socket
+    /// are never delivered.
+    fn add_local_path(conn: &ArcConnection) -> (Pathway, ArcUsc) {
+        let local: std::net::SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let recv_task = |usc: ArcUsc| async move {
+            let _usc = usc;
+            core::future::pending::<()>().await;
+        };
+        let usc =
+            UscRegistry::create_new_usc(local, bind_udp_socket, &TokioRuntime, recv_task).unwrap();
+        let pathway = Pathway::Direct {
+            local: usc.local_addr(),
+            remote: "127.0.0.1:9".parse().unwrap(),
+        };
+        conn.add_initial_path(pathway, usc.clone());
+        (pathway, usc)
+    }
+
+    /// Return the token and the dcid the client currently uses, and the original dcid.
+    fn client_state(conn: &ArcConnection) -> (Vec<u8>, Option<ConnectionId>, ConnectionId) {
+        let guard = conn.0.lock().unwrap();
+        let Normal(connection) = guard.deref() else {
+            panic!("client should be in normal state");
+        };
+        let token = connection.token.lock().unwrap().clone();
+        let dcid = connection.cid_registry.remote.latest_dcid();
+        (token, dcid, connection.initial_dcid)
+    }
+
+    fn retry_packet(original_dcid: &ConnectionId, tampered: bool) -> RetryPacket {
+        let token = b"retry token".to_vec();
+        let header = LongHeaderBuilder::with_cid(
+            ConnectionId::random_gen(8),
+            ConnectionId::from_slice(b"new scid"),
+        )
+        .retry(token.clone(), [0; 16]);
+        let mut bytes = BytesMut::from(&b"\xf0 the retry packet"[..]);
+        bytes.extend_from_slice(&token);
+        let integrity = retry_integrity_tag(original_dcid, &bytes);
+        bytes.extend_from_slice(&integrity);
+        if tampered {
+            *bytes.last_mut().unwrap() ^= 0x01;
+        }
+        RetryPacket { header, bytes }
+    }
+
+    #[tokio::test]
+    async fn tampered_retry_is_ignored() {
+        let conn = client();
+        let (token, dcid, original_dcid) = client_state(&conn);
+
+        conn.recv_retry_packet(&retry_packet(&original_dcid, true));
+        assert_eq!(client_state(&conn), (token, dcid, original_dcid));
+
+        conn.recv_retry_packet(&retry_packet(&original_dcid, false));
+        let (token, dcid, _) = client_state(&conn);
+        assert_eq!(token, b"retry token");
+        assert_eq!(dcid, Some(ConnectionId::from_slice(b"new scid")));
+    }
+
+    #[tokio::test]
+    async fn handshake_not_complete_before_tls_finished() {
+        let conn = client();
+        assert!(!conn.is_handshake_complete());
+        assert!(!conn.is_handshake_confirmed());
+    }
+
+    #[tokio::test]
+    async fn send_window_from_remote_params() {
+        let conn = client();
+        let (params, flow_ctrl, original_dcid) = match conn.0.lock().unwrap().deref() {
+            Normal(connection) => (
+                connection.params.clone(),
+                connection.flow_ctrl.clone(),
+                connection.initial_dcid,
+            ),
+            _ => panic!("client should be in normal state"),
+        };
+        // 没有记忆的传输参数，不发送0-RTT数据
+        assert!(params.remembered().is_none());
+
+        let server_scid = ConnectionId::random_gen(8);
+        params.initial_scid_from_peer_need_equal(server_scid);
+        let mut server_params = ServerParameters::default();
+        server_params
+            .set_initial_source_connection_id(server_scid)
+            .set_initial_max_data(VarInt::from_u32(1 << 20));
+        server_params.set_original_destination_connection_id(original_dcid);
+        let mut raw = Vec::new();
+        raw.put_server_parameters(&server_params);
+        params.recv_remote_params(&raw).unwrap();
+
+        // 对端的initial_max_data到来后，发送窗口不再是占位的额度
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while flow_ctrl.send_limit().unwrap().available() != 1 << 20 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the send window should be reset to the peer's initial_max_data");
+    }
+
+    #[tokio::test]
+    async fn keep_alive_only_with_streams() {
+        let conn = client();
+        let ping_sndbuf = match conn.0.lock().unwrap().deref() {
+            Normal(connection) => connection.data.ping_sndbuf.clone(),
+            _ => panic!("client should be in normal state"),
+        };
+        let interval = Duration::from_millis(10);
+
+        // 没有打开的流，不发送保活的PING帧
+        conn.keep_alive(KeepAlive::WithStreams(interval));
+        tokio::time::sleep(interval * 5).await;
+        assert_eq!(ping_sndbuf.try_read(&mut [0; 8]), 0);
+
+        conn.keep_alive(KeepAlive::Always(interval));
+        tokio::time::sleep(interval * 5).await;
+        assert_eq!(ping_sndbuf.try_read(&mut [0; 8]), 1);
+    }
+
+    #[tokio::test]
+    async fn version_without_negotiation() {
+        // 未经版本协商，直接使用QUIC v1建立连接
+        assert_eq!(client().version(), 0x00000001);
+    }
+
+    #[tokio::test]
+    async fn close_reason_of_rcvd_app_ccf() {
+        let conn = client();
+        assert!(conn.close_reason().is_none());
+
+        let conn_error = match conn.0.lock().unwrap().deref() {
+            Normal(connection) => connection.error.clone(),
+            _ => panic!("client should be in normal state"),
+        };
+        let ccf = ConnectionCloseFrame::new_app(VarInt::from_u32(0x100), "H3_NO_ERROR".into());
+        conn_error.on_ccf_rcvd(&ccf);
+
+        let reason = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                match conn.close_reason() {
+                    Some(reason) => break reason,
+                    None => tokio::task::yield_now().await,
+                }
+            }
+        })
+        .await
+        .expect("the connection should enter draining state");
+        let Error::App(app_error) = reason else {
+            panic!("the peer closed the connection with an application error code");
+        };
+        assert_eq!(app_error.error_code(), VarInt::from_u32(0x100));
+        assert_eq!(app_error.reason(), "H3_NO_ERROR");
+    }
+
+    #[tokio::test]
+    async fn inflight_packets_carry_stream_frame() {
+        let conn = client();
+        let (data, original_dcid) = match conn.0.lock().unwrap().deref() {
+            Normal(connection) => (connection.data.clone(), connection.initial_dcid),
+            _ => panic!("client should be in normal state"),
+        };
+        add_local_path(&conn);
+
+        // 有了0-RTT密钥，流数据就能在0-RTT数据包中发出
+        let provider = rustls::crypto::ring::default_provider();
+        data.zero_rtt_keys.set_keys(ArcTlsSession::initial_keys(
+            &provider,
+            rustls::Side::Client,
+            original_dcid,
+        ));
+        data.streams
+            .recv_frame(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Bi(
+                VarInt::from_u32(1),
+            )))
+            .unwrap();
+        let (_sid, (mut reader, mut writer)) = data.streams.open_bi(1000).await.unwrap().unwrap();
+        writer.write_all(b"inflight").await.unwrap();
+
+        let packet = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let stream_packet = conn.inflight_packets().into_iter().find(|packet| {
+                    packet.epoch == Epoch::Data
+                        && packet
+                            .frames
+                            .iter()
+                            .any(|frame| matches!(frame, FrameType::Stream(_)))
+                });
+                match stream_packet {
+                    Some(packet) => break packet,
+                    None => tokio::task::yield_now().await,
+                }
+            }
+        })
+        .await
+        .expect("the packet carrying the STREAM frame should be in flight");
+        assert!(packet.ack_eliciting);
+        assert!(packet.time_sent.is_some());
+
+        reader.stop(0);
+        writer.cancel(0);
+    }
+
+    #[tokio::test]
+    async fn count_authentic_duplicate_packets() {
+        let conn = client();
+        let (scid, original_dcid) = match conn.0.lock().unwrap().deref() {
+            Normal(connection) => (connection.initial_scid, connection.initial_dcid),
+            _ => panic!("client should be in normal state"),
+        };
+        let (pathway, usc) = add_local_path(&conn);
+
+        // 服务端发来的Initial包，用原始dcid导出的密钥加密
+        let keys = crate::tests::keys(original_dcid);
+        let header = LongHeaderBuilder::with_cid(scid, ConnectionId::random_gen(8)).initial(vec![]);
+        let mut buf = [0u8; 128];
+        let pn = (0, PacketNumber::encode(0, 0));
+        let mut writer =
+            PacketWriter::new(&header, &mut buf, pn, keys.local.packet.tag_len()).unwrap();
+        writer.dump_frame(PingFrame);
+        // 足够长的包，篡改末尾的tag不会影响头部保护的采样
+        writer.pad(40);
+        let packet =
+            writer.encrypt_long_packet(keys.local.header.as_ref(), keys.local.packet.as_ref());
+        let authentic = BytesMut::from(&packet[..]);
+        let mut forged = authentic.clone();
+        *forged.last_mut().unwrap() ^= 0xff;
+
+        for datagram in [authentic.clone(), forged, authentic] {
+            let Some(Ok(Packet::Data(packet))) = PacketReader::new(datagram, 8).next() else {
+                panic!("the datagram should carry a data packet");
+            };
+            Router::try_to_route_packet_from(packet, pathway, &usc).unwrap();
+        }
+        let duplicate_packets = || conn.stats().unwrap().duplicate_packets;
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while duplicate_packets() == 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the replayed packet should be counted");
+        // 包号重复但无法解密的包是伪造的，不算重复包
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(duplicate_packets(), 1);
+    }
+
+    #[tokio::test]
+    async fn close_cancels_spawned_tasks() {
+        let conn = client();
+        conn.keep_alive(KeepAlive::Always(Duration::from_secs(1)));
+        let (spawner, conn_error) = match conn.0.lock().unwrap().deref() {
+            Normal(connection) => (connection.spawner.clone(), connection.error.clone()),
+            _ => panic!("client should be in normal state"),
+        };
+        // TLS握手、传输参数、keep-alive等任务都在运行
+        assert!(spawner.running() > 0);
+
+        let ccf = ConnectionCloseFrame::new_app(VarInt::from_u32(0x100), "H3_NO_ERROR".into());
+        conn_error.on_ccf_rcvd(&ccf);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while spawner.running() > 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("all tasks of the connection should be cancelled once it is closed");
+        assert!(conn.close_reason().is_some());
+    }
+
+    #[derive(Default)]
+    struct CountRuntime {
+        spawned: AtomicUsize,
+        slept: AtomicUsize,
+    }
+
+    impl Runtime for CountRuntime {
+        fn spawn(&self, task: futures::future::BoxFuture<'static, ()>) {
+            self.spawned.fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(task);
+        }
+
+        fn sleep(&self, duration: Duration) -> futures::future::BoxFuture<'static, ()> {
+            self.slept.fetch_add(1, Ordering::Relaxed);
+            Box::pin(tokio::time::sleep(duration))
+        }
+    }
+
+    #[tokio::test]
+    async fn spawn_on_given_runtime() {
+        let runtime = Arc::new(CountRuntime::default());
+        let conn = client_on(runtime.clone());
+        add_local_path(&conn);
+        let spawned = runtime.spawned.load(Ordering::Relaxed);
+        // 收包、帧处理、TLS握手、路径发送等任务都在给定的运行时上运行
+        assert!(spawned > 0);
+
+        // 进入Draining状态后，计时结束连接的任务也在给定的运行时上运行
+        let conn_error = match conn.0.lock().unwrap().deref() {
+            Normal(connection) => connection.error.clone(),
+            _ => panic!("client should be in normal state"),
+        };
+        let slept = runtime.slept.load(Ordering::Relaxed);
+        let ccf = ConnectionCloseFrame::new_app(VarInt::from_u32(0x100), "H3_NO_ERROR".into());
+        conn_error.on_ccf_rcvd(&ccf);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while conn.close_reason().is_none() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the connection should be closed");
+        assert!(runtime.spawned.load(Ordering::Relaxed) > spawned);
+        // Draining的计时器也由给定的运行时驱动
+        assert!(runtime.slept.load(Ordering::Relaxed) > slept);
+    }
+
+    #[tokio::test]
+    async fn closed_resolves_all_waiters() {
+        let conn = client();
+        let waiters = (0..3)
+            .map(|_| {
+                tokio::spawn({
+                    let conn = conn.clone();
+                    async move { conn.closed().await }
+                })
+            })
+            .collect::<Vec<_>>();
+        tokio::task::yield_now().await;
+        assert!(waiters.iter().all(|waiter| !waiter.is_finished()));
+
+        let conn_error = match conn.0.lock().unwrap().deref() {
+            Normal(connection) => connection.error.clone(),
+            _ => panic!("client should be in normal state"),
+        };
+        let ccf = ConnectionCloseFrame::new_app(VarInt::from_u32(0x100), "H3_NO_ERROR".into());
+        conn_error.on_ccf_rcvd(&ccf);
+
+        let expected = Error::from(ccf);
+        for waiter in waiters {
+            let reason = tokio::time::timeout(Duration::from_secs(1), waiter)
+                .await
+                .expect("all waiters should be woken up once the peer closed the connection")
+                .unwrap();
+            assert_eq!(reason, expected);
+        }
+        // 已关闭的连接立即返回
+        assert_eq!(conn.closed().await, expected);
+    }
+}
@@ -37,6 +37,8 @@ impl CcfPackets {
         let ccf = ConnectionCloseFrame::from(error.clone());
         let handshake = hs.map({
             |hs| {
+                // 握手包中不能暴露应用层的关闭原因，需转换成APPLICATION_ERROR
+                let ccf = ccf.conceal();
                 let mut packet = [0; qcongestion::MSS];
                 let size = hs.assemble_ccf_packet(&mut packet, &ccf, initial_scid, last_dcid);
                 (packet, size)
@@ -148,7 +150,7 @@ impl ClosingConnection {
                 (None, Some(one_rtt_packet)) => &[one_rtt_packet],
                 _ => return,
             };
-            _ = usc.send_all_via_pathway(packets, pathway).await;
+            _ = usc.send_all_via_pathway(packets, pathway, None).await;
         }
     }
 
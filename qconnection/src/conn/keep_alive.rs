@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use qbase::frame::PingFrame;
+
+use super::DataStreams;
+use crate::{
+    path::SendBuffer,
+    spawn::{JoinHandle, Spawner},
+};
+
+/// The policy of sending PING frames to keep the connection alive.
+///
+/// QUIC connections are closed silently by the peer after the idle timeout, and the NATs on the
+/// path may also forget the mapping of an idle connection. Sending PING frames periodically keeps
+/// the connection up, but a connection that the application intends to let idle out should not
+/// be kept alive forever.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAlive {
+    /// Never send PING frames to keep the connection alive, this is the default.
+    #[default]
+    Disabled,
+    /// Send a PING frame every interval, even if no stream is open.
+    Always(Duration),
+    /// Send a PING frame every interval, only while at least one stream is open.
+    WithStreams(Duration),
+}
+
+impl KeepAlive {
+    fn interval(&self) -> Option<Duration> {
+        match self {
+            KeepAlive::Disabled => None,
+            KeepAlive::Always(interval) | KeepAlive::WithStreams(interval) => Some(*interval),
+        }
+    }
+
+    fn should_ping(&self, streams: &DataStreams) -> bool {
+        match self {
+            KeepAlive::Disabled => false,
+            KeepAlive::Always(_) => true,
+            KeepAlive::WithStreams(_) => streams.has_open_streams(),
+        }
+    }
+
+    /// Spawn the task that sends PING frames according to the policy, return [`None`] if the
+    /// keep-alive is disabled.
+    ///
+    /// The task runs until it is aborted, or the connection is closed.
+    pub(super) fn spawn(
+        self,
+        spawner: &Spawner,
+        streams: DataStreams,
+        ping_sndbuf: SendBuffer<PingFrame>,
+    ) -> Option<JoinHandle<()>> {
+        let interval = self.interval()?;
+        let timer = spawner.clone();
+        Some(spawner.spawn(async move {
+            loop {
+                timer.sleep(interval).await;
+                if self.should_ping(&streams) {
+                    ping_sndbuf.write(PingFrame);
+                }
+            }
+        }))
+    }
+}
@@ -16,31 +16,38 @@ use qbase::{
     Epoch,
 };
 use qcongestion::{ArcCC, CongestionAlgorithm, CongestionControl};
+use qrecovery::streams::raw::StreamStats;
 use rustls::quic::Keys;
-use tokio::{sync::Notify, task::JoinHandle};
+use tokio::sync::Notify;
 
 use super::{
+    keep_alive::KeepAlive,
     space::{
         data::{DataSpace, DataTracker},
-        handshake::{HandshakeSpace, HandshakeTracker},
-        initial::{InitialSpace, InitialTracker},
+        handshake::HandshakeSpace,
+        initial::InitialSpace,
     },
     ArcLocalCids, ArcRemoteCids, CidRegistry, FlowController, Handshake, RcvdPackets,
 };
 use crate::{
     error::ConnError,
-    path::{ArcPath, ArcPaths, Path, Paths, Pathway},
+    path::{ArcPath, ArcPaths, ArcSpin, Path, Paths, Pathway, SpinObservers},
     router::Router,
+    spawn::{JoinHandle, Runtime, Spawner},
     tls::ArcTlsSession,
 };
 
 pub struct Connection {
     // TOOD?: hide these fields
     pub(super) initial_scid: ConnectionId,
+    // 在握手开始时采用的对端连接ID：客户端为其第一个Initial包的dcid，收到Retry包时用于验证其完整性；
+    // 服务端则为客户端Initial包的scid
+    pub(super) initial_dcid: ConnectionId,
     pub(super) token: Arc<Mutex<Vec<u8>>>,
     pub(super) paths: ArcPaths,
     pub(super) cid_registry: CidRegistry,
     // handshake done的信号
+    pub(super) handshake: Handshake,
     pub(super) flow_ctrl: FlowController,
     pub(super) error: ConnError,
 
@@ -49,6 +56,10 @@ pub struct Connection {
     pub(super) data: DataSpace,
     pub(super) notify: Arc<Notify>, // Notifier for closing the packet receiving task
     pub(super) join_handles: [JoinHandle<RcvdPackets>; 4],
+    pub(super) keep_alive: Option<JoinHandle<()>>,
+    pub(super) spin_observers: SpinObservers,
+    // 连接的后台任务，连接关闭时一并取消
+    pub(super) spawner: Spawner,
 
     pub(super) tls_session: ArcTlsSession,
     pub(super) params: ArcParameters,
@@ -65,6 +76,11 @@ impl Connection {
         initial_keys: Keys,
         streams_ctrl: Box<dyn ControlConcurrency>,
         token_registry: ArcTokenRegistry,
+        initial_rtt: Duration,
+        congestion_algorithm: CongestionAlgorithm,
+        anti_factor: usize,
+        max_send_udp_payload: Option<usize>,
+        runtime: Arc<dyn Runtime>,
     ) -> Self {
         let (initial_packets_entry, rcvd_initial_packets) = mpsc::unbounded();
         let (zero_rtt_packets_entry, rcvd_0rtt_packets) = mpsc::unbounded();
@@ -88,7 +104,7 @@ impl Connection {
                 one_rtt_packets_entry,
             ],
         );
-        let local_cids = ArcLocalCids::new(initial_scid, router_registry);
+        let local_cids = ArcLocalCids::new(initial_scid, router_registry.clone());
         let remote_cids = ArcRemoteCids::new(
             initial_dcid,
             params.local().unwrap().active_connection_id_limit().into(),
@@ -96,8 +112,19 @@ impl Connection {
         );
         let cid_registry = CidRegistry::new(local_cids, remote_cids);
         let handshake = Handshake::new(role, reliable_frames.clone());
-        let flow_ctrl = FlowController::new(65535, 65535, reliable_frames.clone());
+        // 0-RTT阶段尚不知道对端这次的initial_max_data，只能按上次连接记忆的额度发送新数据，
+        // 超出的部分留在流中，等到对端的传输参数到来后，在1-RTT中发送
+        let remembered_max_data = params
+            .remembered()
+            .map(|remembered| remembered.initial_max_data().into_inner());
+        // 接收方向的额度即我方通告的initial_max_data，对端按它发送，须按它及时下发MaxDataFrame
+        let flow_ctrl = FlowController::new(
+            remembered_max_data.unwrap_or(65535),
+            params.local().unwrap().initial_max_data().into_inner(),
+            reliable_frames.clone(),
+        );
         let conn_error = ConnError::default();
+        let spawner = Spawner::new(runtime);
 
         let token = match token_registry.deref() {
             TokenRegistry::Client((server_name, client)) => {
@@ -105,10 +132,14 @@ impl Connection {
             }
             TokenRegistry::Server(_) => Arc::new(Mutex::new(vec![])),
         };
+        let spin_observers = SpinObservers::default();
         let path_creator = Box::new({
             let cid_registry = cid_registry.clone();
+            let spin_observers = spin_observers.clone();
             let flow_ctrl = flow_ctrl.clone();
             let handshake = handshake.clone();
+            let spawner = spawner.clone();
+            let params = params.clone();
 
             let gen_readers = {
                 let initial = initial.clone();
@@ -137,14 +168,15 @@ impl Connection {
             let hs = hs.clone();
             let data = data.clone();
 
-            let initial_tracker =
-                InitialTracker::new(initial.journal.clone(), initial.crypto_stream.outgoing());
-            let hs_tracker = HandshakeTracker::new(hs.journal.clone(), hs.crypto_stream.outgoing());
+            let initial_tracker = initial.tracker();
+            let hs_tracker = hs.tracker();
             let data_tracker = DataTracker::new(
                 data.journal.clone(),
                 reliable_frames.clone(),
                 streams.clone(),
                 data.crypto_stream.outgoing(),
+                data.ping_sndbuf.clone(),
+                conn_error.clone(),
             );
 
             move |pathway, usc| {
@@ -152,7 +184,8 @@ impl Connection {
                 let dcid = cid_registry.remote.apply_dcid();
 
                 let cc = ArcCC::new(
-                    CongestionAlgorithm::Bbr,
+                    congestion_algorithm,
+                    initial_rtt,
                     Duration::from_millis(100),
                     [
                         Box::new(initial_tracker.clone()),
@@ -161,9 +194,24 @@ impl Connection {
                     ],
                     handshake.clone(),
                 );
+                if let Some(remote) = params.remote() {
+                    cc.set_peer_ack_delay_exponent(remote.ack_delay_exponent().into_inner() as u8);
+                    let max_mtu = remote.max_udp_payload_size().into_inner() as usize;
+                    cc.set_max_mtu(max_mtu.min(max_send_udp_payload.unwrap_or(usize::MAX)));
+                }
 
-                let path = Path::new(usc, scid, dcid, cc);
-                if !handshake.is_handshake_done() {
+                let spin = ArcSpin::new(role, spin_observers.clone());
+                let mut path = Path::new(usc, scid, dcid, cc, spin, anti_factor, spawner.clone());
+                if let Some(size) = max_send_udp_payload {
+                    path.set_max_send_udp_payload(size);
+                }
+                // 零长度的连接ID无法区分连接，对端从该路径发来的包只能按四元组路由到本连接
+                if scid.is_empty() {
+                    if let Some(route) = router_registry.route_pathway(pathway) {
+                        path.keep_route(route);
+                    }
+                }
+                if !handshake.is_handshake_confirmed() {
                     if role == Role::Client {
                         path.grant_anti_amplifier();
                     }
@@ -205,9 +253,10 @@ impl Connection {
             &conn_error,
             params.clone(),
             validate,
+            &spawner,
         );
 
-        let join_hs = hs.build(rcvd_hs_packets, &pathes, &notify, &conn_error);
+        let join_hs = hs.build(rcvd_hs_packets, &pathes, &notify, &conn_error, &spawner);
 
         tls_session.keys_upgrade(
             [
@@ -220,15 +269,36 @@ impl Connection {
             handshake.clone(),
             params.clone(),
             conn_error.clone(),
+            &spawner,
+            {
+                let pathes = pathes.clone();
+                move || {
+                    for path in pathes.iter() {
+                        path.cc().wake_sending();
+                    }
+                }
+            },
         );
 
-        tokio::spawn({
+        spawner.spawn({
             let params = params.clone();
             let streams = streams.clone();
             let conn_error = conn_error.clone();
             let cid_registry = cid_registry.clone();
+            let flow_ctrl = flow_ctrl.clone();
+            let pathes = pathes.clone();
             async move {
                 if let Some(Pair { local: _, remote }) = params.await {
+                    let ack_delay_exponent = remote.ack_delay_exponent().into_inner() as u8;
+                    // 探测PMTU的上限，不超过对端能接收的，也不超过本地配置的
+                    let max_mtu = (remote.max_udp_payload_size().into_inner() as usize)
+                        .min(max_send_udp_payload.unwrap_or(usize::MAX));
+                    for path in pathes.iter() {
+                        path.cc().set_peer_ack_delay_exponent(ack_delay_exponent);
+                        path.cc().set_max_mtu(max_mtu);
+                    }
+                    // 以对端这次的initial_max_data为准，它可能比记忆的小；没有0-RTT时则替换掉占位的额度
+                    flow_ctrl.reset_send_window(remote.initial_max_data().into_inner());
                     // pretend to receive the MAX_STREAM frames
                     _ = streams.recv_frame(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Bi(
                         remote.initial_max_streams_bidi(),
@@ -245,6 +315,20 @@ impl Connection {
             }
         });
 
+        // 控制策略扣留的MAX_STREAMS，即使对端从不发送StreamsBlocked，也要在期限到达时下发
+        spawner.spawn({
+            let streams = streams.clone();
+            let timer = spawner.clone();
+            async move {
+                loop {
+                    let deadline =
+                        core::future::poll_fn(|cx| streams.poll_max_streams_deadline(cx)).await;
+                    timer.sleep_until(deadline).await;
+                    streams.on_max_streams_deadline();
+                }
+            }
+        });
+
         let (join_0rtt, join_1rtt) = data.build(
             &pathes,
             &handshake,
@@ -255,20 +339,26 @@ impl Connection {
             rcvd_0rtt_packets,
             rcvd_1rtt_packets,
             token_registry,
+            &spawner,
         );
         let join_handles = [join_initial, join_0rtt, join_hs, join_1rtt];
 
         Self {
             initial_scid,
+            initial_dcid,
             token,
             paths: pathes,
             cid_registry,
+            handshake,
             flow_ctrl,
             initial,
             hs,
             data,
             notify,
             join_handles,
+            keep_alive: None,
+            spin_observers,
+            spawner,
             error: conn_error,
             params,
             tls_session,
@@ -282,7 +372,19 @@ impl Connection {
             .max()
     }
 
+    pub fn keep_alive(&mut self, policy: KeepAlive) {
+        if let Some(task) = self.keep_alive.take() {
+            task.abort();
+        }
+        self.keep_alive = policy.spawn(
+            &self.spawner,
+            self.data.streams.clone(),
+            self.data.ping_sndbuf.clone(),
+        );
+    }
+
     pub fn abort_with_error(&self, error: &Error) {
+        self.spawner.abort_all();
         self.data.on_conn_error(error);
         self.flow_ctrl.on_conn_error(error);
         self.params.on_conn_error(error);
@@ -290,9 +392,125 @@ impl Connection {
         self.notify.notify_waiters();
     }
 
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            early_data_bytes: self.data.early_data_bytes(),
+            early_data_accepted: self.tls_session.is_early_data_accepted(),
+            streams: self.data.streams.stream_stats(),
+            duplicate_packets: self.initial.journal.of_rcvd_packets().duplicate_packets()
+                + self.hs.journal.of_rcvd_packets().duplicate_packets()
+                + self.data.journal.of_rcvd_packets().duplicate_packets(),
+        }
+    }
+
     pub fn update_path_recv_time(&self, pathway: Pathway) {
         if let Some(path) = self.paths.try_get(&pathway).try_unwrap() {
             path.update_recv_time();
         }
     }
+
+    #[cfg(any(test, feature = "inflight-dump"))]
+    pub fn inflight_packets(&self) -> Vec<InflightPacket> {
+        use qbase::frame::{BeFrame, FrameType};
+        use qrecovery::reliable::GuaranteedFrame;
+
+        fn frame_types<F>(
+            packets: Vec<(u64, Vec<F>)>,
+            frame_type: impl Fn(&F) -> FrameType,
+        ) -> Vec<(u64, Vec<FrameType>)> {
+            let frame_types =
+                |(pn, frames): (u64, Vec<F>)| (pn, frames.iter().map(&frame_type).collect());
+            packets.into_iter().map(frame_types).collect()
+        }
+
+        let spaces = [
+            (
+                Epoch::Initial,
+                frame_types(
+                    self.initial.journal.of_sent_packets().inflight_packets(),
+                    BeFrame::frame_type,
+                ),
+            ),
+            (
+                Epoch::Handshake,
+                frame_types(
+                    self.hs.journal.of_sent_packets().inflight_packets(),
+                    BeFrame::frame_type,
+                ),
+            ),
+            (
+                Epoch::Data,
+                frame_types(
+                    self.data.journal.of_sent_packets().inflight_packets(),
+                    |frame| match frame {
+                        GuaranteedFrame::Stream(f) => f.frame_type(),
+                        GuaranteedFrame::Crypto(f) => f.frame_type(),
+                        GuaranteedFrame::Reliable(f) => f.frame_type(),
+                    },
+                ),
+            ),
+        ];
+
+        let mut inflight_packets = Vec::new();
+        for (epoch, packets) in spaces {
+            // 发送时间与是否ack-eliciting由发送该包的路径的拥塞控制器记录
+            let sent = self
+                .paths
+                .iter()
+                .flat_map(|path| path.cc().inflight_packets(epoch))
+                .map(|(pn, time_sent, ack_eliciting)| (pn, (time_sent, ack_eliciting)))
+                .collect::<std::collections::HashMap<_, _>>();
+            for (pn, frames) in packets {
+                let (time_sent, ack_eliciting) = match sent.get(&pn) {
+                    Some((time_sent, ack_eliciting)) => (Some(*time_sent), *ack_eliciting),
+                    None => (None, !frames.is_empty()),
+                };
+                inflight_packets.push(InflightPacket {
+                    epoch,
+                    pn,
+                    time_sent,
+                    ack_eliciting,
+                    frames,
+                });
+            }
+        }
+        inflight_packets
+    }
+}
+
+/// The statistics of a connection, see
+/// [`ArcConnection::stats`](super::ArcConnection::stats).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// The number of bytes of the stream data sent as early data, that is, in the 0-RTT packets.
+    /// The retransmitted data is not counted again.
+    pub early_data_bytes: u64,
+    /// Whether the early data is accepted by the server. If not, the early data is sent again in
+    /// the 1-RTT packets.
+    pub early_data_accepted: bool,
+    /// The number of streams opened, accepted and closed in each direction.
+    pub streams: StreamStats,
+    /// The number of the packets discarded because their packet numbers have been received, in
+    /// all the packet number spaces. They may be duplicated by the network, or replayed by an
+    /// attacker. Only the packets successfully decrypted are counted.
+    pub duplicate_packets: u64,
+}
+
+/// A packet sent but neither acknowledged nor declared lost, see
+/// [`ArcConnection::inflight_packets`](super::ArcConnection::inflight_packets).
+#[cfg(any(test, feature = "inflight-dump"))]
+#[derive(Debug, Clone)]
+pub struct InflightPacket {
+    /// The packet number space of the packet.
+    pub epoch: Epoch,
+    /// The packet number of the packet.
+    pub pn: u64,
+    /// The time the packet was sent, [`None`] if the path sent it has been removed.
+    pub time_sent: Option<std::time::Instant>,
+    /// Whether the packet is ack-eliciting. If the path sent it has been removed, only the
+    /// packet carries the frames to be retransmitted is considered ack-eliciting.
+    pub ack_eliciting: bool,
+    /// The kinds of the frames to be retransmitted in the packet, the trivial frames such as
+    /// PING, ACK, PADDING are not recorded.
+    pub frames: Vec<qbase::frame::FrameType>,
 }
@@ -2,9 +2,10 @@ pub mod data;
 pub mod handshake;
 pub mod initial;
 
-use std::future::Future;
+use std::{collections::VecDeque, future::Future};
 
 pub use data::{ClosingOneRttScope, DataSpace};
+use futures::{Stream, StreamExt};
 pub use handshake::{ClosingHandshakeScope, HandshakeSpace};
 pub use initial::InitialSpace;
 use qbase::{
@@ -13,6 +14,9 @@ use qbase::{
 };
 use tokio::sync::Notify;
 
+/// 密钥就绪之前收到的包无法解密，每个空间最多缓存的个数，超出的包被丢弃
+const MAX_UNDECRYPTABLE_PACKETS: usize = 16;
+
 pub trait RecvPacket {
     fn has_rcvd_ccf(&self, packet: DataPacket) -> bool;
 
@@ -40,13 +44,61 @@ where
     }
 }
 
+/// Receive the next packet of a space, together with the keys to decrypt it.
+///
+/// The packets received before the keys are ready, for example the Handshake packets arriving
+/// before the Initial packet carrying the server's first flight is processed, can not be decrypted
+/// yet. They are buffered in `undecryptable`, at most [`MAX_UNDECRYPTABLE_PACKETS`] ones and the
+/// others are dropped, and are returned in order once the keys are ready, before the packets
+/// received later.
+///
+/// Return `None` if the connection is closing, the packets channel is closed, or the keys are
+/// invalid.
+async fn next_decryptable<T, K, F>(
+    rcvd_packets: &mut (impl Stream<Item = T> + Unpin),
+    undecryptable: &mut VecDeque<T>,
+    get_keys: impl Fn() -> F,
+    notify: &Notify,
+) -> Option<(T, K)>
+where
+    F: Future<Output = Option<K>>,
+{
+    let mut keys = std::pin::pin!(get_keys());
+    loop {
+        tokio::select! {
+            biased;
+            _ = notify.notified() => return None,
+            keys = &mut keys => {
+                keys?;
+                break;
+            }
+            packet = rcvd_packets.next() => {
+                let packet = packet?;
+                if undecryptable.len() < MAX_UNDECRYPTABLE_PACKETS {
+                    undecryptable.push_back(packet);
+                } else {
+                    log::debug!("too many packets received before the keys are ready, drop it");
+                }
+            }
+        }
+    }
+
+    let packet = match undecryptable.pop_front() {
+        Some(packet) => packet,
+        None => any(rcvd_packets.next(), notify).await?,
+    };
+    // 等待包的期间，密钥可能已经失效
+    let keys = any(get_keys(), notify).await?;
+    Some((packet, keys))
+}
+
 #[macro_export]
 macro_rules! pipe {
     (
-        $input:ident |> $var:expr,$method:ident
+        $spawner:ident, $input:ident |> $var:expr,$method:ident
     ) => {{
         #[allow(unused)]
-        ::tokio::spawn({
+        $spawner.spawn_detached({
             let mut input = $input;
             let mut owned_capture = ::std::clone::Clone::clone(&$var);
             async move {
@@ -57,10 +109,10 @@ macro_rules! pipe {
         })
     }};
     (
-        $input:ident |> $($lambda:tt)*
+        $spawner:ident, $input:ident |> $($lambda:tt)*
     ) => {{
         #[allow(unused)]
-        ::tokio::spawn({
+        $spawner.spawn_detached({
             let mut input = $input;
             let mut lambda = $($lambda)*;
             async move {
@@ -71,11 +123,11 @@ macro_rules! pipe {
         })
     }};
     (
-        @error($error:expr)
+        $spawner:ident, @error($error:expr)
         $input:ident |> $var:expr,$method:ident
     ) => {{
         #[allow(unused)]
-        ::tokio::spawn({
+        $spawner.spawn_detached({
             let mut input = $input;
             let mut owned_capture = ::std::clone::Clone::clone(&$var);
             let mut error = ::std::clone::Clone::clone($error);
@@ -91,11 +143,11 @@ macro_rules! pipe {
         })
     }};
     (
-        @error($error:expr)
+        $spawner:ident, @error($error:expr)
         $input:ident |> $($lambda:tt)*
     ) => {{
         #[allow(unused)]
-        ::tokio::spawn({
+        $spawner.spawn_detached({
             let mut input = $input;
             let mut error = ::std::clone::Clone::clone($error);
             let mut lambda = $($lambda)*;
@@ -113,10 +165,22 @@ macro_rules! pipe {
 
 #[cfg(test)]
 mod tests {
-    use futures::{channel::mpsc, SinkExt};
-    use qbase::error::{Error, ErrorKind};
+    use std::{collections::VecDeque, time::Duration};
+
+    use futures::{channel::mpsc, FutureExt, SinkExt};
+    use qbase::{
+        cid::ConnectionId,
+        error::{Error, ErrorKind},
+        packet::keys::ArcKeys,
+    };
+    use tokio::sync::Notify;
 
-    use crate::error::{ConnError, ConnErrorSource};
+    use super::{next_decryptable, MAX_UNDECRYPTABLE_PACKETS};
+    use crate::{
+        error::{ConnError, ConnErrorSource},
+        spawn::Spawner,
+        tls::ArcTlsSession,
+    };
 
     #[derive(Clone, Copy)]
     struct Consumer;
@@ -136,43 +200,45 @@ mod tests {
 
     #[tokio::test]
     async fn macro_expand() {
+        let spawner = Spawner::default();
         let c = Consumer;
         let (mut tx, rx) = mpsc::unbounded::<()>();
         pipe!(
-            rx |> c,consume
+            spawner, rx |> c,consume
         );
         assert!(tx.send(()).await.is_ok());
 
         let c = (Consumer,);
         let (mut tx, rx) = mpsc::unbounded::<()>();
         pipe!(
-            rx |> c.0,consume
+            spawner, rx |> c.0,consume
         );
         assert!(tx.send(()).await.is_ok());
 
         let c = ((Consumer,),);
         let (mut tx, rx) = mpsc::unbounded::<()>();
         pipe!(
-            rx |> c.0.0,consume
+            spawner, rx |> c.0.0,consume
         );
         assert!(tx.send(()).await.is_ok());
 
         let c = (((Consumer,),),);
         let (mut tx, rx) = mpsc::unbounded::<()>();
         pipe!(
-            rx |> c.0.0.0,consume
+            spawner, rx |> c.0.0.0,consume
         );
         assert!(tx.send(()).await.is_ok());
     }
 
     #[tokio::test]
     async fn macro_expand2() {
+        let spawner = Spawner::default();
         let c = Consumer;
         let error = &ConnError::default();
 
         let (mut tx1, rx1) = mpsc::unbounded::<()>();
         pipe!(
-            @error(error)
+            spawner, @error(error)
             rx1 |> c,consume_return_ok
         );
 
@@ -181,12 +247,13 @@ mod tests {
 
     #[tokio::test]
     async fn macro_expand3() {
+        let spawner = Spawner::default();
         let c = Consumer;
         let error = ConnError::default();
 
         let (mut tx1, rx1) = mpsc::unbounded::<()>();
         pipe!(
-            @error(&error)
+            spawner, @error(&error)
             rx1 |> c,consume_return_error
         );
 
@@ -195,4 +262,69 @@ mod tests {
         assert_eq!(kind, ConnErrorSource::Transport);
         assert!(tx1.send(()).await.is_err());
     }
+
+    #[tokio::test]
+    async fn packets_before_keys() {
+        let keys = ArcKeys::new_pending();
+        let notify = Notify::new();
+        let (tx, mut rx) = mpsc::unbounded();
+        let mut undecryptable = VecDeque::new();
+
+        // 密钥就绪之前收到的包被缓存，超出上限的被丢弃
+        for i in 0..MAX_UNDECRYPTABLE_PACKETS + 2 {
+            tx.unbounded_send(i).unwrap();
+        }
+        let mut next = Box::pin(next_decryptable(
+            &mut rx,
+            &mut undecryptable,
+            || keys.get_remote_keys(),
+            &notify,
+        ));
+        assert!((&mut next).now_or_never().is_none());
+        tokio::task::yield_now().await;
+        assert!((&mut next).now_or_never().is_none());
+
+        let provider = rustls::crypto::ring::default_provider();
+        keys.set_keys(ArcTlsSession::initial_keys(
+            &provider,
+            rustls::Side::Client,
+            ConnectionId::random_gen(8),
+        ));
+        let (first, _keys) = tokio::time::timeout(Duration::from_secs(1), next)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first, 0);
+        assert_eq!(undecryptable.len(), MAX_UNDECRYPTABLE_PACKETS - 1);
+
+        // 缓存的包按序先于之后收到的包被处理
+        tx.unbounded_send(100).unwrap();
+        let mut processed = vec![first];
+        while let Some((packet, _keys)) = next_decryptable(
+            &mut rx,
+            &mut undecryptable,
+            || keys.get_remote_keys(),
+            &notify,
+        )
+        .now_or_never()
+        .flatten()
+        {
+            processed.push(packet);
+        }
+        let mut expected = (0..MAX_UNDECRYPTABLE_PACKETS).collect::<Vec<_>>();
+        expected.push(100);
+        assert_eq!(processed, expected);
+
+        // 密钥失效之后，不再接收
+        keys.invalid();
+        tx.unbounded_send(101).unwrap();
+        assert!(next_decryptable(
+            &mut rx,
+            &mut undecryptable,
+            || keys.get_remote_keys(),
+            &notify
+        )
+        .await
+        .is_none());
+    }
 }
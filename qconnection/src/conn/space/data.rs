@@ -1,4 +1,10 @@
-use std::sync::Arc;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use bytes::{BufMut, Bytes};
 use futures::{channel::mpsc, StreamExt};
@@ -7,7 +13,7 @@ use qbase::{
     error::{Error, ErrorKind},
     frame::{
         io::WriteFrame, AckFrame, BeFrame, ConnectionCloseFrame, Frame, FrameReader,
-        PathChallengeFrame, PathResponseFrame, ReceiveFrame, ReliableFrame, SendFrame,
+        PathChallengeFrame, PathResponseFrame, PingFrame, ReceiveFrame, ReliableFrame,
         StreamCtlFrame, StreamFrame,
     },
     packet::{
@@ -32,13 +38,13 @@ use qbase::{
 use qcongestion::{CongestionControl, TrackPackets, MSS};
 use qrecovery::{
     crypto::{CryptoStream, CryptoStreamOutgoing},
-    journal::{ArcRcvdJournal, DataJournal},
+    journal::{ArcRcvdJournal, DataJournal, InvalidPacketNumber},
     reliable::{ArcReliableFrameDeque, GuaranteedFrame},
 };
 use qunreliable::DatagramFlow;
-use tokio::{sync::Notify, task::JoinHandle};
+use tokio::sync::Notify;
 
-use super::any;
+use super::next_decryptable;
 use crate::{
     conn::{
         transmit::DataSpaceReader, CidRegistry, DataStreams, FlowController, Handshake, RcvdPackets,
@@ -47,6 +53,7 @@ use crate::{
     path::{ArcPaths, Path, SendBuffer},
     pipe,
     router::Router,
+    spawn::{JoinHandle, Spawner},
     tx::{PacketMemory, Transaction},
 };
 
@@ -59,6 +66,10 @@ pub struct DataSpace {
     pub reliable_frames: ArcReliableFrameDeque,
     pub streams: DataStreams,
     pub datagrams: DatagramFlow,
+    // 保活的PING帧，见KeepAlive
+    pub ping_sndbuf: SendBuffer<PingFrame>,
+    // 在0-RTT数据包中发出的新的流数据的字节数
+    pub early_data_bytes: Arc<AtomicU64>,
 }
 
 impl DataSpace {
@@ -69,17 +80,31 @@ impl DataSpace {
     ) -> Self {
         let reliable_frames = ArcReliableFrameDeque::with_capacity(8);
         let streams = DataStreams::new(role, local_params, streams_ctrl, reliable_frames.clone());
+        let journal = DataJournal::with_capacity(16);
+        // 0-RTT和1-RTT数据包的AckFrame，其ACK Delay按我方的ack_delay_exponent缩放
+        let ack_delay_exponent = local_params.ack_delay_exponent().into_inner() as u8;
+        journal
+            .of_rcvd_packets()
+            .set_ack_delay_exponent(ack_delay_exponent);
         Self {
             zero_rtt_keys: ArcKeys::new_pending(),
             one_rtt_keys: ArcOneRttKeys::new_pending(),
-            journal: DataJournal::with_capacity(16),
+            journal,
             crypto_stream: CryptoStream::new(4096, 4096),
             reliable_frames,
             streams,
             datagrams: DatagramFlow::new(1024),
+            ping_sndbuf: SendBuffer::default(),
+            early_data_bytes: Arc::default(),
         }
     }
 
+    /// Return how many bytes of the stream data were sent as early data, that is, in the 0-RTT
+    /// packets. The retransmitted data is not counted again.
+    pub fn early_data_bytes(&self) -> u64 {
+        self.early_data_bytes.load(Ordering::Relaxed)
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn build(
         &self,
@@ -92,6 +117,7 @@ impl DataSpace {
         rcvd_0rtt_packets: RcvdPackets,
         rcvd_1rtt_packets: RcvdPackets,
         recv_new_token: ArcTokenRegistry,
+        spawner: &Spawner,
     ) -> (JoinHandle<RcvdPackets>, JoinHandle<RcvdPackets>) {
         let (ack_frames_entry, rcvd_ack_frames) = mpsc::unbounded();
         // 连接级的
@@ -122,6 +148,7 @@ impl DataSpace {
                 Frame::DataBlocked(f) => _ = data_blocked_frames_entry.unbounded_send(f),
                 Frame::Challenge(f) => path.recv_challenge(f),
                 Frame::Response(f) => path.recv_response(f),
+                Frame::ImmediateAck(_) => path.cc().on_immediate_ack(),
                 Frame::StreamCtl(f) => _ = stream_ctrl_frames_entry.unbounded_send(f),
                 Frame::Stream(f, data) => _ = stream_frames_entry.unbounded_send((f, data)),
                 Frame::Crypto(f, bytes) => _ = crypto_frames_entry.unbounded_send((f, bytes)),
@@ -134,9 +161,9 @@ impl DataSpace {
             let data_streams = self.streams.clone();
             let crypto_stream_outgoing = self.crypto_stream.outgoing();
             let sent_journal = self.journal.of_sent_packets();
-            move |ack_frame: &AckFrame| {
+            move |ack_frame: &AckFrame| -> Result<(), Error> {
                 let mut rotate_guard = sent_journal.rotate();
-                rotate_guard.update_largest(ack_frame.largest.into_inner());
+                rotate_guard.update_largest(ack_frame)?;
 
                 for pn in ack_frame.iter().flat_map(|r| r.rev()) {
                     for frame in rotate_guard.on_pkt_acked(pn) {
@@ -154,25 +181,30 @@ impl DataSpace {
                         }
                     }
                 }
+                Ok(())
             }
         };
 
         // Assemble the pipelines of frame processing
-        // TODO: pipe rcvd_new_token_frames
         let local_cids_with_router = Router::revoke(cid_registry.local.clone());
-        pipe!(rcvd_retire_cid_frames |> local_cids_with_router, recv_frame);
-        pipe!(@error(conn_error) rcvd_new_cid_frames |> cid_registry.remote, recv_frame);
-        pipe!(rcvd_max_data_frames |> flow_ctrl.sender, recv_frame);
-        pipe!(rcvd_data_blocked_frames |> flow_ctrl.recver, recv_frame);
-        pipe!(@error(conn_error) rcvd_handshake_done_frames |> *handshake, recv_frame);
-        pipe!(@error(conn_error) rcvd_crypto_frames |> self.crypto_stream.incoming(), recv_frame);
-        pipe!(@error(conn_error) rcvd_stream_ctrl_frames |> self.streams, recv_frame);
+        pipe!(spawner, rcvd_retire_cid_frames |> local_cids_with_router, recv_frame);
+        pipe!(spawner, @error(conn_error) rcvd_new_cid_frames |> cid_registry.remote, recv_frame);
+        pipe!(spawner, rcvd_max_data_frames |> flow_ctrl.sender, recv_frame);
+        pipe!(spawner, rcvd_data_blocked_frames |> flow_ctrl.recver, recv_frame);
+        pipe!(spawner, @error(conn_error) rcvd_handshake_done_frames |> *handshake, recv_frame);
+        pipe!(spawner, @error(conn_error) rcvd_crypto_frames |> self.crypto_stream.incoming(), recv_frame);
+        pipe!(spawner, @error(conn_error) rcvd_stream_ctrl_frames |> self.streams, recv_frame);
         // pipe!(@error(conn_error) rcvd_stream_frames |> receive_stream_frame);
-        pipe!(@error(conn_error) rcvd_datagram_frames |> self.datagrams, recv_frame);
-        pipe!(rcvd_ack_frames |> on_data_acked);
-        pipe!(rcvd_new_token_frames |> recv_new_token,recv_frame);
+        pipe!(spawner, @error(conn_error) rcvd_datagram_frames |> self.datagrams, recv_frame);
+        pipe!(spawner, @error(conn_error) rcvd_ack_frames |> on_data_acked);
+        pipe!(spawner, rcvd_new_token_frames |> recv_new_token,recv_frame);
 
-        self.handle_stream_frame_with_flow_ctrl(flow_ctrl, conn_error.clone(), rcvd_stream_frames);
+        self.handle_stream_frame_with_flow_ctrl(
+            flow_ctrl,
+            conn_error.clone(),
+            rcvd_stream_frames,
+            spawner,
+        );
 
         let join_handler0 = self.parse_rcvd_0rtt_packet_and_dispatch_frames(
             rcvd_0rtt_packets,
@@ -180,6 +212,7 @@ impl DataSpace {
             dispatch_data_frame.clone(),
             notify.clone(),
             conn_error.clone(),
+            spawner,
         );
         let join_handler1 = self.parse_rcvd_1rtt_packet_and_dispatch_frames(
             rcvd_1rtt_packets,
@@ -187,6 +220,7 @@ impl DataSpace {
             dispatch_data_frame,
             notify.clone(),
             conn_error.clone(),
+            spawner,
         );
         (join_handler0, join_handler1)
     }
@@ -198,17 +232,22 @@ impl DataSpace {
         dispatch_frame: impl Fn(Frame, Type, &Path) + Send + 'static,
         notify: Arc<Notify>,
         conn_error: ConnError,
+        spawner: &Spawner,
     ) -> JoinHandle<RcvdPackets> {
-        tokio::spawn({
+        spawner.spawn_detached({
             let rcvd_journal = self.journal.of_rcvd_packets();
             let keys = self.zero_rtt_keys.clone();
             async move {
-                while let Some((mut packet, pathway, usc)) = any(rcvd_packets.next(), &notify).await
+                let mut undecryptable = VecDeque::new();
+                while let Some(((mut packet, pathway, usc), keys)) = next_decryptable(
+                    &mut rcvd_packets,
+                    &mut undecryptable,
+                    || keys.get_remote_keys(),
+                    &notify,
+                )
+                .await
                 {
                     let pty = packet.header.get_type();
-                    let Some(keys) = any(keys.get_remote_keys(), &notify).await else {
-                        break;
-                    };
                     let undecoded_pn = match remove_protection_of_long_packet(
                         keys.remote.header.as_ref(),
                         packet.bytes.as_mut(),
@@ -222,9 +261,11 @@ impl DataSpace {
                         }
                     };
 
-                    let pn = match rcvd_journal.decode_pn(undecoded_pn) {
-                        Ok(pn) => pn,
-                        // TooOld/TooLarge/HasRcvd
+                    // 包号重复的包也要解密，以免把伪造的包算作重复包
+                    let (pn, is_duplicate) = match rcvd_journal.decode_pn(undecoded_pn) {
+                        Ok(pn) => (pn, false),
+                        Err(InvalidPacketNumber::HasRcvd(pn)) => (pn, true),
+                        // TooOld/TooLarge
                         Err(_e) => continue,
                     };
                     let body_offset = packet.offset + undecoded_pn.size();
@@ -235,6 +276,10 @@ impl DataSpace {
                         body_offset,
                     );
                     let Ok(pkt_len) = decrypted else { continue };
+                    if is_duplicate {
+                        rcvd_journal.register_duplicate(pn);
+                        continue;
+                    }
 
                     let path = pathes.get_or_create(pathway, usc);
                     path.on_rcvd(packet.bytes.len());
@@ -252,6 +297,7 @@ impl DataSpace {
                     ) {
                         Ok(is_ack_packet) => {
                             rcvd_journal.register_pn(pn);
+                            rcvd_journal.register_ecn(packet.ecn);
                             path.cc().on_pkt_rcvd(Epoch::Data, pn, is_ack_packet);
                         }
                         Err(e) => conn_error.on_error(e),
@@ -269,17 +315,22 @@ impl DataSpace {
         dispatch_frame: impl Fn(Frame, Type, &Path) + Send + 'static,
         notify: Arc<Notify>,
         conn_error: ConnError,
+        spawner: &Spawner,
     ) -> JoinHandle<RcvdPackets> {
-        tokio::spawn({
+        spawner.spawn_detached({
             let rcvd_journal = self.journal.of_rcvd_packets();
             let keys = self.one_rtt_keys.clone();
             async move {
-                while let Some((mut packet, pathway, usc)) = any(rcvd_packets.next(), &notify).await
+                let mut undecryptable = VecDeque::new();
+                while let Some(((mut packet, pathway, usc), (hpk, pk))) = next_decryptable(
+                    &mut rcvd_packets,
+                    &mut undecryptable,
+                    || keys.get_remote_keys(),
+                    &notify,
+                )
+                .await
                 {
                     let pty = packet.header.get_type();
-                    let Some((hpk, pk)) = any(keys.get_remote_keys(), &notify).await else {
-                        break;
-                    };
                     let (undecoded_pn, key_phase) = match remove_protection_of_short_packet(
                         hpk.as_ref(),
                         packet.bytes.as_mut(),
@@ -293,9 +344,11 @@ impl DataSpace {
                         }
                     };
 
-                    let pn = match rcvd_journal.decode_pn(undecoded_pn) {
-                        Ok(pn) => pn,
-                        // TooOld/TooLarge/HasRcvd
+                    // 包号重复的包也要解密，以免把伪造的包算作重复包
+                    let (pn, is_duplicate) = match rcvd_journal.decode_pn(undecoded_pn) {
+                        Ok(pn) => (pn, false),
+                        Err(InvalidPacketNumber::HasRcvd(pn)) => (pn, true),
+                        // TooOld/TooLarge
                         Err(_e) => continue,
                     };
                     let body_offset = packet.offset + undecoded_pn.size();
@@ -303,6 +356,10 @@ impl DataSpace {
                     let decrypted =
                         decrypt_packet(pk.as_ref(), pn, packet.bytes.as_mut(), body_offset);
                     let Ok(pkt_len) = decrypted else { continue };
+                    if is_duplicate {
+                        rcvd_journal.register_duplicate(pn);
+                        continue;
+                    }
 
                     let path = pathes.get_or_create(pathway, usc);
                     path.on_rcvd(packet.bytes.len());
@@ -320,7 +377,11 @@ impl DataSpace {
                     ) {
                         Ok(is_ack_packet) => {
                             rcvd_journal.register_pn(pn);
+                            rcvd_journal.register_ecn(packet.ecn);
                             path.cc().on_pkt_rcvd(Epoch::Data, pn, is_ack_packet);
+                            if let Type::Short(one_rtt) = pty {
+                                path.spin().on_rcvd(pn, *one_rtt);
+                            }
                         }
                         Err(e) => conn_error.on_error(e),
                     }
@@ -335,9 +396,10 @@ impl DataSpace {
         flow_ctrl: &FlowController,
         conn_error: ConnError,
         mut rcvd_stream_frames: mpsc::UnboundedReceiver<(StreamFrame, Bytes)>,
+        spawner: &Spawner,
     ) {
         // Handling Stream Frames
-        tokio::spawn({
+        spawner.spawn_detached({
             let streams = self.streams.clone();
             let flow_ctrl = flow_ctrl.clone();
             let conn_error = conn_error.clone();
@@ -393,6 +455,8 @@ impl DataSpace {
         self.datagrams.try_load_data_into(&mut packet);
 
         let packet: PacketWriter<'b> = packet.try_into().ok()?;
+        self.early_data_bytes
+            .fetch_add(fresh_data as u64, Ordering::Relaxed);
         Some((
             packet.encrypt_long_packet(keys.local.header.as_ref(), keys.local.packet.as_ref()),
             fresh_data,
@@ -470,6 +534,8 @@ impl DataSpace {
             one_rtt_keys: self.one_rtt_keys.clone(),
             challenge_sndbuf,
             response_sndbuf,
+            ping_sndbuf: self.ping_sndbuf.clone(),
+            early_data_bytes: self.early_data_bytes.clone(),
             crypto_stream_outgoing: self.crypto_stream.outgoing(),
             reliable_frames,
             streams,
@@ -484,6 +550,8 @@ pub struct DataTracker {
     reliable_frames: ArcReliableFrameDeque,
     streams: DataStreams,
     outgoing: CryptoStreamOutgoing,
+    ping_sndbuf: SendBuffer<PingFrame>,
+    conn_error: ConnError,
 }
 
 impl DataTracker {
@@ -492,30 +560,47 @@ impl DataTracker {
         reliable_frames: ArcReliableFrameDeque,
         streams: DataStreams,
         outgoing: CryptoStreamOutgoing,
+        ping_sndbuf: SendBuffer<PingFrame>,
+        conn_error: ConnError,
     ) -> Self {
         Self {
             journal,
             reliable_frames,
             streams,
             outgoing,
+            ping_sndbuf,
+            conn_error,
         }
     }
 }
 
 impl TrackPackets for DataTracker {
-    fn may_loss(&self, pn: u64) {
+    fn may_loss(&self, pn: u64) -> usize {
+        let mut frames = 0;
         for frame in self.journal.of_sent_packets().rotate().may_loss_pkt(pn) {
             match frame {
                 GuaranteedFrame::Stream(f) => self.streams.may_loss_data(&f),
-                GuaranteedFrame::Reliable(f) => self.reliable_frames.send_frame([f]),
+                GuaranteedFrame::Reliable(f) => {
+                    // 丢包严重，等待重传的帧过多，与其无限制地占用内存，不如关闭连接
+                    if let Err(e) = self.reliable_frames.may_loss_frame(f) {
+                        let error = Error::with_default_fty(ErrorKind::Internal, e.to_string());
+                        self.conn_error.on_error(error);
+                    }
+                }
                 GuaranteedFrame::Crypto(f) => self.outgoing.may_loss_data(&f),
             }
+            frames += 1;
         }
+        frames
     }
 
     fn retire(&self, pn: u64) {
         self.journal.of_rcvd_packets().write().retire(pn);
     }
+
+    fn send_ping(&self) {
+        self.ping_sndbuf.write(PingFrame);
+    }
 }
 
 #[derive(Clone)]
@@ -1,10 +1,13 @@
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc};
 
 use bytes::BufMut;
-use futures::{channel::mpsc, StreamExt};
+use futures::channel::mpsc;
 use qbase::{
     cid::ConnectionId,
-    frame::{io::WriteFrame, AckFrame, ConnectionCloseFrame, Frame, FrameReader, ReceiveFrame},
+    error::Error,
+    frame::{
+        io::WriteFrame, AckFrame, ConnectionCloseFrame, Frame, FrameReader, PingFrame, ReceiveFrame,
+    },
     packet::{
         decrypt::{decrypt_packet, remove_protection_of_long_packet},
         encrypt::{encode_long_first_byte, encrypt_packet, protect_header},
@@ -19,16 +22,17 @@ use qbase::{
 use qcongestion::{CongestionControl, TrackPackets, MSS};
 use qrecovery::{
     crypto::{CryptoStream, CryptoStreamOutgoing},
-    journal::{ArcRcvdJournal, HandshakeJournal},
+    journal::{ArcRcvdJournal, HandshakeJournal, InvalidPacketNumber},
 };
-use tokio::{sync::Notify, task::JoinHandle};
+use tokio::sync::Notify;
 
-use super::any;
+use super::next_decryptable;
 use crate::{
     conn::{transmit::HandshakeSpaceReader, RcvdPackets},
     error::ConnError,
-    path::{ArcPaths, Path},
+    path::{ArcPaths, Path, SendBuffer},
     pipe,
+    spawn::{JoinHandle, Spawner},
     tx::{PacketMemory, Transaction},
 };
 
@@ -37,6 +41,8 @@ pub struct HandshakeSpace {
     pub keys: ArcKeys,
     pub journal: HandshakeJournal,
     pub crypto_stream: CryptoStream,
+    // 探测超时时无数据可重传，发送PING
+    pub ping_sndbuf: SendBuffer<PingFrame>,
 }
 
 impl Default for HandshakeSpace {
@@ -45,6 +51,7 @@ impl Default for HandshakeSpace {
             keys: ArcKeys::new_pending(),
             journal: HandshakeJournal::with_capacity(16),
             crypto_stream: CryptoStream::new(4096, 4096),
+            ping_sndbuf: SendBuffer::default(),
         }
     }
 }
@@ -56,6 +63,7 @@ impl HandshakeSpace {
         pathes: &ArcPaths,
         notify: &Arc<Notify>,
         conn_error: &ConnError,
+        spawner: &Spawner,
     ) -> JoinHandle<RcvdPackets> {
         let (crypto_frames_entry, rcvd_crypto_frames) = mpsc::unbounded();
         let (ack_frames_entry, rcvd_ack_frames) = mpsc::unbounded();
@@ -76,26 +84,28 @@ impl HandshakeSpace {
         let on_data_acked = {
             let crypto_stream_outgoing = self.crypto_stream.outgoing();
             let sent_journal = self.journal.of_sent_packets();
-            move |ack_frame: &AckFrame| {
+            move |ack_frame: &AckFrame| -> Result<(), Error> {
                 let mut rotate_guard = sent_journal.rotate();
-                rotate_guard.update_largest(ack_frame.largest.into_inner());
+                rotate_guard.update_largest(ack_frame)?;
 
                 for pn in ack_frame.iter().flat_map(|r| r.rev()) {
                     for frame in rotate_guard.on_pkt_acked(pn) {
                         crypto_stream_outgoing.on_data_acked(&frame);
                     }
                 }
+                Ok(())
             }
         };
 
-        pipe!(@error(conn_error) rcvd_crypto_frames |> self.crypto_stream.incoming(), recv_frame);
-        pipe!(rcvd_ack_frames |> on_data_acked);
+        pipe!(spawner, @error(conn_error) rcvd_crypto_frames |> self.crypto_stream.incoming(), recv_frame);
+        pipe!(spawner, @error(conn_error) rcvd_ack_frames |> on_data_acked);
         self.parse_rcvd_packets_and_dispatch_frames(
             rcvd_packets,
             pathes,
             dispatch_frame,
             notify,
             conn_error,
+            spawner,
         )
     }
 
@@ -106,20 +116,25 @@ impl HandshakeSpace {
         dispatch_frame: impl Fn(Frame, &Path) + Send + 'static,
         notify: &Arc<Notify>,
         conn_error: &ConnError,
+        spawner: &Spawner,
     ) -> JoinHandle<RcvdPackets> {
         let pathes = pathes.clone();
         let conn_error = conn_error.clone();
         let notify = notify.clone();
-        tokio::spawn({
+        spawner.spawn_detached({
             let rcvd_journal = self.journal.of_rcvd_packets();
             let keys = self.keys.clone();
             async move {
-                while let Some((mut packet, pathway, usc)) = any(rcvd_packets.next(), &notify).await
+                let mut undecryptable = VecDeque::new();
+                while let Some(((mut packet, pathway, usc), keys)) = next_decryptable(
+                    &mut rcvd_packets,
+                    &mut undecryptable,
+                    || keys.get_remote_keys(),
+                    &notify,
+                )
+                .await
                 {
                     let pty = packet.header.get_type();
-                    let Some(keys) = any(keys.get_remote_keys(), &notify).await else {
-                        break;
-                    };
                     let undecoded_pn = match remove_protection_of_long_packet(
                         keys.remote.header.as_ref(),
                         packet.bytes.as_mut(),
@@ -133,9 +148,11 @@ impl HandshakeSpace {
                         }
                     };
 
-                    let pn = match rcvd_journal.decode_pn(undecoded_pn) {
-                        Ok(pn) => pn,
-                        // TooOld/TooLarge/HasRcvd
+                    // 包号重复的包也要解密，以免把伪造的包算作重复包
+                    let (pn, is_duplicate) = match rcvd_journal.decode_pn(undecoded_pn) {
+                        Ok(pn) => (pn, false),
+                        Err(InvalidPacketNumber::HasRcvd(pn)) => (pn, true),
+                        // TooOld/TooLarge
                         Err(_e) => continue,
                     };
                     let body_offset = packet.offset + undecoded_pn.size();
@@ -146,6 +163,10 @@ impl HandshakeSpace {
                         body_offset,
                     );
                     let Ok(pkt_len) = decrypted else { continue };
+                    if is_duplicate {
+                        rcvd_journal.register_duplicate(pn);
+                        continue;
+                    }
 
                     let path = pathes.get_or_create(pathway, usc);
                     path.on_rcvd(packet.bytes.len());
@@ -169,6 +190,7 @@ impl HandshakeSpace {
                     ) {
                         Ok(is_ack_packet) => {
                             rcvd_journal.register_pn(pn);
+                            rcvd_journal.register_ecn(packet.ecn);
                             path.cc().on_pkt_rcvd(Epoch::Handshake, pn, is_ack_packet);
                         }
                         Err(e) => conn_error.on_error(e),
@@ -221,6 +243,15 @@ impl HandshakeSpace {
             keys: self.keys.clone(),
             journal: self.journal.clone(),
             crypto_stream_outgoing: self.crypto_stream.outgoing(),
+            ping_sndbuf: self.ping_sndbuf.clone(),
+        }
+    }
+
+    pub fn tracker(&self) -> HandshakeTracker {
+        HandshakeTracker {
+            journal: self.journal.clone(),
+            outgoing: self.crypto_stream.outgoing(),
+            ping_sndbuf: self.ping_sndbuf.clone(),
         }
     }
 }
@@ -326,22 +357,24 @@ impl super::RecvPacket for ClosingHandshakeScope {
 pub struct HandshakeTracker {
     journal: HandshakeJournal,
     outgoing: CryptoStreamOutgoing,
-}
-
-impl HandshakeTracker {
-    pub fn new(journal: HandshakeJournal, outgoing: CryptoStreamOutgoing) -> Self {
-        Self { journal, outgoing }
-    }
+    ping_sndbuf: SendBuffer<PingFrame>,
 }
 
 impl TrackPackets for HandshakeTracker {
-    fn may_loss(&self, pn: u64) {
+    fn may_loss(&self, pn: u64) -> usize {
+        let mut frames = 0;
         for frame in self.journal.of_sent_packets().rotate().may_loss_pkt(pn) {
             self.outgoing.may_loss_data(&frame);
+            frames += 1;
         }
+        frames
     }
 
     fn retire(&self, pn: u64) {
         self.journal.of_rcvd_packets().write().retire(pn);
     }
+
+    fn send_ping(&self) {
+        self.ping_sndbuf.write(PingFrame);
+    }
 }
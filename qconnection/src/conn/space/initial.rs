@@ -1,9 +1,13 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
 
 use bytes::BufMut;
-use futures::{channel::mpsc, StreamExt};
+use futures::channel::mpsc;
 use qbase::{
-    frame::{AckFrame, Frame, FrameReader, ReceiveFrame},
+    error::Error,
+    frame::{AckFrame, Frame, FrameReader, PingFrame, ReceiveFrame},
     packet::{
         decrypt::{decrypt_packet, remove_protection_of_long_packet},
         header::{long::io::LongHeaderBuilder, GetScid, GetType},
@@ -16,16 +20,17 @@ use qbase::{
 use qcongestion::{CongestionControl, TrackPackets};
 use qrecovery::{
     crypto::{CryptoStream, CryptoStreamOutgoing},
-    journal::InitialJournal,
+    journal::{InitialJournal, InvalidPacketNumber},
 };
-use tokio::{sync::Notify, task::JoinHandle};
+use tokio::sync::Notify;
 
-use super::any;
+use super::next_decryptable;
 use crate::{
     conn::{transmit::InitialSpaceReader, ArcRemoteCids, RcvdPackets},
     error::ConnError,
-    path::{ArcPath, ArcPaths, Path},
+    path::{ArcPath, ArcPaths, Path, SendBuffer},
     pipe,
+    spawn::{JoinHandle, Spawner},
     tx::{PacketMemory, Transaction},
 };
 
@@ -34,6 +39,8 @@ pub struct InitialSpace {
     pub keys: ArcKeys,
     pub journal: InitialJournal,
     pub crypto_stream: CryptoStream,
+    // 探测超时时无数据可重传，发送PING
+    pub ping_sndbuf: SendBuffer<PingFrame>,
 }
 
 impl InitialSpace {
@@ -46,6 +53,7 @@ impl InitialSpace {
             keys,
             journal,
             crypto_stream,
+            ping_sndbuf: SendBuffer::default(),
         }
     }
 
@@ -59,6 +67,7 @@ impl InitialSpace {
         conn_error: &ConnError,
         parameters: ArcParameters,
         validate: impl Fn(&[u8], ArcPath) + Send + 'static,
+        spawner: &Spawner,
     ) -> JoinHandle<RcvdPackets> {
         let (crypto_frames_entry, rcvd_crypto_frames) = mpsc::unbounded();
         let (ack_frames_entry, rcvd_ack_frames) = mpsc::unbounded();
@@ -78,20 +87,21 @@ impl InitialSpace {
         let on_data_acked = {
             let crypto_stream_outgoing = self.crypto_stream.outgoing();
             let sent_journal = self.journal.of_sent_packets();
-            move |ack_frame: &AckFrame| {
+            move |ack_frame: &AckFrame| -> Result<(), Error> {
                 let mut rotate_guard = sent_journal.rotate();
-                rotate_guard.update_largest(ack_frame.largest.into_inner());
+                rotate_guard.update_largest(ack_frame)?;
 
                 for pn in ack_frame.iter().flat_map(|r| r.rev()) {
                     for frame in rotate_guard.on_pkt_acked(pn) {
                         crypto_stream_outgoing.on_data_acked(&frame);
                     }
                 }
+                Ok(())
             }
         };
 
-        pipe!(@error(conn_error) rcvd_crypto_frames |> self.crypto_stream.incoming(), recv_frame);
-        pipe!(rcvd_ack_frames |> on_data_acked);
+        pipe!(spawner, @error(conn_error) rcvd_crypto_frames |> self.crypto_stream.incoming(), recv_frame);
+        pipe!(spawner, @error(conn_error) rcvd_ack_frames |> on_data_acked);
 
         self.parse_rcvd_packets_and_dispatch_frames(
             rcvd_packets,
@@ -102,6 +112,7 @@ impl InitialSpace {
             conn_error,
             parameters,
             validate,
+            spawner,
         )
     }
 
@@ -116,22 +127,27 @@ impl InitialSpace {
         conn_error: &ConnError,
         parameters: ArcParameters,
         validate: impl Fn(&[u8], ArcPath) + Send + 'static,
+        spawner: &Spawner,
     ) -> JoinHandle<RcvdPackets> {
         let pathes = pathes.clone();
         let conn_error = conn_error.clone();
-        tokio::spawn({
+        spawner.spawn_detached({
             let rcvd_journal = self.journal.of_rcvd_packets();
             let keys = self.keys.clone();
             let remote_cids = remote_cids.clone();
             let notify = notify.clone();
 
             async move {
-                while let Some((mut packet, pathway, usc)) = any(rcvd_packets.next(), &notify).await
+                let mut undecryptable = VecDeque::new();
+                while let Some(((mut packet, pathway, usc), keys)) = next_decryptable(
+                    &mut rcvd_packets,
+                    &mut undecryptable,
+                    || keys.get_remote_keys(),
+                    &notify,
+                )
+                .await
                 {
                     let pty = packet.header.get_type();
-                    let Some(keys) = any(keys.get_remote_keys(), &notify).await else {
-                        break;
-                    };
                     let undecoded_pn = match remove_protection_of_long_packet(
                         keys.remote.header.as_ref(),
                         packet.bytes.as_mut(),
@@ -145,9 +161,11 @@ impl InitialSpace {
                         }
                     };
 
-                    let pn = match rcvd_journal.decode_pn(undecoded_pn) {
-                        Ok(pn) => pn,
-                        // TooOld/TooLarge/HasRcvd
+                    // 包号重复的包也要解密，以免把伪造的包算作重复包
+                    let (pn, is_duplicate) = match rcvd_journal.decode_pn(undecoded_pn) {
+                        Ok(pn) => (pn, false),
+                        Err(InvalidPacketNumber::HasRcvd(pn)) => (pn, true),
+                        // TooOld/TooLarge
                         Err(_e) => continue,
                     };
                     let body_offset = packet.offset + undecoded_pn.size();
@@ -158,6 +176,10 @@ impl InitialSpace {
                         body_offset,
                     );
                     let Ok(pkt_len) = decrypted else { continue };
+                    if is_duplicate {
+                        rcvd_journal.register_duplicate(pn);
+                        continue;
+                    }
 
                     let path = pathes.get_or_create(pathway, usc);
                     path.on_rcvd(packet.bytes.len());
@@ -183,6 +205,7 @@ impl InitialSpace {
                     ) {
                         Ok(is_ack_packet) => {
                             rcvd_journal.register_pn(pn);
+                            rcvd_journal.register_ecn(packet.ecn);
                             path.cc().on_pkt_rcvd(Epoch::Initial, pn, is_ack_packet);
                         }
                         Err(e) => {
@@ -253,6 +276,15 @@ impl InitialSpace {
             keys: self.keys.clone(),
             journal: self.journal.clone(),
             crypto_stream_outgoing: self.crypto_stream.outgoing(),
+            ping_sndbuf: self.ping_sndbuf.clone(),
+        }
+    }
+
+    pub fn tracker(&self) -> InitialTracker {
+        InitialTracker {
+            journal: self.journal.clone(),
+            outgoing: self.crypto_stream.outgoing(),
+            ping_sndbuf: self.ping_sndbuf.clone(),
         }
     }
 }
@@ -261,22 +293,24 @@ impl InitialSpace {
 pub struct InitialTracker {
     journal: InitialJournal,
     outgoing: CryptoStreamOutgoing,
-}
-
-impl InitialTracker {
-    pub fn new(journal: InitialJournal, outgoing: CryptoStreamOutgoing) -> Self {
-        Self { journal, outgoing }
-    }
+    ping_sndbuf: SendBuffer<PingFrame>,
 }
 
 impl TrackPackets for InitialTracker {
-    fn may_loss(&self, pn: u64) {
+    fn may_loss(&self, pn: u64) -> usize {
+        let mut frames = 0;
         for frame in self.journal.of_sent_packets().rotate().may_loss_pkt(pn) {
             self.outgoing.may_loss_data(&frame);
+            frames += 1;
         }
+        frames
     }
 
     fn retire(&self, pn: u64) {
         self.journal.of_rcvd_packets().write().retire(pn);
     }
+
+    fn send_ping(&self) {
+        self.ping_sndbuf.write(PingFrame);
+    }
 }
@@ -1,9 +1,18 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 use bytes::BufMut;
 use qbase::{
     cid::ConnectionId,
-    frame::{PathChallengeFrame, PathResponseFrame, STREAM_FRAME_MAX_ENCODING_SIZE},
+    frame::{
+        io::WriteFrame, PathChallengeFrame, PathResponseFrame, PingFrame,
+        STREAM_FRAME_MAX_ENCODING_SIZE,
+    },
     packet::{
         encrypt::{
             encode_long_first_byte, encode_short_first_byte, encrypt_packet, protect_header,
@@ -32,6 +41,9 @@ pub struct DataSpaceReader {
     // 数据源
     pub challenge_sndbuf: SendBuffer<PathChallengeFrame>,
     pub response_sndbuf: SendBuffer<PathResponseFrame>,
+    pub ping_sndbuf: SendBuffer<PingFrame>,
+    // 在0-RTT数据包中发出的新的流数据的字节数，见DataSpace::early_data_bytes
+    pub early_data_bytes: Arc<AtomicU64>,
     pub crypto_stream_outgoing: CryptoStreamOutgoing,
     pub reliable_frames: ArcReliableFrameDeque,
     pub streams: DataStreams,
@@ -95,6 +107,14 @@ impl DataSpaceReader {
             in_flight = true;
             body_buf = &mut body_buf[n..];
         }
+        // 保活的PING帧，仅为引起对端的确认
+        let n = self.ping_sndbuf.try_read(body_buf);
+        if n > 0 {
+            new_pkt_guard.record_trivial();
+            is_ack_eliciting = true;
+            in_flight = true;
+            body_buf = &mut body_buf[n..];
+        }
 
         // 4. 检查是否需要发送Ack，若是，且符合（constraints + buf）节制，生成ack并写入，但发送记录并不记录
         let mut sent_ack = None;
@@ -189,6 +209,53 @@ impl DataSpaceReader {
         ))
     }
 
+    /// Read a PMTU probe into the whole `buf`, a 1-RTT packet of a PING frame and the padding.
+    ///
+    /// Returns (pn, sent_size) or None
+    pub fn try_read_mtu_probe(
+        &self,
+        buf: &mut [u8],
+        dcid: ConnectionId,
+        spin: SpinBit,
+        (hpk, pk): (Arc<dyn HeaderProtectionKey>, ArcOneRttPacketKeys),
+    ) -> Option<(u64, usize)> {
+        let hdr = OneRttHeader::new(spin, dcid);
+        let sent_size = buf.len();
+        if sent_size < hdr.size() + 20 {
+            return None;
+        }
+        let (mut hdr_buf, payload_tag) = buf.split_at_mut(hdr.size());
+        let payload_tag_len = payload_tag.len();
+        let tag_len = pk.tag_len();
+        let payload_buf = &mut payload_tag[..payload_tag_len - tag_len];
+
+        let sent_journal = self.journal.of_sent_packets();
+        let mut new_pkt_guard = sent_journal.new_packet();
+        let (pn, encoded_pn) = new_pkt_guard.pn();
+        if payload_buf.remaining_mut() <= encoded_pn.size() {
+            return None;
+        }
+        let (mut pn_buf, mut body_buf) = payload_buf.split_at_mut(encoded_pn.size());
+        // PING帧使探测包被确认，其余全部填充，探测包不携带任何需要重传的数据
+        body_buf.put_frame(&PingFrame);
+        new_pkt_guard.record_trivial();
+        drop(new_pkt_guard);
+        body_buf.put_bytes(0, body_buf.remaining_mut());
+
+        let hdr_len = hdr_buf.len();
+        let pn_len = pn_buf.len();
+        hdr_buf.put_header(&hdr);
+        pn_buf.put_packet_number(encoded_pn);
+
+        let pk_guard = pk.lock_guard();
+        let (key_phase, pk) = pk_guard.get_local();
+        encode_short_first_byte(&mut buf[0], pn_len, key_phase);
+        encrypt_packet(pk.as_ref(), pn, &mut buf[..sent_size], hdr_len + pn_len);
+        protect_header(hpk.as_ref(), &mut buf[..sent_size], hdr_len, pn_len);
+
+        Some((pn, sent_size))
+    }
+
     /// Returns (pn, is_ack_eliciting, sent_size, fresh_bytes, in_flight) or None
     pub fn try_read_0rtt(
         &self,
@@ -300,6 +367,8 @@ impl DataSpaceReader {
             pn_len,
         );
 
+        self.early_data_bytes
+            .fetch_add(fresh_bytes as u64, Ordering::Relaxed);
         // 0RTT包不能发送Ack
         Some((pn, is_ack_eliciting, sent_size, fresh_bytes, in_flight))
     }
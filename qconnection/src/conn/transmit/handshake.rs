@@ -3,6 +3,7 @@ use std::time::Instant;
 use bytes::BufMut;
 use qbase::{
     cid::ConnectionId,
+    frame::PingFrame,
     packet::{
         encrypt::{encode_long_first_byte, encrypt_packet, protect_header},
         header::io::WriteHeader,
@@ -13,11 +14,14 @@ use qbase::{
 };
 use qrecovery::{crypto::CryptoStreamOutgoing, journal::HandshakeJournal};
 
+use crate::path::SendBuffer;
+
 #[derive(Clone)]
 pub struct HandshakeSpaceReader {
     pub(crate) keys: ArcKeys,
     pub(crate) journal: HandshakeJournal,
     pub(crate) crypto_stream_outgoing: CryptoStreamOutgoing,
+    pub(crate) ping_sndbuf: SendBuffer<PingFrame>,
 }
 
 impl HandshakeSpaceReader {
@@ -74,6 +78,15 @@ impl HandshakeSpaceReader {
             is_ack_eliciting = true;
             in_flight = true;
         }
+
+        // 6. 探测超时时无数据可重传，发送PING，使探测包可被确认
+        let n = self.ping_sndbuf.try_read(body_buf);
+        if n > 0 {
+            new_pkt_guard.record_trivial();
+            body_buf = &mut body_buf[n..];
+            is_ack_eliciting = true;
+            in_flight = true;
+        }
         drop(new_pkt_guard); // 持有这把锁的时间越短越好，毕竟下面的加密可能会有点耗时
 
         // 7. 填充，保护头部，加密
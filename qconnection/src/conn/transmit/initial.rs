@@ -6,6 +6,7 @@ use std::{
 use bytes::BufMut;
 use qbase::{
     cid::ConnectionId,
+    frame::PingFrame,
     packet::{
         encrypt::{encode_long_first_byte, encrypt_packet, protect_header},
         header::io::WriteHeader,
@@ -16,12 +17,15 @@ use qbase::{
 };
 use qrecovery::{crypto::CryptoStreamOutgoing, journal::InitialJournal};
 
+use crate::path::SendBuffer;
+
 #[derive(Clone)]
 pub struct InitialSpaceReader {
     pub(crate) token: Arc<Mutex<Vec<u8>>>,
     pub(crate) keys: ArcKeys,
     pub(crate) journal: InitialJournal,
     pub(crate) crypto_stream_outgoing: CryptoStreamOutgoing,
+    pub(crate) ping_sndbuf: SendBuffer<PingFrame>,
 }
 
 impl InitialSpaceReader {
@@ -84,6 +88,15 @@ impl InitialSpaceReader {
             is_ack_eliciting = true;
             in_flight = true;
         }
+
+        // 6. 探测超时时无数据可重传，发送PING，使探测包可被确认
+        let n = self.ping_sndbuf.try_read(body_buf);
+        if n > 0 {
+            new_pkt_guard.record_trivial();
+            body_buf = &mut body_buf[n..];
+            is_ack_eliciting = true;
+            in_flight = true;
+        }
         drop(new_pkt_guard); // 持有这把锁的时间越短越好，毕竟下面的加密可能会有点耗时
 
         let hdr_len = hdr_buf.len();
@@ -99,7 +112,7 @@ impl InitialSpaceReader {
 
         Some((
             move |buf: &mut [u8], len: usize| -> (u64, bool, usize, bool, Option<u64>) {
-                // 6. 填充，保护头部，加密
+                // 7. 填充，保护头部，加密
                 let (_hdr_buf, remain) = buf.split_at_mut(hdr_len - 2);
                 let (mut length_buf, remain) = remain.split_at_mut(2);
                 let (_pn_buf, remain) = remain.split_at_mut(pn_len);
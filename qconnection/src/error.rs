@@ -90,6 +90,40 @@ impl ConnError {
     }
 }
 
+/// The reason why the handshake of a connection did not complete.
+///
+/// It is returned by [`ArcConnection::established`].
+///
+/// [`ArcConnection::established`]: crate::conn::ArcConnection::established
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HandshakeError {
+    /// The TLS handshake failed, such as the certificate is not trusted, or no common protocol
+    /// version is supported, either locally or by the peer.
+    ///
+    /// The `alert` is the TLS alert sent or received in the CONNECTION_CLOSE frame, and the
+    /// `reason` describes the problem.
+    #[error("TLS handshake failed with alert {alert:?}: {reason}")]
+    Tls {
+        alert: rustls::AlertDescription,
+        reason: String,
+    },
+    /// The connection is closed for other reasons before the handshake completes.
+    #[error("connection closed before the handshake completes: {0}")]
+    Closed(Error),
+}
+
+impl From<Error> for HandshakeError {
+    fn from(error: Error) -> Self {
+        match error.kind() {
+            ErrorKind::Crypto(alert) => HandshakeError::Tls {
+                alert: rustls::AlertDescription::from(alert),
+                reason: error.reason().to_owned(),
+            },
+            _ => HandshakeError::Closed(error),
+        }
+    }
+}
+
 /// A future that resolves when a connection error occurs.
 ///
 /// This future is used to track the state of a connection and determine whether it is closing due to an application
@@ -108,7 +142,18 @@ impl std::future::Future for ConnError {
 
 #[cfg(test)]
 mod tests {
-    use qbase::{error::ErrorKind, frame::FrameType::Padding};
+    use bytes::Bytes;
+    use qbase::{
+        error::ErrorKind,
+        frame::{
+            io::WriteFrame, BeFrame, Frame, FrameReader, FrameType, FrameType::Padding, StreamFrame,
+        },
+        packet::r#type::{short::OneRtt, Type},
+        param::CommonParameters,
+        sid::{handy::ConsistentConcurrency, Role, StreamId},
+        varint::VarInt,
+    };
+    use qrecovery::{reliable::ArcReliableFrameDeque, streams::DataStreams};
 
     use super::*;
 
@@ -124,7 +169,8 @@ mod tests {
             }
         });
 
-        let ccf = ConnectionCloseFrame::new(ErrorKind::Internal, None, "Test close frame".into());
+        let ccf =
+            ConnectionCloseFrame::new_quic(ErrorKind::Internal, Padding, "Test close frame".into());
         conn_error.on_ccf_rcvd(&ccf);
 
         _ = task.await;
@@ -165,4 +211,89 @@ mod tests {
 
         _ = task.await;
     }
+
+    #[tokio::test]
+    async fn test_rcvd_app_ccf() {
+        let conn_error = ConnError::default();
+
+        // 对端应用以HTTP/3的错误码关闭连接
+        let error = Error::app(VarInt::from_u32(0x100), "H3_NO_ERROR");
+        let mut buf = Vec::new();
+        buf.put_frame(&ConnectionCloseFrame::from(error.clone()));
+
+        let packet_type = Type::Short(OneRtt::from(0u8));
+        for frame in FrameReader::new(buf.into(), packet_type) {
+            match frame.unwrap() {
+                (Frame::Close(ccf), _) => conn_error.on_ccf_rcvd(&ccf),
+                (frame, _) => panic!("unexpected frame: {frame:?}"),
+            }
+        }
+
+        let (rcvd_error, kind) = conn_error.await;
+        assert_eq!(kind, ConnErrorSource::ReceivedCcf);
+        assert_eq!(rcvd_error, error);
+        let Error::App(app_error) = rcvd_error else {
+            panic!("should be an application error");
+        };
+        assert_eq!(app_error.error_code(), VarInt::from_u32(0x100));
+        assert_eq!(app_error.reason(), "H3_NO_ERROR");
+    }
+
+    #[tokio::test]
+    async fn test_ccf_names_stream_frame() {
+        let streams = DataStreams::new(
+            Role::Server,
+            &CommonParameters::default(),
+            Box::new(ConsistentConcurrency::new(0, 0)),
+            ArcReliableFrameDeque::with_capacity(8),
+        );
+        // 服务端创建的单向流只能发送，对方在其上发送数据是错误
+        let body = Bytes::from_static(b"hello");
+        let stream_frame = StreamFrame::new(StreamId::from(VarInt::from_u32(3)), 0, body.len());
+        let stream_fty = stream_frame.frame_type();
+        let error = streams.recv_data(&(stream_frame, body)).unwrap_err();
+        assert!(matches!(stream_fty, FrameType::Stream(_)));
+        assert_eq!(error.frame_type(), stream_fty);
+
+        let mut buf = Vec::new();
+        buf.put_frame(&ConnectionCloseFrame::from(error.clone()));
+
+        // 对端收到的CONNECTION_CLOSE帧中，携带着引发错误的STREAM帧的类型
+        let conn_error = ConnError::default();
+        let packet_type = Type::Short(OneRtt::from(0u8));
+        for frame in FrameReader::new(buf.into(), packet_type) {
+            match frame.unwrap() {
+                (Frame::Close(ccf), _) => conn_error.on_ccf_rcvd(&ccf),
+                (frame, _) => panic!("unexpected frame: {frame:?}"),
+            }
+        }
+
+        let (rcvd_error, kind) = conn_error.await;
+        assert_eq!(kind, ConnErrorSource::ReceivedCcf);
+        assert_eq!(rcvd_error.kind(), ErrorKind::StreamState);
+        assert_eq!(rcvd_error.frame_type(), stream_fty);
+    }
+
+    #[test]
+    fn test_handshake_error() {
+        let error = Error::with_default_fty(
+            ErrorKind::Crypto(rustls::AlertDescription::UnknownCA.into()),
+            "TLS error: invalid peer certificate: UnknownIssuer",
+        );
+        let handshake_error = HandshakeError::from(error);
+        assert_eq!(
+            handshake_error,
+            HandshakeError::Tls {
+                alert: rustls::AlertDescription::UnknownCA,
+                reason: "TLS error: invalid peer certificate: UnknownIssuer".to_owned(),
+            }
+        );
+        assert!(handshake_error.to_string().contains("UnknownCA"));
+
+        let error = Error::with_default_fty(ErrorKind::ConnectionRefused, "refused");
+        assert_eq!(
+            HandshakeError::from(error.clone()),
+            HandshakeError::Closed(error)
+        );
+    }
 }
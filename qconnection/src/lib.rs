@@ -2,9 +2,32 @@ pub mod conn;
 pub mod error;
 pub mod path;
 pub mod router;
+pub mod spawn;
 pub mod tls;
 pub mod tx;
 pub mod usc;
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use qbase::cid::ConnectionId;
+    use qcongestion::TrackPackets;
+
+    use crate::tls::ArcTlsSession;
+
+    /// A [`TrackPackets`] ignores all the notifications, for the tests don't care about the spaces.
+    pub(crate) struct Mock;
+
+    impl TrackPackets for Mock {
+        fn may_loss(&self, _: u64) -> usize {
+            0
+        }
+        fn retire(&self, _: u64) {}
+        fn send_ping(&self) {}
+    }
+
+    /// The server side initial keys derived from the `cid`.
+    pub(crate) fn keys(cid: ConnectionId) -> rustls::quic::Keys {
+        let provider = rustls::crypto::ring::default_provider();
+        ArcTlsSession::initial_keys(&provider, rustls::Side::Server, cid)
+    }
+}
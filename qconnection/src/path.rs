@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use dashmap::DashMap;
 use deref_derive::{Deref, DerefMut};
@@ -10,23 +10,24 @@ use qbase::{
 use qcongestion::{ArcCC, CongestionControl};
 use qrecovery::reliable::ArcReliableFrameDeque;
 use state::ArcPathState;
-use tokio::time::timeout;
 
 mod anti_amplifier;
 mod pathway;
 mod read;
+mod spin;
 mod state;
 mod util;
 
-use std::sync::atomic::AtomicBool;
-
 pub use anti_amplifier::{ArcAntiAmplifier, DEFAULT_ANTI_FACTOR};
 pub use pathway::{Pathway, RelayAddr};
 pub use read::ReadIntoDatagrams;
+pub use spin::{ArcSpin, SpinObservers};
 pub use util::{Constraints, RecvBuffer, SendBuffer};
 
 use crate::{
     conn::{transmit::*, FlowController},
+    router::PathwayRoute,
+    spawn::Spawner,
     usc::ArcUsc,
 };
 
@@ -52,16 +53,19 @@ pub type ArcPaths = Arc<Paths>;
 /// [`UscRegistry`]: crate::usc::UscRegistry
 #[derive(Clone)]
 pub struct Path {
-    anti_amplifier: ArcAntiAmplifier<DEFAULT_ANTI_FACTOR>,
+    anti_amplifier: ArcAntiAmplifier,
     cc: ArcCC,
     usc: ArcUsc,
     dcid: ArcCidCell<ArcReliableFrameDeque>,
     scid: ConnectionId,
-    spin: Arc<AtomicBool>,
+    spin: ArcSpin,
     challenge_sndbuf: SendBuffer<PathChallengeFrame>,
     response_sndbuf: SendBuffer<PathResponseFrame>,
     response_rcvbuf: RecvBuffer<PathResponseFrame>,
     state: ArcPathState,
+    spawner: Spawner,
+    route: Option<Arc<PathwayRoute>>,
+    max_send_udp_payload: usize,
 }
 
 impl Path {
@@ -80,34 +84,74 @@ impl Path {
     /// space. They are arrays, each element corresponds to a space: intiial space, handshake space,
     /// and data space.
     ///
+    /// The `spin` is the latency spin bit of the path, see [`ArcSpin`].
+    ///
+    /// Before the path is validated, at most `anti_factor` times the amount of data received on
+    /// this path can be sent, see [`ArcAntiAmplifier`]. It's [`DEFAULT_ANTI_FACTOR`] normally.
+    ///
+    /// The tasks of the path are spawned by the `spawner` of the connection, they are cancelled
+    /// once the connection is closed.
     pub fn new(
         usc: ArcUsc,
         scid: ConnectionId,
         dcid: ArcCidCell<ArcReliableFrameDeque>,
         cc: ArcCC,
+        spin: ArcSpin,
+        anti_factor: usize,
+        spawner: Spawner,
     ) -> Self {
         Self {
             usc,
             dcid: dcid.clone(),
             scid,
             cc,
-            anti_amplifier: ArcAntiAmplifier::<DEFAULT_ANTI_FACTOR>::default(),
-            spin: Arc::new(AtomicBool::new(false)),
+            anti_amplifier: ArcAntiAmplifier::new(anti_factor),
+            spin,
             challenge_sndbuf: SendBuffer::default(),
             response_sndbuf: SendBuffer::default(),
             response_rcvbuf: RecvBuffer::default(),
-            state: ArcPathState::new(dcid),
+            state: ArcPathState::new(dcid, &spawner),
+            spawner,
+            route: None,
+            max_send_udp_payload: usize::MAX,
         }
     }
 
+    /// Keep the router entry of the path's [`Pathway`] until the path is dropped.
+    ///
+    /// It's needed if the local connection ID is zero-length, see [`RouterRegistry::route_pathway`].
+    ///
+    /// [`RouterRegistry::route_pathway`]: crate::router::RouterRegistry::route_pathway
+    pub fn keep_route(&mut self, route: PathwayRoute) {
+        self.route = Some(Arc::new(route));
+    }
+
+    /// Cap the size of the datagrams sent on this path.
+    ///
+    /// Some networks drop large UDP datagrams, the datagrams will not be larger than `size`, even if
+    /// the path discovered a larger MTU. By default, only the MTU of the path limits the size.
+    ///
+    /// It must be called before [`Path::begin_sending`] to take effect.
+    pub fn set_max_send_udp_payload(&mut self, size: usize) {
+        self.max_send_udp_payload = size;
+    }
+
     /// Called when a [`PathResponseFrame`] is received.
     pub fn recv_response(&self, frame: PathResponseFrame) {
         self.response_rcvbuf.write(frame);
     }
 
     /// Called when a [`PathChallengeFrame`] is received.
+    ///
+    /// The [`PathResponseFrame`] is buffered in this path, it will only be sent by the sending task
+    /// of this path, to the [`Pathway`] the challenge arrived on, even if this path is not the one
+    /// the connection is mainly using. The response is still limited by the anti-amplifier of this
+    /// path, which is only credited by the datagrams received on this path.
+    ///
+    /// The response is sent urgently, without waiting for the pacer, see [`ArcCC::send_urgently`].
     pub fn recv_challenge(&self, frame: PathChallengeFrame) {
         self.response_sndbuf.write(frame.into());
+        self.cc.send_urgently();
     }
 
     /// Start the [`path verification`] task.
@@ -129,12 +173,13 @@ impl Path {
         // THINK: 这里应该只需要一个ArcRtt，并不需congestion controller出面
         let congestion_ctrl = self.cc.clone();
         let state = self.state.clone();
-        tokio::spawn(async move {
+        let timer = self.spawner.clone();
+        self.spawner.spawn(async move {
             let challenge = PathChallengeFrame::random();
             for _ in 0..3 {
                 let pto = congestion_ctrl.pto_time(Epoch::Data);
                 challenge_sndbuf.write(challenge);
-                match timeout(pto, response_rcvbuf.receive()).await {
+                match timer.timeout(pto, response_rcvbuf.receive()).await {
                     Ok(Some(response)) if *response == *challenge => {
                         anti_amplifier.grant();
                         return;
@@ -175,22 +220,23 @@ impl Path {
             dcid: self.dcid.clone(),
             cc: self.cc.clone(),
             anti_amplifier: self.anti_amplifier.clone(),
-            spin: self.spin.clone(),
+            spin: self.spin.sending(),
             flow_ctrl: flow_ctrl.clone(),
             initial_space_reader: space_readers.0,
             handshake_space_reader: space_readers.1,
             data_space_reader: space_readers.2,
+            max_send_udp_payload: self.max_send_udp_payload,
         };
 
-        tokio::spawn(async move {
+        self.spawner.spawn(async move {
             let mut datagrams = Vec::with_capacity(4);
             loop {
                 let io_vecs = tokio::select! {
                     _ = state.has_been_inactivated() => break,
                     io_vecs = read_into_datagram.read(&mut datagrams) => io_vecs,
                 };
-                let Some(io_vecs) = io_vecs else { break };
-                let send_all = usc.send_all_via_pathway(&io_vecs, pathway);
+                let Some((io_vecs, ecn)) = io_vecs else { break };
+                let send_all = usc.send_all_via_pathway(&io_vecs, pathway, ecn);
                 if let Err(udp_error) = send_all.await {
                     log::warn!(
                         "faild to send datagrams from `{}` to `{}`: {:?}",
@@ -233,6 +279,12 @@ impl Path {
         &self.cc
     }
 
+    /// Get the latency spin bit of the path.
+    #[inline]
+    pub fn spin(&self) -> &ArcSpin {
+        &self.spin
+    }
+
     /// Get the udp socket controller of the path.
     ///
     /// For send datagrams directly to the peer when the connection is clonsing.
@@ -248,6 +300,31 @@ impl Path {
     pub fn grant_anti_amplifier(&self) {
         self.anti_amplifier.grant();
     }
+
+    /// Return whether the peer address of this path is validated, that is, the anti-amplifier limit
+    /// has been lifted.
+    ///
+    /// The limit is lifted once the handshake is complete, a valid token is received, or the path
+    /// validation succeeds, see [`Path::begin_validation`]. The client path is always validated.
+    #[inline]
+    pub fn is_validated(&self) -> bool {
+        self.anti_amplifier.is_granted()
+    }
+}
+
+/// The statistics of a path, returned by [`Paths::stats`].
+///
+/// It's useful to debug the connection migration and the multipath.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathStats {
+    /// The [`Pathway`] of the path, which has the local and remote addresses.
+    pub pathway: Pathway,
+    /// Whether the peer address of the path is validated, see [`Path::is_validated`].
+    pub validated: bool,
+    /// The smoothed RTT of the path.
+    pub rtt: Duration,
+    /// The congestion window of the path, in bytes.
+    pub cwnd: u64,
 }
 
 /// The set of all paths of a connection.
@@ -306,15 +383,16 @@ impl Paths {
             .or_insert_with(|| {
                 let path = (self.creator)(pathway, usc);
                 let state = path.state.clone();
-                tokio::spawn({
+                path.spawner.spawn({
                     let state = state.clone();
                     let cc = path.cc().clone();
+                    let timer = path.spawner.clone();
                     async move {
                         // TOOD: optimize this
                         loop {
                             tokio::select! {
                                 _ = state.has_been_inactivated() => break,
-                                _ = tokio::time::sleep(std::time::Duration::from_millis(10)) => cc.do_tick(),
+                                _ = timer.sleep(Duration::from_millis(10)) => cc.do_tick(),
                             }
                         }
                         pathes.remove(&pathway);
@@ -328,4 +406,307 @@ impl Paths {
             .value()
             .clone()
     }
+
+    /// Return the statistics of all the paths in the set, see [`PathStats`].
+    pub fn stats(&self) -> Vec<PathStats> {
+        self.map
+            .iter()
+            .map(|entry| PathStats {
+                pathway: *entry.key(),
+                validated: entry.is_validated(),
+                rtt: entry.cc.smoothed_rtt(),
+                cwnd: entry.cc.cwnd(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        task::{Context, Poll, Waker},
+        time::{Duration, Instant},
+    };
+
+    use futures::task::noop_waker_ref;
+    use qbase::{
+        cid::ArcRemoteCids,
+        frame::{io::WriteFrame, BeFrame},
+        handshake::Handshake,
+        sid::Role,
+        util::CountWaker,
+        Epoch,
+    };
+    use qcongestion::{CongestionAlgorithm, ManualClock, INITIAL_RTT, MSS};
+
+    use super::*;
+    use crate::{
+        spawn::TokioRuntime,
+        tests::Mock,
+        usc::{bind_udp_socket, UscRegistry},
+    };
+
+    fn pathway(remote: &str) -> Pathway {
+        Pathway::Direct {
+            local: "127.0.0.1:4433".parse().unwrap(),
+            remote: remote.parse().unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn challenge_on_secondary_path() {
+        let reliable_frames = ArcReliableFrameDeque::with_capacity(8);
+        let remote_cids =
+            ArcRemoteCids::new(ConnectionId::random_gen(8), 8, reliable_frames.clone());
+        let creator = Box::new(move |_pathway, usc| {
+            let cc = ArcCC::new(
+                CongestionAlgorithm::Bbr,
+                INITIAL_RTT,
+                Duration::from_millis(100),
+                [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+                Handshake::new(Role::Server, reliable_frames.clone()),
+            );
+            let path = Path::new(
+                usc,
+                ConnectionId::random_gen(8),
+                remote_cids.apply_dcid(),
+                cc,
+                ArcSpin::new(Role::Server, SpinObservers::default()),
+                DEFAULT_ANTI_FACTOR,
+                Spawner::default(),
+            );
+            Arc::new(path)
+        });
+        let paths = Paths::new(creator, Arc::new(|| {}));
+
+        let unspecified: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let recv_task = |usc: ArcUsc| async move {
+            let _usc = usc;
+            core::future::pending::<()>().await;
+        };
+        let usc =
+            UscRegistry::create_new_usc(unspecified, bind_udp_socket, &TokioRuntime, recv_task)
+                .unwrap();
+
+        let primary = paths.get_or_create(pathway("10.0.0.2:5000"), usc.clone());
+        primary.on_rcvd(1200);
+        // 对端迁移到了新地址，在尚未验证的次级路径上发来了PathChallenge
+        let secondary = paths.get_or_create(pathway("10.0.0.3:6000"), usc);
+        secondary.on_rcvd(50);
+        let challenge = PathChallengeFrame::random();
+        secondary.recv_challenge(challenge);
+
+        let mut buf = [0u8; 64];
+        // 主路径不会发送该响应
+        assert_eq!(primary.response_sndbuf().try_read(&mut buf), 0);
+        let n = secondary.response_sndbuf().try_read(&mut buf);
+        assert!(n > 0);
+        let response = PathResponseFrame::from(challenge);
+        let mut expected = vec![];
+        expected.put_frame(&response);
+        assert_eq!(n, response.encoding_size());
+        assert_eq!(&buf[..n], &expected[..]);
+
+        // 次级路径的响应受其自身的抗放大额度限制，与主路径收到的数据无关
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(
+            secondary.anti_amplifier.poll_balance(&mut cx),
+            Poll::Ready(Some(150))
+        );
+        assert_eq!(
+            primary.anti_amplifier.poll_balance(&mut cx),
+            Poll::Ready(Some(3600))
+        );
+    }
+
+    #[tokio::test]
+    async fn validated_by_path_response() {
+        let reliable_frames = ArcReliableFrameDeque::with_capacity(8);
+        let remote_cids =
+            ArcRemoteCids::new(ConnectionId::random_gen(8), 8, reliable_frames.clone());
+        let unspecified: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let recv_task = |usc: ArcUsc| async move {
+            let _usc = usc;
+            core::future::pending::<()>().await;
+        };
+        let usc =
+            UscRegistry::create_new_usc(unspecified, bind_udp_socket, &TokioRuntime, recv_task)
+                .unwrap();
+        let new_path = || {
+            let cc = ArcCC::new(
+                CongestionAlgorithm::Bbr,
+                INITIAL_RTT,
+                Duration::from_millis(100),
+                [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+                Handshake::new(Role::Server, reliable_frames.clone()),
+            );
+            Path::new(
+                usc.clone(),
+                ConnectionId::random_gen(8),
+                remote_cids.apply_dcid(),
+                cc,
+                ArcSpin::new(Role::Server, SpinObservers::default()),
+                DEFAULT_ANTI_FACTOR,
+                Spawner::default(),
+            )
+        };
+
+        // 握手完成时解除限制
+        let path = new_path();
+        assert!(!path.is_validated());
+        path.grant_anti_amplifier();
+        assert!(path.is_validated());
+
+        // 收到匹配的PathResponse时解除限制
+        let path = new_path();
+        path.on_rcvd(1200);
+        path.begin_validation();
+        let mut buf = [0u8; 64];
+        let challenge = loop {
+            let n = path.challenge_sndbuf().try_read(&mut buf);
+            if n > 0 {
+                break PathChallengeFrame::from_slice(&buf[1..n]);
+            }
+            tokio::task::yield_now().await;
+        };
+        assert!(!path.is_validated());
+
+        path.recv_response(PathResponseFrame::from(PathChallengeFrame::random()));
+        tokio::task::yield_now().await;
+        assert!(!path.is_validated());
+
+        path.recv_response(PathResponseFrame::from(challenge));
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !path.is_validated() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the path should be validated by the matching response");
+    }
+
+    #[tokio::test]
+    async fn stats_of_validated_paths() {
+        let reliable_frames = ArcReliableFrameDeque::with_capacity(8);
+        let remote_cids =
+            ArcRemoteCids::new(ConnectionId::random_gen(8), 8, reliable_frames.clone());
+        let creator = Box::new(move |_pathway, usc| {
+            let cc = ArcCC::new(
+                CongestionAlgorithm::NewReno,
+                INITIAL_RTT,
+                Duration::from_millis(100),
+                [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+                Handshake::new(Role::Server, reliable_frames.clone()),
+            );
+            let path = Path::new(
+                usc,
+                ConnectionId::random_gen(8),
+                remote_cids.apply_dcid(),
+                cc,
+                ArcSpin::new(Role::Server, SpinObservers::default()),
+                DEFAULT_ANTI_FACTOR,
+                Spawner::default(),
+            );
+            Arc::new(path)
+        });
+        let paths = Paths::new(creator, Arc::new(|| {}));
+
+        let unspecified: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let recv_task = |usc: ArcUsc| async move {
+            let _usc = usc;
+            core::future::pending::<()>().await;
+        };
+        let usc =
+            UscRegistry::create_new_usc(unspecified, bind_udp_socket, &TokioRuntime, recv_task)
+                .unwrap();
+
+        let primary = paths.get_or_create(pathway("10.0.0.2:5000"), usc.clone());
+        primary.grant_anti_amplifier();
+        let secondary = paths.get_or_create(pathway("10.0.0.3:6000"), usc);
+        secondary.on_rcvd(1200);
+        secondary.begin_validation();
+        let mut buf = [0u8; 64];
+        let challenge = loop {
+            let n = secondary.challenge_sndbuf().try_read(&mut buf);
+            if n > 0 {
+                break PathChallengeFrame::from_slice(&buf[1..n]);
+            }
+            tokio::task::yield_now().await;
+        };
+        secondary.recv_response(PathResponseFrame::from(challenge));
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !secondary.is_validated() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the secondary path should be validated");
+
+        let mut stats = paths.stats();
+        stats.sort_by_key(|stats| stats.pathway.remote_addr());
+        assert_eq!(stats.len(), 2);
+        for (stats, remote) in stats.iter().zip(["10.0.0.2:5000", "10.0.0.3:6000"]) {
+            assert_eq!(stats.pathway, pathway(remote));
+            assert!(stats.validated);
+            // 尚无RTT样本，为初始RTT；尚未发送任何数据，拥塞窗口为NewReno的初始窗口
+            assert_eq!(stats.rtt, INITIAL_RTT);
+            assert_eq!(stats.cwnd, 10 * MSS as u64);
+        }
+    }
+
+    #[tokio::test]
+    async fn response_sent_urgently() {
+        let reliable_frames = ArcReliableFrameDeque::with_capacity(8);
+        let remote_cids =
+            ArcRemoteCids::new(ConnectionId::random_gen(8), 8, reliable_frames.clone());
+        let unspecified: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let recv_task = |usc: ArcUsc| async move {
+            let _usc = usc;
+            core::future::pending::<()>().await;
+        };
+        let usc =
+            UscRegistry::create_new_usc(unspecified, bind_udp_socket, &TokioRuntime, recv_task)
+                .unwrap();
+        // 时钟不走，pacing不再发放新的令牌
+        let cc = ArcCC::with_clock(
+            CongestionAlgorithm::NewReno,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(Role::Server, reliable_frames.clone()),
+            Arc::new(ManualClock::new(Instant::now())),
+        );
+        let path = Path::new(
+            usc,
+            ConnectionId::random_gen(8),
+            remote_cids.apply_dcid(),
+            cc,
+            ArcSpin::new(Role::Server, SpinObservers::default()),
+            DEFAULT_ANTI_FACTOR,
+            Spawner::default(),
+        );
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut pn = 0;
+        while path.cc().poll_send(&mut cx).is_ready() {
+            path.cc()
+                .on_pkt_sent(Epoch::Data, pn, true, MSS, true, None);
+            pn += 1;
+        }
+        // 发送任务正等待pacing
+        let count = Arc::new(CountWaker::default());
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(path.cc().poll_send(&mut cx).is_pending());
+
+        let challenge = PathChallengeFrame::random();
+        path.recv_challenge(challenge);
+        // 发送任务被立即唤醒，且不必等待pacing，即可把PathResponse发出去
+        assert_eq!(count.count(), 1);
+        assert_eq!(path.cc().poll_send(&mut cx), Poll::Ready(MSS));
+        let mut buf = [0u8; 64];
+        let n = path.response_sndbuf().try_read(&mut buf);
+        assert_eq!(n, PathResponseFrame::from(challenge).encoding_size());
+    }
 }
@@ -11,12 +11,15 @@ use std::{
 use deref_derive::Deref;
 use futures::task::AtomicWaker;
 
+/// The default anti-amplification factor, three times as [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html#section-8-2) required.
 pub const DEFAULT_ANTI_FACTOR: usize = 3;
 /// Therefore, after receiving packets from an address that is not yet validated,
 /// an endpoint MUST limit the amount of data it sends to the unvalidated address
-/// to N(three) times the amount of data received from that address.
-#[derive(Debug, Default)]
-pub struct AntiAmplifier<const N: usize> {
+/// to N(three by default) times the amount of data received from that address.
+#[derive(Debug)]
+pub struct AntiAmplifier {
+    // 放大系数，默认为DEFAULT_ANTI_FACTOR
+    factor: usize,
     // Each time data is received, credit is increased;
     // each time data is sent, credit is consumed.
     credit: AtomicUsize,
@@ -26,17 +29,34 @@ pub struct AntiAmplifier<const N: usize> {
     state: AtomicU8,
 }
 
-impl<const N: usize> AntiAmplifier<N> {
+impl Default for AntiAmplifier {
+    fn default() -> Self {
+        Self::new(DEFAULT_ANTI_FACTOR)
+    }
+}
+
+impl AntiAmplifier {
     const NORMAL: u8 = 0;
     const GRANTED: u8 = 1;
     const ABORTED: u8 = 2;
 
-    /// Store N * amount of credit
+    /// Create an anti-amplifier which allows sending `factor` times the amount of data received.
+    pub fn new(factor: usize) -> Self {
+        Self {
+            factor,
+            credit: AtomicUsize::new(0),
+            waker: AtomicWaker::new(),
+            state: AtomicU8::new(Self::NORMAL),
+        }
+    }
+
+    /// Store factor * amount of credit
     pub fn on_rcvd(&self, amount: usize) {
         if self.state.load(Ordering::Acquire) != Self::NORMAL {
             return;
         }
-        self.credit.fetch_add(amount * N, Ordering::AcqRel);
+        self.credit
+            .fetch_add(amount * self.factor, Ordering::AcqRel);
         self.waker.wake();
     }
 
@@ -93,6 +113,12 @@ impl<const N: usize> AntiAmplifier<N> {
         }
     }
 
+    /// Return whether the limit has been lifted by [`AntiAmplifier::grant`], that is, the address of
+    /// the peer is validated.
+    pub fn is_granted(&self) -> bool {
+        self.state.load(Ordering::Acquire) == Self::GRANTED
+    }
+
     pub fn abort(&self) {
         if self
             .state
@@ -111,15 +137,20 @@ impl<const N: usize> AntiAmplifier<N> {
 
 /// A sendable and receivable shared controller for anti-N-times amplification attack
 #[derive(Debug, Default, Clone, Deref)]
-pub struct ArcAntiAmplifier<const N: usize>(Arc<AntiAmplifier<N>>);
+pub struct ArcAntiAmplifier(Arc<AntiAmplifier>);
+
+impl ArcAntiAmplifier {
+    /// Create an anti-amplifier with the given `factor`, see [`AntiAmplifier::new`].
+    pub fn new(factor: usize) -> Self {
+        Self(Arc::new(AntiAmplifier::new(factor)))
+    }
 
-impl<const N: usize> ArcAntiAmplifier<N> {
     pub fn balance(&self) -> Self {
         Self(self.0.clone())
     }
 }
 
-impl<const N: usize> Future for ArcAntiAmplifier<N> {
+impl Future for ArcAntiAmplifier {
     type Output = Option<usize>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<usize>> {
@@ -137,7 +168,7 @@ mod tests {
 
     #[test]
     fn test_deposit_and_poll_apply() {
-        let anti_amplifier = ArcAntiAmplifier::<3>::default();
+        let anti_amplifier = ArcAntiAmplifier::default();
         let mut cx = Context::from_waker(noop_waker_ref());
 
         // Initially, no credit
@@ -159,7 +190,7 @@ mod tests {
 
     #[test]
     fn test_multiple_deposits() {
-        let anti_amplifier = ArcAntiAmplifier::<3>::default();
+        let anti_amplifier = ArcAntiAmplifier::default();
         let mut cx = Context::from_waker(noop_waker_ref());
 
         // Deposit 1 unit of data, should add 3 units of credit
@@ -178,4 +209,34 @@ mod tests {
         anti_amplifier.on_sent(5);
         assert_eq!(anti_amplifier.credit.load(Ordering::Acquire), 1);
     }
+
+    #[test]
+    fn test_custom_factor() {
+        let anti_amplifier = ArcAntiAmplifier::new(2);
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        // 放大系数为2，收到多少数据，最多只能发送其2倍
+        anti_amplifier.on_rcvd(600);
+        assert_eq!(
+            anti_amplifier.poll_balance(&mut cx),
+            Poll::Ready(Some(1200))
+        );
+        anti_amplifier.on_sent(1200);
+        assert_eq!(anti_amplifier.poll_balance(&mut cx), Poll::Pending);
+    }
+
+    #[test]
+    fn test_is_granted() {
+        let anti_amplifier = ArcAntiAmplifier::default();
+        assert!(!anti_amplifier.is_granted());
+        anti_amplifier.grant();
+        assert!(anti_amplifier.is_granted());
+        // 已经解除限制后，不会再被中止
+        anti_amplifier.abort();
+        assert!(anti_amplifier.is_granted());
+
+        let aborted = ArcAntiAmplifier::default();
+        aborted.abort();
+        assert!(!aborted.is_granted());
+    }
 }
@@ -16,7 +16,6 @@ use qcongestion::{ArcCC, CongestionControl, MSS};
 use qrecovery::reliable::ArcReliableFrameDeque;
 
 use super::{
-    anti_amplifier::DEFAULT_ANTI_FACTOR,
     util::{ApplyConstraints, Constraints},
     ArcAntiAmplifier,
 };
@@ -28,14 +27,21 @@ pub struct ReadIntoDatagrams {
     pub(super) dcid: ArcCidCell<ArcReliableFrameDeque>,
     pub(super) spin: Arc<AtomicBool>,
     pub(super) cc: ArcCC,
-    pub(super) anti_amplifier: ArcAntiAmplifier<DEFAULT_ANTI_FACTOR>,
+    pub(super) anti_amplifier: ArcAntiAmplifier,
     pub(super) flow_ctrl: FlowController,
     pub(super) initial_space_reader: InitialSpaceReader,
     pub(super) handshake_space_reader: HandshakeSpaceReader,
     pub(super) data_space_reader: DataSpaceReader,
+    // 发送数据报的大小上限，即使路径探测到了更大的MTU，也不超过它
+    pub(super) max_send_udp_payload: usize,
 }
 
 impl ReadIntoDatagrams {
+    // 本次组装的数据报大小，取路径当前MTU与配置上限的较小者
+    fn datagram_size(&self) -> usize {
+        self.cc.mtu().min(self.max_send_udp_payload)
+    }
+
     fn read_into_datagram(
         &self,
         constraints: &mut Constraints,
@@ -60,9 +66,16 @@ impl ReadIntoDatagrams {
                 self.read_other_space(constraints, flow_limit, remain, dcid)
             };
 
-            let padding_len = if wrote == 0 { MSS.min(send_quota) } else { 0 };
+            // 包含Initial数据包的数据报，须填充至MSS大小。Initial数据包之后可能合并了其他空间的数据包，
+            // 它们已经加密完成，与位置无关，将其整体后移，把padding填充在Initial数据包内，而非数据报末尾。
+            // 路径的MTU大于MSS时，合并的数据包可能已使数据报超过MSS，此时无需再填充
+            let datagram_len = MSS.max(len + wrote).min(send_quota);
+            let initial_len = datagram_len.saturating_sub(wrote).max(len);
+            if initial_len > len && wrote > 0 {
+                buffer.copy_within(len..len + wrote, initial_len);
+            }
             let (pn, is_ack_eliciting, sent_bytes, in_flight, sent_ack) =
-                padding(buffer, padding_len);
+                padding(buffer, initial_len);
             self.cc.on_pkt_sent(
                 Epoch::Initial,
                 pn,
@@ -72,7 +85,7 @@ impl ReadIntoDatagrams {
                 sent_ack,
             );
             // 减除initial数据包已经commit的
-            constraints.commit(sent_bytes - len, in_flight);
+            constraints.commit(sent_bytes.saturating_sub(len), in_flight);
             (wrote + sent_bytes, fresh_bytes)
         } else {
             self.read_other_space(constraints, flow_limit, buffer, dcid)
@@ -105,8 +118,8 @@ impl ReadIntoDatagrams {
                     None,
                 );
                 buffer = &mut buffer[sent_bytes..];
-                // 0Rtt数据包不会发送Ack
-                constraints.commit(sent_bytes, false);
+                // 0Rtt数据包不会发送Ack，但其中的数据帧仍需消耗发送配额
+                constraints.commit(sent_bytes, in_flight);
                 fresh_bytes += fresh_len;
                 written += sent_bytes;
             }
@@ -178,11 +191,35 @@ impl ReadIntoDatagrams {
         0
     }
 
+    // 探测包大于路径当前的MTU，而拥塞控制给出的额度至多一个MTU，因此只要额度允许发送满MTU的数据报，
+    // 就可以发送探测包，探测包仍计入拥塞窗口。抗放大攻击的额度或配置的上限不足时，本次不探测
+    fn read_mtu_probe(
+        &self,
+        send_quota: usize,
+        credit_limit: usize,
+        datagram: &mut [u8],
+        dcid: ConnectionId,
+    ) -> Option<usize> {
+        let size = datagram.len();
+        if size > self.max_send_udp_payload || size > credit_limit || send_quota < self.cc.mtu() {
+            return None;
+        }
+        let keys = self.data_space_reader.one_rtt_keys()?;
+        let spin = SpinBit::from(self.spin.load(Ordering::Relaxed));
+        let (pn, sent_bytes) = self
+            .data_space_reader
+            .try_read_mtu_probe(datagram, dcid, spin, keys)?;
+        self.cc
+            .on_pkt_sent(Epoch::Data, pn, true, sent_bytes, true, None);
+        self.cc.on_mtu_probe_sent(pn, sent_bytes);
+        Some(sent_bytes)
+    }
+
     fn poll_read_inner(
         &self,
         cx: &mut Context<'_>,
-        buffers: &mut Vec<[u8; MSS]>,
-    ) -> Poll<Option<(usize, usize)>> {
+        buffers: &mut Vec<Vec<u8>>,
+    ) -> Poll<Option<(usize, usize, Option<u8>)>> {
         let send_quota = core::task::ready!(self.cc.poll_send(cx));
         let Some(dcid) = core::task::ready!(self.dcid.poll_borrow_cid(cx)) else {
             return Poll::Ready(None);
@@ -192,7 +229,8 @@ impl ReadIntoDatagrams {
         };
         // 流量控制，受控于对方允许的最大数据，不得超过
         // 作用于新数据，Stream帧中的新数据
-        // 当流量限制为0的时候，仍然可以发送Stream中的旧数据，以及其他帧
+        // 当流量限制为0的时候，仍然可以发送Stream中的旧数据，以及Ack等其他不受流控的帧，不能就此结束
+        // 只有连接出错时，流量控制才不可用
         // WARN: 流量控制提供指引到最终反馈时，不可解锁，否则其他发送任务会共享流量限制，导致流量限制失效
         let Ok(mut send_flow_credit) = self.flow_ctrl.send_limit() else {
             // 返回None，表示结束
@@ -200,6 +238,25 @@ impl ReadIntoDatagrams {
         };
         let flow_limit = send_flow_credit.available();
         let mut constraints = Constraints::new(credit_limit, send_quota);
+        // 在组装本组数据报之前确定ECN标记，期间记录的数据包都按此标记发送
+        let ecn = self.cc.begin_datagrams();
+
+        // PMTU探测包单独成一个数据报，不与其他数据报一起GSO发送
+        if let Some(size) = self.cc.mtu_probe() {
+            if buffers.is_empty() {
+                buffers.push(Vec::new());
+            }
+            let datagram = &mut buffers[0];
+            datagram.resize(size, 0);
+            if let Some(sent_bytes) = self.read_mtu_probe(send_quota, credit_limit, datagram, *dcid)
+            {
+                self.anti_amplifier.on_sent(sent_bytes);
+                return Poll::Ready(Some((1, sent_bytes, ecn)));
+            }
+        }
+
+        // 为了使用GSO，除最后一个外，各数据报大小须一致
+        let max_datagram_size = self.datagram_size();
 
         // 遍历，填充每一个包
 
@@ -213,10 +270,13 @@ impl ReadIntoDatagrams {
             let datagram = match buffers.get_mut(buffers_used) {
                 Some(buffer) => buffer,
                 None => {
-                    buffers.push([0; MSS]);
+                    buffers.push(Vec::new());
                     &mut buffers[buffers_used]
                 }
             };
+            datagram.resize(max_datagram_size, 0);
+            // 合并在本数据报中的各个数据包，共享本数据报的空间
+            constraints.next_datagram(max_datagram_size);
 
             let (datagram_size, fresh_bytes) =
                 self.read_into_datagram(&mut constraints, flow_limit, datagram, *dcid);
@@ -233,29 +293,36 @@ impl ReadIntoDatagrams {
 
             // 本数据报尚未被填满，如果本数据报包含一个1rtt数据包，在“后面填充padding”是不行的，因为那些padding会被认为是1rtt的一部分
             // 就会导致发送出的数据包无法被对端解析，所以这里直接break掉
-            if datagram_size < MSS {
+            if datagram_size < max_datagram_size {
                 break;
             }
         }
 
         if buffers_used == 0 {
             // 就算Constraints允许发送，但也不一定真的有数据供发送
+            // 若连接级流量控制额度耗尽，新的流数据只能等对端的MaxData帧扩大额度后才能发送
+            if flow_limit == 0 {
+                send_flow_credit.register_waker(cx.waker().clone());
+            }
             return Poll::Pending;
         }
 
+        // 每个空间的数据包都写在受Constraints约束的缓冲区内，本次组装的数据总量不会超过抗放大攻击的信用额度
+        debug_assert!(total_bytes <= credit_limit);
         // dcid被Drop时自动将dcid的借用归还，可能会触发淘汰dcid
         // self.dcid.return_back();
         // 最终将要发送前，反馈给各个限制条件。除了拥塞控制的，在每个Epoch发包后，都已直接反馈给cc过了
         self.anti_amplifier.on_sent(total_bytes);
         send_flow_credit.post_sent(total_fresh_bytes);
         // 返回这个后，datagrams肯定等着被发送了
-        Poll::Ready(Some((buffers_used, last_buffer_written)))
+        Poll::Ready(Some((buffers_used, last_buffer_written, ecn)))
     }
 
     /// Read data into the given buffers, and return the UDP datagrams that will be sent.
     ///
-    /// `buffers` is a vector of buffers, each buffer is a fixed-size([`MSS`]) array of bytes. Each time this function
-    /// is called, it will try to read data into these `buffers`, the old data in the buffers will be overwritten.
+    /// `buffers` is a vector of buffers, each buffer is resized to the datagram size, which is the current MTU of the path
+    /// but no larger than the configured [`max_send_udp_payload`]. Each time this function is called, it will try to read
+    /// data into these `buffers`, the old data in the buffers will be overwritten.
     ///
     /// This method will read the frame to be sent and assemble the data packet in the order of initial, 0-rtt, handshake,
     /// 1-rtt. It will try to read as much data as possible in one call. If the `buffers` are not enough to hold all the
@@ -266,20 +333,27 @@ impl ReadIntoDatagrams {
     /// of GSO features. Each [`IoSlice`] in it is a complete datagram contains quic packet(s).
     ///
     /// Returned [`IoSlice`]s can be sent separately, but it is best to use the method provided by [`qudp`] to send in
-    /// batches using GSO(if supported).[`ArcUsc`] is a wrapper around the [`qudp::UdpSocketController`]. It also provides
+    /// batches using GSO(if supported).[`ArcUsc`] is a wrapper around the [`qudp::AsyncUdpSocket`]. It also provides
     /// a [`method`] to send all the datagrams.
     ///
     /// In order to take advantage of GSO, the return value is a vector of [`IoSlice`], except for the last [`IoSlice`],
-    /// the length of other [`IoSlice`]s must be [`MSS`].
+    /// the length of other [`IoSlice`]s must be the datagram size.
     ///
     /// This is a async method, if there are no data to be sent, the call will be blocked.
     ///
+    /// The returned datagrams must be sent with the returned ECN codepoint, it's the marking the packets in them are
+    /// recorded with by the congestion controller.
+    ///
     /// Once the path become inactive, [`None`] will be returned, this means the path will not be used to send data anymore.
     ///
     /// [`ArcUsc`]: crate::usc::ArcUsc
     /// [`method`]: crate::usc::ArcUsc::send_all_via_pathway
-    pub async fn read<'ds>(&self, buffers: &'ds mut Vec<[u8; MSS]>) -> Option<Vec<IoSlice<'ds>>> {
-        let (buffers_used, last_buffer_written) =
+    /// [`max_send_udp_payload`]: crate::path::Path::set_max_send_udp_payload
+    pub async fn read<'ds>(
+        &self,
+        buffers: &'ds mut Vec<Vec<u8>>,
+    ) -> Option<(Vec<IoSlice<'ds>>, Option<u8>)> {
+        let (buffers_used, last_buffer_written, ecn) =
             core::future::poll_fn(|cx| self.poll_read_inner(cx, buffers)).await?;
 
         debug_assert!(buffers_used > 0);
@@ -289,6 +363,720 @@ impl ReadIntoDatagrams {
                 &buffers[buffers_used - 1][..last_buffer_written],
             )))
             .collect::<Vec<_>>();
-        Some(datagrams)
+        Some((datagrams, ecn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        task::Waker,
+        time::Duration,
+    };
+
+    use bytes::BytesMut;
+    use futures::FutureExt;
+    use qbase::{
+        cid::ArcRemoteCids,
+        frame::{MaxDataFrame, MaxStreamsFrame, ReceiveFrame, StreamCtlFrame},
+        handshake::Handshake,
+        packet::{
+            keys::{ArcKeys, ArcOneRttKeys},
+            long, DataHeader, Packet, PacketReader,
+        },
+        param::{ArcParameters, ClientParameters, CommonParameters},
+        sid::{handy::ConsistentConcurrency, Role},
+        util::CountWaker,
+        varint::VarInt,
+    };
+    use qcongestion::{CongestionAlgorithm, TrackPackets, INITIAL_RTT};
+    use qrecovery::crypto::CryptoStream;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::{
+        conn::space::{data::DataTracker, DataSpace, HandshakeSpace, InitialSpace},
+        error::ConnError,
+        spawn::Spawner,
+        tests::{keys, Mock},
+        tls::ArcTlsSession,
+    };
+
+    fn server_reader() -> (ReadIntoDatagrams, InitialSpace, HandshakeSpace) {
+        let reliable_frames = ArcReliableFrameDeque::with_capacity(8);
+        let scid = ConnectionId::random_gen(8);
+        let dcid = ConnectionId::random_gen(8);
+        let remote_cids = ArcRemoteCids::new(dcid, 8, reliable_frames.clone());
+
+        let initial = InitialSpace::new(ArcKeys::with_keys(keys(dcid)));
+        let hs = HandshakeSpace::default();
+        hs.keys.set_keys(keys(scid));
+        let data = DataSpace::new(
+            Role::Server,
+            &CommonParameters::default(),
+            Box::new(ConsistentConcurrency::new(0, 0)),
+        );
+
+        let cc = ArcCC::new(
+            CongestionAlgorithm::Bbr,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(Role::Server, reliable_frames.clone()),
+        );
+        let reader = ReadIntoDatagrams {
+            scid,
+            dcid: remote_cids.apply_dcid(),
+            spin: Arc::new(AtomicBool::new(false)),
+            cc,
+            anti_amplifier: ArcAntiAmplifier::default(),
+            flow_ctrl: FlowController::new(65535, 65535, reliable_frames.clone()),
+            initial_space_reader: initial.reader(Arc::new(Mutex::new(vec![]))),
+            handshake_space_reader: hs.reader(),
+            data_space_reader: data.reader(
+                Default::default(),
+                Default::default(),
+                reliable_frames,
+                data.streams.clone(),
+                data.datagrams.clone(),
+            ),
+            max_send_udp_payload: usize::MAX,
+        };
+        (reader, initial, hs)
+    }
+
+    /// 持有0-RTT密钥、尚未获得1-RTT密钥的客户端，连接级流量控制额度为记忆的initial_max_data
+    fn zero_rtt_reader(remembered_max_data: u64) -> (ReadIntoDatagrams, InitialSpace, DataSpace) {
+        let reliable_frames = ArcReliableFrameDeque::with_capacity(8);
+        let scid = ConnectionId::random_gen(8);
+        let dcid = ConnectionId::random_gen(8);
+        let remote_cids = ArcRemoteCids::new(dcid, 8, reliable_frames.clone());
+
+        let initial = InitialSpace::new(ArcKeys::with_keys(keys(dcid)));
+        let hs = HandshakeSpace::default();
+        let data = DataSpace::new(
+            Role::Client,
+            &CommonParameters::default(),
+            Box::new(ConsistentConcurrency::new(0, 0)),
+        );
+        data.zero_rtt_keys.set_keys(keys(dcid));
+
+        let cc = ArcCC::new(
+            CongestionAlgorithm::Bbr,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            Handshake::new(Role::Client, reliable_frames.clone()),
+        );
+        let anti_amplifier = ArcAntiAmplifier::default();
+        anti_amplifier.grant();
+        let reader = ReadIntoDatagrams {
+            scid,
+            dcid: remote_cids.apply_dcid(),
+            spin: Arc::new(AtomicBool::new(false)),
+            cc,
+            anti_amplifier,
+            flow_ctrl: FlowController::new(
+                remembered_max_data,
+                65535,
+                ArcReliableFrameDeque::with_capacity(8),
+            ),
+            initial_space_reader: initial.reader(Arc::new(Mutex::new(vec![]))),
+            handshake_space_reader: hs.reader(),
+            data_space_reader: data.reader(
+                Default::default(),
+                Default::default(),
+                reliable_frames,
+                data.streams.clone(),
+                data.datagrams.clone(),
+            ),
+            max_send_udp_payload: usize::MAX,
+        };
+        (reader, initial, data)
+    }
+
+    #[tokio::test]
+    async fn coalesce_initial_and_handshake() {
+        let (reader, initial, hs) = server_reader();
+        // 服务端收到了客户端填充至MSS的Initial数据报
+        reader.anti_amplifier.on_rcvd(MSS);
+
+        initial
+            .crypto_stream
+            .writer()
+            .write_all(&[0; 90])
+            .await
+            .unwrap();
+        hs.crypto_stream
+            .writer()
+            .write_all(&[0; 600])
+            .await
+            .unwrap();
+
+        let mut buffers = vec![];
+        let (datagrams, ecn) = reader.read(&mut buffers).await.unwrap();
+        // 服务端的首个响应，Initial和Handshake数据包合并在同一个数据报中
+        assert_eq!(datagrams.len(), 1);
+        // ECN验证的测试阶段，数据报标记ECT(0)
+        assert_eq!(ecn, Some(0b10));
+        assert_eq!(datagrams[0].len(), MSS);
+
+        let datagram = BytesMut::from(&datagrams[0][..]);
+        let packets = PacketReader::new(datagram, 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(packets.len(), 2);
+        assert!(matches!(
+            &packets[0],
+            Packet::Data(packet) if matches!(packet.header, DataHeader::Long(long::DataHeader::Initial(_)))
+        ));
+        assert!(matches!(
+            &packets[1],
+            Packet::Data(packet) if matches!(packet.header, DataHeader::Long(long::DataHeader::Handshake(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn coalesce_within_max_send_udp_payload() {
+        let (mut reader, initial, hs) = server_reader();
+        reader.anti_amplifier.grant();
+        reader.max_send_udp_payload = 1000;
+
+        initial
+            .crypto_stream
+            .writer()
+            .write_all(&[0; 90])
+            .await
+            .unwrap();
+        hs.crypto_stream
+            .writer()
+            .write_all(&[0; 4000])
+            .await
+            .unwrap();
+
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        assert!(datagrams.len() > 1);
+        // 合并在同一数据报中的数据包，总和不超过配置的最大UDP载荷
+        assert!(datagrams.iter().all(|datagram| datagram.len() <= 1000));
+        assert_eq!(datagrams[0].len(), 1000);
+
+        let packets = PacketReader::new(BytesMut::from(&datagrams[0][..]), 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(matches!(
+            &packets[..],
+            [Packet::Data(initial), Packet::Data(hs)]
+                if matches!(initial.header, DataHeader::Long(long::DataHeader::Initial(_)))
+                    && matches!(hs.header, DataHeader::Long(long::DataHeader::Handshake(_)))
+        ));
+        for datagram in &datagrams[1..] {
+            let packets = PacketReader::new(BytesMut::from(&datagram[..]), 8)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert!(!packets.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn pad_initial_only_datagram() {
+        let (reader, initial, _hs) = server_reader();
+        reader.anti_amplifier.on_rcvd(MSS);
+
+        initial
+            .crypto_stream
+            .writer()
+            .write_all(&[0; 90])
+            .await
+            .unwrap();
+
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(datagrams[0].len(), MSS);
+
+        let datagram = BytesMut::from(&datagrams[0][..]);
+        let packets = PacketReader::new(datagram, 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        // padding在Initial数据包内部，而不是追加到数据报末尾成为无法解析的数据
+        assert_eq!(packets.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn limited_by_anti_amplification() {
+        let (reader, initial, hs) = server_reader();
+        // 地址尚未验证，服务端仅收到了100字节
+        reader.anti_amplifier.on_rcvd(100);
+
+        initial
+            .crypto_stream
+            .writer()
+            .write_all(&[0; 900])
+            .await
+            .unwrap();
+        hs.crypto_stream
+            .writer()
+            .write_all(&[0; 900])
+            .await
+            .unwrap();
+
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        let sent = datagrams
+            .iter()
+            .map(|datagram| datagram.len())
+            .sum::<usize>();
+        assert!(sent > 0 && sent <= 300);
+        drop(datagrams);
+
+        // 额度已用完，即使还有数据待发送，也要等待收到新的数据
+        assert!(reader.read(&mut buffers).now_or_never().is_none());
+
+        reader.anti_amplifier.on_rcvd(100);
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        let sent = sent
+            + datagrams
+                .iter()
+                .map(|datagram| datagram.len())
+                .sum::<usize>();
+        assert!(sent <= 600);
+    }
+
+    #[tokio::test]
+    async fn ack_without_flow_credit() {
+        let (mut reader, initial, _hs) = server_reader();
+        let reliable_frames = ArcReliableFrameDeque::with_capacity(8);
+        reader.flow_ctrl = FlowController::new(0, 65535, reliable_frames.clone());
+        reader.anti_amplifier.on_rcvd(MSS);
+        // 收到了对端的Initial数据包，需要回复Ack
+        initial.journal.of_rcvd_packets().register_pn(0);
+        reader.cc.on_pkt_rcvd(Epoch::Initial, 0, true);
+
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        assert_eq!(datagrams.len(), 1);
+
+        let datagram = BytesMut::from(&datagrams[0][..]);
+        let packets = PacketReader::new(datagram, 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(packets.len(), 1);
+        assert!(matches!(
+            &packets[0],
+            Packet::Data(packet) if matches!(packet.header, DataHeader::Long(long::DataHeader::Initial(_)))
+        ));
+        // 没有发送新的流数据，不应通知对端DataBlocked
+        assert!(reliable_frames.try_read(&mut [0; 64]).is_none());
+    }
+
+    #[tokio::test]
+    async fn ack_only_packet_accounting() {
+        let (reader, _initial, hs) = server_reader();
+        reader.anti_amplifier.on_rcvd(MSS);
+        // 收到了对端的Handshake数据包，需要回复仅包含Ack的数据包
+        hs.journal.of_rcvd_packets().register_pn(0);
+        reader.cc.on_pkt_rcvd(Epoch::Handshake, 0, true);
+
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        assert_eq!(datagrams.len(), 1);
+        let sent = datagrams[0].len();
+        let datagram = BytesMut::from(&datagrams[0][..]);
+        drop(datagrams);
+        let packets = PacketReader::new(datagram, 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(matches!(
+            &packets[..],
+            [Packet::Data(packet)] if matches!(packet.header, DataHeader::Long(long::DataHeader::Handshake(_)))
+        ));
+
+        // 不消耗流量控制额度，但和其他数据包一样计入抗放大攻击的发送量，见RFC 9000 Section 8.1
+        assert_eq!(reader.flow_ctrl.send_limit().unwrap().available(), 65535);
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert_eq!(
+            reader.anti_amplifier.poll_balance(&mut cx),
+            Poll::Ready(Some(3 * MSS - sent))
+        );
+    }
+
+    #[tokio::test]
+    async fn max_data_wakes_blocked_sending() {
+        let (mut reader, _initial, _hs) = server_reader();
+        reader.flow_ctrl = FlowController::new(0, 65535, ArcReliableFrameDeque::with_capacity(8));
+        reader.anti_amplifier.on_rcvd(MSS);
+
+        let count = Arc::new(CountWaker::default());
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut buffers = vec![];
+        // 连接级流量控制额度为0，没有数据可发
+        assert!(reader.poll_read_inner(&mut cx, &mut buffers).is_pending());
+
+        reader
+            .flow_ctrl
+            .sender
+            .recv_frame(&MaxDataFrame {
+                max_data: VarInt::from_u32(1024),
+            })
+            .unwrap();
+        assert!(count.count() > 0);
+        assert_eq!(reader.flow_ctrl.send_limit().unwrap().available(), 1024);
+    }
+
+    #[tokio::test]
+    async fn wake_sending_without_tick() {
+        let (reader, _initial, hs) = server_reader();
+        reader.anti_amplifier.on_rcvd(MSS);
+
+        let count = Arc::new(CountWaker::default());
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut buffers = vec![];
+        // 尚无数据可发，发送任务挂起
+        assert!(reader.poll_read_inner(&mut cx, &mut buffers).is_pending());
+
+        // 写入数据并不会唤醒发送任务，需等待下一次tick，或主动唤醒
+        hs.crypto_stream
+            .writer()
+            .write_all(&[0; 100])
+            .await
+            .unwrap();
+        assert_eq!(count.count(), 0);
+        reader.cc.wake_sending();
+        assert_eq!(count.count(), 1);
+        // 被唤醒后立即组装出数据包
+        assert!(matches!(
+            reader.poll_read_inner(&mut cx, &mut buffers),
+            Poll::Ready(Some((1, _, _)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn flush_handshake_data_without_tick() {
+        let (reader, initial, hs) = server_reader();
+        reader.anti_amplifier.grant();
+
+        let count = Arc::new(CountWaker::default());
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut buffers = vec![];
+        assert!(reader.poll_read_inner(&mut cx, &mut buffers).is_pending());
+
+        let params = ArcParameters::new_client(ClientParameters::default(), None);
+        params.set_initial_scid(ConnectionId::random_gen(8));
+        let tls_config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
+        let tls_session = ArcTlsSession::new_client(
+            rustls::pki_types::ServerName::try_from("quic.test.net").unwrap(),
+            Arc::new(tls_config),
+            &params,
+        );
+        let data_crypto_stream = CryptoStream::new(1 << 20, 1 << 20);
+        let cc = reader.cc.clone();
+        tls_session.keys_upgrade(
+            [
+                &initial.crypto_stream,
+                &hs.crypto_stream,
+                &data_crypto_stream,
+            ],
+            ArcKeys::new_pending(),
+            ArcOneRttKeys::new_pending(),
+            Handshake::new(Role::Client, ArcReliableFrameDeque::with_capacity(8)),
+            params,
+            ConnError::default(),
+            &Spawner::default(),
+            move || cc.wake_sending(),
+        );
+
+        // TLS产生ClientHello后立即唤醒发送任务，不必等待下一次tick
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while count.count() == 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the sending task should be woken by the handshake data");
+        assert!(matches!(
+            reader.poll_read_inner(&mut cx, &mut buffers),
+            Poll::Ready(Some((1, _, _)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn anti_amplification_factor() {
+        let (mut reader, initial, _hs) = server_reader();
+        reader.anti_amplifier = ArcAntiAmplifier::new(2);
+        reader.anti_amplifier.on_rcvd(400);
+
+        initial
+            .crypto_stream
+            .writer()
+            .write_all(&[0; 2000])
+            .await
+            .unwrap();
+        // 放大系数为2，收到400字节，最多只能发送800字节
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        let sent = datagrams
+            .iter()
+            .map(|datagram| datagram.len())
+            .sum::<usize>();
+        assert_eq!(sent, 800);
+
+        // 信用额度用尽，需等收到更多数据
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert!(reader.anti_amplifier.poll_balance(&mut cx).is_pending());
+    }
+
+    #[tokio::test]
+    async fn clamp_datagram_size() {
+        let (mut reader, _initial, hs) = server_reader();
+        reader.anti_amplifier.grant();
+        // 路径探测到了1472字节的MTU，但配置了更保守的上限
+        reader.cc.raise_mtu(1472);
+        reader.max_send_udp_payload = 1252;
+
+        hs.crypto_stream
+            .writer()
+            .write_all(&[0; 2000])
+            .await
+            .unwrap();
+
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        assert!(datagrams.len() > 1);
+        // 除最后一个外，各数据报都填满至上限，以便GSO
+        let (last, others) = datagrams.split_last().unwrap();
+        assert!(others.iter().all(|datagram| datagram.len() == 1252));
+        assert!(last.len() <= 1252);
+        drop(datagrams);
+
+        // 不设上限时，数据报按路径的MTU组装
+        reader.max_send_udp_payload = usize::MAX;
+        hs.crypto_stream
+            .writer()
+            .write_all(&[0; 2000])
+            .await
+            .unwrap();
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        assert_eq!(datagrams[0].len(), 1472);
+    }
+
+    #[tokio::test]
+    async fn coalesce_one_rtt_beyond_mss() {
+        let (mut reader, initial, data) = zero_rtt_reader(65535);
+        reader.data_space_reader.one_rtt_keys = crate::tls::tests::client_one_rtt_keys().await;
+        reader.cc.raise_mtu(1472);
+
+        initial
+            .crypto_stream
+            .writer()
+            .write_all(&[0; 90])
+            .await
+            .unwrap();
+        data.streams
+            .recv_frame(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Bi(
+                VarInt::from_u32(1),
+            )))
+            .unwrap();
+        let (_sid, (mut stream_reader, mut writer)) =
+            data.streams.open_bi(65535).await.unwrap().unwrap();
+        writer.write_all(&[0; 2000]).await.unwrap();
+
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        // 合并的1-RTT数据包使数据报超过了MSS，Initial数据包无需再填充，也不能被覆盖
+        assert_eq!(datagrams[0].len(), 1472);
+        let packets = PacketReader::new(BytesMut::from(&datagrams[0][..]), 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(matches!(
+            &packets[..],
+            [Packet::Data(initial), Packet::Data(one_rtt)]
+                if matches!(initial.header, DataHeader::Long(long::DataHeader::Initial(_)))
+                    && matches!(one_rtt.header, DataHeader::Short(_))
+        ));
+        drop(datagrams);
+
+        stream_reader.stop(0);
+        writer.cancel(0);
+    }
+
+    #[tokio::test]
+    async fn send_mtu_probe() {
+        let (mut reader, _initial, _data) = zero_rtt_reader(65535);
+        reader.data_space_reader.one_rtt_keys = crate::tls::tests::client_one_rtt_keys().await;
+        // 握手确认之后才探测PMTU
+        let handshake = Handshake::new(Role::Server, ArcReliableFrameDeque::with_capacity(8));
+        handshake.done();
+        reader.cc = ArcCC::new(
+            CongestionAlgorithm::Bbr,
+            INITIAL_RTT,
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            handshake,
+        );
+        reader.cc.set_max_mtu(1452);
+
+        // 没有数据要发，也单独发送一个探测包，大小为MSS与上限的中间值
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(datagrams[0].len(), 1326);
+        let packets = PacketReader::new(BytesMut::from(&datagrams[0][..]), 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(matches!(
+            &packets[..],
+            [Packet::Data(probe)] if matches!(probe.header, DataHeader::Short(_))
+        ));
+        drop(datagrams);
+
+        // 探测包未被确认之前，不再探测，MTU也不变
+        assert_eq!(reader.cc.mtu_probe(), None);
+        assert_eq!(reader.cc.mtu(), MSS);
+    }
+
+    #[tokio::test]
+    async fn zero_rtt_within_remembered_max_data() {
+        let (reader, _initial, data) = zero_rtt_reader(100);
+        // 记忆的传输参数允许打开1条双向流
+        data.streams
+            .recv_frame(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Bi(
+                VarInt::from_u32(1),
+            )))
+            .unwrap();
+        let (_sid, (mut stream_reader, mut writer)) =
+            data.streams.open_bi(1000).await.unwrap().unwrap();
+        writer.write_all(&[0; 300]).await.unwrap();
+
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        let datagram = BytesMut::from(&datagrams[0][..]);
+        drop(datagrams);
+        let packets = PacketReader::new(datagram, 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(matches!(
+            &packets[..],
+            [Packet::Data(packet)] if matches!(packet.header, DataHeader::Long(long::DataHeader::ZeroRtt(_)))
+        ));
+        // 0-RTT数据包中的新数据不超过记忆的initial_max_data，剩下的流数据不能再发
+        assert_eq!(reader.flow_ctrl.send_limit().unwrap().available(), 0);
+        // 这些新数据作为早期数据计数
+        assert_eq!(data.early_data_bytes(), 100);
+        assert!(reader.read(&mut buffers).now_or_never().is_none());
+
+        // 握手完成，0-RTT密钥被丢弃，服务端这次的传输参数到来
+        data.zero_rtt_keys.invalid();
+        reader.flow_ctrl.reset_send_window(1000);
+        assert_eq!(reader.flow_ctrl.send_limit().unwrap().available(), 900);
+        // 超出的流数据并未丢弃，仍在流中等待1-RTT发送
+        let (frame, _n, fresh) = data.streams.try_read_data(&mut [0; 1000], 900).unwrap();
+        assert_eq!(frame.offset(), 100);
+        assert_eq!(fresh, 200);
+
+        stream_reader.stop(0);
+        writer.cancel(0);
+    }
+
+    #[tokio::test]
+    async fn stream_data_recorded_in_flight() {
+        let (reader, _initial, data) = zero_rtt_reader(1000);
+        data.streams
+            .recv_frame(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Bi(
+                VarInt::from_u32(1),
+            )))
+            .unwrap();
+        let (_sid, (mut stream_reader, mut writer)) =
+            data.streams.open_bi(1000).await.unwrap().unwrap();
+        writer.write_all(&[0; 300]).await.unwrap();
+
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        assert_eq!(datagrams.len(), 1);
+        drop(datagrams);
+        // 流数据全部打包发出，未留在流中，新数据计入了连接级流量控制；没有流数据可发时，也不会再发空的数据包
+        assert!(data.streams.try_read_data(&mut [0; 1000], 1000).is_none());
+        assert_eq!(reader.flow_ctrl.send_limit().unwrap().available(), 700);
+        assert!(reader.read(&mut buffers).now_or_never().is_none());
+
+        // 记录在发包记录中的STREAM帧，在包被判定丢失时重新交给流发送
+        let tracker = DataTracker::new(
+            data.journal.clone(),
+            data.reliable_frames.clone(),
+            data.streams.clone(),
+            data.crypto_stream.outgoing(),
+            data.ping_sndbuf.clone(),
+            ConnError::default(),
+        );
+        assert_eq!(tracker.may_loss(0), 1);
+        let (frame, _n, fresh) = data.streams.try_read_data(&mut [0; 1000], 1000).unwrap();
+        assert_eq!(frame.offset(), 0);
+        assert_eq!(frame.len(), 300);
+        assert_eq!(fresh, 0);
+
+        stream_reader.stop(0);
+        writer.cancel(0);
+    }
+
+    #[tokio::test]
+    async fn coalesce_ack_with_stream_data() {
+        let (mut reader, _initial, data) = zero_rtt_reader(65535);
+        reader.data_space_reader.one_rtt_keys = crate::tls::tests::client_one_rtt_keys().await;
+        // 收到了对端的1-RTT数据包，其中有空档，需要立即回复Ack
+        for pn in [0, 2] {
+            data.journal.of_rcvd_packets().register_pn(pn);
+            reader.cc.on_pkt_rcvd(Epoch::Data, pn, true);
+        }
+        assert!(reader.cc.need_ack(Epoch::Data).is_some());
+        // 同时有流数据待发送
+        data.streams
+            .recv_frame(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Bi(
+                VarInt::from_u32(1),
+            )))
+            .unwrap();
+        let (_sid, (mut stream_reader, mut writer)) =
+            data.streams.open_bi(1000).await.unwrap().unwrap();
+        writer.write_all(&[0; 300]).await.unwrap();
+
+        let mut buffers = vec![];
+        let (datagrams, _ecn) = reader.read(&mut buffers).await.unwrap();
+        assert_eq!(datagrams.len(), 1);
+        let datagram = BytesMut::from(&datagrams[0][..]);
+        drop(datagrams);
+        let packets = PacketReader::new(datagram, 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(matches!(
+            &packets[..],
+            [Packet::Data(packet)] if matches!(packet.header, DataHeader::Short(_))
+        ));
+        // Ack和流数据合并在同一个1-RTT数据包中发出，不再单独发送仅包含Ack的数据包
+        assert!(reader.cc.need_ack(Epoch::Data).is_none());
+        assert!(data.streams.try_read_data(&mut [0; 1000], 1000).is_none());
+        assert_eq!(reader.flow_ctrl.send_limit().unwrap().available(), 65235);
+        assert!(reader.read(&mut buffers).now_or_never().is_none());
+
+        let tracker = DataTracker::new(
+            data.journal.clone(),
+            data.reliable_frames.clone(),
+            data.streams.clone(),
+            data.crypto_stream.outgoing(),
+            data.ping_sndbuf.clone(),
+            ConnError::default(),
+        );
+        assert_eq!(tracker.may_loss(0), 1);
+
+        stream_reader.stop(0);
+        writer.cancel(0);
     }
 }
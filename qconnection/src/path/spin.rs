@@ -0,0 +1,125 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use futures::channel::mpsc;
+use qbase::{packet::SpinBit, sid::Role};
+
+/// The observers of the spin bit flips of the peer, shared by all paths of a connection.
+///
+/// Network measurement tools can subscribe to it, each time the spin bit observed from the
+/// peer flips on any path, the new value is sent to every subscriber. The time between two
+/// flips is about one RTT.
+#[derive(Debug, Default, Clone)]
+pub struct SpinObservers(Arc<Mutex<Vec<mpsc::UnboundedSender<SpinBit>>>>);
+
+impl SpinObservers {
+    /// Subscribe to the spin bit flips, the subscription ends when the receiver is dropped.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<SpinBit> {
+        let (tx, rx) = mpsc::unbounded();
+        self.0.lock().unwrap().push(tx);
+        rx
+    }
+
+    fn notify(&self, spin: SpinBit) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.unbounded_send(spin).is_ok());
+    }
+}
+
+/// The latency spin bit of a path.
+///
+/// The server sets the spin value to the spin bit of 1-RTT packet with the largest packet number
+/// received, and the client sets it to the inverse. So the spin value of the client flips once
+/// per RTT. See [Section 17.4](https://www.rfc-editor.org/rfc/rfc9000.html#name-latency-spin-bit)
+/// of [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html).
+#[derive(Debug, Clone)]
+pub struct ArcSpin {
+    role: Role,
+    // 发送的1-RTT包中的spin bit，由发送任务读取
+    sending: Arc<AtomicBool>,
+    // 收到的最大包号，及其中的spin bit
+    rcvd: Arc<Mutex<Option<(u64, SpinBit)>>>,
+    observers: SpinObservers,
+}
+
+impl ArcSpin {
+    pub fn new(role: Role, observers: SpinObservers) -> Self {
+        Self {
+            role,
+            sending: Arc::new(AtomicBool::new(false)),
+            rcvd: Arc::default(),
+            observers,
+        }
+    }
+
+    /// Return the spin bit that will be sent in the 1-RTT packets.
+    pub fn current(&self) -> SpinBit {
+        if self.sending.load(Ordering::Relaxed) {
+            SpinBit::One
+        } else {
+            SpinBit::Zero
+        }
+    }
+
+    pub(super) fn sending(&self) -> Arc<AtomicBool> {
+        self.sending.clone()
+    }
+
+    /// Called when a 1-RTT packet is received, the reordered packets are ignored.
+    pub fn on_rcvd(&self, pn: u64, spin: SpinBit) {
+        let mut rcvd = self.rcvd.lock().unwrap();
+        let peer = match *rcvd {
+            Some((largest, _)) if pn <= largest => return,
+            Some((_, peer)) => Some(peer),
+            None => None,
+        };
+        *rcvd = Some((pn, spin));
+        drop(rcvd);
+
+        let sending = match self.role {
+            Role::Client => !spin,
+            Role::Server => spin,
+        };
+        self.sending
+            .store(sending == SpinBit::One, Ordering::Relaxed);
+        // 对端的spin bit初始视作0
+        if peer.unwrap_or_default() != spin {
+            self.observers.notify(spin);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spin_per_rtt() {
+        let observers = SpinObservers::default();
+        let mut flips = observers.subscribe();
+        let client = ArcSpin::new(Role::Client, SpinObservers::default());
+        let server = ArcSpin::new(Role::Server, observers);
+
+        // 每个来回，客户端发送的spin bit翻转一次，服务端回显
+        let mut expected = SpinBit::Zero;
+        for pn in 0..4 {
+            assert_eq!(client.current(), expected);
+            server.on_rcvd(pn, client.current());
+            assert_eq!(server.current(), expected);
+            client.on_rcvd(pn, server.current());
+            expected = !expected;
+            assert_eq!(client.current(), expected);
+        }
+        // 服务端观察到的翻转：1、0、1
+        let observed = std::iter::from_fn(|| flips.try_recv().ok()).collect::<Vec<_>>();
+        assert_eq!(observed, [SpinBit::One, SpinBit::Zero, SpinBit::One]);
+
+        // 乱序到达的旧包不影响spin bit
+        client.on_rcvd(0, SpinBit::One);
+        assert_eq!(client.current(), expected);
+    }
+}
@@ -9,6 +9,8 @@ use qbase::cid::ArcCidCell;
 use qrecovery::reliable::ArcReliableFrameDeque;
 use tokio::sync::Notify;
 
+use crate::spawn::Spawner;
+
 /// Represents the current state of the path.
 #[derive(Debug, Clone)]
 pub enum PathState {
@@ -30,7 +32,7 @@ impl ArcPathState {
     /// Creates a new instance of the struct and spawns a background task to monitor its activity.
     ///
     /// This function initializes the struct with the current time as the initial receive time and
-    /// spawns a Tokio task that periodically checks if the path has been inactive for a specified
+    /// spawns a task by the `spawner` that periodically checks if the path has been inactive for a specified
     /// duration (currently 30 seconds).
     ///
     /// The background task runs in a loop, comparing the current time with the last recorded
@@ -38,7 +40,7 @@ impl ArcPathState {
     /// to the [`InActive`] state and the task terminates.
     ///
     /// [`InActive`]: PathState::InActive
-    pub fn new(cid: ArcCidCell<ArcReliableFrameDeque>) -> Self {
+    pub fn new(cid: ArcCidCell<ArcReliableFrameDeque>, spawner: &Spawner) -> Self {
         let state = Self {
             state: Arc::new(
                 PathState::Active {
@@ -50,8 +52,9 @@ impl ArcPathState {
             ),
         };
 
-        tokio::spawn({
+        spawner.spawn({
             let state = state.clone();
+            let timer = spawner.clone();
             async move {
                 loop {
                     let now = time::Instant::now();
@@ -65,7 +68,7 @@ impl ArcPathState {
                         state.to_inactive();
                         break;
                     }
-                    tokio::time::sleep_until((recv_time + time).into()).await
+                    timer.sleep_until(recv_time + time).await
                 }
             }
         });
@@ -155,6 +155,11 @@ pub struct Constraints {
     // 发送配额，源于拥塞控制算法，随着时间的流逝，得到的本次Burst应当发送的数据量
     // 作用于ack-eliciting数据包，除非该包只发送Padding/Ack/Ccf帧
     send_quota: usize,
+    /// The remaining space of the datagram being assembled, shared by the packets coalesced into it.
+    ///
+    /// It is unlimited until [`Constraints::next_datagram`] is called.
+    // 当前数据报剩余的空间，合并在同一数据报中的数据包共享
+    datagram_budget: usize,
 }
 
 impl Constraints {
@@ -163,9 +168,22 @@ impl Constraints {
         Self {
             credit_limit,
             send_quota,
+            datagram_budget: usize::MAX,
         }
     }
 
+    /// Start assembling the next datagram, whose size must not exceed `size`.
+    ///
+    /// The packets committed until the next call are coalesced into the same datagram, they share the `size`.
+    pub fn next_datagram(&mut self, size: usize) {
+        self.datagram_budget = size;
+    }
+
+    /// Return the remaining space of the datagram being assembled.
+    pub fn datagram_budget(&self) -> usize {
+        self.datagram_budget
+    }
+
     /// Return whether the constraints are available(More frames can be send).
     ///
     /// The conditions for ending is the credit limit is used up. Even if the send quota is not used up, packets that
@@ -179,25 +197,28 @@ impl Constraints {
         self.credit_limit > 0
     }
 
-    /// Constrain the buffer, make it smaller than the limit and quota.
+    /// Constrain the buffer, make it smaller than the limit, the quota and the datagram budget.
     pub fn constrain<'b>(&self, buf: &'b mut [u8]) -> &'b mut [u8] {
         let min_len = buf
             .remaining_mut()
             .min(self.credit_limit)
-            .min(self.send_quota);
+            .min(self.send_quota)
+            .min(self.datagram_budget);
         &mut buf[..min_len]
     }
 
     /// Commit consumption of credit limit and send quota.
     ///
-    /// The `len` is how much data was written to the constrained buffer, `is_just_ack` instruct whether the send quota
-    /// should be consume.
+    /// The `len` is how much data was written to the constrained buffer, it always consumes the credit limit, as all the
+    /// bytes sent count toward the anti-amplification limit. `in_flight` instructs whether the send quota should be
+    /// consumed, the packets only contain Ack/Ccf frames are not in flight.
     ///
     /// See [section-12.4-14.4.1](https//rfc-editor.org/rfc/rfc9000.html#section-12.4-14.4.1)
     /// and [table 3](https//rfc-editor.org/rfc/rfc9000.html#table-3)
     /// of [RFC9000](https//rfc-editor.org/rfc/rfc9000.html) for more details.
     pub fn commit(&mut self, len: usize, in_flight: bool) {
         self.credit_limit = self.credit_limit.saturating_sub(len);
+        self.datagram_budget = self.datagram_budget.saturating_sub(len);
         if in_flight {
             self.send_quota = self.send_quota.saturating_sub(len);
         }
@@ -1,6 +1,6 @@
 use std::sync::LazyLock;
 
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use qbase::{
     cid::{ConnectionId, GenUniqueCid},
     error::Error,
@@ -13,6 +13,9 @@ use crate::{conn::PacketEntry, path::Pathway, usc::ArcUsc};
 /// Global Router for managing connections.
 static ROUTER: LazyLock<DashMap<ConnectionId, [PacketEntry; 4]>> = LazyLock::new(DashMap::new);
 
+/// 使用零长度连接ID的连接，无法以连接ID区分，只能以收包的四元组路由
+static PATHWAY_ROUTER: LazyLock<DashMap<Pathway, [PacketEntry; 4]>> = LazyLock::new(DashMap::new);
+
 /// A interface to control the global router, which used to route packets to the corresponding connection.
 pub struct Router;
 
@@ -22,6 +25,10 @@ impl Router {
     /// The argument `packet` is the packet to be routed, `pathway` and `usc` is where the packet
     /// comes from,you can read the [`Pathway`] and [`ArcUsc`]'s documents for more information.
     ///
+    /// The packet is routed by its destination connection ID, which is one of the local CIDs the
+    /// connection issued. The packet with a zero-length destination connection ID is routed by
+    /// the `pathway` it comes from instead, see [`RouterRegistry::route_pathway`].
+    ///
     /// If the connection does not exist, the packet will be returned with out any modification.
     pub fn try_to_route_packet_from(
         packet: DataPacket,
@@ -29,16 +36,23 @@ impl Router {
         usc: &ArcUsc,
     ) -> Result<(), DataPacket> {
         let dcid = packet.header.get_dcid();
-        let Some(entries) = ROUTER.get(dcid) else {
-            return Err(packet);
-        };
         let index = match packet.header {
             DataHeader::Long(long::DataHeader::Initial(_)) => 0,
             DataHeader::Long(long::DataHeader::ZeroRtt(_)) => 1,
             DataHeader::Long(long::DataHeader::Handshake(_)) => 2,
             DataHeader::Short(_) => 3,
         };
-        _ = entries[index].unbounded_send((packet, pathway, usc.clone()));
+        let entry = if dcid.is_empty() {
+            PATHWAY_ROUTER
+                .get(&pathway)
+                .map(|entries| entries[index].clone())
+        } else {
+            ROUTER.get(dcid).map(|entries| entries[index].clone())
+        };
+        let Some(entry) = entry else {
+            return Err(packet);
+        };
+        _ = entry.unbounded_send((packet, pathway, usc.clone()));
         Ok(())
     }
 
@@ -46,6 +60,9 @@ impl Router {
     ///
     /// Return a [`RouterRegistry`], a wrapper around the connection's local CIDs. it can be used to
     /// generate a new unique CID and add a router entry to the global router.
+    ///
+    /// A zero-length `scid` is not added to the global router, the connection using it should be
+    /// routed by [`RouterRegistry::route_pathway`].
     pub fn registry<ISSUED>(
         scid: ConnectionId,
         issued_cids: ISSUED,
@@ -54,7 +71,9 @@ impl Router {
     where
         ISSUED: SendFrame<NewConnectionIdFrame>,
     {
-        ROUTER.insert(scid, packet_entries.clone());
+        if !scid.is_empty() {
+            ROUTER.insert(scid, packet_entries.clone());
+        }
         RouterRegistry {
             issued_cids,
             packet_entries,
@@ -84,6 +103,48 @@ pub struct RouterRegistry<ISSUED> {
     packet_entries: [PacketEntry; 4],
 }
 
+impl<ISSUED> RouterRegistry<ISSUED> {
+    /// Route the packets with zero-length destination connection ID received from the `pathway`
+    /// to the connection, until the returned [`PathwayRoute`] is dropped.
+    ///
+    /// It's used by the connection whose local connection ID is zero-length, its paths can only be
+    /// distinguished by the 4-tuple.
+    ///
+    /// Return `None` if the `pathway` is already routed to another connection, the packets from it
+    /// keep being routed to that connection.
+    pub fn route_pathway(&self, pathway: Pathway) -> Option<PathwayRoute> {
+        let entry = self.packet_entries[0].clone();
+        match PATHWAY_ROUTER.entry(pathway) {
+            // 已被其他连接占用，不能覆盖，否则那个连接的包会被错误地路由到本连接
+            Entry::Occupied(occupied) if !occupied.get()[0].same_receiver(&entry) => return None,
+            Entry::Occupied(_) => {}
+            Entry::Vacant(vacant) => {
+                vacant.insert(self.packet_entries.clone());
+            }
+        }
+        Some(PathwayRoute { pathway, entry })
+    }
+}
+
+/// The router entry of a [`Pathway`], see [`RouterRegistry::route_pathway`].
+///
+/// The entry is removed from the global router when it is dropped.
+#[derive(Debug)]
+pub struct PathwayRoute {
+    pathway: Pathway,
+    // 用于识别路由表项是否属于本连接
+    entry: PacketEntry,
+}
+
+impl Drop for PathwayRoute {
+    fn drop(&mut self) {
+        // 只移除本连接的路由表项
+        PATHWAY_ROUTER.remove_if(&self.pathway, |_, entries| {
+            entries[0].same_receiver(&self.entry)
+        });
+    }
+}
+
 impl<T> SendFrame<NewConnectionIdFrame> for RouterRegistry<T>
 where
     T: SendFrame<NewConnectionIdFrame>,
@@ -133,3 +194,145 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use bytes::BytesMut;
+    use futures::{channel::mpsc, StreamExt};
+    use qbase::{
+        cid::ArcLocalCids,
+        packet::{header::OneRttHeader, LongHeaderBuilder, SpinBit},
+    };
+    use qrecovery::reliable::ArcReliableFrameDeque;
+
+    use super::*;
+    use crate::{
+        conn::RcvdPackets,
+        spawn::TokioRuntime,
+        usc::{bind_udp_socket, UscRegistry},
+    };
+
+    fn packet_entries() -> ([PacketEntry; 4], [RcvdPackets; 4]) {
+        let (entries, rcvd): (Vec<_>, Vec<_>) = (0..4).map(|_| mpsc::unbounded()).unzip();
+        (entries.try_into().unwrap(), rcvd.try_into().unwrap())
+    }
+
+    fn one_rtt_packet(dcid: ConnectionId) -> DataPacket {
+        DataPacket {
+            header: DataHeader::Short(OneRttHeader::new(SpinBit::Zero, dcid)),
+            bytes: BytesMut::new(),
+            offset: 0,
+            ecn: None,
+        }
+    }
+
+    fn usc() -> ArcUsc {
+        let unspecified: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let recv_task = |usc: ArcUsc| async move {
+            let _usc = usc;
+            core::future::pending::<()>().await;
+        };
+        UscRegistry::create_new_usc(unspecified, bind_udp_socket, &TokioRuntime, recv_task).unwrap()
+    }
+
+    fn pathway(remote: &str) -> Pathway {
+        Pathway::Direct {
+            local: "127.0.0.1:4433".parse().unwrap(),
+            remote: remote.parse().unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn route_by_issued_cids() {
+        let usc = usc();
+        let (entries, [_, _, mut rcvd_hs_packets, mut rcvd_1rtt_packets]) = packet_entries();
+        let scid = ConnectionId::random_gen_with_mark(8, 0x80, 0x7F);
+        let registry = Router::registry(scid, ArcReliableFrameDeque::with_capacity(8), entries);
+        let local_cids = ArcLocalCids::new(scid, registry);
+        // 对端允许的活跃连接ID上限，新签发的连接ID也加入路由
+        local_cids.set_limit(4).unwrap();
+        let cids = local_cids.active_cids();
+        assert_eq!(cids.len(), 4);
+
+        let pathway = pathway("10.0.0.2:5000");
+        for cid in &cids {
+            assert!(Router::try_to_route_packet_from(one_rtt_packet(*cid), pathway, &usc).is_ok());
+            let (packet, from, _) = rcvd_1rtt_packets.next().await.unwrap();
+            assert_eq!(packet.header.get_dcid(), cid);
+            assert_eq!(from, pathway);
+        }
+        // 长包头的包，按类型交给对应的空间
+        let handshake = DataPacket {
+            header: DataHeader::Long(long::DataHeader::Handshake(
+                LongHeaderBuilder::with_cid(cids[2], ConnectionId::random_gen(8)).handshake(),
+            )),
+            bytes: BytesMut::new(),
+            offset: 0,
+            ecn: None,
+        };
+        assert!(Router::try_to_route_packet_from(handshake, pathway, &usc).is_ok());
+        let (packet, ..) = rcvd_hs_packets.next().await.unwrap();
+        assert_eq!(packet.header.get_dcid(), &cids[2]);
+
+        // 未签发的，以及被移除的连接ID，都不再路由
+        let unknown = ConnectionId::random_gen_with_mark(8, 0x80, 0x7F);
+        assert!(Router::try_to_route_packet_from(one_rtt_packet(unknown), pathway, &usc).is_err());
+        cids.iter().for_each(Router::remove);
+        assert!(Router::try_to_route_packet_from(one_rtt_packet(cids[1]), pathway, &usc).is_err());
+    }
+
+    #[tokio::test]
+    async fn route_zero_length_cid_by_pathway() {
+        let usc = usc();
+        let (entries, [.., mut rcvd_1rtt_packets]) = packet_entries();
+        let scid = ConnectionId::default();
+        let registry = Router::registry(scid, ArcReliableFrameDeque::with_capacity(8), entries);
+        assert!(!ROUTER.contains_key(&scid));
+
+        let primary = pathway("10.0.0.3:5000");
+        let route = registry.route_pathway(primary).unwrap();
+        assert!(Router::try_to_route_packet_from(one_rtt_packet(scid), primary, &usc).is_ok());
+        let (_, from, _) = rcvd_1rtt_packets.next().await.unwrap();
+        assert_eq!(from, primary);
+
+        // 其他四元组的包不属于该连接
+        let other = pathway("10.0.0.4:5000");
+        assert!(Router::try_to_route_packet_from(one_rtt_packet(scid), other, &usc).is_err());
+        drop(route);
+        assert!(Router::try_to_route_packet_from(one_rtt_packet(scid), primary, &usc).is_err());
+    }
+
+    #[tokio::test]
+    async fn keep_pathway_routed_to_its_owner() {
+        let usc = usc();
+        let scid = ConnectionId::default();
+        let (entries, [.., mut owner_rcvd_packets]) = packet_entries();
+        let owner = Router::registry(scid, ArcReliableFrameDeque::with_capacity(8), entries);
+        let (entries, [.., mut other_rcvd_packets]) = packet_entries();
+        let other = Router::registry(scid, ArcReliableFrameDeque::with_capacity(8), entries);
+
+        let pathway = pathway("10.0.0.5:5000");
+        let owner_route = owner.route_pathway(pathway).unwrap();
+        // 该四元组已属于另一个连接，不能被抢占
+        assert!(other.route_pathway(pathway).is_none());
+        assert!(Router::try_to_route_packet_from(one_rtt_packet(scid), pathway, &usc).is_ok());
+        let (_, from, _) = owner_rcvd_packets.next().await.unwrap();
+        assert_eq!(from, pathway);
+
+        // 所属连接释放了该四元组之后，其他连接才能使用它
+        drop(owner_route);
+        let other_route = other.route_pathway(pathway).unwrap();
+        assert!(Router::try_to_route_packet_from(one_rtt_packet(scid), pathway, &usc).is_ok());
+        let (_, from, _) = other_rcvd_packets.next().await.unwrap();
+        assert_eq!(from, pathway);
+
+        // 被拒绝的路由请求，不影响该四元组所属的连接
+        drop(owner.route_pathway(pathway));
+        assert!(Router::try_to_route_packet_from(one_rtt_packet(scid), pathway, &usc).is_ok());
+        assert!(other_rcvd_packets.next().await.is_some());
+        drop(other_route);
+        assert!(Router::try_to_route_packet_from(one_rtt_packet(scid), pathway, &usc).is_err());
+    }
+}
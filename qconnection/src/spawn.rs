@@ -0,0 +1,313 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::{pin, Pin},
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::{
+    channel::oneshot::{self, Canceled},
+    future::{self, AbortHandle, Abortable, BoxFuture, Either},
+    FutureExt,
+};
+
+/// The async runtime that runs the background tasks and the timers of the connections.
+///
+/// The tasks are spawned on the current tokio runtime by [`TokioRuntime`] by default, implement
+/// this trait to run them on another runtime.
+pub trait Runtime: Send + Sync {
+    /// Spawn the `task` to run in the background until it completes.
+    fn spawn(&self, task: BoxFuture<'static, ()>);
+
+    /// Return a future that completes after the `duration` has elapsed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// The default [`Runtime`], spawns the tasks on the current tokio runtime, and uses the tokio
+/// timers.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioRuntime;
+
+impl Runtime for TokioRuntime {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        tokio::spawn(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// The error returned by [`timeout`] when the future does not complete in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+/// Wait for the `future` to complete within the `duration` timed by the `runtime`, or return [`Elapsed`].
+pub async fn timeout<F: Future>(
+    runtime: &dyn Runtime,
+    duration: Duration,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    match future::select(pin!(future), runtime.sleep(duration)).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(Elapsed),
+    }
+}
+
+/// The handle of a task spawned by the [`Spawner`], await it for the output of the task.
+///
+/// Unlike the [`AbortHandle`], dropping it does not cancel the task, the task keeps running in the
+/// background. If the task is cancelled, [`Canceled`] will be returned.
+#[derive(Debug)]
+pub struct JoinHandle<T> {
+    output: oneshot::Receiver<T>,
+    abort_handle: AbortHandle,
+}
+
+impl<T> JoinHandle<T> {
+    /// Cancel the task.
+    pub fn abort(&self) {
+        self.abort_handle.abort();
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, Canceled>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.output.poll_unpin(cx)
+    }
+}
+
+#[derive(Debug)]
+struct Task {
+    abort_handle: AbortHandle,
+    // 任务被运行时丢弃之后，无法再升级
+    alive: Weak<()>,
+}
+
+impl Task {
+    fn is_finished(&self) -> bool {
+        self.alive.strong_count() == 0
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tasks {
+    tasks: Vec<Task>,
+    aborted: bool,
+}
+
+/// The handle to spawn the background tasks of a connection.
+///
+/// The tasks of a connection, such as the TLS handshake, the sending tasks of the paths and the
+/// keep-alive task, are spawned through the same [`Spawner`], so that they are cancelled together
+/// by [`Spawner::abort_all`] when the connection is closed, rather than leaked until the things
+/// they wait for happen.
+///
+/// The packet receiving tasks and the tasks closing the connection are spawned by
+/// [`Spawner::spawn_detached`], they end by themselves when the connection is closed, and hand the
+/// received packets over to the closing connection.
+///
+/// All the tasks run on the [`Runtime`] the [`Spawner`] is created with, and the timers of the
+/// connection are driven by it too.
+///
+/// The tasks spawned after [`Spawner::abort_all`] are cancelled immediately.
+#[derive(Clone)]
+pub struct Spawner {
+    runtime: Arc<dyn Runtime>,
+    tasks: Arc<Mutex<Tasks>>,
+}
+
+impl fmt::Debug for Spawner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spawner")
+            .field("tasks", &self.tasks)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Spawner {
+    fn default() -> Self {
+        Self::new(Arc::new(TokioRuntime))
+    }
+}
+
+impl Spawner {
+    /// Create a new [`Spawner`] runs the tasks on the given `runtime`.
+    pub fn new(runtime: Arc<dyn Runtime>) -> Self {
+        Self {
+            runtime,
+            tasks: Default::default(),
+        }
+    }
+
+    fn spawn_task<F>(&self, future: F) -> (JoinHandle<F::Output>, Task)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (abort_handle, registration) = AbortHandle::new_pair();
+        let (tx, output) = oneshot::channel();
+        let alive = Arc::new(());
+        let task = Task {
+            abort_handle: abort_handle.clone(),
+            alive: Arc::downgrade(&alive),
+        };
+        let future = Abortable::new(future, registration);
+        self.runtime.spawn(Box::pin(async move {
+            let _alive = alive;
+            if let Ok(output) = future.await {
+                _ = tx.send(output);
+            }
+        }));
+        let join_handle = JoinHandle {
+            output,
+            abort_handle,
+        };
+        (join_handle, task)
+    }
+
+    /// Spawn a task of the connection, it will be cancelled by [`Spawner::abort_all`].
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (join_handle, task) = self.spawn_task(future);
+        let mut tasks = self.tasks.lock().unwrap();
+        if tasks.aborted {
+            join_handle.abort();
+        } else {
+            // 顺便清理已经结束的任务，避免无限增长
+            tasks.tasks.retain(|task| !task.is_finished());
+            tasks.tasks.push(task);
+        }
+        join_handle
+    }
+
+    /// Spawn a task that outlives the connection, [`Spawner::abort_all`] does not cancel it.
+    ///
+    /// The task must end by itself.
+    pub fn spawn_detached<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.spawn_task(future).0
+    }
+
+    /// Cancel all the tasks spawned by [`Spawner::spawn`], including those to be spawned later.
+    pub fn abort_all(&self) {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.aborted = true;
+        tasks
+            .tasks
+            .iter()
+            .for_each(|task| task.abort_handle.abort());
+    }
+
+    /// Return a future that completes after the `duration`, driven by the [`Runtime`].
+    pub fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.runtime.sleep(duration)
+    }
+
+    /// Return a future that completes at the `deadline`, driven by the [`Runtime`].
+    pub fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        self.runtime
+            .sleep(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Wait for the `future` to complete within the `duration`, or return [`Elapsed`].
+    pub async fn timeout<F: Future>(
+        &self,
+        duration: Duration,
+        future: F,
+    ) -> Result<F::Output, Elapsed> {
+        timeout(self.runtime.as_ref(), duration, future).await
+    }
+
+    /// Return the number of the tasks spawned by [`Spawner::spawn`] that are still running.
+    ///
+    /// The tasks cancelled by [`Spawner::abort_all`] are counted until the runtime drops them.
+    pub fn running(&self) -> usize {
+        let tasks = self.tasks.lock().unwrap();
+        tasks
+            .tasks
+            .iter()
+            .filter(|task| !task.is_finished())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_abort_all() {
+        let spawner = Spawner::default();
+        let finished = spawner.spawn(async {});
+        let pending = spawner.spawn(std::future::pending::<()>());
+        let detached = spawner.spawn_detached(tokio::time::sleep(Duration::from_millis(10)));
+        finished.await.unwrap();
+        assert_eq!(spawner.running(), 1);
+
+        spawner.abort_all();
+        assert!(pending.await.is_err());
+        assert_eq!(spawner.running(), 0);
+        // 脱离连接的任务不受影响
+        detached.await.unwrap();
+
+        // 之后再产生的任务立即被取消
+        let late = spawner.spawn(tokio::time::sleep(Duration::from_secs(1)));
+        assert!(late.await.is_err());
+    }
+
+    #[derive(Default)]
+    struct CountRuntime(AtomicUsize);
+
+    impl Runtime for CountRuntime {
+        fn spawn(&self, task: BoxFuture<'static, ()>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(task);
+        }
+
+        fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Box::pin(tokio::time::sleep(duration))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_runtime() {
+        let runtime = Arc::new(CountRuntime::default());
+        let spawner = Spawner::new(runtime.clone());
+        assert_eq!(spawner.spawn(async { 1 }).await, Ok(1));
+        assert_eq!(spawner.spawn_detached(async { 2 }).await, Ok(2));
+        assert_eq!(runtime.0.load(Ordering::Relaxed), 2);
+
+        let timeout = Duration::from_millis(10);
+        assert_eq!(spawner.timeout(timeout, async { 3 }).await, Ok(3));
+        assert_eq!(
+            spawner.timeout(timeout, std::future::pending::<()>()).await,
+            Err(Elapsed)
+        );
+        assert_eq!(runtime.0.load(Ordering::Relaxed), 4);
+    }
+}
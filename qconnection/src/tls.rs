@@ -17,9 +17,12 @@ use rustls::{
     quic::{KeyChange, Keys},
     Side,
 };
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::watch,
+};
 
-use crate::{conn::Handshake, error::ConnError};
+use crate::{conn::Handshake, error::ConnError, spawn::Spawner};
 
 type TlsConnection = rustls::quic::Connection;
 
@@ -54,6 +57,11 @@ impl TlsSession {
     }
 
     fn try_get_parameters(&mut self, params: &ArcParameters) -> Result<(), Error> {
+        // 恢复会话时，客户端在收到服务端的EncryptedExtensions之前，得到的是上次连接记忆的传输参数，
+        // 而客户端要到握手完成时才获得1-RTT密钥，届时再读取服务端本次的传输参数
+        if matches!(self.tls_conn, TlsConnection::Client(_)) && self.is_handshaking() {
+            return Ok(());
+        }
         if !params.has_rcvd_remote_params() {
             if let Some(raw) = self.tls_conn.quic_transport_parameters() {
                 params.recv_remote_params(raw)?;
@@ -66,10 +74,22 @@ impl TlsSession {
         self.tls_conn.alert()
     }
 
+    fn negotiated_cipher_suite(&self) -> Option<rustls::SupportedCipherSuite> {
+        self.tls_conn.negotiated_cipher_suite()
+    }
+
     fn is_handshaking(&self) -> bool {
         self.tls_conn.is_handshaking()
     }
 
+    fn is_early_data_accepted(&self) -> bool {
+        match &self.tls_conn {
+            TlsConnection::Client(client_conn) => client_conn.is_early_data_accepted(),
+            // 服务端处理ClientHello时决定是否接受0-RTT，拒绝时会丢弃早期数据的密钥
+            TlsConnection::Server(server_conn) => server_conn.zero_rtt_keys().is_some(),
+        }
+    }
+
     fn server_name(&self) -> Option<&str> {
         match &self.tls_conn {
             TlsConnection::Server(server_conn) => server_conn.server_name(),
@@ -83,6 +103,7 @@ struct ReadAndProcess<'r> {
     messages: &'r mut Vec<u8>,
     parameters: &'r ArcParameters,
     handshake: &'r Handshake,
+    handshaked: &'r watch::Sender<bool>,
 }
 
 impl futures::Future for ReadAndProcess<'_> {
@@ -104,6 +125,8 @@ impl futures::Future for ReadAndProcess<'_> {
 
         if !tls_conn.is_handshaking() {
             this.handshake.done();
+            this.handshaked
+                .send_if_modified(|done| !std::mem::replace(done, true));
         }
 
         tls_conn.try_get_parameters(this.parameters)?;
@@ -115,8 +138,13 @@ impl futures::Future for ReadAndProcess<'_> {
 /// The shared TLS session for QUIC's TLS handshake.
 ///
 /// This is a wrapper around the [`rustls::quic::Connection`], which is a QUIC-specific TLS connection.
+///
+/// The completion of the handshake can be subscribed by [`ArcTlsSession::handshaked`].
 #[derive(Debug, Clone)]
-pub struct ArcTlsSession(Arc<Mutex<Result<TlsSession, Error>>>);
+pub struct ArcTlsSession(
+    Arc<Mutex<Result<TlsSession, Error>>>,
+    Arc<watch::Sender<bool>>,
+);
 
 impl ArcTlsSession {
     /// The QUIC version used by the TLS session.
@@ -138,7 +166,10 @@ impl ArcTlsSession {
             params,
         );
         let connection = rustls::quic::Connection::Client(client_connection.unwrap());
-        Self(Arc::new(Mutex::new(Ok(connection.into()))))
+        Self(
+            Arc::new(Mutex::new(Ok(connection.into()))),
+            Arc::new(watch::Sender::new(false)),
+        )
     }
 
     /// Create a new server-side TLS session.
@@ -149,7 +180,10 @@ impl ArcTlsSession {
         let server_connection =
             rustls::quic::ServerConnection::new(tls_config, Self::QUIC_VERSION, params).unwrap();
         let connection = rustls::quic::Connection::Server(server_connection);
-        Self(Arc::new(Mutex::new(Ok(connection.into()))))
+        Self(
+            Arc::new(Mutex::new(Ok(connection.into()))),
+            Arc::new(watch::Sender::new(false)),
+        )
     }
 
     /// Generate the keys for the initial packet protection.
@@ -191,9 +225,16 @@ impl ArcTlsSession {
             messages: buf,
             parameters,
             handshake,
+            handshaked: &self.1,
         }
     }
 
+    /// Subscribe to the completion of the TLS handshake, the value turns to `true` once the
+    /// handshake is complete, and never changes if the handshake fails.
+    pub fn handshaked(&self) -> watch::Receiver<bool> {
+        self.1.subscribe()
+    }
+
     /// Start the TLS handshake, automatically upgrade the keys, and transmit tls data.
     ///
     /// The [`CryptoStream`]s are provide for TLS connection to transmit the encrypted data.
@@ -206,7 +247,14 @@ impl ArcTlsSession {
     /// The [`Handshake`] is used to notify the other components that the handshake is completed,
     /// for server, it should send the [`HandshakeDoneFrame`] to the client.
     ///
+    /// The tasks are spawned by the [`Spawner`] of the connection.
+    ///
+    /// The `flush` is called each time the handshake data produced by TLS is written into the
+    /// [`CryptoStream`], it should wake the sending tasks of the paths, so that the CRYPTO frames
+    /// are sent right away, rather than waiting for the next tick.
+    ///
     /// [`HandshakeDoneFrame`]: qbase::frame::HandshakeDoneFrame
+    #[allow(clippy::too_many_arguments)]
     pub fn keys_upgrade(
         &self,
         crypto_streams: [&CryptoStream; 3],
@@ -215,13 +263,15 @@ impl ArcTlsSession {
         handshake: Handshake,
         parameters: ArcParameters,
         conn_error: ConnError,
+        spawner: &Spawner,
+        flush: impl Fn() + Send + 'static,
     ) {
         let for_each_epoch = |epoch: Epoch| {
             let mut crypto_stream_reader = crypto_streams[epoch].reader();
             let tls_session = self.clone();
             let conn_error = conn_error.clone();
 
-            tokio::spawn(async move {
+            spawner.spawn(async move {
                 let mut read_buf = [0u8; 1500];
                 while let Ok(read) = crypto_stream_reader.read(&mut read_buf[..]).await {
                     let mut guard = tls_session.0.lock().unwrap();
@@ -245,7 +295,7 @@ impl ArcTlsSession {
             })
         };
 
-        tokio::spawn({
+        spawner.spawn({
             let tls_session = self.clone();
 
             let mut crypto_stream_writers =
@@ -275,6 +325,7 @@ impl ArcTlsSession {
                             conn_error.on_error(error);
                             break;
                         }
+                        flush();
                     }
 
                     if let Some(key_change) = key_upgrade {
@@ -313,4 +364,328 @@ impl ArcTlsSession {
             .and_then(TlsSession::server_name)
             .map(ToString::to_string)
     }
+
+    /// Retrieves the TLS cipher suite negotiated by the handshake, or [`None`] before the
+    /// ServerHello is processed.
+    ///
+    /// The packets are protected with the AEAD and the header protection algorithms of the suite,
+    /// see [Section 5](https://www.rfc-editor.org/rfc/rfc9001#section-5) of RFC 9001, they are
+    /// available through [`rustls::Tls13CipherSuite::quic`].
+    ///
+    /// Returns [`None`] once the connection is closed.
+    pub fn negotiated_cipher_suite(&self) -> Option<rustls::SupportedCipherSuite> {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .ok()
+            .and_then(TlsSession::negotiated_cipher_suite)
+    }
+
+    /// Returns whether the early data (0-RTT) is accepted by the server.
+    ///
+    /// For client, it turns `true` once the server's EncryptedExtensions indicates the early data
+    /// is accepted, read [`rustls::quic::ClientConnection::is_early_data_accepted`] for more. For
+    /// server, it's `true` if the server decided to accept the early data offered by the client.
+    ///
+    /// Returns `false` once the connection is closed.
+    pub fn is_early_data_accepted(&self) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_ok_and(TlsSession::is_early_data_accepted)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures::FutureExt;
+    use qbase::{
+        frame::ReceiveFrame,
+        param::{ClientParameters, ServerParameters},
+        sid::Role,
+    };
+    use qrecovery::reliable::ArcReliableFrameDeque;
+    use rustls::{
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{verify_tls12_signature, verify_tls13_signature},
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+        DigitallySignedStruct, SignatureScheme,
+    };
+
+    use super::*;
+
+    // 测试证书已过期，跳过证书验证
+    #[derive(Debug)]
+    struct SkipServerVerification(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for SkipServerVerification {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            let algorithms = &self.0.signature_verification_algorithms;
+            verify_tls12_signature(message, cert, dss, algorithms)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            let algorithms = &self.0.signature_verification_algorithms;
+            verify_tls13_signature(message, cert, dss, algorithms)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+
+    struct Endpoint {
+        tls_session: ArcTlsSession,
+        crypto_streams: [CryptoStream; 3],
+        handshake: Handshake,
+        one_rtt_keys: ArcOneRttKeys,
+        conn_error: ConnError,
+    }
+
+    impl Endpoint {
+        fn new(role: Role, tls_session: ArcTlsSession, parameters: ArcParameters) -> Self {
+            let endpoint = Self {
+                tls_session,
+                crypto_streams: [(); 3].map(|_| CryptoStream::new(1 << 20, 1 << 20)),
+                handshake: Handshake::new(role, ArcReliableFrameDeque::with_capacity(8)),
+                one_rtt_keys: ArcOneRttKeys::new_pending(),
+                conn_error: ConnError::default(),
+            };
+            let [initial, hs, data] = &endpoint.crypto_streams;
+            endpoint.tls_session.keys_upgrade(
+                [initial, hs, data],
+                ArcKeys::new_pending(),
+                endpoint.one_rtt_keys.clone(),
+                endpoint.handshake.clone(),
+                parameters,
+                endpoint.conn_error.clone(),
+                &Spawner::default(),
+                || {},
+            );
+            endpoint
+        }
+
+        // 将待发送的CRYPTO数据切分成小帧，逆序交付给对端，返回交付的帧数
+        fn deliver_reversed(&self, peer: &Endpoint) -> usize {
+            let mut delivered = 0;
+            for epoch in Epoch::EPOCHS {
+                let outgoing = self.crypto_streams[epoch].outgoing();
+                let mut frames = Vec::new();
+                let mut buf = [0u8; 64];
+                while let Some((frame, written)) = outgoing.try_read_data(&mut buf) {
+                    let len = frame.length.into_inner() as usize;
+                    let data = Bytes::copy_from_slice(&buf[written - len..written]);
+                    outgoing.on_data_acked(&frame);
+                    frames.push((frame, data));
+                }
+                delivered += frames.len();
+                let incoming = peer.crypto_streams[epoch].incoming();
+                for frame in frames.iter().rev() {
+                    incoming.recv_frame(frame).unwrap();
+                }
+            }
+            delivered
+        }
+    }
+
+    fn server_config(provider: Arc<CryptoProvider>) -> rustls::ServerConfig {
+        let keychain = "../gm-quic/examples/keychain";
+        // 附带根证书，使服务端的证书flight更大
+        let cert_chain = ["quic.test.net/quic-test-net-ECC.crt", "root/rootCA-ECC.crt"]
+            .map(|file| CertificateDer::from_pem_file(format!("{keychain}/{file}")).unwrap())
+            .to_vec();
+        let key =
+            PrivateKeyDer::from_pem_file(format!("{keychain}/quic.test.net/quic-test-net-ECC.key"))
+                .unwrap();
+        rustls::ServerConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(cert_chain, key)
+            .unwrap()
+    }
+
+    fn client_config(provider: Arc<CryptoProvider>) -> rustls::ClientConfig {
+        rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(SkipServerVerification(provider)))
+            .with_no_client_auth()
+    }
+
+    fn endpoints(
+        client_config: rustls::ClientConfig,
+        server_config: rustls::ServerConfig,
+    ) -> (Endpoint, Endpoint) {
+        let client_scid = ConnectionId::random_gen(8);
+        let server_scid = ConnectionId::random_gen(8);
+        let origin_dcid = ConnectionId::random_gen(8);
+        let client_params = ArcParameters::new_client(ClientParameters::default(), None);
+        client_params.set_initial_scid(client_scid);
+        client_params.initial_scid_from_peer_need_equal(server_scid);
+        client_params.original_dcid_from_server_need_equal(origin_dcid);
+        let server_params = ArcParameters::new_server(ServerParameters::default());
+        server_params.set_initial_scid(server_scid);
+        server_params.set_original_dcid(origin_dcid);
+        server_params.initial_scid_from_peer_need_equal(client_scid);
+
+        let client_tls = ArcTlsSession::new_client(
+            ServerName::try_from("quic.test.net").unwrap(),
+            Arc::new(client_config),
+            &client_params,
+        );
+        let server_tls = ArcTlsSession::new_server(Arc::new(server_config), &server_params);
+        let client = Endpoint::new(Role::Client, client_tls, client_params);
+        let server = Endpoint::new(Role::Server, server_tls, server_params);
+        (client, server)
+    }
+
+    /// 完成一次握手，返回客户端得到的1-RTT密钥，供组装1-RTT数据包的测试使用
+    pub(crate) async fn client_one_rtt_keys() -> ArcOneRttKeys {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let (client, server) = endpoints(client_config(provider.clone()), server_config(provider));
+        while !(client.handshake.is_handshake_complete()
+            && server.handshake.is_handshake_complete())
+        {
+            client.deliver_reversed(&server);
+            server.deliver_reversed(&client);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        client.one_rtt_keys
+    }
+
+    #[tokio::test]
+    async fn test_handshake_with_reordered_crypto_frames() {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let (client, server) = endpoints(client_config(provider.clone()), server_config(provider));
+
+        let mut max_flight = 0;
+        let exchange = async {
+            while !(client.handshake.is_handshake_complete()
+                && server.handshake.is_handshake_complete())
+            {
+                max_flight = max_flight
+                    .max(client.deliver_reversed(&server))
+                    .max(server.deliver_reversed(&client));
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), exchange)
+            .await
+            .expect("the handshake should complete with the reordered crypto frames");
+        // 证书所在的flight被切分成了许多帧
+        assert!(max_flight > 10);
+        assert!(client.one_rtt_keys.get_local_keys().is_some());
+        assert!(server.one_rtt_keys.get_local_keys().is_some());
+        assert!(client.conn_error.now_or_never().is_none());
+        assert!(server.conn_error.now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_early_data_accepted_on_resumption() {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut client_config = client_config(provider.clone());
+        client_config.enable_early_data = true;
+        let mut server_config = server_config(provider);
+        // QUIC要求max_early_data_size为0xffffffff
+        server_config.max_early_data_size = u32::MAX;
+
+        async fn exchange(client: &Endpoint, server: &Endpoint) {
+            let exchange = async {
+                while !(client.handshake.is_handshake_complete()
+                    && server.handshake.is_handshake_complete())
+                {
+                    client.deliver_reversed(server);
+                    server.deliver_reversed(client);
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                // 握手完成之后，服务端才发出会话票据
+                for _ in 0..3 {
+                    server.deliver_reversed(client);
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(5), exchange)
+                .await
+                .expect("the handshake should complete");
+        }
+
+        // 首次连接没有会话票据，无法发送早期数据
+        let (client, server) = endpoints(client_config.clone(), server_config.clone());
+        exchange(&client, &server).await;
+        assert!(!client.tls_session.is_early_data_accepted());
+        assert!(!server.tls_session.is_early_data_accepted());
+
+        // 恢复会话时，服务端接受了早期数据
+        let (client, server) = endpoints(client_config, server_config);
+        exchange(&client, &server).await;
+        assert!(client.tls_session.is_early_data_accepted());
+        assert!(server.tls_session.is_early_data_accepted());
+
+        let error = Error::with_default_fty(ErrorKind::None, "closed");
+        client.tls_session.on_conn_error(&error);
+        assert!(!client.tls_session.is_early_data_accepted());
+    }
+
+    #[tokio::test]
+    async fn test_negotiated_cipher_suite() {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        // 客户端只提供TLS_AES_128_GCM_SHA256
+        let client_provider = Arc::new(CryptoProvider {
+            cipher_suites: vec![rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256],
+            ..rustls::crypto::ring::default_provider()
+        });
+        let (client, server) = endpoints(client_config(client_provider), server_config(provider));
+        assert!(client.tls_session.negotiated_cipher_suite().is_none());
+
+        let exchange = async {
+            while !(client.handshake.is_handshake_complete()
+                && server.handshake.is_handshake_complete())
+            {
+                client.deliver_reversed(&server);
+                server.deliver_reversed(&client);
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), exchange)
+            .await
+            .expect("the handshake should complete");
+        for endpoint in [&client, &server] {
+            let suite = endpoint.tls_session.negotiated_cipher_suite().unwrap();
+            assert_eq!(suite.suite(), rustls::CipherSuite::TLS13_AES_128_GCM_SHA256);
+            let quic = suite.tls13().and_then(|suite| suite.quic).unwrap();
+            assert_eq!(quic.aead_key_len(), 16);
+        }
+
+        let error = Error::with_default_fty(ErrorKind::None, "closed");
+        client.tls_session.on_conn_error(&error);
+        assert!(client.tls_session.negotiated_cipher_suite().is_none());
+    }
 }
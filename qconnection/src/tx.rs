@@ -36,7 +36,7 @@ use crate::{
         space::{DataSpace, HandshakeSpace, InitialSpace},
         Credit, FlowController,
     },
-    path::{ArcAntiAmplifier, Constraints, SendBuffer, DEFAULT_ANTI_FACTOR},
+    path::{ArcAntiAmplifier, Constraints, SendBuffer},
 };
 
 /// 发送一个数据包，
@@ -176,8 +176,10 @@ pub struct Transaction<'a> {
     scid: ConnectionId,
     borrowed_dcid: BorrowedCid<'a, ArcReliableFrameDeque>,
     cc: &'a ArcCC,
+    anti_amplifier: &'a ArcAntiAmplifier,
     flow_limit: Credit<'a>,
     constraints: Constraints,
+    max_udp_payload: usize,
 }
 
 impl<'a> Transaction<'a> {
@@ -185,7 +187,7 @@ impl<'a> Transaction<'a> {
         scid: ConnectionId,
         dcid: &'a DcidCell,
         cc: &'a ArcCC,
-        anti_amplifier: &'a ArcAntiAmplifier<DEFAULT_ANTI_FACTOR>,
+        anti_amplifier: &'a ArcAntiAmplifier,
         flow_ctrl: &'a FlowController,
     ) -> PrepareTransaction<'a> {
         PrepareTransaction {
@@ -194,6 +196,7 @@ impl<'a> Transaction<'a> {
             cc,
             anti_amplifier,
             flow_ctrl,
+            max_udp_payload: usize::MAX,
         }
     }
 
@@ -219,7 +222,7 @@ impl<'a> Transaction<'a> {
         token: Vec<u8>,
         initial_space: &InitialSpace,
     ) -> Option<(AssembledPacket<'b>, Option<u64>)> {
-        initial_space.try_assemble(self, token, self.constraints.constrain(buf))
+        initial_space.try_assemble(self, token, self.constrain(buf))
     }
 
     pub fn load_0rtt_data<'b>(
@@ -228,7 +231,7 @@ impl<'a> Transaction<'a> {
         path_challenge_frames: &SendBuffer<PathChallengeFrame>,
         data_space: &DataSpace,
     ) -> Option<(AssembledPacket<'b>, usize)> {
-        data_space.try_assemble_0rtt(self, path_challenge_frames, self.constraints.constrain(buf))
+        data_space.try_assemble_0rtt(self, path_challenge_frames, self.constrain(buf))
     }
 
     pub fn load_handshake_space<'b>(
@@ -236,7 +239,7 @@ impl<'a> Transaction<'a> {
         buf: &'b mut [u8],
         hs_space: &HandshakeSpace,
     ) -> Option<(AssembledPacket<'b>, Option<u64>)> {
-        hs_space.try_assemble(self, self.constraints.constrain(buf))
+        hs_space.try_assemble(self, self.constrain(buf))
     }
 
     pub fn load_1rtt_data<'b>(
@@ -253,10 +256,30 @@ impl<'a> Transaction<'a> {
             spin,
             path_challenge_frames,
             path_response_frames,
-            self.constraints.constrain(buf),
+            self.constrain(buf),
         )
     }
 
+    /// Start assembling the next datagram.
+    ///
+    /// The packets loaded between two calls are coalesced into the same datagram, they share the
+    /// budget of the datagram, which is the maximum UDP payload of the transaction, see
+    /// [`PrepareTransaction::max_send_udp_payload`]. Once the budget is used up, no more packet
+    /// can be loaded until this method is called.
+    pub fn next_datagram(&mut self) {
+        self.constraints.next_datagram(self.max_udp_payload);
+    }
+
+    /// Return the remaining space of the datagram being assembled.
+    pub fn datagram_budget(&self) -> usize {
+        self.constraints.datagram_budget()
+    }
+
+    // 组装缓冲区不得超过当前数据报剩余的空间，即使对端或路径允许更大的数据报
+    fn constrain<'b>(&self, buf: &'b mut [u8]) -> &'b mut [u8] {
+        self.constraints.constrain(buf)
+    }
+
     pub fn commit(
         &mut self,
         epoch: Epoch,
@@ -265,6 +288,8 @@ impl<'a> Transaction<'a> {
         ack: Option<u64>,
     ) {
         self.constraints.commit(packet.size(), packet.in_flight());
+        // 每个数据包都及时消耗抗放大攻击的额度，下一次事务才能得到正确的额度
+        self.anti_amplifier.on_sent(packet.size());
         self.flow_limit.post_sent(fresh_data);
         self.cc.on_pkt_sent(
             epoch,
@@ -281,8 +306,29 @@ pub struct PrepareTransaction<'a> {
     scid: ConnectionId,
     dcid: &'a DcidCell,
     cc: &'a ArcCC,
-    anti_amplifier: &'a ArcAntiAmplifier<DEFAULT_ANTI_FACTOR>,
+    anti_amplifier: &'a ArcAntiAmplifier,
     flow_ctrl: &'a FlowController,
+    max_udp_payload: usize,
+}
+
+impl PrepareTransaction<'_> {
+    /// Cap the size of the datagrams assembled in the transaction.
+    ///
+    /// Some networks drop large UDP datagrams, the packets will not be assembled into a buffer larger
+    /// than `size`, even if the peer advertised, or the path discovered, a larger one. By default,
+    /// the size of the buffers given to the transaction is used.
+    ///
+    /// # Panics
+    ///
+    /// Values below 1200 are invalid, QUIC requires to send datagrams of at least 1200 bytes.
+    pub fn max_send_udp_payload(mut self, size: usize) -> Self {
+        assert!(
+            size >= 1200,
+            "Values below 1200 are invalid for max_send_udp_payload"
+        );
+        self.max_udp_payload = size;
+        self
+    }
 }
 
 impl<'a> Future for PrepareTransaction<'a> {
@@ -293,6 +339,8 @@ impl<'a> Future for PrepareTransaction<'a> {
         let Some(credit_limit) = ready!(self.anti_amplifier.poll_balance(cx)) else {
             return Poll::Ready(None);
         };
+        // 流量控制额度为0时，仅不能发送新的流数据，Ack、Ping等其他帧仍可发送，事务照常进行；
+        // 只有连接出错，流量控制不可用时，才放弃发送
         let Ok(flow_limit) = self.flow_ctrl.send_limit() else {
             return Poll::Ready(None);
         };
@@ -300,12 +348,150 @@ impl<'a> Future for PrepareTransaction<'a> {
             return Poll::Ready(None);
         };
 
+        let mut constraints = Constraints::new(credit_limit, send_quota);
+        constraints.next_datagram(self.max_udp_payload);
         Poll::Ready(Some(Transaction {
             scid: self.scid,
             borrowed_dcid,
             cc: self.cc,
+            anti_amplifier: self.anti_amplifier,
             flow_limit,
-            constraints: Constraints::new(send_quota, credit_limit),
+            constraints,
+            max_udp_payload: self.max_udp_payload,
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::BytesMut;
+    use qbase::{
+        cid::ArcRemoteCids,
+        handshake::Handshake,
+        packet::{keys::ArcKeys, long, DataHeader, Packet, PacketReader},
+        sid::Role,
+    };
+    use qcongestion::{CongestionAlgorithm, INITIAL_RTT};
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::tests::{keys, Mock};
+
+    struct Server {
+        scid: ConnectionId,
+        dcid: DcidCell,
+        cc: ArcCC,
+        anti_amplifier: ArcAntiAmplifier,
+        flow_ctrl: FlowController,
+        initial: InitialSpace,
+        hs: HandshakeSpace,
+    }
+
+    impl Server {
+        fn new() -> Self {
+            let reliable_frames = ArcReliableFrameDeque::with_capacity(8);
+            let scid = ConnectionId::random_gen(8);
+            let dcid = ConnectionId::random_gen(8);
+            let remote_cids = ArcRemoteCids::new(dcid, 8, reliable_frames.clone());
+            let hs = HandshakeSpace::default();
+            hs.keys.set_keys(keys(scid));
+            Self {
+                scid,
+                dcid: remote_cids.apply_dcid(),
+                cc: ArcCC::new(
+                    CongestionAlgorithm::Bbr,
+                    INITIAL_RTT,
+                    Duration::from_millis(100),
+                    [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+                    Handshake::new(Role::Server, reliable_frames.clone()),
+                ),
+                anti_amplifier: ArcAntiAmplifier::default(),
+                flow_ctrl: FlowController::new(65535, 65535, reliable_frames),
+                initial: InitialSpace::new(ArcKeys::with_keys(keys(dcid))),
+                hs,
+            }
+        }
+
+        fn prepare(&self) -> PrepareTransaction<'_> {
+            Transaction::prepare(
+                self.scid,
+                &self.dcid,
+                &self.cc,
+                &self.anti_amplifier,
+                &self.flow_ctrl,
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn clamp_udp_payload() {
+        let server = Server::new();
+        server.anti_amplifier.grant();
+        let mut writer = server.initial.crypto_stream.writer();
+        writer.write_all(&[0; 4000]).await.unwrap();
+
+        let mut tx = server.prepare().max_send_udp_payload(1252).await.unwrap();
+        // 不受拥塞控制的配额限制，仅考察最大UDP载荷的约束
+        tx.constraints = Constraints::new(usize::MAX, usize::MAX);
+        tx.next_datagram();
+        // 对端通告的max_udp_payload_size为1472，按此分配缓冲区
+        let mut buf = [0u8; 1472];
+        let mut sent = 0;
+        while let Some((packet, ack)) = tx.load_initial_space(&mut buf, vec![], &server.initial) {
+            assert!(packet.size() <= 1252);
+            sent += packet.size();
+            tx.commit(Epoch::Initial, &packet, 0, ack);
+            tx.next_datagram();
+        }
+        assert!(sent > 1252);
+    }
+
+    #[tokio::test]
+    async fn coalesce_within_datagram_budget() {
+        let server = Server::new();
+        server.anti_amplifier.grant();
+        let mut writer = server.initial.crypto_stream.writer();
+        writer.write_all(&[0; 800]).await.unwrap();
+        let mut writer = server.hs.crypto_stream.writer();
+        writer.write_all(&[0; 4000]).await.unwrap();
+
+        let mut tx = server.prepare().max_send_udp_payload(1252).await.unwrap();
+        tx.constraints = Constraints::new(usize::MAX, usize::MAX);
+        tx.next_datagram();
+        // 缓冲区比配置的最大UDP载荷大，合并的数据包总和仍不能超过它
+        let mut datagram = [0u8; 1472];
+        let (packet, ack) = tx
+            .load_initial_space(&mut datagram, vec![], &server.initial)
+            .unwrap();
+        let mut written = packet.size();
+        tx.commit(Epoch::Initial, &packet, 0, ack);
+        assert_eq!(tx.datagram_budget(), 1252 - written);
+        let (packet, ack) = tx
+            .load_handshake_space(&mut datagram[written..], &server.hs)
+            .unwrap();
+        written += packet.size();
+        tx.commit(Epoch::Handshake, &packet, 0, ack);
+        assert_eq!(written, 1252);
+        // 数据报已满，不再合并更多的数据包
+        assert!(tx
+            .load_handshake_space(&mut datagram[written..], &server.hs)
+            .is_none());
+
+        let packets = PacketReader::new(BytesMut::from(&datagram[..written]), 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(matches!(
+            &packets[..],
+            [Packet::Data(initial), Packet::Data(hs)]
+                if matches!(initial.header, DataHeader::Long(long::DataHeader::Initial(_)))
+                    && matches!(hs.header, DataHeader::Long(long::DataHeader::Handshake(_)))
+        ));
+
+        // 下一个数据报重新获得完整的预算
+        tx.next_datagram();
+        let (packet, _ack) = tx.load_handshake_space(&mut datagram, &server.hs).unwrap();
+        assert_eq!(packet.size(), 1252);
+    }
+}
@@ -9,24 +9,49 @@ use std::{
 
 use dashmap::DashMap;
 use deref_derive::Deref;
+use futures::future::{AbortHandle, Abortable};
 use qcongestion::MSS;
-use tokio::task::JoinHandle;
+use qudp::AsyncUdpSocket;
 
-use crate::path::Pathway;
+use crate::{path::Pathway, spawn::Runtime};
 
 /// 全局的usc注册管理，用于查找已有的usc，key是绑定的本地地址，包括v4和v6的地址
-static USC_REGISTRY: LazyLock<DashMap<SocketAddr, (ArcUsc, JoinHandle<()>)>> =
+static USC_REGISTRY: LazyLock<DashMap<SocketAddr, (ArcUsc, AbortHandle)>> =
     LazyLock::new(DashMap::new);
 
+/// The function binds an [`AsyncUdpSocket`] to the given address, which is used to create the [`ArcUsc`].
+///
+/// By default, [`bind_udp_socket`] is used to bind the os udp socket. Provide other functions to
+/// use the custom sockets, such as sockets based on io_uring, or the in-process sockets for testing.
+pub type SocketFactory =
+    Arc<dyn Fn(SocketAddr) -> io::Result<Arc<dyn AsyncUdpSocket>> + Send + Sync>;
+
+/// Bind the os udp socket to the given address, the default [`SocketFactory`].
+pub fn bind_udp_socket(addr: SocketAddr) -> io::Result<Arc<dyn AsyncUdpSocket>> {
+    Ok(Arc::new(qudp::UdpSocketController::new(addr)?))
+}
+
+/// Return a [`SocketFactory`] that binds the os udp sockets with the given `options`.
+pub fn bind_udp_socket_with(options: qudp::SocketOptions) -> SocketFactory {
+    Arc::new(move |addr| {
+        Ok(Arc::new(qudp::UdpSocketController::with_options(
+            addr, options,
+        )?))
+    })
+}
+
 /// A interface to get or create the [`ArcUsc`] that corresponding to local udp socket.
 pub struct UscRegistry;
 
 impl UscRegistry {
     /// Get the exist [`ArcUsc`] which bound the given [`SocketAddr`], or crate one.
     ///
+    /// The `bind` function is called to bind the socket when a new [`ArcUsc`] need to be created,
+    /// read [`SocketFactory`] for more details.
+    ///
     /// The `recv_task` generate a future task to receive and process datagrams from the udp socket,
-    /// the task spawned must take the ownership of the [`ArcUsc`], and dont drop it until a udp error
-    /// occur.
+    /// the task spawned on the `runtime` must take the ownership of the [`ArcUsc`], and dont drop it
+    /// until a udp error occur.
     ///
     /// When the [`ArcUsc`] is no longer used, the task spawned will be aborted, and the bound address
     /// will be free automatically.
@@ -36,14 +61,20 @@ impl UscRegistry {
     ///
     /// For server, the address will not be freed until the address is unbined by server, or the server
     /// is closed.
-    pub fn get_or_create_usc<Task, F>(addr: SocketAddr, recv_task: F) -> io::Result<ArcUsc>
+    pub fn get_or_create_usc<B, Task, F>(
+        addr: SocketAddr,
+        bind: B,
+        runtime: &dyn Runtime,
+        recv_task: F,
+    ) -> io::Result<ArcUsc>
     where
+        B: FnOnce(SocketAddr) -> io::Result<Arc<dyn AsyncUdpSocket>>,
         Task: Future<Output = ()> + Send + 'static,
         F: FnOnce(ArcUsc) -> Task,
     {
         // for port 0, its always create a new usc
         if addr.port() == 0 {
-            return UscRegistry::create_new_usc(addr, recv_task);
+            return UscRegistry::create_new_usc(addr, bind, runtime, recv_task);
         }
 
         // for other ports, lock the entry for avoiding the racing condition
@@ -52,12 +83,12 @@ impl UscRegistry {
             return Ok(entry.get().0.clone());
         }
 
-        let usc = Arc::new(qudp::UdpSocketController::new(addr)?);
+        let usc = bind(addr)?;
         let addr = usc.local_addr()?;
 
         let usc = ArcUsc { usc, addr };
 
-        let recv_task = tokio::spawn(recv_task(usc.clone()));
+        let recv_task = spawn_recv_task(runtime, recv_task(usc.clone()));
         entry.insert((usc.clone(), recv_task));
 
         Ok(usc)
@@ -67,24 +98,43 @@ impl UscRegistry {
     ///
     /// This is similar to [`UscRegistry::get_or_create_usc`], but it will return an error if the
     /// address is already bound.
-    pub fn create_new_usc<Task, F>(addr: SocketAddr, recv_task: F) -> io::Result<ArcUsc>
+    pub fn create_new_usc<B, Task, F>(
+        addr: SocketAddr,
+        bind: B,
+        runtime: &dyn Runtime,
+        recv_task: F,
+    ) -> io::Result<ArcUsc>
     where
+        B: FnOnce(SocketAddr) -> io::Result<Arc<dyn AsyncUdpSocket>>,
         Task: Future<Output = ()> + Send + 'static,
         F: FnOnce(ArcUsc) -> Task,
     {
-        let usc = Arc::new(qudp::UdpSocketController::new(addr)?);
+        let usc = bind(addr)?;
         let addr = usc.local_addr()?;
 
         let usc = ArcUsc { usc, addr };
 
-        let recv_task = tokio::spawn(recv_task(usc.clone()));
+        let recv_task = spawn_recv_task(runtime, recv_task(usc.clone()));
         USC_REGISTRY.insert(addr, (usc.clone(), recv_task));
 
         Ok(usc)
     }
 }
 
-/// A wrapper around the [`UdpSocketController`] that can be shared across threads.
+fn spawn_recv_task(
+    runtime: &dyn Runtime,
+    task: impl Future<Output = ()> + Send + 'static,
+) -> AbortHandle {
+    let (abort_handle, registration) = AbortHandle::new_pair();
+    let task = Abortable::new(task, registration);
+    runtime.spawn(Box::pin(async move {
+        _ = task.await;
+    }));
+    abort_handle
+}
+
+/// A wrapper around the [`AsyncUdpSocket`], such as the [`UdpSocketController`], that can be shared
+/// across threads.
 ///
 /// This struct also provide useful methods to send datagrams via a given [`Pathway`].
 ///
@@ -92,7 +142,7 @@ impl UscRegistry {
 #[derive(Debug, Clone, Deref)]
 pub struct ArcUsc {
     #[deref]
-    usc: Arc<qudp::UdpSocketController>,
+    usc: Arc<dyn AsyncUdpSocket>,
     addr: SocketAddr,
 }
 
@@ -102,26 +152,32 @@ impl ArcUsc {
         self.addr
     }
 
-    /// Poll send the datagrams via the given pathway.
+    /// Create a [`qudp::Receiver`] to receive datagrams from the udp socket.
+    pub fn receiver(&self) -> qudp::Receiver<'_> {
+        qudp::Receiver::new(self.usc.as_ref())
+    }
+
+    /// Poll send the datagrams via the given pathway, marked with the given ECN codepoint.
     pub fn poll_send_via(
         &self,
         cx: &mut Context,
         iovecs: &[IoSlice],
         pathway: Pathway,
+        ecn: Option<u8>,
     ) -> Poll<io::Result<usize>> {
         // todo: append relay hdr
         let hdr = qudp::PacketHeader {
             src: pathway.local_addr(),
             dst: pathway.dst_addr(),
             ttl: 64,
-            ecn: None,
+            ecn,
             seg_size: MSS as u16,
             gso: true,
         };
         self.usc.poll_send(iovecs, &hdr, cx)
     }
 
-    /// Send all the datagrams via the given pathway.
+    /// Send all the datagrams via the given pathway, marked with the given ECN codepoint.
     ///
     /// The returned future completes when all the datagrams are sent, or an error occurs(or occured)
     /// on udp socket.
@@ -129,11 +185,13 @@ impl ArcUsc {
         &'s self,
         iovecs: &'s [IoSlice<'s>],
         pathway: Pathway,
+        ecn: Option<u8>,
     ) -> SendAllViaPathWay<'s> {
         SendAllViaPathWay {
             usc: self,
             iovecs,
             pathway,
+            ecn,
         }
     }
 }
@@ -158,6 +216,7 @@ pub struct SendAllViaPathWay<'s> {
     usc: &'s ArcUsc,
     iovecs: &'s [IoSlice<'s>],
     pathway: Pathway,
+    ecn: Option<u8>,
 }
 
 impl Unpin for SendAllViaPathWay<'_> {}
@@ -169,7 +228,7 @@ impl Future for SendAllViaPathWay<'_> {
         let this = self.get_mut();
         let iovecs = &mut this.iovecs;
         while !iovecs.is_empty() {
-            let send_once = this.usc.poll_send_via(cx, iovecs, this.pathway);
+            let send_once = this.usc.poll_send_via(cx, iovecs, this.pathway, this.ecn);
             let n = ready!(send_once)?;
             *iovecs = &iovecs[n..];
         }
@@ -180,6 +239,7 @@ impl Future for SendAllViaPathWay<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::spawn::TokioRuntime;
 
     #[tokio::test]
     async fn bind() {
@@ -194,34 +254,53 @@ mod tests {
         {
             // bind unspecified
             // hold the usc or it will be dropped immediately.
-            let usc = UscRegistry::get_or_create_usc(unspecified, recv_task);
+            let usc = UscRegistry::get_or_create_usc(
+                unspecified,
+                bind_udp_socket,
+                &TokioRuntime,
+                recv_task,
+            );
             assert!(usc.is_ok());
             assert_eq!(USC_REGISTRY.len(), 1);
 
-            let usc = UscRegistry::get_or_create_usc(unspecified, recv_task);
+            let usc = UscRegistry::get_or_create_usc(
+                unspecified,
+                bind_udp_socket,
+                &TokioRuntime,
+                recv_task,
+            );
             assert!(usc.is_ok());
             assert_eq!(USC_REGISTRY.len(), 2);
 
-            let usc = UscRegistry::create_new_usc(unspecified, recv_task);
+            let usc =
+                UscRegistry::create_new_usc(unspecified, bind_udp_socket, &TokioRuntime, recv_task);
             assert!(usc.is_ok());
             assert_eq!(USC_REGISTRY.len(), 3);
 
-            let usc = UscRegistry::create_new_usc(unspecified, recv_task);
+            let usc =
+                UscRegistry::create_new_usc(unspecified, bind_udp_socket, &TokioRuntime, recv_task);
             assert!(usc.is_ok());
             assert_eq!(USC_REGISTRY.len(), 4);
 
             // bind specified, and reuse the address
-            let usc = UscRegistry::create_new_usc(specified, recv_task);
+            let usc =
+                UscRegistry::create_new_usc(specified, bind_udp_socket, &TokioRuntime, recv_task);
             assert!(usc.is_ok());
             assert_eq!(USC_REGISTRY.len(), 5);
 
             // faild beacuse the address is already bound
-            let usc = UscRegistry::create_new_usc(specified, recv_task);
+            let usc =
+                UscRegistry::create_new_usc(specified, bind_udp_socket, &TokioRuntime, recv_task);
             assert!(usc.is_err());
             assert_eq!(USC_REGISTRY.len(), 5);
 
             // its ok to get the exist usc
-            let usc = UscRegistry::get_or_create_usc(specified, recv_task);
+            let usc = UscRegistry::get_or_create_usc(
+                specified,
+                bind_udp_socket,
+                &TokioRuntime,
+                recv_task,
+            );
             assert!(usc.is_ok());
             assert_eq!(USC_REGISTRY.len(), 5);
         }
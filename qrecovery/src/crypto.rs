@@ -321,6 +321,7 @@ impl CryptoStream {
 
 #[cfg(test)]
 mod tests {
+    use futures::FutureExt;
     use qbase::{
         frame::{CryptoFrame, ReceiveFrame},
         varint::VarInt,
@@ -352,4 +353,67 @@ mod tests {
         crypto_stream.reader().read_exact(&mut buf).await.unwrap();
         assert_eq!(&buf[..], b"hello world");
     }
+
+    #[tokio::test]
+    async fn test_read_out_of_order() {
+        let crypto_stream = CryptoStream::new(0, 0);
+        let incoming = crypto_stream.incoming();
+        let mut reader = crypto_stream.reader();
+        let flight = (0..=255u8).collect::<Vec<_>>();
+        let frame = |offset: usize, len: usize| {
+            let crypto_frame = CryptoFrame {
+                offset: VarInt::try_from(offset).unwrap(),
+                length: VarInt::try_from(len).unwrap(),
+            };
+            let data = bytes::Bytes::copy_from_slice(&flight[offset..offset + len]);
+            (crypto_frame, data)
+        };
+
+        // 后半段先到，前半段缺失时不可读
+        incoming.recv_frame(&frame(192, 64)).unwrap();
+        incoming.recv_frame(&frame(128, 64)).unwrap();
+        let mut buf = [0u8; 256];
+        assert!(reader.read(&mut buf).now_or_never().is_none());
+
+        // 头部的连续数据一到达就交付，不必等待整个flight
+        incoming.recv_frame(&frame(0, 64)).unwrap();
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 64);
+        assert_eq!(buf[..64], flight[..64]);
+
+        // 补上空洞后，之前乱序到达的数据一并交付
+        incoming.recv_frame(&frame(64, 64)).unwrap();
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 192);
+        assert_eq!(buf[..192], flight[64..]);
+    }
+
+    #[tokio::test]
+    async fn test_read_overlapping_retransmission() {
+        let crypto_stream = CryptoStream::new(0, 0);
+        let incoming = crypto_stream.incoming();
+        let mut reader = crypto_stream.reader();
+        let flight = (0..=255u8).collect::<Vec<_>>();
+        let frame = |offset: usize, len: usize| {
+            let crypto_frame = CryptoFrame {
+                offset: VarInt::try_from(offset).unwrap(),
+                length: VarInt::try_from(len).unwrap(),
+            };
+            let data = bytes::Bytes::copy_from_slice(&flight[offset..offset + len]);
+            (crypto_frame, data)
+        };
+
+        incoming.recv_frame(&frame(100, 100)).unwrap();
+        // 重传的数据被重新切分，与已收到的数据部分重叠
+        incoming.recv_frame(&frame(50, 100)).unwrap();
+        incoming.recv_frame(&frame(150, 106)).unwrap();
+        incoming.recv_frame(&frame(0, 120)).unwrap();
+
+        // 重叠的部分只交付一次
+        let mut buf = [0u8; 512];
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 256);
+        assert_eq!(buf[..256], flight[..]);
+
+        // 已经交付过的数据再次到达，不会再被读到
+        incoming.recv_frame(&frame(0, 256)).unwrap();
+        assert!(reader.read(&mut buf).now_or_never().is_none());
+    }
 }
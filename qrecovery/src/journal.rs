@@ -72,4 +72,59 @@ mod tests {
             Ok(0)
         );
     }
+
+    #[test]
+    fn test_inflight_stream_frame() {
+        use qbase::{frame::StreamFrame, sid::StreamId, varint::VarInt};
+
+        use super::*;
+        let space = DataJournal::with_capacity(10);
+        let sent_journal = space.of_sent_packets();
+        let stream_frame = StreamFrame::new(StreamId::from(VarInt::from_u32(0)), 0, 5);
+        sent_journal
+            .new_packet()
+            .record_frame(GuaranteedFrame::Stream(stream_frame.clone()));
+
+        let inflight = sent_journal.inflight_packets();
+        assert_eq!(inflight.len(), 1);
+        let (pn, frames) = &inflight[0];
+        assert_eq!(*pn, 0);
+        assert_eq!(frames, &[GuaranteedFrame::Stream(stream_frame)]);
+    }
+
+    #[test]
+    fn test_data_space_ack() {
+        use std::time::Instant;
+
+        use super::*;
+        let space = DataJournal::with_capacity(10);
+        let rcvd_journal = space.of_rcvd_packets();
+        // 乱序收到数据包，3、4、7、8未收到
+        for pn in [1, 0, 9, 5, 2, 6] {
+            let pn = rcvd_journal.decode_pn(PacketNumber::encode(pn, 0)).unwrap();
+            rcvd_journal.register_pn(pn);
+        }
+        let ack = rcvd_journal
+            .gen_ack_frame_util(9, Instant::now(), 1000)
+            .unwrap();
+        assert_eq!(ack.iter().collect::<Vec<_>>(), [9..=9, 5..=6, 0..=2]);
+
+        // 生成的AckFrame不会超过指定的largest
+        let ack = rcvd_journal
+            .gen_ack_frame_util(6, Instant::now(), 1000)
+            .unwrap();
+        assert_eq!(ack.iter().collect::<Vec<_>>(), [5..=6, 0..=2]);
+
+        // 失活并滑走的记录不再出现在AckFrame中
+        {
+            let mut writer = rcvd_journal.write();
+            for pn in 0..5 {
+                writer.retire(pn);
+            }
+        }
+        let ack = rcvd_journal
+            .gen_ack_frame_util(9, Instant::now(), 1000)
+            .unwrap();
+        assert_eq!(ack.iter().collect::<Vec<_>>(), [9..=9, 5..=6]);
+    }
 }
@@ -4,18 +4,41 @@ use std::{
 };
 
 use qbase::{
-    frame::{io::WriteFrame, AckFrame},
+    frame::{io::WriteFrame, AckFrame, EcnCounts},
     packet::PacketNumber,
     util::IndexDeque,
     varint::{VarInt, VARINT_MAX},
 };
 use thiserror::Error;
 
+/// 收包记录的数目上限，超出后，最旧的那些已被反馈过足够多次的记录会被遗忘
+///
+/// 对方若从不确认我方的AckFrame，收包记录就无法失活滑走，需以此限制其内存占用。
+const MAX_RCVD_RECORDS: usize = 4096;
+
+/// 收包记录数目的硬上限，超出后，最旧的记录无论是否被反馈过，都会被遗忘
+///
+/// 收包记录空档过多时，一个AckFrame容不下所有的ACK Range，最旧的那些包可能永远不会被反馈，
+/// 只按反馈次数遗忘的话，收包记录仍会无限增长。
+const MAX_RCVD_RECORDS_HARD: usize = 4 * MAX_RCVD_RECORDS;
+
+/// 收到的包在这么多个AckFrame中反馈过之后，即便对方未确认这些AckFrame，也被认为对方已经知晓。
+/// 这是乱序容忍度的初始值，收到重复的包多了，容忍度会随之增大
+const ENOUGH_REPORTS: u8 = 3;
+
+/// 每收到这么多个重复的包，乱序容忍度增加1
+const DUPLICATES_PER_TOLERANCE: u64 = 8;
+
+/// 乱序容忍度的上限，以免收包记录因重放的包而一直无法遗忘
+const MAX_DISORDER_TOLERANCE: u8 = 16;
+
 /// Packet有收到/没收到2种状态，状态也有有效/失活2种状态，失活的可以滑走
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct State {
     is_active: bool,
     is_received: bool,
+    // 该包在多少个AckFrame中被反馈过
+    reported: u8,
 }
 
 impl Default for State {
@@ -23,6 +46,7 @@ impl Default for State {
         Self {
             is_active: true,
             is_received: false,
+            reported: 0,
         }
     }
 }
@@ -32,6 +56,7 @@ impl State {
         Self {
             is_active: true,
             is_received: true,
+            reported: 0,
         }
     }
 
@@ -48,22 +73,41 @@ pub enum InvalidPacketNumber {
     #[error("packet number too large")]
     TooLarge,
     #[error("packet with this number has been received")]
-    HasRcvd,
+    HasRcvd(u64),
 }
 
 /// 纯碎的一个收包记录，主要用于：
 /// - 记录包有无收到
 /// - 根据某个largest pktno，生成ack frame（ack frame不能超过buf大小）
 /// - 确定记录不再需要，可以被丢弃，滑走
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct RcvdJournal {
     queue: IndexDeque<State, VARINT_MAX>,
+    // 收到的包号重复的数据包数目，可能是网络重复，也可能是重放攻击
+    duplicate_packets: u64,
+    // 收到的包要在多少个AckFrame中反馈过，其记录才能在超出上限时被遗忘。
+    // 重复的包多，说明对方没能及时收到我方的AckFrame而重传，须更久地保留收包记录
+    disorder_tolerance: u8,
+    // 我方的ack_delay_exponent传输参数，AckFrame中的ACK Delay以2^ack_delay_exponent微秒为单位
+    ack_delay_exponent: u8,
+    // 收到的带ECN标记的数据包数目，尚未收到过带ECN标记的包时为None
+    ecn_counts: Option<EcnCounts>,
+}
+
+impl Default for RcvdJournal {
+    fn default() -> Self {
+        Self::with_capacity(0)
+    }
 }
 
 impl RcvdJournal {
     fn with_capacity(capacity: usize) -> Self {
         Self {
             queue: IndexDeque::with_capacity(capacity),
+            duplicate_packets: 0,
+            disorder_tolerance: ENOUGH_REPORTS,
+            ack_delay_exponent: 3,
+            ecn_counts: None,
         }
     }
 
@@ -78,11 +122,19 @@ impl RcvdJournal {
             is_received: true, ..
         }) = self.queue.get(pn)
         {
-            return Err(InvalidPacketNumber::HasRcvd);
+            return Err(InvalidPacketNumber::HasRcvd(pn));
         }
         Ok(pn)
     }
 
+    fn on_rcvd_duplicate(&mut self, pn: u64) {
+        self.duplicate_packets += 1;
+        let widened = self.duplicate_packets / DUPLICATES_PER_TOLERANCE;
+        self.disorder_tolerance =
+            (ENOUGH_REPORTS as u64 + widened).min(MAX_DISORDER_TOLERANCE as u64) as u8;
+        log::debug!("packet {pn} has been received, discard the duplicate one");
+    }
+
     fn on_rcvd_pn(&mut self, pn: u64) {
         if let Some(record) = self.queue.get_mut(pn) {
             record.is_received = true;
@@ -91,6 +143,40 @@ impl RcvdJournal {
                 .insert(pn, State::new_rcvd())
                 .expect("packet number never exceed limit");
         }
+        self.forget_oldest();
+    }
+
+    fn on_rcvd_ecn(&mut self, ecn: u8) {
+        // Not-ECT的包不计数
+        if ecn & 0b11 == 0 {
+            return;
+        }
+        let counts = self.ecn_counts.get_or_insert(EcnCounts {
+            ect0: VarInt::from_u32(0),
+            ect1: VarInt::from_u32(0),
+            ce: VarInt::from_u32(0),
+        });
+        let count = match ecn & 0b11 {
+            0b10 => &mut counts.ect0,
+            0b01 => &mut counts.ect1,
+            _ => &mut counts.ce,
+        };
+        *count = VarInt::from_u64(count.into_inner() + 1).expect("packets never exceed limit");
+    }
+
+    /// 记录超出上限时，遗忘最旧的那些记录，包括未收到的空档，以及已被反馈过足够多次的包。
+    /// 尚未被充分反馈的包则尽量保留，以免对方误以为其丢失，但记录总数不超过硬上限。
+    /// 被遗忘的包号之后再收到，会被当作太旧的包丢弃。
+    fn forget_oldest(&mut self) {
+        let excess = self.queue.len().saturating_sub(MAX_RCVD_RECORDS);
+        let n = self
+            .queue
+            .iter()
+            .take(excess)
+            .take_while(|s| !s.is_received || s.reported >= self.disorder_tolerance)
+            .count();
+        let hard_excess = self.queue.len().saturating_sub(MAX_RCVD_RECORDS_HARD);
+        self.queue.advance(n.max(hard_excess));
     }
 
     fn gen_ack_frame_util(
@@ -103,27 +189,40 @@ impl RcvdJournal {
             .queue
             .iter_with_idx()
             .rev()
-            .skip_while(|(pktno, _)| *pktno > largest);
+            .skip_while(|(pktno, _)| *pktno > largest)
+            .peekable();
 
-        // 注意assert中的next消耗掉一个单位，若要去掉assert，first_range还需减1
         assert!(
-            iter.by_ref()
-                .next()
+            iter.next()
                 .expect("largest in recv pkt records must be record")
                 .1
                 .is_received
         );
+        // 数一段连续的收到或未收到的包，不多消耗其后的包
+        let mut run = |is_received: bool| {
+            let mut count = 0usize;
+            while iter
+                .next_if(|(_, s)| s.is_received == is_received)
+                .is_some()
+            {
+                count += 1;
+            }
+            count
+        };
 
         let largest = VarInt::from_u64(largest).unwrap();
-        let delay = VarInt::from_u64(rcvd_time.elapsed().as_micros() as u64).unwrap();
+        let delay = rcvd_time.elapsed().as_micros() as u64 >> self.ack_delay_exponent;
+        let delay = VarInt::from_u64(delay).unwrap();
         // Minimum length with at least ACK frame type, largest, delay, range count, first_range (at least 1 byte for 0)
-        let min_len = 1 + largest.encoding_size() + delay.encoding_size() + 1 + 1;
+        // and the ECN counts if any packet marked with ECN has been received
+        let ecn_len = self.ecn_counts.map_or(0, |ecn| ecn.encoding_size());
+        let min_len = 1 + largest.encoding_size() + delay.encoding_size() + 1 + 1 + ecn_len;
         if capacity < min_len {
             return None;
         }
         capacity -= min_len;
 
-        let first_range = iter.by_ref().take_while(|(_, s)| s.is_received).count();
+        let first_range = run(true);
         let mut ack_range_count = 0u64;
         let mut ranges = Vec::with_capacity(16);
         loop {
@@ -141,18 +240,15 @@ impl RcvdJournal {
             }
             capacity -= additional_count_encoding;
 
-            if iter.next().is_none() {
+            // Gap和ACK Range Length字段都是实际包数减1
+            let gap = run(false);
+            let acked = run(true);
+            if gap == 0 || acked == 0 {
                 break;
             }
-            let gap = iter.by_ref().take_while(|(_, s)| !s.is_received).count();
 
-            if iter.next().is_none() {
-                break;
-            }
-            let acked = iter.by_ref().take_while(|(_, s)| s.is_received).count();
-
-            let gap = VarInt::try_from(gap).unwrap();
-            let acked = VarInt::try_from(acked).unwrap();
+            let gap = VarInt::try_from(gap - 1).unwrap();
+            let acked = VarInt::try_from(acked - 1).unwrap();
             if capacity < gap.encoding_size() + acked.encoding_size() {
                 break;
             }
@@ -167,19 +263,35 @@ impl RcvdJournal {
             delay,
             first_range: unsafe { VarInt::from_u64_unchecked(first_range as u64) },
             ranges,
-            ecn: None,
+            ecn: self.ecn_counts,
         })
     }
 
+    /// 生成AckFrame，并记下其中反馈的包又被反馈了一次
+    fn report_ack_frame_util(
+        &mut self,
+        largest: u64,
+        rcvd_time: Instant,
+        capacity: usize,
+    ) -> Option<AckFrame> {
+        let ack_frame = self.gen_ack_frame_util(largest, rcvd_time, capacity)?;
+        for pn in ack_frame.iter().flatten() {
+            if let Some(record) = self.queue.get_mut(pn) {
+                record.reported = record.reported.saturating_add(1);
+            }
+        }
+        Some(ack_frame)
+    }
+
     fn read_ack_frame_util(
-        &self,
+        &mut self,
         mut buf: &mut [u8],
         largest: u64,
         recv_time: Instant,
     ) -> Option<usize> {
         // TODO: 未来替换成，不用申请Vec先生成AckFrame，从largest往后开始成对生成
         let buf_len = buf.len();
-        let ack_frame = self.gen_ack_frame_util(largest, recv_time, buf_len)?;
+        let ack_frame = self.report_ack_frame_util(largest, recv_time, buf_len)?;
         buf.put_frame(&ack_frame);
         Some(buf_len - buf.len())
     }
@@ -197,8 +309,8 @@ impl RcvdJournal {
 }
 
 /// Records for received packets, decode the packet number and generate ack frames.
-// 接收数据包队列，各处共享的，判断包是否收到以及生成ack frame，只需要读锁；
-// 记录新收到的数据包，或者失活旧数据包并滑走，才需要写锁。
+// 接收数据包队列，各处共享的，判断包是否收到只需要读锁；
+// 记录新收到的数据包，生成ack frame（要记下包被反馈的次数），或者失活旧数据包并滑走，才需要写锁。
 #[derive(Debug, Clone, Default)]
 pub struct ArcRcvdJournal {
     inner: Arc<RwLock<RcvdJournal>>,
@@ -221,7 +333,8 @@ impl ArcRcvdJournal {
     /// for more details about decode packet number.
     ///
     /// If the packet is too old or has been received, or the pn is too big, this method will return
-    /// an error.
+    /// an error. The packet has been received may still be decrypted, to count it by
+    /// [`ArcRcvdJournal::register_duplicate`] only if it is authentic.
     ///
     /// Note that although the packet number successful decoded, it does not mean that the packet is
     /// valid, and the frames in it is valid.
@@ -244,6 +357,54 @@ impl ArcRcvdJournal {
         self.inner.write().unwrap().on_rcvd_pn(pn);
     }
 
+    /// Count a duplicate packet, whose packet number `pn` has been received, as
+    /// [`InvalidPacketNumber::HasRcvd`] tells.
+    ///
+    /// The duplicate packet must be successfully decrypted, the forged packets that can not be
+    /// decrypted are not counted. It is discarded without processing the frames in it.
+    pub fn register_duplicate(&self, pn: u64) {
+        self.inner.write().unwrap().on_rcvd_duplicate(pn);
+    }
+
+    /// Count the ECN codepoint of the packet just registered by [`ArcRcvdJournal::register_pn`].
+    ///
+    /// Once a packet marked with ECN has been received, the ack frames generated carry the ECN
+    /// counts, see [Section 13.4.1](https://www.rfc-editor.org/rfc/rfc9000.html#name-reporting-ecn-counts)
+    /// of RFC 9000.
+    pub fn register_ecn(&self, ecn: Option<u8>) {
+        if let Some(ecn) = ecn {
+            self.inner.write().unwrap().on_rcvd_ecn(ecn);
+        }
+    }
+
+    /// Set the ack_delay_exponent transport parameter of local, the `ACK Delay` field of the ack
+    /// frames generated is scaled by it, see [Section 19.3](https://www.rfc-editor.org/rfc/rfc9000.html#name-ack-frames)
+    /// of RFC 9000.
+    ///
+    /// It is 3 by default, the default value of the transport parameter.
+    pub fn set_ack_delay_exponent(&self, exponent: u8) {
+        self.inner.write().unwrap().ack_delay_exponent = exponent;
+    }
+
+    /// Return the number of the packets discarded because their packet number has been received.
+    ///
+    /// A packet reusing a packet number is never processed twice, it may be duplicated by the
+    /// network, or be a replay from an attacker. Only the duplicate packets registered by
+    /// [`ArcRcvdJournal::register_duplicate`] are counted.
+    pub fn duplicate_packets(&self) -> u64 {
+        self.inner.read().unwrap().duplicate_packets
+    }
+
+    /// Return how many ack frames a received packet must be reported in, before its record can be
+    /// forgotten once the records exceed the limit.
+    ///
+    /// It starts at 3, and grows as more duplicate packets are received, which means the peer
+    /// retransmits them because our ack frames are not received in time, so the records should be
+    /// kept longer on such a path.
+    pub fn disorder_tolerance(&self) -> u8 {
+        self.inner.read().unwrap().disorder_tolerance
+    }
+
     pub fn gen_ack_frame_util(
         &self,
         largest: u64,
@@ -251,16 +412,18 @@ impl ArcRcvdJournal {
         capacity: usize,
     ) -> Option<AckFrame> {
         self.inner
-            .read()
+            .write()
             .unwrap()
-            .gen_ack_frame_util(largest, rcvd_time, capacity)
+            .report_ack_frame_util(largest, rcvd_time, capacity)
     }
 
     /// Generate an ack frame which ack the received frames until `largest`.
     ///
     /// This method will write an ack frame into the `buf`. The `Ack Delay` field of the frame is
-    /// the argument `recv_time` as microsec, the `Largest Acknowledged` field of the frame is the
-    /// `largest` frame, the ranges in ack frame will not exceed `largest`.
+    /// the time elapsed since `recv_time`, scaled by the ack_delay_exponent set by
+    /// [`ArcRcvdJournal::set_ack_delay_exponent`]. The `Largest Acknowledged` field of the frame is
+    /// the `largest` frame, the ranges in ack frame will not exceed `largest`, and they are in
+    /// descending order.
     pub fn read_ack_frame_util(
         &self,
         buf: &mut [u8],
@@ -268,7 +431,7 @@ impl ArcRcvdJournal {
         recv_time: Instant,
     ) -> Option<usize> {
         self.inner
-            .read()
+            .write()
             .unwrap()
             .read_ack_frame_util(buf, largest, recv_time)
     }
@@ -302,6 +465,10 @@ impl Drop for ArcRcvdPktRecordsWriter<'_> {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use qbase::frame::BeFrame;
+
     use super::*;
 
     #[test]
@@ -317,14 +484,16 @@ mod tests {
             records.inner.read().unwrap().queue.get(0).unwrap(),
             &State {
                 is_active: true,
-                is_received: false
+                is_received: false,
+                reported: 0,
             }
         );
         assert_eq!(
             records.inner.read().unwrap().queue.get(1).unwrap(),
             &State {
                 is_active: true,
-                is_received: true
+                is_received: true,
+                reported: 0,
             }
         );
 
@@ -351,4 +520,169 @@ mod tests {
             Err(InvalidPacketNumber::TooOld)
         );
     }
+
+    #[test]
+    fn test_duplicate_packet() {
+        let records = ArcRcvdJournal::default();
+        let mut processed = 0;
+        // 同一个数据包被重放3次，只有第一次会被处理
+        for _ in 0..3 {
+            match records.decode_pn(PacketNumber::encode(3, 0)) {
+                Ok(pn) => {
+                    processed += 1;
+                    records.register_pn(pn);
+                }
+                // 重复的包解密成功后才计数
+                Err(InvalidPacketNumber::HasRcvd(pn)) => records.register_duplicate(pn),
+                Err(e) => panic!("unexpected error: {e}"),
+            }
+        }
+        assert_eq!(processed, 1);
+        assert_eq!(records.duplicate_packets(), 2);
+
+        // 包号重复但解密失败的包，可能是伪造的，不计数
+        assert_eq!(
+            records.decode_pn(PacketNumber::encode(3, 0)),
+            Err(InvalidPacketNumber::HasRcvd(3))
+        );
+        assert_eq!(records.duplicate_packets(), 2);
+
+        // 尚未被注册的包号，比如解密失败，不算重复
+        assert_eq!(records.decode_pn(PacketNumber::encode(2, 0)), Ok(2));
+        assert_eq!(records.decode_pn(PacketNumber::encode(2, 0)), Ok(2));
+        assert_eq!(records.duplicate_packets(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_packets_widen_disorder_tolerance() {
+        let records = ArcRcvdJournal::default();
+        assert_eq!(records.disorder_tolerance(), ENOUGH_REPORTS);
+        records.register_pn(0);
+        records.register_pn(1);
+
+        let duplicate = |pn: u64, times: u64| {
+            for _ in 0..times {
+                assert_eq!(
+                    records.decode_pn(PacketNumber::encode(pn, 0)),
+                    Err(InvalidPacketNumber::HasRcvd(pn))
+                );
+                records.register_duplicate(pn);
+            }
+        };
+        // 重复的包不够多时，容忍度不变
+        duplicate(0, DUPLICATES_PER_TOLERANCE - 1);
+        assert_eq!(records.disorder_tolerance(), ENOUGH_REPORTS);
+        duplicate(1, 1);
+        assert_eq!(records.disorder_tolerance(), ENOUGH_REPORTS + 1);
+        duplicate(0, DUPLICATES_PER_TOLERANCE);
+        assert_eq!(records.disorder_tolerance(), ENOUGH_REPORTS + 2);
+
+        // 容忍度有上限
+        duplicate(1, DUPLICATES_PER_TOLERANCE * MAX_DISORDER_TOLERANCE as u64);
+        assert_eq!(records.disorder_tolerance(), MAX_DISORDER_TOLERANCE);
+    }
+
+    #[test]
+    fn test_gen_ack_frame() {
+        let records = ArcRcvdJournal::default();
+        for pn in [0, 1, 3, 4] {
+            records.register_pn(pn);
+        }
+        let rcvd_time = Instant::now() - Duration::from_millis(80);
+        let ack_frame = records.gen_ack_frame_util(4, rcvd_time, 1200).unwrap();
+        assert_eq!(ack_frame.largest, VarInt::from_u32(4));
+        // 3、4号包连续收到，之后缺失了2号包，再之前收到了0、1号包
+        assert_eq!(ack_frame.first_range, VarInt::from_u32(1));
+        assert_eq!(
+            ack_frame.ranges,
+            vec![(VarInt::from_u32(0), VarInt::from_u32(1))]
+        );
+        assert_eq!(ack_frame.iter().collect::<Vec<_>>(), vec![3..=4, 0..=1]);
+        // 默认的ack_delay_exponent为3，ACK Delay以8微秒为单位
+        let delay = ack_frame.delay.into_inner();
+        assert!((10000..10000 + 1000 / 8).contains(&delay));
+
+        records.set_ack_delay_exponent(0);
+        let ack_frame = records.gen_ack_frame_util(4, rcvd_time, 1200).unwrap();
+        let delay = ack_frame.delay.into_inner();
+        assert!((80000..80000 + 1000).contains(&delay));
+    }
+
+    #[test]
+    fn test_ecn_counts_in_ack_frame() {
+        let records = ArcRcvdJournal::default();
+        records.register_pn(0);
+        records.register_ecn(None);
+        let ack_frame = records.gen_ack_frame_util(0, Instant::now(), 1200).unwrap();
+        assert_eq!(ack_frame.ecn, None);
+
+        // 一旦收到了带ECN标记的包，AckFrame就要携带ECN计数，Not-ECT的包不计数
+        for (pn, ecn) in [(1, 0b10), (2, 0b11), (3, 0b10), (4, 0b00)] {
+            records.register_pn(pn);
+            records.register_ecn(Some(ecn));
+        }
+        let mut buf = [0u8; 1200];
+        let n = records
+            .read_ack_frame_util(&mut buf, 4, Instant::now())
+            .unwrap();
+        let ack_frame = records.gen_ack_frame_util(4, Instant::now(), 1200).unwrap();
+        assert_eq!(
+            ack_frame.ecn,
+            Some(EcnCounts {
+                ect0: VarInt::from_u32(2),
+                ect1: VarInt::from_u32(0),
+                ce: VarInt::from_u32(1),
+            })
+        );
+        assert_eq!(buf[0], 0x03);
+        assert_eq!(n, ack_frame.encoding_size());
+    }
+
+    #[test]
+    fn test_bounded_rcvd_records() {
+        let records = ArcRcvdJournal::default();
+        let len = || records.inner.read().unwrap().queue.len();
+        let mut buf = [0u8; 1200];
+        // 对方每隔一个包号发送一个包，且从不确认我方的AckFrame，收包记录无法失活滑走
+        for pn in (0..4 * MAX_RCVD_RECORDS as u64).step_by(2) {
+            assert_eq!(records.decode_pn(PacketNumber::encode(pn, 0)), Ok(pn));
+            records.register_pn(pn);
+            records.read_ack_frame_util(&mut buf, pn, Instant::now());
+            assert!(len() <= MAX_RCVD_RECORDS + 1);
+        }
+        // 被遗忘的包号再收到，作为太旧的包丢弃
+        let forgotten = records.inner.read().unwrap().queue.offset() - 1;
+        assert_eq!(
+            records.decode_pn(PacketNumber::encode(forgotten, 0)),
+            Err(InvalidPacketNumber::TooOld)
+        );
+    }
+
+    #[test]
+    fn test_keep_unreported_records() {
+        let records = ArcRcvdJournal::default();
+        let len = || records.inner.read().unwrap().queue.len();
+        // 尚未在AckFrame中反馈过的包，超出上限后仍被保留，直到硬上限
+        let largest = 2 * MAX_RCVD_RECORDS as u64;
+        for pn in (0..=largest).step_by(2) {
+            records.register_pn(pn);
+        }
+        assert_eq!(len(), largest as usize + 1);
+
+        // 一个AckFrame容不下所有的ACK Range，只能反馈最近的那些包，最旧的包永远不会被反馈
+        let mut buf = [0u8; 1200];
+        for pn in (largest + 2..=2 * MAX_RCVD_RECORDS_HARD as u64).step_by(2) {
+            records.register_pn(pn);
+            if pn % 64 == 0 {
+                records.read_ack_frame_util(&mut buf, pn, Instant::now());
+            }
+            assert!(len() <= MAX_RCVD_RECORDS_HARD);
+        }
+        // 最新的那些包仍有记录
+        let largest = 2 * MAX_RCVD_RECORDS_HARD as u64;
+        assert_eq!(
+            records.decode_pn(PacketNumber::encode(largest, largest - 1)),
+            Err(InvalidPacketNumber::HasRcvd(largest))
+        );
+    }
 }
@@ -5,7 +5,13 @@ use std::{
 };
 
 use deref_derive::{Deref, DerefMut};
-use qbase::{packet::PacketNumber, util::IndexDeque, varint::VARINT_MAX};
+use qbase::{
+    error::{Error, ErrorKind},
+    frame::{AckFrame, BeFrame},
+    packet::PacketNumber,
+    util::IndexDeque,
+    varint::VARINT_MAX,
+};
 
 /// 记录发送的数据包的状态，包括
 /// - Flighting: 数据包正在传输中
@@ -110,10 +116,11 @@ impl<T> SentJournal<T> {
     }
 
     fn auto_drain(&mut self) {
+        // 只能移除头部连续的、已确认或已丢失的数据包，否则会把仍在传输中的数据包的帧一并移除
         let (n, f) = self
             .records
             .iter()
-            .filter(|s| !matches!(s, SentPktState::Flighting(_)))
+            .take_while(|s| !matches!(s, SentPktState::Flighting(_)))
             .fold((0usize, 0usize), |(n, f), s| (n + 1, f + s.nframes()));
         self.records.advance(n);
         let _ = self.queue.drain(..f);
@@ -174,6 +181,29 @@ impl<T> ArcSentJournal<T> {
     }
 }
 
+#[cfg(any(test, feature = "inflight-dump"))]
+impl<T: Clone> ArcSentJournal<T> {
+    /// Return the packets in flight, neither acknowledged nor may lost, with the frames in them,
+    /// for debugging the stalled connection.
+    ///
+    /// Each item is the packet number and the frames recorded by [`NewPacketGuard::record_frame`],
+    /// a packet with only trivial frames has no frames.
+    pub fn inflight_packets(&self) -> Vec<(u64, Vec<T>)> {
+        let inner = self.0.lock().unwrap();
+        let mut offset = 0;
+        let mut packets = Vec::new();
+        for (pn, state) in inner.records.iter_with_idx() {
+            let nframes = state.nframes();
+            if let SentPktState::Flighting(_) = state {
+                let frames = inner.queue.range(offset..offset + nframes).cloned();
+                packets.push((pn, frames.collect()));
+            }
+            offset += nframes;
+        }
+        packets
+    }
+}
+
 /// Handle the peer's ack frame and feed back the frames in the acknowledged or possibly lost packets to other components.
 pub struct RotateGuard<'a, T> {
     inner: MutexGuard<'a, SentJournal<T>>,
@@ -182,14 +212,34 @@ pub struct RotateGuard<'a, T> {
 impl<T: Clone> RotateGuard<'_, T> {
     /// Handle the [`Largest Acknowledged`] field of the ack frame from peer.
     ///
+    /// The largest acknowledged packet number never regresses, an out of order or duplicate ack
+    /// frame with a smaller largest acknowledged will be ignored.
+    ///
+    /// An endpoint that acknowledges a packet never sent is a connection error of type
+    /// PROTOCOL_VIOLATION. All the packet numbers in the ack frame are not larger than the
+    /// largest acknowledged, so checking it is enough, the frame must not be handled further if
+    /// an error is returned.
+    ///
     /// [`Largest Acknowleged`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-ack-frames
-    pub fn update_largest(&mut self, largest: u64) {
+    pub fn update_largest(&mut self, ack_frame: &AckFrame) -> Result<(), Error> {
+        let largest = ack_frame.largest.into_inner();
+        if largest >= self.inner.records.largest() {
+            return Err(Error::new(
+                ErrorKind::ProtocolViolation,
+                ack_frame.frame_type(),
+                format!("acknowledged packet {largest} has never been sent"),
+            ));
+        }
         if largest > self.inner.largest_acked_pktno {
             self.inner.largest_acked_pktno = largest;
         }
+        Ok(())
     }
 
     /// Called when the packet sent is acked by peer, return the frames in that packet.
+    ///
+    /// It is idempotent, the packet that has been acked yields no frames, so that the duplicate
+    /// ack frames will not feed back the frames repeatedly.
     pub fn on_pkt_acked(&mut self, pn: u64) -> impl Iterator<Item = T> + '_ {
         self.inner.on_pkt_acked(pn)
     }
@@ -272,3 +322,119 @@ impl<T> Drop for NewPacketGuard<'_, T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use qbase::varint::VarInt;
+
+    use super::*;
+
+    fn ack_frame(largest: u64) -> AckFrame {
+        AckFrame {
+            largest: VarInt::from_u64(largest).unwrap(),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        }
+    }
+
+    fn send_packets(journal: &ArcSentJournal<u64>, npkts: u64, nframes: u64) {
+        for pn in 0..npkts {
+            let mut guard = journal.new_packet();
+            for i in 0..nframes {
+                guard.record_frame(pn * nframes + i);
+            }
+        }
+    }
+
+    fn on_ack(journal: &ArcSentJournal<u64>, largest: u64, pns: &[u64]) -> Vec<u64> {
+        let mut guard = journal.rotate();
+        guard.update_largest(&ack_frame(largest)).unwrap();
+        pns.iter()
+            .flat_map(|pn| guard.on_pkt_acked(*pn).collect::<Vec<_>>())
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicate_ack() {
+        let journal = ArcSentJournal::with_capacity(8);
+        send_packets(&journal, 4, 2);
+
+        assert_eq!(on_ack(&journal, 3, &[3, 2]), vec![6, 7, 4, 5]);
+        // the same ack frame again, no frames should be acked twice
+        assert_eq!(on_ack(&journal, 3, &[3, 2]), Vec::<u64>::new());
+        assert_eq!(on_ack(&journal, 1, &[1, 0]), vec![2, 3, 0, 1]);
+        assert_eq!(on_ack(&journal, 3, &[3, 2, 1, 0]), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_ack_after_partially_acked() {
+        let journal = ArcSentJournal::with_capacity(8);
+        send_packets(&journal, 3, 1);
+
+        // the packet 0 is still in flight, its frames must not be drained
+        assert_eq!(on_ack(&journal, 1, &[1]), vec![1]);
+        assert_eq!(on_ack(&journal, 1, &[1]), Vec::<u64>::new());
+        assert_eq!(on_ack(&journal, 2, &[2, 0]), vec![2, 0]);
+        assert_eq!(on_ack(&journal, 2, &[2, 1, 0]), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_largest_acked_never_regresses() {
+        let journal = ArcSentJournal::<u64>::with_capacity(8);
+        send_packets(&journal, 4, 1);
+
+        journal.rotate().update_largest(&ack_frame(3)).unwrap();
+        journal.rotate().update_largest(&ack_frame(1)).unwrap();
+        assert_eq!(journal.0.lock().unwrap().largest_acked_pktno, 3);
+    }
+
+    #[test]
+    fn test_inflight_packets() {
+        let journal = ArcSentJournal::<u64>::with_capacity(8);
+        send_packets(&journal, 3, 2);
+        journal.new_packet().record_trivial();
+
+        assert_eq!(on_ack(&journal, 1, &[1]), vec![2, 3]);
+        assert_eq!(
+            journal.inflight_packets(),
+            vec![(0, vec![0, 1]), (2, vec![4, 5]), (3, vec![])]
+        );
+        journal.rotate().may_loss_pkt(0).for_each(drop);
+        assert_eq!(
+            journal.inflight_packets(),
+            vec![(2, vec![4, 5]), (3, vec![])]
+        );
+    }
+
+    #[test]
+    fn test_ack_unsent_packet() {
+        let journal = ArcSentJournal::<u64>::with_capacity(8);
+        // 尚未发送任何数据包
+        let error = journal.rotate().update_largest(&ack_frame(0)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ProtocolViolation);
+
+        send_packets(&journal, 4, 1);
+        let error = journal.rotate().update_largest(&ack_frame(4)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ProtocolViolation);
+        assert_eq!(journal.0.lock().unwrap().largest_acked_pktno, 0);
+        assert!(journal.rotate().update_largest(&ack_frame(3)).is_ok());
+    }
+
+    #[test]
+    fn test_ack_range_past_largest_sent() {
+        let journal = ArcSentJournal::<u64>::with_capacity(8);
+        send_packets(&journal, 4, 1);
+
+        // 确认2..=6，其中4..=6从未发送，整个AckFrame都不被处理
+        let mut frame = ack_frame(6);
+        frame.first_range = VarInt::from_u32(4);
+        let error = journal.rotate().update_largest(&frame).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ProtocolViolation);
+        assert_eq!(journal.0.lock().unwrap().largest_acked_pktno, 0);
+
+        // 未曾发送的包号，即使被误传入，也不会越界
+        assert_eq!(on_ack(&journal, 3, &[3, 2, 7]), vec![3, 2]);
+    }
+}
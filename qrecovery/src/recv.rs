@@ -6,5 +6,5 @@ mod recver;
 
 pub use incoming::Incoming;
 pub use rcvbuf::RecvBuf;
-pub use reader::Reader;
+pub use reader::{ReadStatus, Reader};
 pub use recver::ArcRecver;
@@ -64,6 +64,7 @@ where
                 }
             }
         }
+        self.0.on_received(fresh_data);
         Ok((is_into_rcvd, fresh_data))
     }
 
@@ -71,8 +72,17 @@ where
     ///
     /// If all data sent by the peer has not been received, receiving a stream reset frame will cause
     /// any read calls to return an error, received data will be discarded.
+    ///
+    /// The error returned by the [`Reader`] wraps a [`ResetStreamError`] carrying the application
+    /// error code of the frame, such as the HTTP/3 error code.
+    ///
+    /// Return a [`FinalSize`] error if the final size of the frame is smaller than the data
+    /// received, or differs from the final size known before.
+    ///
+    /// [`Reader`]: crate::recv::Reader
+    /// [`ResetStreamError`]: qbase::frame::ResetStreamError
+    /// [`FinalSize`]: qbase::error::ErrorKind::FinalSize
     pub fn recv_reset(&self, reset_frame: &ResetStreamFrame) -> Result<(), QuicError> {
-        // TODO: ResetStream中还有错误信息，比如http3的错误码，看是否能用到
         let mut recver = self.0.recver();
         let inner = recver.deref_mut();
         if let Ok(receiving_state) = inner {
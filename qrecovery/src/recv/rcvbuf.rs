@@ -349,6 +349,46 @@ impl RecvBuf {
         Some(origin - buf.remaining_mut())
     }
 
+    /// Copy the continuous data into `buf` without consuming them, return the size of data copied.
+    ///
+    /// Only the continuous data starting from the read offset could be peeked, just like
+    /// [`RecvBuf::try_read`], but the read offset will not be advanced, so the next peek or read
+    /// will get the same data.
+    ///
+    /// # Example
+    ///
+    /// ``` rust
+    /// # use bytes::{Bytes, BytesMut};
+    /// # use qrecovery::recv::RecvBuf;
+    /// let mut recvbuf = RecvBuf::default();
+    /// recvbuf.recv(0, Bytes::from("hello"));
+    /// recvbuf.recv(6, Bytes::from("world"));
+    ///
+    /// let mut peeked = [0u8; 8];
+    /// assert_eq!(recvbuf.peek(&mut &mut peeked[..]), Some(5));
+    /// assert_eq!(&peeked[..5], b"hello");
+    /// assert_eq!(recvbuf.nread(), 0);
+    ///
+    /// let mut dst = BytesMut::new();
+    /// recvbuf.try_read(&mut dst);
+    /// assert_eq!(dst.as_ref(), b"hello");
+    /// ```
+    pub fn peek(&self, buf: &mut impl BufMut) -> Option<usize> {
+        let seg = self.segments.front()?;
+        if seg.offset != self.nread {
+            return None;
+        }
+        let origin = buf.remaining_mut();
+        for frag in &seg.fragments {
+            let n = buf.remaining_mut().min(frag.len());
+            buf.put_slice(&frag[..n]);
+            if n < frag.len() {
+                break;
+            }
+        }
+        Some(origin - buf.remaining_mut())
+    }
+
     /// The length of continuous data received, which can be compared with the final sizeknown as `SizeKnown`.
     ///
     /// If they match, it indicates that all the data has been received.
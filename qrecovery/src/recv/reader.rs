@@ -65,6 +65,17 @@ use super::recver::{ArcRecver, Recver};
 #[derive(Debug)]
 pub struct Reader<TX>(pub(crate) ArcRecver<TX>);
 
+/// How a receiving stream ended, see [`Reader::status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadStatus {
+    /// The peer finished the stream with FIN, and all data has been read.
+    Finished,
+    /// The peer aborted the stream with a [`RESET_STREAM frame`] carrying the error code.
+    ///
+    /// [`RESET_STREAM frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-reset_stream-frames
+    Reset(u64),
+}
+
 impl<TX> Reader<TX>
 where
     TX: SendFrame<StopSendingFrame>,
@@ -95,6 +106,84 @@ where
     }
 }
 
+impl<TX> Reader<TX> {
+    /// Return the number of bytes received from the peer on the stream.
+    ///
+    /// The data received repeatedly is counted only once, the data received out of order is
+    /// counted even it can not be read yet.
+    pub fn bytes_received(&self) -> u64 {
+        self.0.received()
+    }
+
+    /// Return the number of bytes read from the stream by the application.
+    pub fn bytes_consumed(&self) -> u64 {
+        self.0.consumed()
+    }
+
+    /// Return how the stream ended, or `None` if the stream is still in progress.
+    ///
+    /// A stream finished with FIN is considered ended only after all its data has been read, while
+    /// a stream reset by the peer ends as soon as the [`RESET_STREAM frame`] is received, the data
+    /// not read yet is discarded. If the connection is closed, the stream does not end up with
+    /// either status, `None` is returned.
+    ///
+    /// [`RESET_STREAM frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-reset_stream-frames
+    pub fn status(&self) -> Option<ReadStatus> {
+        match self.0.recver().as_ref().ok()? {
+            Recver::DataRead => Some(ReadStatus::Finished),
+            Recver::ResetRcvd(reset) | Recver::ResetRead(reset) => {
+                Some(ReadStatus::Reset(reset.error_code()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Attempt to copy the received continuous data into `buf` without consuming them.
+    ///
+    /// Unlike [`poll_read`], the data peeked remain in the stream, so that a subsequent read or
+    /// peek returns the same bytes. It's useful for protocol parsers to inspect the upcoming bytes.
+    /// Only the continuous data is exposed, the out-of-order data would not be peeked until all
+    /// the data before it has been received.
+    ///
+    /// Like [`poll_read`], if no data is available, the current task will be woken up when
+    /// some data is available; it returns `Ok(0)` after all data has been read, or an error if
+    /// the stream has been reset.
+    ///
+    /// [`poll_read`]: AsyncRead::poll_read
+    pub fn poll_peek(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<usize>> {
+        let mut recver = self.0.recver();
+        let receiving_state = recver.as_mut().map_err(|e| e.clone())?;
+        let origin = buf.filled().len();
+        let peeked = match receiving_state {
+            Recver::Recv(r) => r.poll_peek(cx, buf),
+            Recver::SizeKnown(r) => r.poll_peek(cx, buf),
+            Recver::DataRcvd(r) => {
+                r.poll_peek(buf);
+                Poll::Ready(Ok(()))
+            }
+            Recver::DataRead => Poll::Ready(Ok(())),
+            // 和读不同，窥视不会改变接收状态
+            Recver::ResetRcvd(reset) | Recver::ResetRead(reset) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+            }
+        };
+        peeked.map_ok(|()| buf.filled().len() - origin)
+    }
+
+    /// Copy the received continuous data into `buf` without consuming them, return the size of
+    /// data copied.
+    ///
+    /// See [`Reader::poll_peek`] for more details.
+    pub async fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buf = ReadBuf::new(buf);
+        std::future::poll_fn(|cx| self.poll_peek(cx, &mut buf)).await
+    }
+}
+
 impl<TX> AsyncRead for Reader<TX>
 where
     TX: SendFrame<MaxStreamDataFrame>,
@@ -106,8 +195,9 @@ where
     ) -> Poll<io::Result<()>> {
         let mut recver = self.0.recver();
         let receiving_state = recver.as_mut().map_err(|e| e.clone())?;
+        let filled = buf.filled().len();
         // 能相当清楚地看到应用层读取数据驱动的接收状态演变
-        match receiving_state {
+        let poll = match receiving_state {
             Recver::Recv(r) => r.poll_read(cx, buf),
             Recver::SizeKnown(r) => r.poll_read(cx, buf),
             Recver::DataRcvd(r) => {
@@ -126,7 +216,9 @@ where
             Recver::ResetRead(reset) => {
                 Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
             }
-        }
+        };
+        self.0.on_consumed(buf.filled().len() - filled);
+        poll
     }
 }
 
@@ -143,3 +235,167 @@ impl<TX> Drop for Reader<TX> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use futures::FutureExt;
+    use qbase::{
+        error::ErrorKind,
+        frame::{ResetStreamError, ResetStreamFrame, StreamFrame},
+        sid::StreamId,
+        varint::VarInt,
+    };
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::recv::Incoming;
+
+    #[derive(Debug, Clone)]
+    struct FramesTx;
+
+    impl SendFrame<StopSendingFrame> for FramesTx {
+        fn send_frame<I: IntoIterator<Item = StopSendingFrame>>(&self, _iter: I) {}
+    }
+
+    impl SendFrame<MaxStreamDataFrame> for FramesTx {
+        fn send_frame<I: IntoIterator<Item = MaxStreamDataFrame>>(&self, _iter: I) {}
+    }
+
+    #[tokio::test]
+    async fn test_peek_then_read() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let recver = ArcRecver::new(sid, 1024, FramesTx);
+        let incoming = Incoming::new(recver.clone());
+        let mut reader = Reader(recver);
+
+        // "world" is out of order, only "hello" could be peeked
+        let mut frame = StreamFrame::new(sid, 6, 5);
+        frame.set_eos_flag(true);
+        incoming.recv_data(&frame, Bytes::from("world")).unwrap();
+        let frame = StreamFrame::new(sid, 0, 5);
+        incoming.recv_data(&frame, Bytes::from("hello")).unwrap();
+
+        let mut peeked = [0u8; 16];
+        assert_eq!(reader.peek(&mut peeked).await.unwrap(), 5);
+        assert_eq!(&peeked[..5], b"hello");
+        // peek does not advance the read offset
+        assert_eq!(reader.peek(&mut peeked[..3]).await.unwrap(), 3);
+        assert_eq!(&peeked[..3], b"hel");
+
+        let mut read = [0u8; 16];
+        assert_eq!(reader.read(&mut read).await.unwrap(), 5);
+        assert_eq!(read[..5], peeked[..5]);
+
+        let frame = StreamFrame::new(sid, 5, 1);
+        incoming.recv_data(&frame, Bytes::from(" ")).unwrap();
+        assert_eq!(reader.peek(&mut peeked).await.unwrap(), 6);
+        assert_eq!(&peeked[..6], b" world");
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).await.unwrap();
+        assert_eq!(rest, " world");
+        assert_eq!(reader.peek(&mut peeked).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_bytes_received_and_consumed() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let recver = ArcRecver::new(sid, 1024, FramesTx);
+        let incoming = Incoming::new(recver.clone());
+        let mut reader = Reader(recver);
+
+        let mut frame = StreamFrame::new(sid, 6, 5);
+        frame.set_eos_flag(true);
+        incoming.recv_data(&frame, Bytes::from("world")).unwrap();
+        let frame = StreamFrame::new(sid, 0, 5);
+        incoming.recv_data(&frame, Bytes::from("hello")).unwrap();
+        // 重复收到的数据不计入
+        incoming.recv_data(&frame, Bytes::from("hello")).unwrap();
+        assert_eq!((reader.bytes_received(), reader.bytes_consumed()), (10, 0));
+
+        let mut read = [0u8; 16];
+        assert_eq!(reader.read(&mut read).await.unwrap(), 5);
+        assert_eq!(reader.bytes_consumed(), 5);
+
+        let frame = StreamFrame::new(sid, 5, 1);
+        incoming.recv_data(&frame, Bytes::from(" ")).unwrap();
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).await.unwrap();
+        // 进入DataRead之后仍保留计数
+        assert_eq!((reader.bytes_received(), reader.bytes_consumed()), (11, 11));
+    }
+
+    #[tokio::test]
+    async fn test_finished_status() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let recver = ArcRecver::new(sid, 1024, FramesTx);
+        let incoming = Incoming::new(recver.clone());
+        let mut reader = Reader(recver);
+
+        let mut frame = StreamFrame::new(sid, 0, 5);
+        frame.set_eos_flag(true);
+        incoming.recv_data(&frame, Bytes::from("hello")).unwrap();
+        // 数据都已收到，但还未读完
+        assert_eq!(reader.status(), None);
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data, b"hello");
+        assert_eq!(reader.status(), Some(ReadStatus::Finished));
+    }
+
+    #[tokio::test]
+    async fn test_reset_status() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let recver = ArcRecver::new(sid, 1024, FramesTx);
+        let incoming = Incoming::new(recver.clone());
+        let mut reader = Reader(recver);
+
+        let frame = StreamFrame::new(sid, 0, 5);
+        incoming.recv_data(&frame, Bytes::from("hello")).unwrap();
+        assert_eq!(reader.status(), None);
+
+        incoming
+            .recv_reset(&ResetStreamFrame {
+                stream_id: sid,
+                app_error_code: VarInt::from_u32(7),
+                final_size: VarInt::from_u32(5),
+            })
+            .unwrap();
+        assert_eq!(reader.status(), Some(ReadStatus::Reset(7)));
+        let mut data = Vec::new();
+        assert!(reader.read_to_end(&mut data).await.is_err());
+        assert_eq!(reader.status(), Some(ReadStatus::Reset(7)));
+    }
+
+    #[tokio::test]
+    async fn test_reset_while_reading() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let recver = ArcRecver::new(sid, 1024, FramesTx);
+        let incoming = Incoming::new(recver.clone());
+        let mut reader = Reader(recver);
+
+        let frame = StreamFrame::new(sid, 0, 5);
+        incoming.recv_data(&frame, Bytes::from("hello")).unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 5);
+        // 数据读完了，等待更多的数据
+        let mut read = Box::pin(async { reader.read(&mut buf).await });
+        assert!((&mut read).now_or_never().is_none());
+
+        // 最终大小小于已收到的数据，是对端的错误
+        let reset = |final_size| ResetStreamFrame {
+            stream_id: sid,
+            app_error_code: VarInt::from_u32(0x10c),
+            final_size: VarInt::from_u32(final_size),
+        };
+        let error = incoming.recv_reset(&reset(3)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::FinalSize);
+
+        // 正在进行的读取被唤醒，得到对端给出的错误码
+        incoming.recv_reset(&reset(8)).unwrap();
+        let error = read.await.unwrap_err();
+        let reset = error.get_ref().unwrap().downcast_ref::<ResetStreamError>();
+        assert_eq!(reset.map(ResetStreamError::error_code), Some(0x10c));
+    }
+}
@@ -1,6 +1,9 @@
 use std::{
     io,
-    sync::{Arc, Mutex, MutexGuard},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
     task::{Context, Poll, Waker},
 };
 
@@ -144,6 +147,20 @@ impl<TX> Recv<TX> {
         Ok(final_size)
     }
 
+    pub(super) fn poll_peek(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut impl BufMut,
+    ) -> Poll<io::Result<()>> {
+        if self.rcvbuf.is_readable() {
+            self.rcvbuf.peek(buf);
+            Poll::Ready(Ok(()))
+        } else {
+            self.read_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
     pub(super) fn is_stopped(&self) -> bool {
         self.stop_state.is_some()
     }
@@ -247,6 +264,20 @@ impl<TX> SizeKnown<TX> {
         }
     }
 
+    pub(super) fn poll_peek(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut impl BufMut,
+    ) -> Poll<io::Result<()>> {
+        if self.rcvbuf.is_readable() {
+            self.rcvbuf.peek(buf);
+            Poll::Ready(Ok(()))
+        } else {
+            self.read_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
     pub(super) fn recv_reset(&mut self, reset_frame: &ResetStreamFrame) -> Result<u64, Error> {
         let final_size = reset_frame.final_size.into_inner();
         if final_size != self.final_size {
@@ -325,6 +356,11 @@ impl DataRcvd {
         self.rcvbuf.try_read(buf);
     }
 
+    /// Same as [`DataRcvd::poll_read`], but the data will not be consumed.
+    pub(super) fn poll_peek(&self, buf: &mut impl BufMut) {
+        self.rcvbuf.peek(buf);
+    }
+
     pub(super) fn is_all_read(&self) -> bool {
         self.rcvbuf.is_empty()
     }
@@ -362,7 +398,12 @@ impl<TX> Recver<TX> {
 /// [`Incoming`]: super::Incoming
 /// [`Reader`]: super::Reader
 #[derive(Debug, Clone)]
-pub struct ArcRecver<TX>(Arc<Mutex<Result<Recver<TX>, Error>>>);
+pub struct ArcRecver<TX> {
+    state: Arc<Mutex<Result<Recver<TX>, Error>>>,
+    // 收到的、应用层读取的字节数，接收缓冲区在DataRead、Reset状态下被释放，单独记录
+    received: Arc<AtomicU64>,
+    consumed: Arc<AtomicU64>,
+}
 
 impl<TX> ArcRecver<TX>
 where
@@ -370,14 +411,32 @@ where
 {
     #[doc(hidden)]
     pub(crate) fn new(stream_id: StreamId, buf_size: u64, frames_tx: TX) -> Self {
-        ArcRecver(Arc::new(Mutex::new(Ok(Recver::new(
-            stream_id, buf_size, frames_tx,
-        )))))
+        ArcRecver {
+            state: Arc::new(Mutex::new(Ok(Recver::new(stream_id, buf_size, frames_tx)))),
+            received: Arc::default(),
+            consumed: Arc::default(),
+        }
     }
 }
 
 impl<TX> ArcRecver<TX> {
     pub(super) fn recver(&self) -> MutexGuard<Result<Recver<TX>, Error>> {
-        self.0.lock().unwrap()
+        self.state.lock().unwrap()
+    }
+
+    pub(super) fn on_received(&self, fresh: usize) {
+        self.received.fetch_add(fresh as u64, Ordering::Release);
+    }
+
+    pub(super) fn received(&self) -> u64 {
+        self.received.load(Ordering::Acquire)
+    }
+
+    pub(super) fn on_consumed(&self, n: usize) {
+        self.consumed.fetch_add(n as u64, Ordering::Release);
+    }
+
+    pub(super) fn consumed(&self) -> u64 {
+        self.consumed.load(Ordering::Acquire)
     }
 }
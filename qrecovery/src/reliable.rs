@@ -11,6 +11,7 @@ use qbase::{
     frame::{io::WriteFrame, BeFrame, CryptoFrame, ReliableFrame, SendFrame, StreamFrame},
     packet::MarshalFrame,
 };
+use thiserror::Error;
 
 /// The kind of frame which guaratend to be received by peer.
 ///
@@ -23,12 +24,45 @@ pub enum GuaranteedFrame {
     Reliable(ReliableFrame),
 }
 
+/// Too many frames are waiting to be retransmitted, see [`ArcReliableFrameDeque::may_loss_frame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("too many reliable frames to retransmit, exceed the limit {0}")]
+pub struct TooManyRetransmissions(pub usize);
+
+#[derive(Debug, Default)]
+struct ReliableFrames {
+    // 被判定丢失、等待重传的帧，优先于新的帧发送
+    retransmissions: VecDeque<ReliableFrame>,
+    fresh: VecDeque<ReliableFrame>,
+}
+
+impl ReliableFrames {
+    fn front(&self) -> Option<&ReliableFrame> {
+        self.retransmissions.front().or(self.fresh.front())
+    }
+
+    fn pop_front(&mut self) -> Option<ReliableFrame> {
+        self.retransmissions
+            .pop_front()
+            .or_else(|| self.fresh.pop_front())
+    }
+
+    #[cfg(test)]
+    fn is_empty(&self) -> bool {
+        self.retransmissions.is_empty() && self.fresh.is_empty()
+    }
+}
+
 /// A deque for data space to send reliable frames.
 ///
 /// Like its name, it is just a queue. [`DataStreams`] or other components that need to send reliable
 /// frames write frames to this queue by calling [`SendFrame::send_frame`]. The transport layer can
 /// read the frames in the queue and encode them into the send buffer by calling [`try_read`].
 ///
+/// The frames declared lost are put back by [`may_loss_frame`], they are read before the new
+/// frames, so that the peer gets the lost state updates as soon as possible. At most
+/// [`MAX_RETRANSMISSIONS`] frames can wait to be retransmitted.
+///
 /// # Example
 /// ```rust
 /// use qbase::frame::{HandshakeDoneFrame, SendFrame};
@@ -39,17 +73,25 @@ pub enum GuaranteedFrame {
 /// ```
 ///
 /// [`try_read`]: ArcReliableFrameDeque::try_read
+/// [`may_loss_frame`]: ArcReliableFrameDeque::may_loss_frame
+/// [`MAX_RETRANSMISSIONS`]: ArcReliableFrameDeque::MAX_RETRANSMISSIONS
 /// [`DataStreams`]: crate::streams::DataStreams
 #[derive(Debug, Default, Clone)]
-pub struct ArcReliableFrameDeque(Arc<Mutex<VecDeque<ReliableFrame>>>);
+pub struct ArcReliableFrameDeque(Arc<Mutex<ReliableFrames>>);
 
 impl ArcReliableFrameDeque {
+    /// The maximum number of frames waiting to be retransmitted.
+    pub const MAX_RETRANSMISSIONS: usize = 4096;
+
     /// Create a new empty deque with at least the specified capacity.
     pub fn with_capacity(capacity: usize) -> Self {
-        Self(Arc::new(Mutex::new(VecDeque::with_capacity(capacity))))
+        Self(Arc::new(Mutex::new(ReliableFrames {
+            retransmissions: VecDeque::new(),
+            fresh: VecDeque::with_capacity(capacity),
+        })))
     }
 
-    fn lock_guard(&self) -> MutexGuard<'_, VecDeque<ReliableFrame>> {
+    fn lock_guard(&self) -> MutexGuard<'_, ReliableFrames> {
         self.0.lock().unwrap()
     }
 
@@ -60,7 +102,7 @@ impl ArcReliableFrameDeque {
     ///
     /// If the read success, the frame and the number of bytes written will be return.
     pub fn try_read(&self, mut buf: &mut [u8]) -> Option<(ReliableFrame, usize)> {
-        let mut deque = self.0.lock().unwrap();
+        let mut deque = self.lock_guard();
         let frame = deque.front()?;
         if frame.max_encoding_size() <= buf.len() || frame.encoding_size() <= buf.len() {
             let buf_len = buf.len();
@@ -71,20 +113,43 @@ impl ArcReliableFrameDeque {
         }
     }
 
+    /// Load the frames in deque into the `packet` in order, as many as the `packet` can hold.
+    ///
+    /// Once the next frame can't be encoded into the remaining bytes of the `packet`, the loading
+    /// stops, the frame and the frames after it are left at the front of the deque, they will be
+    /// loaded into the next packet.
     pub fn try_load_frames_into<B, P>(&self, packet: &mut P)
     where
         B: BufMut,
         P: Deref<Target = B> + MarshalFrame<ReliableFrame>,
     {
-        let mut deque = self.0.lock().unwrap();
+        let mut deque = self.lock_guard();
         while let Some(frame) = deque.front() {
-            if frame.max_encoding_size() <= packet.remaining_mut()
-                || frame.encoding_size() <= packet.remaining_mut()
+            if frame.max_encoding_size() > packet.remaining_mut()
+                && frame.encoding_size() > packet.remaining_mut()
             {
-                packet.dump_frame(deque.pop_front().unwrap());
+                break;
             }
+            let frame = deque.pop_front().unwrap();
+            packet.dump_frame(frame);
         }
     }
+
+    /// Put back the `frame` which may be lost, it will be retransmitted before the new frames.
+    ///
+    /// If there are already [`MAX_RETRANSMISSIONS`] frames waiting to be retransmitted, the frame
+    /// is discarded and [`TooManyRetransmissions`] is returned. It means the connection suffers
+    /// severe loss, it's better to close the connection than to let the deque grow unboundedly.
+    ///
+    /// [`MAX_RETRANSMISSIONS`]: ArcReliableFrameDeque::MAX_RETRANSMISSIONS
+    pub fn may_loss_frame(&self, frame: ReliableFrame) -> Result<(), TooManyRetransmissions> {
+        let mut deque = self.lock_guard();
+        if deque.retransmissions.len() >= Self::MAX_RETRANSMISSIONS {
+            return Err(TooManyRetransmissions(Self::MAX_RETRANSMISSIONS));
+        }
+        deque.retransmissions.push_back(frame);
+        Ok(())
+    }
 }
 
 impl<T> SendFrame<T> for ArcReliableFrameDeque
@@ -92,6 +157,112 @@ where
     T: Into<ReliableFrame>,
 {
     fn send_frame<I: IntoIterator<Item = T>>(&self, iter: I) {
-        self.lock_guard().extend(iter.into_iter().map(Into::into));
+        self.lock_guard()
+            .fresh
+            .extend(iter.into_iter().map(Into::into));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qbase::{frame::RetireConnectionIdFrame, varint::VarInt};
+
+    use super::*;
+
+    struct TinyPacket<'b> {
+        buf: &'b mut [u8],
+        frames: Vec<ReliableFrame>,
+    }
+
+    impl<'b> Deref for TinyPacket<'b> {
+        type Target = &'b mut [u8];
+
+        fn deref(&self) -> &Self::Target {
+            &self.buf
+        }
+    }
+
+    impl MarshalFrame<ReliableFrame> for TinyPacket<'_> {
+        fn dump_frame(&mut self, frame: ReliableFrame) -> Option<ReliableFrame> {
+            let mut buf = core::mem::take(&mut self.buf);
+            buf.put_frame(&frame);
+            self.buf = buf;
+            self.frames.push(frame);
+            None
+        }
+    }
+
+    fn retire(sequence: u32) -> ReliableFrame {
+        ReliableFrame::RetireConnectionId(RetireConnectionIdFrame {
+            sequence: VarInt::from_u32(sequence),
+        })
+    }
+
+    #[test]
+    fn test_load_frames_into_tiny_packet() {
+        let deque = ArcReliableFrameDeque::with_capacity(4);
+        deque.send_frame((0..3).map(retire));
+
+        // 每个帧编码为2字节，5字节只能容纳2个帧，剩下的帧不会溢出
+        let mut buf = [0u8; 5];
+        let mut packet = TinyPacket {
+            buf: &mut buf,
+            frames: vec![],
+        };
+        deque.try_load_frames_into(&mut packet);
+        assert_eq!(packet.frames, vec![retire(0), retire(1)]);
+        assert_eq!(packet.buf.len(), 1);
+
+        // 剩下的帧按原顺序留在队首，在下一个包中发送
+        deque.send_frame([retire(3)]);
+        let mut buf = [0u8; 5];
+        let mut packet = TinyPacket {
+            buf: &mut buf,
+            frames: vec![],
+        };
+        deque.try_load_frames_into(&mut packet);
+        assert_eq!(packet.frames, vec![retire(2), retire(3)]);
+        assert!(deque.lock_guard().is_empty());
+    }
+
+    #[test]
+    fn test_retransmissions_before_new_frames() {
+        let deque = ArcReliableFrameDeque::with_capacity(4);
+        deque.send_frame((0..2).map(retire));
+        // 之前发出的帧被判定丢失，排在尚未发送的新帧之前
+        deque.may_loss_frame(retire(8)).unwrap();
+        deque.may_loss_frame(retire(9)).unwrap();
+
+        let mut buf = [0u8; 64];
+        let mut packet = TinyPacket {
+            buf: &mut buf,
+            frames: vec![],
+        };
+        deque.try_load_frames_into(&mut packet);
+        assert_eq!(
+            packet.frames,
+            vec![retire(8), retire(9), retire(0), retire(1)]
+        );
+
+        deque.send_frame([retire(2)]);
+        deque.may_loss_frame(retire(0)).unwrap();
+        let (frame, _) = deque.try_read(&mut [0u8; 64]).unwrap();
+        assert_eq!(frame, retire(0));
+    }
+
+    #[test]
+    fn test_retransmissions_bounded() {
+        let deque = ArcReliableFrameDeque::with_capacity(4);
+        for sequence in 0..ArcReliableFrameDeque::MAX_RETRANSMISSIONS as u32 {
+            deque.may_loss_frame(retire(sequence)).unwrap();
+        }
+        assert_eq!(
+            deque.may_loss_frame(retire(0)),
+            Err(TooManyRetransmissions(
+                ArcReliableFrameDeque::MAX_RETRANSMISSIONS
+            ))
+        );
+        // 新的帧不受重传上限的限制
+        deque.send_frame([retire(0)]);
     }
 }
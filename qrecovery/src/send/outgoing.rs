@@ -1,9 +1,10 @@
-use std::ops::{DerefMut, Range};
+use std::ops::{Deref, DerefMut, Range};
 
 use bytes::BufMut;
 use qbase::{
     error::Error as QuicError,
     frame::{io::WriteDataFrame, ResetStreamError, ShouldCarryLength, StreamFrame},
+    packet::MarshalDataFrame,
     sid::StreamId,
     util::DescribeData,
     varint::{VarInt, VARINT_MAX},
@@ -12,6 +13,11 @@ use qbase::{
 use super::sender::{ArcSender, DataSentSender, Sender, SendingSender};
 
 /// An struct for protocol layer to manage the sending part of a stream.
+///
+/// The offsets of the stream data are tracked regardless of the packets carrying them. The 0-RTT
+/// and 1-RTT packets share the same packet number space, so the data sent in 0-RTT packets
+/// continues seamlessly in 1-RTT packets after the handshake, and the data lost in 0-RTT packets is
+/// retransmitted in 1-RTT packets at the same offsets.
 #[derive(Debug, Clone)]
 pub struct Outgoing<TX>(ArcSender<TX>);
 
@@ -22,13 +28,15 @@ impl<TX: Clone> Outgoing<TX> {
     ///
     /// ## Returns:
     ///
-    /// If no data is written to the buffer, return [`None`], or a tuple will be returned:
+    /// If no data is written to the buffer, or the sending is paused by [`Writer::pause`],
+    /// return [`None`], or a tuple will be returned:
     /// * [`StreamFrame`]: Stream frame obtained by reading
     /// * [`usize`]:       The length of the stream data that was read
     /// * [`bool`]:        Whether the data is fresh(not retransmitted)
     /// * [`usize`]:       How much data was written to the buffer
     ///
     /// [`DataStreams::try_read_data`]: crate::streams::raw::DataStreams::try_read_data
+    /// [`Writer::pause`]: super::Writer::pause
     pub fn try_read(
         &self,
         sid: StreamId,
@@ -36,6 +44,9 @@ impl<TX: Clone> Outgoing<TX> {
         tokens: usize,
         flow_limit: usize,
     ) -> Option<(StreamFrame, usize, bool, usize)> {
+        if self.0.is_paused() {
+            return None;
+        }
         let capacity = buf.len();
         let write = |(offset, is_fresh, data, is_eos): (u64, bool, (&[u8], &[u8]), bool)| {
             let mut frame = StreamFrame::new(sid, offset, data.len());
@@ -46,7 +57,9 @@ impl<TX: Clone> Outgoing<TX> {
                     buf.put_data_frame(&frame, &data);
                 }
                 ShouldCarryLength::PaddingFirst(n) => {
-                    (&mut buf[n..]).put_data_frame(&frame, &data);
+                    // 前面填充PADDING帧，且要计入写入的长度，否则后续写入的帧会覆盖这个Stream帧
+                    buf.put_bytes(0, n);
+                    buf.put_data_frame(&frame, &data);
                 }
                 ShouldCarryLength::ShouldAfter(_not_carry_len, _carry_len) => {
                     frame.carry_length();
@@ -59,6 +72,66 @@ impl<TX: Clone> Outgoing<TX> {
         let predicate = |offset| {
             StreamFrame::estimate_max_capacity(capacity, sid, offset).map(|c| tokens.min(c))
         };
+        self.pick_up(predicate, flow_limit, write)
+    }
+
+    /// Load the data that the application has written into the `packet`.
+    ///
+    /// See [`DataStreams::try_load_data_into`] for more about this method.
+    ///
+    /// Unlike [`Outgoing::try_read`], the stream frame always carries the length field, because
+    /// the packet may be followed by other frames. The room of the length field is reserved when
+    /// estimating how much data can be loaded.
+    ///
+    /// ## Returns:
+    ///
+    /// If no data is loaded into the packet, or the sending is paused by [`Writer::pause`],
+    /// return [`None`], or a tuple will be returned:
+    /// * [`usize`]:       The length of the stream data that was loaded
+    /// * [`bool`]:        Whether the data is fresh(not retransmitted)
+    ///
+    /// [`DataStreams::try_load_data_into`]: crate::streams::raw::DataStreams::try_load_data_into
+    /// [`Writer::pause`]: super::Writer::pause
+    pub fn try_load_data_into<B, P>(
+        &self,
+        sid: StreamId,
+        packet: &mut P,
+        tokens: usize,
+        flow_limit: usize,
+    ) -> Option<(usize, bool)>
+    where
+        B: BufMut,
+        P: Deref<Target = B> + for<'a> MarshalDataFrame<StreamFrame, (&'a [u8], &'a [u8])>,
+    {
+        if self.0.is_paused() {
+            return None;
+        }
+        let capacity = packet.remaining_mut();
+        // 为长度字段预留空间，长度不会超过capacity，其编码长度也不会超过capacity的编码长度
+        let len_encoding_size = VarInt::try_from(capacity).ok()?.encoding_size();
+        let write = |(offset, is_fresh, data, is_eos): (u64, bool, (&[u8], &[u8]), bool)| {
+            let mut frame = StreamFrame::new(sid, offset, data.len());
+            frame.set_eos_flag(is_eos);
+            frame.carry_length();
+            packet.dump_frame_with_data(frame, data);
+            (data.len(), is_fresh)
+        };
+
+        let predicate = |offset| {
+            StreamFrame::estimate_max_capacity(capacity, sid, offset)
+                .map(|c| c.saturating_sub(len_encoding_size))
+                .filter(|&c| c > 0)
+                .map(|c| tokens.min(c))
+        };
+        self.pick_up(predicate, flow_limit, write)
+    }
+
+    fn pick_up<R>(
+        &self,
+        predicate: impl Fn(u64) -> Option<usize>,
+        flow_limit: usize,
+        write: impl FnOnce((u64, bool, (&[u8], &[u8]), bool)) -> R,
+    ) -> Option<R> {
         let mut sender = self.0.sender();
         let inner = sender.deref_mut();
 
@@ -126,9 +199,11 @@ impl<TX> Outgoing<TX> {
                 }
                 Sender::Sending(s) => {
                     s.on_data_acked(range);
+                    self.0.on_acked(s.acked());
                 }
                 Sender::DataSent(s) => {
                     s.on_data_acked(range, is_fin);
+                    self.0.on_acked(s.acked());
                     if s.is_all_rcvd() {
                         s.wake_all();
                         *sending_state = Sender::DataRcvd;
@@ -145,7 +220,10 @@ impl<TX> Outgoing<TX> {
     /// Called when the data sent to peer may lost.
     ///
     /// * `range` is the range of stream data that may lost.
-    pub fn may_loss_data(&self, range: &Range<u64>) {
+    ///
+    /// * `is_fin` indicates whether the lost stream frame contains the `FIN` flag, if so, the `FIN`
+    ///   flag will be sent again.
+    pub fn may_loss_data(&self, range: &Range<u64>, is_fin: bool) {
         let mut sender = self.0.sender();
         let inner = sender.deref_mut();
         if let Ok(sending_state) = inner {
@@ -157,7 +235,7 @@ impl<TX> Outgoing<TX> {
                     s.may_loss_data(range);
                 }
                 Sender::DataSent(s) => {
-                    s.may_loss_data(range);
+                    s.may_loss_data(range, is_fin);
                 }
                 // ignore loss
                 _ => (),
@@ -167,41 +245,31 @@ impl<TX> Outgoing<TX> {
 
     /// Called when the [`STOP_SENDING frame`] sent by the peer is received.
     ///
-    /// If the stream has not been closed, the stream will be reset and then a [`RESET_STREAM frame`] will
-    /// be sent to the peer to reset the peer. in this case, the method will return `true`.
+    /// If the stream has not been closed, the stream will be reset: the data buffered but never
+    /// sent is discarded, no more stream data will be sent, and the pending writes will fail. The
+    /// returned [`ResetStreamError`] carries the final size, which is the end of the data that has
+    /// been sent, the caller should send a [`RESET_STREAM frame`] to the peer with it.
     ///
-    /// If the stream has closed, `false` will be returned, and the method will do nothing.
+    /// If the stream has closed, `None` will be returned, and the method will do nothing.
     ///
     /// [`STOP_SENDING frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-stop_sending-frames
-    /// [`STREAM_RESET frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-reset_stream-frames
-    pub fn on_stopped(&self, error_code: u64) -> bool {
+    /// [`RESET_STREAM frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-reset_stream-frames
+    pub fn on_stopped(&self, error_code: u64) -> Option<ResetStreamError> {
         let mut sender = self.0.sender();
-        let inner = sender.deref_mut();
-        match inner {
-            Ok(sending_state) => match sending_state {
-                Sender::Ready(_) => {
-                    unreachable!("never send data before recv data");
-                }
-                Sender::Sending(s) => {
-                    let final_size = s.stop();
-                    *sending_state = Sender::ResetSent(ResetStreamError::new(
-                        VarInt::from_u64(error_code).expect("app error code must not exceed 2^62"),
-                        VarInt::from_u64(final_size).expect("final size must not exceed 2^62"),
-                    ));
-                    true
-                }
-                Sender::DataSent(s) => {
-                    let final_size = s.stop();
-                    *sending_state = Sender::ResetSent(ResetStreamError::new(
-                        VarInt::from_u64(error_code).expect("app error code must not exceed 2^62"),
-                        VarInt::from_u64(final_size).expect("final size must not exceed 2^62"),
-                    ));
-                    true
-                }
-                _ => false,
-            },
-            Err(_) => false,
-        }
+        let sending_state = sender.as_mut().ok()?;
+        let final_size = match sending_state {
+            Sender::Ready(s) => s.stop(),
+            Sender::Sending(s) => s.stop(),
+            Sender::DataSent(s) => s.stop(),
+            _ => return None,
+        };
+        let reset = ResetStreamError::new(
+            VarInt::from_u64(error_code).expect("app error code must not exceed 2^62"),
+            VarInt::from_u64(final_size).expect("final size must not exceed 2^62"),
+        );
+        // 发送缓冲区随状态一并释放
+        *sending_state = Sender::ResetSent(reset);
+        Some(reset)
     }
 
     /// Called When the [`RESET_STREAM frame`] previously sent to the peer is acknowledged
@@ -224,6 +292,20 @@ impl<TX> Outgoing<TX> {
         }
     }
 
+    /// Return the number of bytes held in the send buffer, written by the application but not yet
+    /// acknowledged continuously by the peer, including the data sent but unacknowledged.
+    ///
+    /// The send buffer is released once the stream is reset or all data has been received by the
+    /// peer, 0 will be returned then.
+    pub fn buffered_bytes(&self) -> u64 {
+        match self.0.sender().deref() {
+            Ok(Sender::Ready(_) | Sender::Sending(_) | Sender::DataSent(_)) => {
+                self.0.written() - self.0.acked()
+            }
+            _ => 0,
+        }
+    }
+
     /// When a connection-level error occurs, all data streams must be notified.
     /// Their reading and writing should be terminated, accompanied the error of the connection.
     pub fn on_conn_error(&self, err: &QuicError) {
@@ -1,7 +1,10 @@
 use std::{
     io,
     ops::{DerefMut, Range},
-    sync::{Arc, Mutex, MutexGuard},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
     task::{Context, Poll, Waker},
 };
 
@@ -123,6 +126,12 @@ impl<TX> ReadySender<TX> {
             waker.wake();
         }
     }
+
+    /// 传输层使用，对端创建的双向流在我方发送数据之前，就可能被对端STOP_SENDING
+    pub(super) fn stop(&mut self) -> u64 {
+        self.wake_all();
+        self.sndbuf.sent()
+    }
 }
 
 /// 状态转换，ReaderSender => SendingSender
@@ -218,6 +227,10 @@ impl<TX> SendingSender<TX> {
             .map(|(offset, is_fresh, data)| (offset, is_fresh, data, false))
     }
 
+    pub(super) fn acked(&self) -> u64 {
+        self.sndbuf.acked()
+    }
+
     pub(super) fn on_data_acked(&mut self, range: &Range<u64>) {
         self.sndbuf.on_data_acked(range);
         if self.sndbuf.is_all_rcvd() {
@@ -263,8 +276,8 @@ impl<TX> SendingSender<TX> {
     /// 传输层使用
     pub(super) fn stop(&mut self) -> u64 {
         self.wake_all();
-        // Actually, these remaining data is not acked and will not be acked
-        self.sndbuf.written()
+        // 未发送过的数据直接丢弃，不计入最终大小；已发送的剩余数据也不会再被确认了
+        self.sndbuf.sent()
     }
 }
 
@@ -323,17 +336,21 @@ impl<TX> DataSentSender<TX> {
         P: Fn(u64) -> Option<usize>,
     {
         let final_size = self.sndbuf.written();
+        let all_sent = self.sndbuf.sent() == final_size;
         self.sndbuf
             .pick_up(&predicate, flow_limit)
             .map(|(offset, is_fresh, data)| {
+                // 只有包含了最终偏移的Stream帧才携带fin，数据分多帧发送时，只有真正的最后一帧携带
                 let is_eos = offset + data.len() as u64 == final_size;
-                if is_eos {
+                if is_eos && self.fin_state == FinState::None {
                     self.fin_state = FinState::Sent;
                 }
                 (offset, is_fresh, data, is_eos)
             })
             .or_else(|| {
-                if self.fin_state == FinState::None {
+                // 数据都发送过了，才能单独发送携带fin的空Stream帧；否则受流控所限未发送的数据，
+                // 其最终大小将超出流控的限制
+                if self.fin_state == FinState::None && all_sent {
                     let _ = predicate(final_size)?;
                     self.fin_state = FinState::Sent;
                     Some((final_size, false, (&[], &[]), true))
@@ -343,6 +360,10 @@ impl<TX> DataSentSender<TX> {
             })
     }
 
+    pub(super) fn acked(&self) -> u64 {
+        self.sndbuf.acked()
+    }
+
     pub(super) fn on_data_acked(&mut self, range: &Range<u64>, is_fin: bool) {
         self.sndbuf.on_data_acked(range);
         if is_fin {
@@ -362,8 +383,12 @@ impl<TX> DataSentSender<TX> {
         self.sndbuf.is_all_rcvd() && self.fin_state == FinState::Rcvd
     }
 
-    pub(super) fn may_loss_data(&mut self, range: &Range<u64>) {
-        self.sndbuf.may_loss_data(range)
+    pub(super) fn may_loss_data(&mut self, range: &Range<u64>, is_fin: bool) {
+        self.sndbuf.may_loss_data(range);
+        // 携带fin的Stream帧丢了，fin需重新发送，或随着重传的最后一段数据，或单独的空Stream帧
+        if is_fin && self.fin_state == FinState::Sent {
+            self.fin_state = FinState::None;
+        }
     }
 
     pub(super) fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
@@ -395,8 +420,8 @@ impl<TX> DataSentSender<TX> {
 
     pub(super) fn stop(&mut self) -> u64 {
         self.wake_all();
-        // Actually, these remaining data is not acked and will not be acked
-        self.sndbuf.written()
+        // 未发送过的数据直接丢弃，不计入最终大小；已发送的剩余数据也不会再被确认了
+        self.sndbuf.sent()
     }
 }
 
@@ -427,28 +452,67 @@ impl<TX> Sender<TX> {
 /// [`Outgoing`]: super::Outgoing
 /// [`Writer`]: super::Writer
 #[derive(Debug, Clone)]
-pub struct ArcSender<TX>(Arc<Mutex<Result<Sender<TX>, Error>>>);
+pub struct ArcSender<TX> {
+    state: Arc<Mutex<Result<Sender<TX>, Error>>>,
+    // 应用层暂停发送，独立于发送状态机，状态转换时不会丢失
+    paused: Arc<AtomicBool>,
+    // 应用层写入的、对端连续确认的字节数，发送缓冲区在DataRcvd、Reset状态下被释放，单独记录
+    written: Arc<AtomicU64>,
+    acked: Arc<AtomicU64>,
+}
 
 impl<TX> ArcSender<TX> {
     #[doc(hidden)]
     pub(crate) fn new(stream_id: StreamId, buf_size: u64, reset_frame_tx: TX) -> Self {
-        ArcSender(Arc::new(Mutex::new(Ok(Sender::new(
-            stream_id,
-            buf_size,
-            reset_frame_tx,
-        )))))
+        ArcSender {
+            state: Arc::new(Mutex::new(Ok(Sender::new(
+                stream_id,
+                buf_size,
+                reset_frame_tx,
+            )))),
+            paused: Arc::new(AtomicBool::new(false)),
+            written: Arc::default(),
+            acked: Arc::default(),
+        }
     }
 }
 
 impl<TX> ArcSender<TX> {
     pub(crate) fn revise_buffer_size(&self, snd_buf_size: u64) {
         let mut sender = self.sender();
-        if let Ok(Sender::Ready(s)) = sender.deref_mut() {
-            s.update_window(snd_buf_size);
+        // 被动打开的流在被应用接受之前，可能已经因为发送任务的读取而进入Sending状态
+        match sender.deref_mut() {
+            Ok(Sender::Ready(s)) => s.update_window(snd_buf_size),
+            Ok(Sender::Sending(s)) => s.update_window(snd_buf_size),
+            _ => {}
         }
     }
 
     pub(super) fn sender(&self) -> MutexGuard<Result<Sender<TX>, Error>> {
-        self.0.lock().unwrap()
+        self.state.lock().unwrap()
+    }
+
+    pub(super) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Release);
+    }
+
+    pub(super) fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    pub(super) fn on_written(&self, n: usize) {
+        self.written.fetch_add(n as u64, Ordering::Release);
+    }
+
+    pub(super) fn written(&self) -> u64 {
+        self.written.load(Ordering::Acquire)
+    }
+
+    pub(super) fn on_acked(&self, acked: u64) {
+        self.acked.fetch_max(acked, Ordering::Release);
+    }
+
+    pub(super) fn acked(&self) -> u64 {
+        self.acked.load(Ordering::Acquire)
     }
 }
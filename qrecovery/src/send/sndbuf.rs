@@ -102,6 +102,14 @@ impl PartialOrd for State {
 struct BufMap(VecDeque<State>, u64);
 
 impl BufMap {
+    // 已发送过的数据的结尾，Pending区间只会出现在末尾
+    fn sent(&self) -> u64 {
+        match self.0.back() {
+            Some(s) if s.color() == Color::Pending => s.offset(),
+            _ => self.1,
+        }
+    }
+
     // 追加写数据
     fn extend_to(&mut self, pos: u64) -> u64 {
         debug_assert!(
@@ -140,6 +148,10 @@ impl BufMap {
                 } else {
                     available.min(flow_limit)
                 };
+                // 没有额度的区间跳过，否则会拆出空的区间，发出空的Stream帧
+                if allowance == 0 {
+                    return None;
+                }
                 Some((idx, allowance, state))
             })
             .map(|(index, allowance, state)| {
@@ -314,17 +326,25 @@ impl BufMap {
                     s.offset()
                 );
                 let mut drain_start = idx;
-                if pre_color == Color::Flighting {
-                    s.set_color(Color::Lost);
-                    drain_start = self.same_before(idx, Color::Lost) + 1;
-                } else {
-                    drain_start += 1;
+                match pre_color {
+                    Color::Flighting => {
+                        s.set_color(Color::Lost);
+                        drain_start = self.same_before(idx, Color::Lost) + 1;
+                    }
+                    // 起始的区间已被确认，不能把之后的区间合并进来，否则其后的区间将被当作已确认
+                    Color::Recved => {
+                        self.lost_from(idx + 1, range.end);
+                        return;
+                    }
+                    _ => drain_start += 1,
                 }
                 (drain_start, false, idx + 1, pre_color)
             }
             Err(idx) => {
                 if idx == 0 {
-                    (1, false, 0, Color::Recved)
+                    // 起始之前的数据都已确认并被移除了，同样从第一个区间开始判定丢失
+                    self.lost_from(0, range.end);
+                    return;
                 } else {
                     let s = self.0.get(idx - 1).unwrap();
                     let pre_color = s.color();
@@ -567,6 +587,19 @@ impl SendBuf {
         self.state.1
     }
 
+    /// Return the end of the data that has been sent at least once.
+    ///
+    /// The data after it was written but has never been picked up, it is not counted in the
+    /// final size if the stream is reset.
+    pub fn sent(&self) -> u64 {
+        self.state.sent()
+    }
+
+    /// Return the end of the data that has been continuously acknowledged by the peer.
+    pub fn acked(&self) -> u64 {
+        self.offset
+    }
+
     /// Return the number of bytes can be written without reallocation.
     pub fn remaining_mut(&self) -> usize {
         self.data.capacity() - self.data.len()
@@ -1084,4 +1117,51 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_bufmap_lost_after_recved() {
+        // 丢失区间从已确认的区间开始，其后的区间不能被合并进已确认的区间
+        let mut buf_map = BufMap(
+            vec![
+                State::encode(0, Color::Recved),
+                State::encode(10, Color::Lost),
+                State::encode(50, Color::Pending),
+            ]
+            .into(),
+            80,
+        );
+        buf_map.may_loss(&(0..20));
+        assert_eq!(
+            buf_map.0,
+            vec![
+                State::encode(0, Color::Recved),
+                State::encode(10, Color::Lost),
+                State::encode(50, Color::Pending),
+            ]
+        );
+
+        buf_map.0[1].set_color(Color::Flighting);
+        buf_map.may_loss(&(0..30));
+        assert_eq!(
+            buf_map.0,
+            vec![
+                State::encode(0, Color::Recved),
+                State::encode(10, Color::Lost),
+                State::encode(30, Color::Flighting),
+                State::encode(50, Color::Pending),
+            ]
+        );
+
+        // 头部已确认的区间被移除之后，丢失区间从移除的部分开始
+        buf_map.0.pop_front();
+        buf_map.may_loss(&(0..40));
+        assert_eq!(
+            buf_map.0,
+            vec![
+                State::encode(10, Color::Lost),
+                State::encode(40, Color::Flighting),
+                State::encode(50, Color::Pending),
+            ]
+        );
+    }
 }
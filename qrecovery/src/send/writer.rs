@@ -100,6 +100,89 @@ where
     }
 }
 
+impl<TX> Writer<TX> {
+    /// Pause sending the data of the stream, without resetting the stream.
+    ///
+    /// While paused, neither the new data nor the lost data will be picked up from the buffer to
+    /// send, the data that has been written stays in the buffer. The data can still be written
+    /// until the buffer is full, so the backpressure is applied to the application as usual.
+    ///
+    /// Call [`Writer::resume`] to continue sending.
+    pub fn pause(&self) {
+        self.0.set_paused(true);
+    }
+
+    /// Resume sending the data of the stream paused by [`Writer::pause`].
+    pub fn resume(&self) {
+        self.0.set_paused(false);
+    }
+
+    /// Return whether sending the stream is paused by [`Writer::pause`].
+    pub fn is_paused(&self) -> bool {
+        self.0.is_paused()
+    }
+
+    /// Return the number of bytes written to the stream by the application.
+    pub fn bytes_written(&self) -> u64 {
+        self.0.written()
+    }
+
+    /// Return the number of bytes acknowledged by the peer, only the data acknowledged continuously
+    /// from the beginning of the stream is counted.
+    ///
+    /// Once it reaches [`Writer::bytes_written`] after the stream is shutdowned, all the data has
+    /// been received by the peer. It no longer grows after the stream is reset.
+    pub fn bytes_acked(&self) -> u64 {
+        self.0.acked()
+    }
+
+    /// Poll whether all the data written to the stream, including the FIN, has been acknowledged
+    /// by the peer.
+    ///
+    /// Unlike [`poll_shutdown`], this method does not finish the stream. The stream must have been
+    /// shutdowned, otherwise the data written later could never be confirmed, and an error will be
+    /// returned. After that, it is ready with `Ok(())` once the peer acknowledges everything, the
+    /// application can release the resources related to the stream.
+    ///
+    /// If the stream is reset or the connection is closed, an error will be returned.
+    ///
+    /// [`poll_shutdown`]: AsyncWrite::poll_shutdown
+    pub fn poll_all_data_acked(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut sender = self.0.sender();
+        let sending_state = sender.as_mut().map_err(|e| e.clone())?;
+        match sending_state {
+            Sender::Ready(_) | Sender::Sending(_) => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the stream has not been shutdowned",
+            ))),
+            Sender::DataSent(s) => {
+                // 与poll_shutdown共用shutdown_waker，两者都需要独占Writer，不会同时等待
+                let result = s.poll_shutdown(cx);
+                if result.is_ready() {
+                    s.wake_all();
+                    *sending_state = Sender::DataRcvd;
+                }
+                result
+            }
+            Sender::DataRcvd => Poll::Ready(Ok(())),
+            Sender::ResetSent(reset) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+            }
+            Sender::ResetRcvd(reset) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+            }
+        }
+    }
+
+    /// Wait for all the data written to the stream, including the FIN, to be acknowledged by the
+    /// peer.
+    ///
+    /// See [`Writer::poll_all_data_acked`] for more details.
+    pub async fn all_data_acked(&mut self) -> io::Result<()> {
+        core::future::poll_fn(|cx| self.poll_all_data_acked(cx)).await
+    }
+}
+
 impl<TX: Clone> AsyncWrite for Writer<TX> {
     /// 往sndbuf里面写数据，直到写满MAX_STREAM_DATA，等通告窗口更新再写
     fn poll_write(
@@ -109,7 +192,7 @@ impl<TX: Clone> AsyncWrite for Writer<TX> {
     ) -> Poll<io::Result<usize>> {
         let mut sender = self.0.sender();
         let sending_state = sender.as_mut().map_err(|e| e.clone())?;
-        match sending_state {
+        let poll = match sending_state {
             Sender::Ready(s) => s.poll_write(cx, buf),
             Sender::Sending(s) => s.poll_write(cx, buf),
             Sender::DataSent(_) => Poll::Ready(Err(io::Error::new(
@@ -126,7 +209,11 @@ impl<TX: Clone> AsyncWrite for Writer<TX> {
             Sender::ResetRcvd(reset) => {
                 Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
             }
+        };
+        if let Poll::Ready(Ok(n)) = poll {
+            self.0.on_written(n);
         }
+        poll
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
@@ -217,3 +304,223 @@ impl<TX> Drop for Writer<TX> {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use qbase::{sid::StreamId, varint::VarInt};
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::send::Outgoing;
+
+    #[derive(Debug, Clone)]
+    struct FramesTx;
+
+    impl SendFrame<ResetStreamFrame> for FramesTx {
+        fn send_frame<I: IntoIterator<Item = ResetStreamFrame>>(&self, _iter: I) {}
+    }
+
+    #[tokio::test]
+    async fn test_all_data_acked() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let sender = ArcSender::new(sid, 1024, FramesTx);
+        let outgoing = Outgoing::new(sender.clone());
+        let mut writer = Writer(sender);
+
+        writer.write_all(b"hello").await.unwrap();
+        // 尚未shutdown，后续写入的数据无从确认
+        assert!(writer.all_data_acked().await.is_err());
+
+        // 仅标记结束，不等待对端确认
+        assert!(writer.shutdown().now_or_never().is_none());
+        let mut buf = [0u8; 64];
+        let (frame, len, is_fresh, _) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert!(frame.is_fin() && is_fresh);
+        assert_eq!(len, 5);
+        assert!(writer.all_data_acked().now_or_never().is_none());
+
+        assert!(outgoing.on_data_acked(&(0..5), true));
+        writer.all_data_acked().await.unwrap();
+        writer.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let sender = ArcSender::new(sid, 1024, FramesTx);
+        let outgoing = Outgoing::new(sender.clone());
+        let mut writer = Writer(sender);
+        let mut buf = [0u8; 64];
+
+        writer.pause();
+        assert!(writer.is_paused());
+        // 暂停期间仍可写入缓冲区，但不会被发送
+        writer.write_all(b"hello").await.unwrap();
+        assert!(outgoing.try_read(sid, &mut buf, 64, 64).is_none());
+
+        writer.resume();
+        let (frame, len, is_fresh, _) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert_eq!((frame.offset(), len, is_fresh), (0, 5, true));
+
+        // 暂停同样阻止重传丢失的数据
+        outgoing.may_loss_data(&(0..5), false);
+        writer.pause();
+        assert!(outgoing.try_read(sid, &mut buf, 64, 64).is_none());
+        writer.resume();
+        let (frame, len, is_fresh, _) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert_eq!((frame.offset(), len, is_fresh), (0, 5, false));
+        writer.cancel(0);
+    }
+
+    #[tokio::test]
+    async fn test_bytes_written_and_acked() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let sender = ArcSender::new(sid, 1024, FramesTx);
+        let outgoing = Outgoing::new(sender.clone());
+        let mut writer = Writer(sender);
+        let mut buf = [0u8; 64];
+
+        writer.write_all(b"hello world").await.unwrap();
+        assert!(writer.shutdown().now_or_never().is_none());
+        assert_eq!((writer.bytes_written(), writer.bytes_acked()), (11, 0));
+        let (_, len, ..) = outgoing.try_read(sid, &mut buf, 64, 5).unwrap();
+        assert_eq!(len, 5);
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert!(frame.is_fin());
+        assert_eq!(len, 6);
+
+        // 乱序的确认不计入，直到头部连续
+        assert!(!outgoing.on_data_acked(&(5..11), true));
+        assert_eq!(writer.bytes_acked(), 0);
+        assert!(outgoing.on_data_acked(&(0..5), false));
+        // 进入DataRcvd之后仍保留计数
+        assert_eq!((writer.bytes_written(), writer.bytes_acked()), (11, 11));
+        writer.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fin_with_data() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let sender = ArcSender::new(sid, 1024, FramesTx);
+        let outgoing = Outgoing::new(sender.clone());
+        let mut writer = Writer(sender);
+        let mut buf = [0u8; 64];
+
+        writer.write_all(b"hello world").await.unwrap();
+        assert!(writer.shutdown().now_or_never().is_none());
+        // 分两帧发送，只有包含最终偏移的帧携带fin
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 64, 5).unwrap();
+        assert_eq!((frame.offset(), len, frame.is_fin()), (0, 5, false));
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert_eq!((frame.offset(), len, frame.is_fin()), (5, 6, true));
+        // fin随数据发出，不再单独发送空的Stream帧
+        assert!(outgoing.try_read(sid, &mut buf, 64, 64).is_none());
+
+        // 重传中间的数据不携带fin，重传最后的数据仍携带fin
+        outgoing.may_loss_data(&(0..5), false);
+        outgoing.may_loss_data(&(5..11), true);
+        let (frame, len, is_fresh, _) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert_eq!((frame.offset(), len, frame.is_fin()), (0, 11, true));
+        assert!(!is_fresh);
+        assert!(outgoing.try_read(sid, &mut buf, 64, 64).is_none());
+
+        assert!(outgoing.on_data_acked(&(0..11), true));
+        writer.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_empty_fin_frame() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let sender = ArcSender::new(sid, 1024, FramesTx);
+        let outgoing = Outgoing::new(sender.clone());
+        let mut writer = Writer(sender);
+        let mut buf = [0u8; 64];
+
+        writer.write_all(b"hello").await.unwrap();
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert_eq!((len, frame.is_fin()), (5, false));
+
+        // 所有数据发送完之后才结束，单独发送一个携带fin的空Stream帧，它不占用流控
+        assert!(writer.shutdown().now_or_never().is_none());
+        let (frame, len, is_fresh, _) = outgoing.try_read(sid, &mut buf, 64, 0).unwrap();
+        assert_eq!((frame.offset(), len, frame.is_fin()), (5, 0, true));
+        assert!(!is_fresh);
+        assert!(outgoing.try_read(sid, &mut buf, 64, 64).is_none());
+
+        // 空的fin帧丢失，需重新发送
+        outgoing.may_loss_data(&(5..5), true);
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert_eq!((frame.offset(), len, frame.is_fin()), (5, 0, true));
+
+        assert!(!outgoing.on_data_acked(&(5..5), true));
+        assert!(outgoing.on_data_acked(&(0..5), false));
+        writer.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fin_after_flow_limited_data() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let sender = ArcSender::new(sid, 1024, FramesTx);
+        let outgoing = Outgoing::new(sender.clone());
+        let mut writer = Writer(sender);
+        let mut buf = [0u8; 64];
+
+        writer.write_all(b"hello").await.unwrap();
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert_eq!((len, frame.is_fin()), (5, false));
+        writer.write_all(b" world").await.unwrap();
+        assert!(writer.shutdown().now_or_never().is_none());
+
+        // 受流控限制，剩余数据发出之前，不能单独发送fin，其最终大小超出了流控的限制
+        assert!(outgoing.try_read(sid, &mut buf, 64, 0).is_none());
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert_eq!((frame.offset(), len, frame.is_fin()), (5, 6, true));
+
+        assert!(outgoing.on_data_acked(&(0..11), true));
+        writer.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_padding_before_frame() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let sender = ArcSender::new(sid, 1024, FramesTx);
+        let outgoing = Outgoing::new(sender.clone());
+        let mut writer = Writer(sender);
+        let mut buf = [0xffu8; 64];
+
+        // 帧头2字节，数据61字节，剩下的1字节不够编码长度字段，只能在帧前填充
+        writer.write_all(&[1; 61]).await.unwrap();
+        let (frame, len, _, written) = outgoing.try_read(sid, &mut buf, 64, 64).unwrap();
+        assert_eq!((frame.offset(), len), (0, 61));
+        // 填充的PADDING帧也计入写入的长度，否则之后写入的帧会把这个Stream帧覆盖掉
+        assert_eq!(written, 64);
+        assert_eq!(buf[0], 0);
+
+        writer.cancel(0);
+    }
+
+    #[tokio::test]
+    async fn test_stopped_by_peer() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let sender = ArcSender::new(sid, 8, FramesTx);
+        let outgoing = Outgoing::new(sender.clone());
+        let mut writer = Writer(sender);
+        let mut buf = [0u8; 64];
+
+        // 流控只允许写入8字节，剩余的数据阻塞等待
+        let mut write = Box::pin(writer.write_all(b"hello world"));
+        assert!(write.as_mut().now_or_never().is_none());
+        let (_frame, len, _, _) = outgoing.try_read(sid, &mut buf, 64, 3).unwrap();
+        assert_eq!(len, 3);
+
+        // 未发送的数据被丢弃，最终大小只计入已发送的部分
+        let reset = outgoing.on_stopped(7).unwrap();
+        assert_eq!(reset.error_code(), 7);
+        assert_eq!(reset.combine(sid).final_size.into_inner(), 3);
+        let err = write.await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(outgoing.try_read(sid, &mut buf, 64, 64).is_none());
+        assert!(outgoing.on_stopped(7).is_none());
+    }
+}
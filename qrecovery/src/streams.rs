@@ -34,11 +34,13 @@ use qbase::{
     param::CommonParameters,
     sid::{ControlConcurrency, Role, StreamId},
 };
+pub use scheduler::{RoundRobin, StreamScheduler};
 
 use crate::{recv::Reader, send::Writer};
 mod io;
 mod listener;
 pub mod raw;
+mod scheduler;
 
 #[derive(Debug, Clone)]
 pub struct Ext<T: Clone>(T);
@@ -198,3 +200,98 @@ where
         self.inner.poll_open_uni_stream(cx, self.snd_wnd_size)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use qbase::{
+        frame::{MaxStreamsFrame, STREAM_FRAME_MAX_ENCODING_SIZE},
+        sid::handy::ConsistentConcurrency,
+        varint::VarInt,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    struct CtrlFrames;
+
+    impl SendFrame<StreamCtlFrame> for CtrlFrames {
+        fn send_frame<I: IntoIterator<Item = StreamCtlFrame>>(&self, _iter: I) {}
+    }
+
+    fn read_frame(
+        streams: &DataStreams<CtrlFrames>,
+        buf_len: usize,
+    ) -> Option<(StreamFrame, Bytes)> {
+        let mut buf = vec![0; buf_len];
+        let (frame, written, _fresh) = streams.try_read_data(&mut buf, usize::MAX)?;
+        // 数据总是位于帧的末尾
+        let data = Bytes::copy_from_slice(&buf[written - frame.len()..written]);
+        Some((frame, data))
+    }
+
+    #[tokio::test]
+    async fn test_stream_across_0rtt_and_1rtt() {
+        let client = DataStreams::new(
+            Role::Client,
+            &CommonParameters::default(),
+            Box::new(ConsistentConcurrency::new(0, 0)),
+            CtrlFrames,
+        );
+        let mut server_params = CommonParameters::default();
+        server_params.set_initial_max_streams_uni(1);
+        server_params.set_initial_max_stream_data_uni(VarInt::from_u32(1024));
+        let server = DataStreams::new(
+            Role::Server,
+            &server_params,
+            Box::new(ConsistentConcurrency::new(0, 1)),
+            CtrlFrames,
+        );
+
+        // 0-RTT时，客户端使用记住的传输参数打开流
+        client
+            .recv_frame(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Uni(
+                VarInt::from_u32(1),
+            )))
+            .unwrap();
+        let (_sid, mut writer) = client.open_uni(1024).await.unwrap().unwrap();
+        let message = b"sent partly in 0-RTT, and the rest in 1-RTT";
+        writer.write_all(message).await.unwrap();
+        let mut shutdown = Box::pin(writer.shutdown());
+        assert!(shutdown.as_mut().now_or_never().is_none());
+
+        // 两个0-RTT数据包，各携带一小段数据，第一个丢失了
+        let small = STREAM_FRAME_MAX_ENCODING_SIZE + 8;
+        let lost = read_frame(&client, small).unwrap();
+        let zero_rtt = read_frame(&client, small).unwrap();
+        assert_eq!(lost.0.offset(), 0);
+        assert_eq!(zero_rtt.0.offset(), lost.0.range().end);
+        client.may_loss_data(&lost.0);
+
+        // 握手完成后，1-RTT数据包中的数据接续在0-RTT之后，丢失的数据在原偏移处重传
+        let mut sent = vec![zero_rtt];
+        while let Some(frame) = read_frame(&client, 1200) {
+            sent.push(frame);
+        }
+        assert!(sent[1..]
+            .iter()
+            .any(|(frame, data)| { frame.range() == lost.0.range() && data == &lost.1 }));
+        assert!(sent
+            .iter()
+            .all(|(frame, _)| frame.range().end <= message.len() as u64));
+
+        for frame in &sent {
+            server.recv_frame(frame).unwrap();
+        }
+        let (_sid, mut reader) = server.accept_uni().await.unwrap();
+        let mut rcvd = Vec::new();
+        reader.read_to_end(&mut rcvd).await.unwrap();
+        assert_eq!(rcvd, message);
+
+        for (frame, _) in sent {
+            client.on_data_acked(frame);
+        }
+        shutdown.await.unwrap();
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, VecDeque},
     sync::{
         atomic::{AtomicU8, Ordering},
         Arc, Mutex, MutexGuard,
@@ -9,6 +9,7 @@ use std::{
 use deref_derive::{Deref, DerefMut};
 use qbase::{error::Error as QuicError, sid::StreamId};
 
+use super::scheduler::{RoundRobin, StreamScheduler};
 use crate::{recv::Incoming, send::Outgoing};
 
 #[derive(Debug, Clone)]
@@ -43,18 +44,22 @@ impl IOState {
     }
 }
 
-#[derive(Debug, Clone, Deref, DerefMut)]
+#[derive(Debug, Deref, DerefMut)]
 pub(super) struct Output<TX> {
     #[deref]
     pub(super) outgoings: BTreeMap<StreamId, (Outgoing<TX>, IOState)>,
-    pub(super) cursor: Option<(StreamId, usize)>,
+    // 决定各流发送数据的先后顺序及数据量
+    pub(super) scheduler: Box<dyn StreamScheduler>,
+    // 我方打开的、尚未发送过STREAM帧的流，按打开的顺序排列
+    pub(super) unannounced: VecDeque<StreamId>,
 }
 
 impl<TX> Output<TX> {
     fn new() -> Self {
         Self {
             outgoings: BTreeMap::default(),
-            cursor: None,
+            scheduler: Box::new(RoundRobin::default()),
+            unannounced: VecDeque::default(),
         }
     }
 }
@@ -94,6 +99,17 @@ impl<TX> ArcOutputGuard<'_, TX> {
         };
     }
 
+    /// Insert a stream opened by us, its first STREAM frame will be sent in the order of opening.
+    pub(super) fn open(&mut self, sid: StreamId, outgoing: Outgoing<TX>, io_state: IOState) {
+        match self.0.as_mut() {
+            Ok(set) => {
+                set.insert(sid, (outgoing, io_state));
+                set.unannounced.push_back(sid);
+            }
+            Err(e) => unreachable!("output is invalid: {e}"),
+        };
+    }
+
     pub(super) fn on_conn_error(&mut self, error: &QuicError) {
         match self.0.as_ref() {
             Ok(set) => set.values().for_each(|(o, _)| o.on_conn_error(error)),
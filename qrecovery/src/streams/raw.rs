@@ -1,6 +1,8 @@
 use std::{
     ops::Deref,
+    sync::Mutex,
     task::{ready, Context, Poll},
+    time::Instant,
 };
 
 use bytes::BufMut;
@@ -16,12 +18,12 @@ use qbase::{
         remote_sid::{AcceptSid, ExceedLimitError},
         ControlConcurrency, Dir, Role, StreamId, StreamIds,
     },
-    varint::VarInt,
 };
 
 use super::{
     io::{ArcInput, ArcOutput, IOState},
     listener::{AcceptBiStream, AcceptUniStream, ArcListener},
+    scheduler::StreamScheduler,
     Ext,
 };
 use crate::{
@@ -125,6 +127,29 @@ where
     input: ArcInput<Ext<TX>>,
     // 对方主动创建的流
     listener: ArcListener<Ext<TX>>,
+    // 各方向上打开、接受、结束的流的数量
+    stats: Mutex<StreamStats>,
+}
+
+/// The number of streams opened, accepted and closed in each direction, see
+/// [`DataStreams::stream_stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStats {
+    /// The number of bidirectional streams opened by us.
+    pub opened_bi: u64,
+    /// The number of unidirectional streams opened by us.
+    pub opened_uni: u64,
+    /// The number of bidirectional streams created by the peer, whether or not they have been
+    /// accepted by the application.
+    pub accepted_bi: u64,
+    /// The number of unidirectional streams created by the peer, whether or not they have been
+    /// accepted by the application.
+    pub accepted_uni: u64,
+    /// The number of bidirectional streams terminated, both the sending and the receiving parts
+    /// are finished or reset.
+    pub closed_bi: u64,
+    /// The number of unidirectional streams terminated, its only part is finished or reset.
+    pub closed_uni: u64,
 }
 
 fn wrapper_error(fty: FrameType) -> impl FnOnce(ExceedLimitError) -> QuicError {
@@ -139,10 +164,16 @@ where
     ///
     /// # Fairness
     ///
-    /// It's fair between streams. We have implemented a token bucket algorithm, and the [`try_read_data`]
-    /// method will read the data of each stream sequentially. Starting from the first stream, when
-    /// a stream exhausts its tokens (default is 4096, depending on the priority of the stream), or
-    /// there is no data to send, the method will move to the next stream, and so on.
+    /// The order and the amount of data the streams send are decided by the [`StreamScheduler`]
+    /// installed by [`DataStreams::set_scheduler`]. It's fair between streams by default, the
+    /// [`RoundRobin`] scheduler reads the data of each stream sequentially. Starting from the first
+    /// stream, when a stream exhausts its tokens ([`RoundRobin::TOKENS`]), or there is no data to
+    /// send, the method will move to the next stream, and so on.
+    ///
+    /// Ahead of that, the streams opened by us that have not sent any stream frame yet send their
+    /// first stream frame in the order they were opened, so that they become visible to the peer in
+    /// the same order. For example, the HTTP/3 control stream opened first is seen by the peer
+    /// before the request streams, as long as it has data to send.
     ///
     /// # Flow control
     ///
@@ -166,72 +197,116 @@ where
     /// * [`usize`]: The number of bytes written to the buffer.
     /// * [`usize`]: The number of new data writen to the buffer.
     ///
-    /// [`try_read_data`]: DataStreams::try_read_data
+    /// [`RoundRobin`]: super::RoundRobin
+    /// [`RoundRobin::TOKENS`]: super::RoundRobin::TOKENS
     /// [`write`]: tokio::io::AsyncWriteExt::write
     pub fn try_read_data(
         &self,
         buf: &mut [u8],
         flow_limit: usize,
     ) -> Option<(StreamFrame, usize, usize)> {
-        // todo: use core::range instead in rust 2024
-        use core::ops::Bound::*;
-
         if buf.len() < STREAM_FRAME_MAX_ENCODING_SIZE + 1 {
             return None;
         }
+        self.try_load_with(buf.len(), |outgoing, sid, tokens| {
+            outgoing.try_read(sid, buf, tokens, flow_limit).map(
+                |(frame, data_len, is_fresh, written)| {
+                    let fresh = if is_fresh { data_len } else { 0 };
+                    ((frame, written, fresh), data_len)
+                },
+            )
+        })
+    }
+
+    // 按照先首帧、再由调度器决定的顺序，依次尝试各流，直到其中一个流读出了数据
+    // load返回读取的结果，以及读出的流数据长度
+    fn try_load_with<R>(
+        &self,
+        capacity: usize,
+        mut load: impl FnMut(&Outgoing<Ext<TX>>, StreamId, usize) -> Option<(R, usize)>,
+    ) -> Option<R> {
         let mut guard = self.output.streams();
         let output = guard.as_mut().ok()?;
 
-        // 该tokens是令牌桶算法的token，为了多条Stream的公平性，给每个流定期地发放tokens，不累积
-        // 各流轮流按令牌桶算法发放的tokens来整理数据去发送
-        const DEFAULT_TOKENS: usize = 4096;
-        let streams: &mut dyn Iterator<Item = _> = match &output.cursor {
-            // [sid+1..] + [..=sid]
-            Some((sid, tokens)) if *tokens == 0 => &mut output
-                .outgoings
-                .range((Excluded(sid), Unbounded))
-                .chain(output.outgoings.range(..=sid))
-                .map(|(sid, outgoing)| (*sid, outgoing, DEFAULT_TOKENS)),
-            // [sid] + [sid+1..] + [..sid]
-            Some((sid, tokens)) => &mut Option::into_iter(
-                output
-                    .outgoings
-                    .get(sid)
-                    .map(|outgoing| (*sid, outgoing, *tokens)),
-            )
-            .chain(
-                output
-                    .outgoings
-                    .range((Excluded(sid), Unbounded))
-                    .chain(output.outgoings.range(..sid))
-                    .map(|(sid, outgoing)| (*sid, outgoing, DEFAULT_TOKENS)),
-            ),
-            // [..]
-            None => &mut output
-                .outgoings
-                .range(..)
-                .map(|(sid, outgoing)| (*sid, outgoing, DEFAULT_TOKENS)),
-        };
-        for (sid, (outgoing, _s), tokens) in streams.into_iter() {
-            if let Some((frame, data_len, is_fresh, written)) =
-                outgoing.try_read(sid, buf, tokens, flow_limit)
-            {
-                output.cursor = Some((sid, tokens - data_len));
-                return Some((frame, written, if is_fresh { data_len } else { 0 }));
+        // 我方新打开的流，按打开的顺序优先发送第一个STREAM帧，使对端按打开的顺序看到这些流，
+        // 比如HTTP/3的控制流先于请求流打开，其数据也会先于请求流的数据发出
+        let mut i = 0;
+        while let Some(&sid) = output.unannounced.get(i) {
+            let Some((outgoing, _s)) = output.outgoings.get(&sid) else {
+                // 尚未发送数据就被重置并确认了
+                output.unannounced.remove(i);
+                continue;
+            };
+            if let Some((result, data_len)) = load(outgoing, sid, capacity) {
+                output.unannounced.remove(i);
+                output.scheduler.on_data_sent(sid, data_len);
+                return Some(result);
+            }
+            i += 1;
+        }
+
+        // 其余的流，由调度器决定发送的先后顺序，以及各流能发送的数据量
+        let sids = output.outgoings.keys().copied().collect::<Vec<_>>();
+        for (sid, tokens) in output.scheduler.schedule(&sids, capacity) {
+            let Some((outgoing, _s)) = output.outgoings.get(&sid) else {
+                continue;
+            };
+            if tokens == 0 {
+                continue;
+            }
+            if let Some((result, data_len)) = load(outgoing, sid, tokens) {
+                output.scheduler.on_data_sent(sid, data_len);
+                return Some(result);
             }
         }
         None
     }
 
-    /// Try to load data from streams into the `packet`,
-    /// with a `flow_limit` which limits the max size of fresh data.
-    /// Returns the size of fresh data.
-    pub fn try_load_data_into<B, P>(&self, _packet: &mut P, _flow_limit: usize) -> usize
+    /// Install the [`StreamScheduler`] which decides the emission order of the streams, and how much
+    /// data each of them can send, replacing the previous one.
+    ///
+    /// The scheduler is [`RoundRobin`] by default. It's ignored if a connection error occurred.
+    ///
+    /// [`RoundRobin`]: super::RoundRobin
+    pub fn set_scheduler(&self, scheduler: Box<dyn StreamScheduler>) {
+        if let Ok(output) = self.output.streams().as_mut() {
+            output.scheduler = scheduler;
+        }
+    }
+
+    /// Try to load as much data as possible from the streams into the `packet`, with a
+    /// `flow_limit` which limits the max size of fresh data.
+    ///
+    /// The streams are picked in the same order as [`try_read_data`], each stream frame loaded
+    /// carries the length field so that other frames can follow it in the packet. The loading
+    /// stops when the packet is full, or none of the streams has data to send.
+    ///
+    /// Returns the size of fresh data loaded, the caller should consume the same amount of the
+    /// connection-level flow control credit.
+    ///
+    /// [`try_read_data`]: DataStreams::try_read_data
+    pub fn try_load_data_into<B, P>(&self, packet: &mut P, flow_limit: usize) -> usize
     where
         B: BufMut,
         P: Deref<Target = B> + for<'a> MarshalDataFrame<StreamFrame, (&'a [u8], &'a [u8])>,
     {
-        todo!("try load as much data as possible into the packet, return the fresh data size")
+        let mut fresh_bytes = 0;
+        loop {
+            let capacity = packet.remaining_mut();
+            if capacity < STREAM_FRAME_MAX_ENCODING_SIZE + 1 {
+                break;
+            }
+            let loaded = self.try_load_with(capacity, |outgoing, sid, tokens| {
+                outgoing
+                    .try_load_data_into(sid, packet, tokens, flow_limit - fresh_bytes)
+                    .map(|(data_len, is_fresh)| (if is_fresh { data_len } else { 0 }, data_len))
+            });
+            match loaded {
+                Some(fresh) => fresh_bytes += fresh,
+                None => break,
+            }
+        }
+        fresh_bytes
     }
 
     /// Called when the stream frame acked.
@@ -245,7 +320,7 @@ where
                 if is_all_rcvd {
                     s.shutdown_send();
                     if s.is_terminated() {
-                        self.stream_ids.remote.on_end_of_stream(frame.id);
+                        self.on_stream_terminated(frame.id);
                     }
                 }
             }
@@ -267,7 +342,7 @@ where
             .ok()
             .and_then(|set| set.get(&stream_frame.id))
         {
-            o.may_loss_data(&stream_frame.range());
+            o.may_loss_data(&stream_frame.range(), stream_frame.is_fin());
         }
     }
 
@@ -280,9 +355,7 @@ where
                 o.on_reset_acked();
                 s.shutdown_send();
                 if s.is_terminated() {
-                    self.stream_ids
-                        .remote
-                        .on_end_of_stream(reset_frame.stream_id);
+                    self.on_stream_terminated(reset_frame.stream_id);
                 }
             }
             // 如果流是双向的，接收部分的流独立地管理结束。其实是上层应用决定接收的部分是否同时结束
@@ -293,6 +366,10 @@ where
     ///
     /// If the correspoding stream is not exist, `accept` the stream.
     ///
+    /// The window of a stream is only extended when the application reads it, so the data
+    /// buffered for a stream not accepted by the application yet never exceeds the initial
+    /// max_stream_data transport parameter, the excess is a flow control error of the peer.
+    ///
     /// Actually calls the [`Incoming::recv_data`] method of the corresponding stream.
     pub fn recv_data(
         &self,
@@ -313,6 +390,14 @@ where
                     format!("local {sid} cannot receive STREAM_FRAME"),
                 ));
             }
+            // 我方尚未创建的流，对方不可能在其上发送数据
+            if !self.stream_ids.local.is_allocated(sid) {
+                return Err(QuicError::new(
+                    ErrorKind::StreamState,
+                    stream_frame.frame_type(),
+                    format!("local {sid} has not been opened"),
+                ));
+            }
         }
 
         if let Ok(set) = self.input.streams().as_mut() {
@@ -322,7 +407,7 @@ where
                     // 数据被接收完的，忽略后续的ResetStreamFrame
                     s.shutdown_receive();
                     if s.is_terminated() {
-                        self.stream_ids.remote.on_end_of_stream(sid);
+                        self.on_stream_terminated(sid);
                     }
                     set.remove(&sid);
                 }
@@ -360,7 +445,7 @@ where
                         incoming.recv_reset(reset)?;
                         s.shutdown_receive();
                         if s.is_terminated() {
-                            self.stream_ids.remote.on_end_of_stream(reset.stream_id);
+                            self.on_stream_terminated(reset.stream_id);
                         }
                     }
                 }
@@ -380,21 +465,18 @@ where
                     self.try_accept_sid(sid)
                         .map_err(wrapper_error(stop_sending.frame_type()))?;
                 }
-                if self
+                if let Some(reset) = self
                     .output
                     .streams()
                     .as_mut()
                     .ok()
                     .and_then(|set| set.get(&sid))
-                    .map(|(outgoing, _s)| outgoing.on_stopped(stop_sending.app_err_code.into()))
-                    .unwrap_or(false)
+                    .and_then(|(outgoing, _s)| {
+                        outgoing.on_stopped(stop_sending.app_err_code.into())
+                    })
                 {
                     self.ctrl_frames
-                        .send_frame([StreamCtlFrame::ResetStream(ResetStreamFrame {
-                            stream_id: sid,
-                            app_error_code: VarInt::from_u32(0),
-                            final_size: VarInt::from_u32(0),
-                        })]);
+                        .send_frame([StreamCtlFrame::ResetStream(reset.combine(sid))]);
                 }
             }
             StreamCtlFrame::MaxStreamData(max_stream_data) => {
@@ -411,7 +493,15 @@ where
                     }
                     self.try_accept_sid(sid)
                         .map_err(wrapper_error(max_stream_data.frame_type()))?;
+                } else if !self.stream_ids.local.is_allocated(sid) {
+                    // 我方尚未创建的流，对方不可能为其更新窗口
+                    return Err(QuicError::new(
+                        ErrorKind::StreamState,
+                        max_stream_data.frame_type(),
+                        format!("local {sid} has not been opened"),
+                    ));
                 }
+                // 已经结束并被回收的流，其MaxStreamData直接忽略
                 if let Some((outgoing, _s)) = self
                     .output
                     .streams()
@@ -503,6 +593,7 @@ where
             output: ArcOutput::new(),
             input: ArcInput::default(),
             listener: ArcListener::new(),
+            stats: Mutex::default(),
             ctrl_frames,
         }
     }
@@ -525,8 +616,9 @@ where
             let arc_sender = self.create_sender(sid, snd_buf_size);
             let arc_recver = self.create_recver(sid, self.local_bi_stream_rcvbuf_size);
             let io_state = IOState::bidirection();
-            output.insert(sid, Outgoing::new(arc_sender.clone()), io_state.clone());
+            output.open(sid, Outgoing::new(arc_sender.clone()), io_state.clone());
             input.insert(sid, Incoming::new(arc_recver.clone()), io_state);
+            self.stats.lock().unwrap().opened_bi += 1;
             Poll::Ready(Ok(Some((sid, (Reader(arc_recver), Writer(arc_sender))))))
         } else {
             Poll::Ready(Ok(None))
@@ -546,7 +638,8 @@ where
         if let Some(sid) = ready!(self.stream_ids.local.poll_alloc_sid(cx, Dir::Uni)) {
             let arc_sender = self.create_sender(sid, snd_buf_size);
             let io_state = IOState::send_only();
-            output.insert(sid, Outgoing::new(arc_sender.clone()), io_state);
+            output.open(sid, Outgoing::new(arc_sender.clone()), io_state);
+            self.stats.lock().unwrap().opened_uni += 1;
             Poll::Ready(Ok(Some((sid, Writer(arc_sender)))))
         } else {
             Poll::Ready(Ok(None))
@@ -561,6 +654,67 @@ where
         self.listener.accept_uni_stream()
     }
 
+    /// Returns how many new streams in the `dir` direction can be opened before being blocked
+    /// by the peer's MAX_STREAMS limit.
+    pub fn available_streams(&self, dir: Dir) -> u64 {
+        self.stream_ids.local.available_streams(dir)
+    }
+
+    /// Poll the instant at which the MAX_STREAMS limit held back by the [`ControlConcurrency`]
+    /// should be granted, see [`ArcRemoteStreamIds::poll_deadline`].
+    ///
+    /// [`ArcRemoteStreamIds::poll_deadline`]: qbase::sid::ArcRemoteStreamIds::poll_deadline
+    pub fn poll_max_streams_deadline(&self, cx: &mut Context<'_>) -> Poll<Instant> {
+        self.stream_ids.remote.poll_deadline(cx)
+    }
+
+    /// Called once the instant returned by [`DataStreams::poll_max_streams_deadline`] is reached,
+    /// the held back MAX_STREAMS frames will be sent if the strategy grants them.
+    pub fn on_max_streams_deadline(&self) {
+        self.stream_ids.remote.on_deadline();
+    }
+
+    /// Returns whether there is any stream not terminated yet, both the streams opened by local
+    /// and the streams accepted from the peer.
+    ///
+    /// Always return `false` once a connection error occurred.
+    pub fn has_open_streams(&self) -> bool {
+        let has_output = matches!(self.output.streams().as_ref(), Ok(output) if !output.is_empty());
+        let has_input = matches!(self.input.streams().as_ref(), Ok(input) if !input.is_empty());
+        has_output || has_input
+    }
+
+    /// Returns the number of bytes buffered for sending in all the streams, the data written by
+    /// the application but not yet acknowledged by the peer, see [`Outgoing::buffered_bytes`].
+    ///
+    /// Always return 0 once a connection error occurred.
+    pub fn send_buffered_bytes(&self) -> u64 {
+        match self.output.streams().as_ref() {
+            Ok(output) => output
+                .outgoings
+                .values()
+                .map(|(outgoing, _s)| outgoing.buffered_bytes())
+                .sum(),
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns the number of streams opened, accepted and closed so far, see [`StreamStats`].
+    ///
+    /// The streams aborted by a connection error are not counted as closed.
+    pub fn stream_stats(&self) -> StreamStats {
+        *self.stats.lock().unwrap()
+    }
+
+    fn on_stream_terminated(&self, sid: StreamId) {
+        self.stream_ids.remote.on_end_of_stream(sid);
+        let mut stats = self.stats.lock().unwrap();
+        match sid.dir() {
+            Dir::Bi => stats.closed_bi += 1,
+            Dir::Uni => stats.closed_uni += 1,
+        }
+    }
+
     fn try_accept_sid(&self, sid: StreamId) -> Result<(), ExceedLimitError> {
         match sid.dir() {
             Dir::Bi => self.try_accept_bi_sid(sid),
@@ -594,6 +748,7 @@ where
                     input.insert(sid, Incoming::new(arc_recver.clone()), io_state.clone());
                     output.insert(sid, Outgoing::new(arc_sender.clone()), io_state);
                     listener.push_bi_stream(sid, (arc_recver, arc_sender));
+                    self.stats.lock().unwrap().accepted_bi += 1;
                 }
                 Ok(())
             }
@@ -620,6 +775,7 @@ where
                     let io_state = IOState::receive_only();
                     input.insert(sid, Incoming::new(arc_receiver.clone()), io_state);
                     listener.push_uni_stream(sid, arc_receiver);
+                    self.stats.lock().unwrap().accepted_uni += 1;
                 }
                 Ok(())
             }
@@ -634,3 +790,562 @@ where
         ArcRecver::new(sid, buf_size, Ext(self.ctrl_frames.clone()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        future::Future,
+        pin::Pin,
+        sync::{Arc, Mutex},
+    };
+
+    use bytes::Bytes;
+    use qbase::{
+        frame::{
+            io::WriteDataFrame, MaxStreamDataFrame, MaxStreamsFrame, StopSendingFrame,
+            StreamCtlFrame,
+        },
+        sid::handy::ConsistentConcurrency,
+        varint::VarInt,
+    };
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    struct CtrlFrames;
+
+    impl SendFrame<StreamCtlFrame> for CtrlFrames {
+        fn send_frame<I: IntoIterator<Item = StreamCtlFrame>>(&self, _iter: I) {}
+    }
+
+    #[derive(Debug, Default, Clone)]
+    struct RecordedFrames(Arc<Mutex<Vec<StreamCtlFrame>>>);
+
+    impl SendFrame<StreamCtlFrame> for RecordedFrames {
+        fn send_frame<I: IntoIterator<Item = StreamCtlFrame>>(&self, iter: I) {
+            self.0.lock().unwrap().extend(iter);
+        }
+    }
+
+    fn stream_frame(sid: StreamId) -> (StreamFrame, Bytes) {
+        let body = Bytes::from_static(b"hello");
+        (StreamFrame::new(sid, 0, body.len()), body)
+    }
+
+    #[test]
+    fn test_recv_data_on_send_only_stream() {
+        let mut params = CommonParameters::default();
+        params.set_initial_max_stream_data_uni(VarInt::from_u32(1024));
+        let streams = DataStreams::new(
+            Role::Client,
+            &params,
+            Box::new(ConsistentConcurrency::new(10, 10)),
+            CtrlFrames,
+        );
+
+        // 我方创建的单向流只能发送
+        let local_uni = StreamId::from(VarInt::from_u32(2));
+        let error = streams.recv_data(&stream_frame(local_uni)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::StreamState);
+
+        // 我方尚未打开的双向流
+        let local_bi = StreamId::from(VarInt::from_u32(0));
+        let error = streams.recv_data(&stream_frame(local_bi)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::StreamState);
+
+        // 对方创建的流则可以接收数据
+        let remote_uni = StreamId::from(VarInt::from_u32(3));
+        assert_eq!(streams.recv_data(&stream_frame(remote_uni)), Ok(5));
+    }
+
+    #[test]
+    fn test_recv_max_stream_data() {
+        let streams = DataStreams::new(
+            Role::Client,
+            &CommonParameters::default(),
+            Box::new(ConsistentConcurrency::new(10, 10)),
+            CtrlFrames,
+        );
+        let max_stream_data = |sid| {
+            StreamCtlFrame::MaxStreamData(MaxStreamDataFrame {
+                stream_id: sid,
+                max_stream_data: VarInt::from_u32(1024),
+            })
+        };
+
+        // 对方创建的单向流，我方只接收
+        let remote_uni = StreamId::from(VarInt::from_u32(3));
+        let error = streams
+            .recv_stream_control(&max_stream_data(remote_uni))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::StreamState);
+
+        // 我方尚未打开的单向流
+        let local_uni = StreamId::from(VarInt::from_u32(2));
+        let error = streams
+            .recv_stream_control(&max_stream_data(local_uni))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::StreamState);
+
+        // 打开之后被重置并确认，流已被回收，迟到的MaxStreamData被忽略
+        streams
+            .recv_stream_control(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Uni(
+                VarInt::from_u32(1),
+            )))
+            .unwrap();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let Poll::Ready(Ok(Some((sid, mut writer)))) = streams.poll_open_uni_stream(&mut cx, 1024)
+        else {
+            panic!("the uni stream should be opened");
+        };
+        assert_eq!(sid, local_uni);
+        writer.cancel(0);
+        streams.on_reset_acked(ResetStreamFrame {
+            stream_id: sid,
+            app_error_code: VarInt::from_u32(0),
+            final_size: VarInt::from_u32(0),
+        });
+        assert_eq!(streams.recv_stream_control(&max_stream_data(sid)), Ok(()));
+    }
+
+    #[test]
+    fn test_first_frames_in_open_order() {
+        let streams = DataStreams::new(
+            Role::Client,
+            &CommonParameters::default(),
+            Box::new(ConsistentConcurrency::new(10, 10)),
+            CtrlFrames,
+        );
+        for max_streams in [
+            MaxStreamsFrame::Bi(VarInt::from_u32(1)),
+            MaxStreamsFrame::Uni(VarInt::from_u32(1)),
+        ] {
+            streams
+                .recv_stream_control(&StreamCtlFrame::MaxStreams(max_streams))
+                .unwrap();
+        }
+
+        // 先打开单向流(类似HTTP/3的控制流)，再打开双向流，单向流的流ID反而更大
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let Poll::Ready(Ok(Some((uni_sid, mut control)))) =
+            streams.poll_open_uni_stream(&mut cx, 1024)
+        else {
+            panic!("the uni stream should be opened");
+        };
+        let Poll::Ready(Ok(Some((bi_sid, (mut reader, mut request))))) =
+            streams.poll_open_bi_stream(&mut cx, 1024)
+        else {
+            panic!("the bi stream should be opened");
+        };
+        assert!(bi_sid < uni_sid);
+
+        // 请求流先写入数据，控制流的数据仍然先被发出
+        let mut write = |writer: &mut Writer<_>, data: &[u8]| {
+            let poll = Pin::new(writer).poll_write(&mut cx, data);
+            assert!(matches!(poll, Poll::Ready(Ok(n)) if n == data.len()));
+        };
+        write(&mut request, b"request");
+        write(&mut control, b"settings");
+
+        let mut buf = [0u8; 1200];
+        let (frame, _, fresh) = streams.try_read_data(&mut buf, usize::MAX).unwrap();
+        assert_eq!(frame.id, uni_sid);
+        assert_eq!(fresh, 8);
+        let (frame, _, fresh) = streams.try_read_data(&mut buf, usize::MAX).unwrap();
+        assert_eq!(frame.id, bi_sid);
+        assert_eq!(fresh, 7);
+        assert!(streams.try_read_data(&mut buf, usize::MAX).is_none());
+
+        control.cancel(0);
+        request.cancel(0);
+        reader.stop(0);
+    }
+
+    // 按权重分配各流的发送数据量，发送量与权重之比最小的流优先
+    #[derive(Debug, Default)]
+    struct WeightedScheduler {
+        weights: HashMap<StreamId, usize>,
+        sent: HashMap<StreamId, usize>,
+    }
+
+    impl StreamScheduler for WeightedScheduler {
+        fn schedule(&mut self, streams: &[StreamId], budget: usize) -> Vec<(StreamId, usize)> {
+            let total_weight = streams.iter().map(|sid| self.weights[sid]).sum::<usize>();
+            let mut schedule = streams
+                .iter()
+                .map(|sid| (*sid, budget * self.weights[sid] / total_weight))
+                .collect::<Vec<_>>();
+            schedule.sort_by_key(|(sid, _)| {
+                self.sent.get(sid).copied().unwrap_or(0) * total_weight / self.weights[sid]
+            });
+            schedule
+        }
+
+        fn on_data_sent(&mut self, sid: StreamId, len: usize) {
+            *self.sent.entry(sid).or_default() += len;
+        }
+    }
+
+    #[test]
+    fn test_custom_scheduler() {
+        let streams = DataStreams::new(
+            Role::Client,
+            &CommonParameters::default(),
+            Box::new(ConsistentConcurrency::new(10, 10)),
+            CtrlFrames,
+        );
+        streams
+            .recv_stream_control(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Bi(
+                VarInt::from_u32(2),
+            )))
+            .unwrap();
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut open_bi = || {
+            let Poll::Ready(Ok(Some((sid, (reader, writer))))) =
+                streams.poll_open_bi_stream(&mut cx, 1 << 16)
+            else {
+                panic!("the bi stream should be opened");
+            };
+            (sid, reader, writer)
+        };
+        let (heavy, mut heavy_reader, mut heavy_writer) = open_bi();
+        let (light, mut light_reader, mut light_writer) = open_bi();
+        streams.set_scheduler(Box::new(WeightedScheduler {
+            weights: HashMap::from([(heavy, 3), (light, 1)]),
+            ..Default::default()
+        }));
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        for writer in [&mut heavy_writer, &mut light_writer] {
+            let poll = Pin::new(writer).poll_write(&mut cx, &[0; 1 << 15]);
+            assert!(matches!(poll, Poll::Ready(Ok(n)) if n == 1 << 15));
+        }
+
+        // 两条流首个STREAM帧按打开的顺序发送，之后按3:1的权重分配发送的数据量
+        let mut sent = HashMap::<StreamId, usize>::new();
+        let mut buf = [0u8; 1200];
+        for _ in 0..40 {
+            let (frame, _, fresh) = streams.try_read_data(&mut buf, usize::MAX).unwrap();
+            *sent.entry(frame.id).or_default() += fresh;
+        }
+        let (heavy_sent, light_sent) = (sent[&heavy], sent[&light]);
+        assert!(heavy_sent > light_sent * 5 / 2 && heavy_sent < light_sent * 7 / 2);
+
+        heavy_writer.cancel(0);
+        light_writer.cancel(0);
+        heavy_reader.stop(0);
+        light_reader.stop(0);
+    }
+
+    struct TinyPacket<'b> {
+        buf: &'b mut [u8],
+        frames: Vec<StreamFrame>,
+    }
+
+    impl<'b> Deref for TinyPacket<'b> {
+        type Target = &'b mut [u8];
+
+        fn deref(&self) -> &Self::Target {
+            &self.buf
+        }
+    }
+
+    impl<'a> MarshalDataFrame<StreamFrame, (&'a [u8], &'a [u8])> for TinyPacket<'_> {
+        fn dump_frame_with_data(
+            &mut self,
+            frame: StreamFrame,
+            data: (&'a [u8], &'a [u8]),
+        ) -> Option<StreamFrame> {
+            let mut buf = core::mem::take(&mut self.buf);
+            buf.put_data_frame(&frame, &data);
+            self.buf = buf;
+            self.frames.push(frame);
+            None
+        }
+    }
+
+    #[test]
+    fn test_load_data_into_packet() {
+        let streams = DataStreams::new(
+            Role::Client,
+            &CommonParameters::default(),
+            Box::new(ConsistentConcurrency::new(10, 10)),
+            CtrlFrames,
+        );
+        streams
+            .recv_stream_control(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Bi(
+                VarInt::from_u32(2),
+            )))
+            .unwrap();
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut open_bi = || {
+            let Poll::Ready(Ok(Some((sid, (reader, writer))))) =
+                streams.poll_open_bi_stream(&mut cx, 1 << 16)
+            else {
+                panic!("the bi stream should be opened");
+            };
+            (sid, reader, writer)
+        };
+        let (sid1, mut reader1, mut writer1) = open_bi();
+        let (sid2, mut reader2, mut writer2) = open_bi();
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        for writer in [&mut writer1, &mut writer2] {
+            let poll = Pin::new(writer).poll_write(&mut cx, &[0; 1000]);
+            assert!(matches!(poll, Poll::Ready(Ok(1000))));
+        }
+
+        // 一个包中装入多个流的STREAM帧，直到包被装满
+        let mut buf = [0u8; 1200];
+        let mut packet = TinyPacket {
+            buf: &mut buf,
+            frames: vec![],
+        };
+        let fresh = streams.try_load_data_into(&mut packet, usize::MAX);
+        assert_eq!(
+            packet.frames.iter().map(|f| f.id).collect::<Vec<_>>(),
+            vec![sid1, sid2]
+        );
+        assert_eq!(packet.frames[0].len(), 1000);
+        assert_eq!(fresh, packet.frames.iter().map(|f| f.len()).sum::<usize>());
+        // 每个STREAM帧都携带了长度字段，写入的字节数与帧的编码长度一致
+        let written = 1200 - packet.buf.len();
+        assert_eq!(
+            written,
+            packet
+                .frames
+                .iter()
+                .map(|f| f.encoding_size())
+                .sum::<usize>()
+        );
+        assert!(packet.buf.len() <= STREAM_FRAME_MAX_ENCODING_SIZE);
+        let sent2 = packet.frames[1].len();
+
+        // 受连接级流量控制的限制，新数据不超过flow_limit
+        let mut buf = [0u8; 1200];
+        let mut packet = TinyPacket {
+            buf: &mut buf,
+            frames: vec![],
+        };
+        let fresh = streams.try_load_data_into(&mut packet, 100);
+        assert_eq!(fresh, 100);
+        assert_eq!(packet.frames.len(), 1);
+        assert_eq!(packet.frames[0].id, sid2);
+        assert_eq!(packet.frames[0].offset(), sent2 as u64);
+
+        writer1.cancel(0);
+        writer2.cancel(0);
+        reader1.stop(0);
+        reader2.stop(0);
+    }
+
+    #[test]
+    fn test_send_buffered_bytes() {
+        let streams = DataStreams::new(
+            Role::Client,
+            &CommonParameters::default(),
+            Box::new(ConsistentConcurrency::new(10, 10)),
+            CtrlFrames,
+        );
+        streams
+            .recv_stream_control(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Bi(
+                VarInt::from_u32(2),
+            )))
+            .unwrap();
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut open_bi = || {
+            let Poll::Ready(Ok(Some((sid, (reader, writer))))) =
+                streams.poll_open_bi_stream(&mut cx, 1024)
+            else {
+                panic!("the bi stream should be opened");
+            };
+            (sid, reader, writer)
+        };
+        let (_sid1, mut reader1, mut writer1) = open_bi();
+        let (_sid2, mut reader2, mut writer2) = open_bi();
+        assert_eq!(streams.send_buffered_bytes(), 0);
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut write = |writer: &mut Writer<_>, data: &[u8]| {
+            let poll = Pin::new(writer).poll_write(&mut cx, data);
+            assert!(matches!(poll, Poll::Ready(Ok(n)) if n == data.len()));
+        };
+        write(&mut writer1, b"hello");
+        write(&mut writer2, b"world!");
+        assert_eq!(streams.send_buffered_bytes(), 11);
+
+        // 发出的数据在被确认之前仍留在发送缓冲区中
+        let mut buf = [0u8; 1200];
+        let (frame1, ..) = streams.try_read_data(&mut buf, usize::MAX).unwrap();
+        let (frame2, ..) = streams.try_read_data(&mut buf, usize::MAX).unwrap();
+        assert_eq!(streams.send_buffered_bytes(), 11);
+
+        // 对端确认之后才从发送缓冲区中释放
+        streams.on_data_acked(frame1);
+        assert_eq!(streams.send_buffered_bytes(), 6);
+        streams.on_data_acked(frame2);
+        assert_eq!(streams.send_buffered_bytes(), 0);
+
+        writer1.cancel(0);
+        writer2.cancel(0);
+        reader1.stop(0);
+        reader2.stop(0);
+    }
+
+    #[test]
+    fn test_reset_on_stop_sending() {
+        let frames = RecordedFrames::default();
+        let streams = DataStreams::new(
+            Role::Client,
+            &CommonParameters::default(),
+            Box::new(ConsistentConcurrency::new(10, 10)),
+            frames.clone(),
+        );
+        streams
+            .recv_stream_control(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Uni(
+                VarInt::from_u32(1),
+            )))
+            .unwrap();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let Poll::Ready(Ok(Some((sid, mut writer)))) = streams.poll_open_uni_stream(&mut cx, 1024)
+        else {
+            panic!("the uni stream should be opened");
+        };
+        let poll = Pin::new(&mut writer).poll_write(&mut cx, b"hello world");
+        assert!(matches!(poll, Poll::Ready(Ok(11))));
+
+        let mut buf = [0u8; 1200];
+        let (frame, ..) = streams.try_read_data(&mut buf, 5).unwrap();
+        assert_eq!(frame.range(), 0..5);
+        frames.0.lock().unwrap().clear();
+
+        // 重置流使用对方要求的错误码，最终大小为已发送的数据量，未发送的数据被丢弃
+        streams
+            .recv_stream_control(&StreamCtlFrame::StopSending(StopSendingFrame::new(
+                sid,
+                VarInt::from_u32(0x11),
+            )))
+            .unwrap();
+        assert_eq!(
+            frames.0.lock().unwrap().as_slice(),
+            [StreamCtlFrame::ResetStream(ResetStreamFrame {
+                stream_id: sid,
+                app_error_code: VarInt::from_u32(0x11),
+                final_size: VarInt::from_u32(5),
+            })]
+        );
+    }
+
+    #[test]
+    fn test_stream_stats() {
+        let mut params = CommonParameters::default();
+        params.set_initial_max_stream_data_uni(VarInt::from_u32(1024));
+        params.set_initial_max_stream_data_bidi_remote(VarInt::from_u32(1024));
+        let streams = DataStreams::new(
+            Role::Client,
+            &params,
+            Box::new(ConsistentConcurrency::new(10, 10)),
+            CtrlFrames,
+        );
+        streams
+            .recv_stream_control(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Bi(
+                VarInt::from_u32(1),
+            )))
+            .unwrap();
+        streams
+            .recv_stream_control(&StreamCtlFrame::MaxStreams(MaxStreamsFrame::Uni(
+                VarInt::from_u32(1),
+            )))
+            .unwrap();
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let Poll::Ready(Ok(Some((_, (mut reader, mut bi_writer))))) =
+            streams.poll_open_bi_stream(&mut cx, 1024)
+        else {
+            panic!("the bi stream should be opened");
+        };
+        let Poll::Ready(Ok(Some((uni_sid, mut uni_writer)))) =
+            streams.poll_open_uni_stream(&mut cx, 1024)
+        else {
+            panic!("the uni stream should be opened");
+        };
+        assert_eq!(
+            streams.stream_stats(),
+            StreamStats {
+                opened_bi: 1,
+                opened_uni: 1,
+                ..Default::default()
+            }
+        );
+
+        // 对方创建的流，在应用层接受之前就已计入
+        let remote_bi = StreamId::from(VarInt::from_u32(1));
+        let remote_uni = StreamId::from(VarInt::from_u32(3));
+        streams.recv_data(&stream_frame(remote_bi)).unwrap();
+        streams.recv_data(&stream_frame(remote_uni)).unwrap();
+        let stats = streams.stream_stats();
+        assert_eq!((stats.accepted_bi, stats.accepted_uni), (1, 1));
+
+        // 单向流发送完毕且被确认，流结束
+        let poll = Pin::new(&mut uni_writer).poll_shutdown(&mut cx);
+        assert!(poll.is_pending());
+        let mut buf = [0u8; 1200];
+        let (frame, ..) = streams.try_read_data(&mut buf, usize::MAX).unwrap();
+        assert_eq!(frame.id, uni_sid);
+        streams.on_data_acked(frame);
+        let stats = streams.stream_stats();
+        assert_eq!((stats.closed_bi, stats.closed_uni), (0, 1));
+
+        bi_writer.cancel(0);
+        reader.stop(0);
+    }
+
+    #[test]
+    fn test_unaccepted_stream_buffer_capped() {
+        let mut params = CommonParameters::default();
+        params.set_initial_max_stream_data_uni(VarInt::from_u32(1024));
+        let frames = RecordedFrames::default();
+        let streams = DataStreams::new(
+            Role::Client,
+            &params,
+            Box::new(ConsistentConcurrency::new(10, 10)),
+            frames.clone(),
+        );
+
+        // 应用层尚未接受该流，其缓冲的数据最多只能到初始的流控窗口
+        let remote_uni = StreamId::from(VarInt::from_u32(3));
+        let body = Bytes::from(vec![0u8; 1024]);
+        let frame = StreamFrame::new(remote_uni, 0, body.len());
+        assert_eq!(streams.recv_data(&(frame, body)), Ok(1024));
+        let body = Bytes::from_static(b"x");
+        let frame = StreamFrame::new(remote_uni, 1024, body.len());
+        let error = streams.recv_data(&(frame, body)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::FlowControl);
+        // 没有读取，也就不会扩大窗口
+        assert!(frames.0.lock().unwrap().is_empty());
+
+        // 接受并读取之后，才通过MaxStreamData扩大窗口
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut accept = streams.accept_uni();
+        let Poll::Ready(Ok((sid, mut reader))) = Pin::new(&mut accept).poll(&mut cx) else {
+            panic!("the uni stream should be accepted");
+        };
+        assert_eq!(sid, remote_uni);
+        let mut buf = [0u8; 1024];
+        let mut read_buf = ReadBuf::new(&mut buf);
+        let poll = Pin::new(&mut reader).poll_read(&mut cx, &mut read_buf);
+        assert!(matches!(poll, Poll::Ready(Ok(()))));
+        assert_eq!(read_buf.filled().len(), 1024);
+        assert!(matches!(
+            frames.0.lock().unwrap()[..],
+            [StreamCtlFrame::MaxStreamData(MaxStreamDataFrame { stream_id, .. })] if stream_id == remote_uni
+        ));
+
+        reader.stop(0);
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+
+use qbase::sid::StreamId;
+
+/// Decides which streams send their data first and how much each of them can send, when the
+/// stream frames are assembled into the packets.
+///
+/// Each time a stream frame is about to be read by [`DataStreams::try_read_data`],
+/// [`StreamScheduler::schedule`] is called with the streams that may have data to send. The
+/// streams returned are tried in order, until one of them has data to send, then
+/// [`StreamScheduler::on_data_sent`] is called back with how much data it sent.
+///
+/// The first STREAM frame of the streams opened by us are always sent in the order of opening,
+/// before the other stream frames, regardless of the scheduler.
+///
+/// The default scheduler is [`RoundRobin`]. Applications can install their own scheduler by
+/// [`DataStreams::set_scheduler`], for example, HTTP/3 can schedule the streams according to the
+/// [extensible priorities](https://www.rfc-editor.org/rfc/rfc9218.html).
+///
+/// [`DataStreams::try_read_data`]: crate::streams::raw::DataStreams::try_read_data
+/// [`DataStreams::set_scheduler`]: crate::streams::raw::DataStreams::set_scheduler
+pub trait StreamScheduler: fmt::Debug + Send {
+    /// Called back before reading a stream frame, decides the emission order of the `streams` and
+    /// how much data each of them can send in the frame.
+    ///
+    /// `streams` are the streams may have data to send, in ascending order of their stream IDs.
+    /// `budget` is the size of the buffer the frame will be written to, the stream data in the
+    /// frame never exceeds it.
+    ///
+    /// Returns the streams in the order they should be tried, with the maximum amount of data they
+    /// can send in the frame. The streams not returned, or allocated 0 bytes, will not send data
+    /// this time.
+    fn schedule(&mut self, streams: &[StreamId], budget: usize) -> Vec<(StreamId, usize)>;
+
+    /// Called back after `len` bytes data of the stream `sid` is read into a stream frame.
+    fn on_data_sent(&mut self, sid: StreamId, len: usize);
+}
+
+/// The default [`StreamScheduler`], the streams send their data in turn.
+///
+/// Each stream can send at most [`RoundRobin::TOKENS`] bytes of data before its turn ends, the
+/// unused tokens are not accumulated to the next turn.
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    // 当前轮到的流，以及它本轮剩余的tokens
+    cursor: Option<(StreamId, usize)>,
+}
+
+impl RoundRobin {
+    /// The amount of data each stream can send in its turn.
+    pub const TOKENS: usize = 4096;
+}
+
+impl StreamScheduler for RoundRobin {
+    fn schedule(&mut self, streams: &[StreamId], _budget: usize) -> Vec<(StreamId, usize)> {
+        let (start, tokens) = match self.cursor {
+            // [sid] + [sid+1..] + [..sid]
+            Some((sid, tokens)) if tokens > 0 => match streams.binary_search(&sid) {
+                Ok(idx) => (idx, tokens),
+                Err(idx) => (idx, Self::TOKENS),
+            },
+            // [sid+1..] + [..=sid]
+            Some((sid, _)) => (streams.partition_point(|s| *s <= sid), Self::TOKENS),
+            // [..]
+            None => (0, Self::TOKENS),
+        };
+        let (before, after) = streams.split_at(start);
+        after
+            .iter()
+            .chain(before)
+            .enumerate()
+            .map(|(i, &sid)| (sid, if i == 0 { tokens } else { Self::TOKENS }))
+            .collect()
+    }
+
+    fn on_data_sent(&mut self, sid: StreamId, len: usize) {
+        let tokens = match self.cursor {
+            Some((cur, tokens)) if cur == sid && tokens > 0 => tokens,
+            _ => Self::TOKENS,
+        };
+        self.cursor = Some((sid, tokens.saturating_sub(len)));
+    }
+}
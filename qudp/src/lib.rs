@@ -1,4 +1,5 @@
 use std::{
+    fmt,
     future::Future,
     io::{self, IoSlice, IoSliceMut},
     net::SocketAddr,
@@ -57,13 +58,94 @@ impl Default for PacketHeader {
     }
 }
 
+/// An asynchronous udp socket, the datagrams are sent and received in batches.
+///
+/// [`UdpSocketController`] is the implementation on the os udp socket. Implement this trait to provide
+/// other sockets to the quic connections, such as sockets based on io_uring, or in-process sockets
+/// for testing.
+pub trait AsyncUdpSocket: fmt::Debug + core::marker::Send + Sync {
+    /// Return the local address that the socket bound.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Send the datagrams in `bufs` with the given header, return the number of the datagrams sent.
+    ///
+    /// If [`PacketHeader::gso`] is enabled, the length of each datagram in `bufs` is equal to
+    /// [`PacketHeader::seg_size`], except the last one.
+    fn poll_send(
+        &self,
+        bufs: &[IoSlice<'_>],
+        hdr: &PacketHeader,
+        cx: &mut Context,
+    ) -> Poll<io::Result<usize>>;
+
+    /// Receive datagrams into `bufs`, return the number of the datagrams received.
+    ///
+    /// The header of each datagram is written into `hdrs` at the same index, the length of the
+    /// datagram is [`PacketHeader::seg_size`].
+    fn poll_recv(
+        &self,
+        bufs: &mut [IoSliceMut<'_>],
+        hdrs: &mut [PacketHeader],
+        cx: &mut Context,
+    ) -> Poll<io::Result<usize>>;
+}
+
+/// The options applied to the os udp socket, see [`UdpSocketController::with_options`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SocketOptions {
+    dscp: u8,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+}
+
+impl SocketOptions {
+    /// Mark the outgoing datagrams with the given DSCP codepoint, for QoS.
+    ///
+    /// The DSCP is the higher 6 bits of the TOS byte in IPv4, or the Traffic Class in IPv6. The lower
+    /// 2 bits are the ECN codepoint, which remains decided by each datagram, see [`PacketHeader::ecn`].
+    ///
+    /// Setting DSCP is only supported on unix platforms now.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dscp` exceeds 6 bits.
+    pub fn dscp(mut self, dscp: u8) -> Self {
+        assert!(dscp < 64, "DSCP is a 6 bits codepoint");
+        self.dscp = dscp;
+        self
+    }
+
+    /// Set the size of the receive buffer of the socket, the `SO_RCVBUF` option.
+    ///
+    /// Note that the os may adjust the size, for example, linux doubles the value.
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Set the size of the send buffer of the socket, the `SO_SNDBUF` option.
+    ///
+    /// Note that the os may adjust the size, for example, linux doubles the value.
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+}
+
 #[derive(Debug)]
 pub struct UdpSocketController {
     io: tokio::net::UdpSocket,
+    // TOS字节的高6位，低2位为ECN
+    dscp: u8,
 }
 
 impl UdpSocketController {
     pub fn new(addr: SocketAddr) -> io::Result<Self> {
+        Self::with_options(addr, SocketOptions::default())
+    }
+
+    /// Bind the udp socket to the given address, and apply the `options` to it.
+    pub fn with_options(addr: SocketAddr, options: SocketOptions) -> io::Result<Self> {
         let domain = if addr.is_ipv4() {
             Domain::IPV4
         } else {
@@ -71,17 +153,28 @@ impl UdpSocketController {
         };
 
         let socket = Socket::new(domain, Type::DGRAM, None)?;
+        if let Some(size) = options.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = options.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
         if let Err(e) = socket.bind(&addr.into()) {
             log::error!("Failed to bind socket: {}", e);
             return Err(io::Error::new(io::ErrorKind::AddrInUse, e));
         }
 
+        // tokio requires the socket to be in non-blocking mode before registering it
+        socket.set_nonblocking(true)?;
         let io = tokio::net::UdpSocket::from_std(socket.into())?;
 
         // TODO: 会报错
         // io.set_ttl(DEFAULT_TTL as u32)?;
 
-        let socket = Self { io };
+        let socket = Self {
+            io,
+            dscp: options.dscp,
+        };
         socket.config()?;
         Ok(socket)
     }
@@ -118,6 +211,33 @@ impl UdpSocketController {
     }
 }
 
+impl AsyncUdpSocket for UdpSocketController {
+    #[inline]
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocketController::local_addr(self)
+    }
+
+    #[inline]
+    fn poll_send(
+        &self,
+        bufs: &[IoSlice<'_>],
+        hdr: &PacketHeader,
+        cx: &mut Context,
+    ) -> Poll<io::Result<usize>> {
+        UdpSocketController::poll_send(self, bufs, hdr, cx)
+    }
+
+    #[inline]
+    fn poll_recv(
+        &self,
+        bufs: &mut [IoSliceMut<'_>],
+        hdrs: &mut [PacketHeader],
+        cx: &mut Context,
+    ) -> Poll<io::Result<usize>> {
+        UdpSocketController::poll_recv(self, bufs, hdrs, cx)
+    }
+}
+
 trait Io {
     fn config(&self) -> io::Result<()>;
 
@@ -138,20 +258,12 @@ impl UdpSocketController {
     }
 
     pub fn receiver(&self) -> Receiver {
-        Receiver {
-            usc: self,
-            iovecs: (0..BATCH_SIZE)
-                .map(|_| [0u8; 1500].to_vec())
-                .collect::<Vec<_>>(),
-            headers: (0..BATCH_SIZE)
-                .map(|_| PacketHeader::default())
-                .collect::<Vec<_>>(),
-        }
+        Receiver::new(self)
     }
 }
 
 pub struct Send<'a> {
-    pub usc: &'a UdpSocketController,
+    pub usc: &'a dyn AsyncUdpSocket,
     pub iovecs: &'a [IoSlice<'a>],
     pub header: PacketHeader,
 }
@@ -166,12 +278,25 @@ impl Future for Send<'_> {
 }
 
 pub struct Receiver<'u> {
-    pub usc: &'u UdpSocketController,
+    pub usc: &'u dyn AsyncUdpSocket,
     pub iovecs: Vec<Vec<u8>>,
     pub headers: Vec<PacketHeader>,
 }
 
-impl Receiver<'_> {
+impl<'u> Receiver<'u> {
+    /// Create a receiver that receives [`BATCH_SIZE`] datagrams at most each time from the `usc`.
+    pub fn new(usc: &'u dyn AsyncUdpSocket) -> Self {
+        Self {
+            usc,
+            iovecs: (0..BATCH_SIZE)
+                .map(|_| [0u8; 1500].to_vec())
+                .collect::<Vec<_>>(),
+            headers: (0..BATCH_SIZE)
+                .map(|_| PacketHeader::default())
+                .collect::<Vec<_>>(),
+        }
+    }
+
     #[inline]
     pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<io::Result<usize>> {
         let mut bufs = self
@@ -188,3 +313,22 @@ impl Receiver<'_> {
         core::future::poll_fn(|cx| self.poll_recv(cx)).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_buffer_size() {
+        let options = SocketOptions::default()
+            .recv_buffer_size(4096)
+            .send_buffer_size(8192);
+        let usc =
+            UdpSocketController::with_options("127.0.0.1:0".parse().unwrap(), options).unwrap();
+        let socket = socket2::SockRef::from(&usc.io);
+        // linux doubles the value set, to allow space for bookkeeping overhead
+        assert_eq!(socket.recv_buffer_size().unwrap(), 2 * 4096);
+        assert_eq!(socket.send_buffer_size().unwrap(), 2 * 8192);
+    }
+}
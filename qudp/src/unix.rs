@@ -52,6 +52,12 @@ impl Io for UdpSocketController {
             //  incoming packets.  It contains a byte which specifies the
             //  Type of Service/Precedence field of the packet header.
             self.setsockopt(libc::IPPROTO_IP, libc::IP_RECVTOS, OPTION_ON);
+            if self.dscp != 0 {
+                self.setsockopt(libc::IPPROTO_IP, libc::IP_TOS, (self.dscp << 2) as _);
+            }
+        }
+        if !is_ipv4 && self.dscp != 0 {
+            self.setsockopt(libc::IPPROTO_IPV6, libc::IPV6_TCLASS, (self.dscp << 2) as _);
         }
 
         if is_ipv4 {
@@ -97,6 +103,11 @@ impl Io for UdpSocketController {
 
     fn sendmsg(&self, bufs: &[IoSlice<'_>], send_hdr: &PacketHeader) -> io::Result<usize> {
         let io = socket2::SockRef::from(&self.io);
+        // 控制消息中的ECN即整个TOS字节，会覆盖socket上设置的TOS，须与DSCP合并
+        let send_hdr = &PacketHeader {
+            ecn: Some(self.dscp << 2 | send_hdr.ecn.unwrap_or(0) & 0b11),
+            ..*send_hdr
+        };
 
         let gso_size = if send_hdr.gso {
             let max_gso = self.max_gso_segments();
@@ -102,6 +102,9 @@ impl Io for UdpSocketController {
             self.setsockopt(WinSock::IPPROTO_IPV6, WinSock::IPV6_RECVTCLASS, OPTION_ON);
             self.setsockopt(WinSock::IPPROTO_IPV6, WinSock::IPV6_PKTINFO, OPTION_ON);
         }
+        if self.dscp != 0 {
+            log::warn!("Setting DSCP is not supported on windows yet, ignored");
+        }
         Ok(())
     }
 
@@ -11,7 +11,8 @@ use qbase::{
 
 use super::{
     reader::{ReceivedDatagramFrames, UnreliableReader},
-    writer::{DatagramFrameSink, UnreliableWriter},
+    stats::{DatagramCounters, DatagramStats},
+    writer::{DatagramFrameSink, SendOrder, UnreliableWriter},
 };
 use crate::{UnreliableIncoming, UnreliableOutgoing};
 
@@ -34,8 +35,11 @@ impl DatagramFlow {
     /// [`max_datagram_frame_size`]: https://www.rfc-editor.org/rfc/rfc9221.html#name-transport-parameter
     #[inline]
     pub fn new(local_max_datagram_frame_size: u64) -> Self {
-        let reader = ReceivedDatagramFrames::new(local_max_datagram_frame_size as _);
-        let writer = DatagramFrameSink::new();
+        let counters = Arc::new(DatagramCounters::default());
+        let mut reader = ReceivedDatagramFrames::new(local_max_datagram_frame_size as _);
+        reader.counters = counters.clone();
+        let mut writer = DatagramFrameSink::new();
+        writer.counters = counters;
 
         Self {
             incoming: UnreliableIncoming(Arc::new(Mutex::new(Ok(reader)))),
@@ -49,6 +53,25 @@ impl DatagramFlow {
         self.outgoing.try_read_datagram(buf)
     }
 
+    /// See [`UnreliableOutgoing::set_send_order`] for more details.
+    #[inline]
+    pub fn set_send_order(&self, order: SendOrder) {
+        self.outgoing.set_send_order(order)
+    }
+
+    /// See [`UnreliableIncoming::set_capacity`] for more details.
+    #[inline]
+    pub fn set_recv_capacity(&self, capacity: usize) {
+        self.incoming.set_capacity(capacity)
+    }
+
+    /// Return the counters of the datagrams sent, received and dropped.
+    ///
+    /// Return `None` if the connection is closing or already closed.
+    pub fn stats(&self) -> Option<DatagramStats> {
+        self.outgoing.stats()
+    }
+
     pub fn try_load_data_into(&self, packet: &mut PacketWriter<'_>) {
         self.outgoing.try_load_data_into(packet)
     }
@@ -93,3 +116,45 @@ impl ReceiveFrame<(DatagramFrame, bytes::Bytes)> for DatagramFlow {
         self.incoming.recv_datagram(frame, body.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use qbase::{error::ErrorKind, frame::FrameType};
+
+    use super::*;
+
+    #[test]
+    fn test_datagram_stats() {
+        let flow = DatagramFlow::new(1024);
+        let writer = flow.writer(16).unwrap();
+
+        // 超过对端允许的大小
+        assert!(writer.send(&[0; 16]).is_err());
+        writer.send(b"hello").unwrap();
+        let mut buffer = [0; 1024];
+        assert!(flow.try_read_datagram(&mut buffer).is_some());
+
+        // 接收队列已满，丢弃新到的数据报
+        flow.set_recv_capacity(1);
+        for data in [&b"hello"[..], &b"world"[..]] {
+            flow.recv_frame(&(DatagramFrame::new(None), Bytes::copy_from_slice(data)))
+                .unwrap();
+        }
+
+        let expected = DatagramStats {
+            datagrams_sent: 1,
+            datagrams_received: 1,
+            datagrams_dropped_send: 1,
+            datagrams_dropped_recv: 1,
+        };
+        assert_eq!(flow.stats(), Some(expected));
+
+        flow.on_conn_error(&Error::new(
+            ErrorKind::ProtocolViolation,
+            FrameType::Datagram(0),
+            "test",
+        ));
+        assert_eq!(flow.stats(), None);
+    }
+}
@@ -14,6 +14,8 @@ use qbase::{
     frame::{BeFrame, DatagramFrame},
 };
 
+use crate::stats::DatagramCounters;
+
 /// An asynchronous queue that caches received datagram frames from peer.
 ///
 /// For protocol layer, this struct represents as the [`UnreliableIncoming`]. Once a datagram frame is received, the method
@@ -25,7 +27,7 @@ use qbase::{
 ///
 /// Because of some trade off, only one [`UnreliableReader`] can exist at the same time, try to create a new reader when
 /// there has been a reader will result an error. See [`UnreliableIncoming::new_reader`] for more.
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct ReceivedDatagramFrames {
     /// The maximum size of the datagram that can be received.
     ///
@@ -35,6 +37,14 @@ pub struct ReceivedDatagramFrames {
     local_max_size: usize,
     /// The internal queue for caching the received datagrams.
     queue: VecDeque<Bytes>,
+    /// The maximum number of datagrams cached in the queue, the datagrams received when the queue is full are dropped.
+    ///
+    /// Unlimited by default, see [`UnreliableIncoming::set_capacity`].
+    capacity: usize,
+    /// The counters of the datagrams received and dropped, shared with the [`DatagramFlow`].
+    ///
+    /// [`DatagramFlow`]: crate::DatagramFlow
+    pub(crate) counters: Arc<DatagramCounters>,
     /// The waker for waking up the task that is waiting for the data to be read.
     ///
     /// When a datagram is received, the waker will be used to wake up the task.
@@ -50,6 +60,8 @@ impl ReceivedDatagramFrames {
         Self {
             local_max_size,
             queue: Default::default(),
+            capacity: usize::MAX,
+            counters: Default::default(),
             waker: Default::default(),
             reader_exist: false,
         }
@@ -97,6 +109,8 @@ impl UnreliableIncoming {
     ///
     /// If the connection is closing or closed, the new datagram will be ignored.
     ///
+    /// If the queue is full, the new datagram will be dropped, see [`UnreliableIncoming::set_capacity`].
+    ///
     /// If there is a task waiting for the data to be read, the task will be woken up when the datagram is received.
     pub fn recv_datagram(&self, frame: &DatagramFrame, data: bytes::Bytes) -> Result<(), Error> {
         let reader = &mut self.0.lock().unwrap();
@@ -116,7 +130,14 @@ impl UnreliableIncoming {
             ));
         }
 
+        // 应用层来不及读取，队列已满，丢弃新到的数据报
+        if reader.queue.len() >= reader.capacity {
+            reader.counters.on_dropped_recv();
+            return Ok(());
+        }
+
         reader.queue.push_back(data);
+        reader.counters.on_received();
         if let Some(waker) = reader.waker.take() {
             waker.wake();
         }
@@ -124,6 +145,17 @@ impl UnreliableIncoming {
         Ok(())
     }
 
+    /// Set the maximum number of the received datagrams that are cached for the application to read, the default is
+    /// unlimited.
+    ///
+    /// Once the queue is full, the datagrams received are dropped until the application reads some. The datagrams
+    /// already queued beyond the new capacity are kept.
+    pub fn set_capacity(&self, capacity: usize) {
+        if let Ok(reader) = self.0.lock().unwrap().as_mut() {
+            reader.capacity = capacity;
+        }
+    }
+
     /// When a connection error occurs, the error will be set to the reader.
     ///
     /// Any subsequent calls to [`UnreliableIncoming::new_reader`], [`UnreliableReader::poll_recv`], [`UnreliableReader::read`]
@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The counters of the datagrams of a connection, returned by [`DatagramFlow::stats`].
+///
+/// [`DatagramFlow::stats`]: crate::DatagramFlow::stats
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DatagramStats {
+    /// The number of datagrams written into the packets to send.
+    pub datagrams_sent: u64,
+    /// The number of datagrams received from the peer and queued for the application to read.
+    pub datagrams_received: u64,
+    /// The number of datagrams dropped before sending, because they exceed the maximum size the
+    /// peer allows, or they are evicted from the full [`SendOrder::Lifo`] queue.
+    ///
+    /// [`SendOrder::Lifo`]: crate::SendOrder::Lifo
+    pub datagrams_dropped_send: u64,
+    /// The number of datagrams received but dropped, because the receive queue is full, see
+    /// [`UnreliableIncoming::set_capacity`].
+    ///
+    /// [`UnreliableIncoming::set_capacity`]: crate::UnreliableIncoming::set_capacity
+    pub datagrams_dropped_recv: u64,
+}
+
+/// The counters shared by the incoming and the outgoing sides of the datagram flow.
+#[derive(Debug, Default)]
+pub(crate) struct DatagramCounters {
+    sent: AtomicU64,
+    received: AtomicU64,
+    dropped_send: AtomicU64,
+    dropped_recv: AtomicU64,
+}
+
+impl DatagramCounters {
+    pub(crate) fn on_sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_received(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_dropped_send(&self, count: usize) {
+        self.dropped_send.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_dropped_recv(&self) {
+        self.dropped_recv.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> DatagramStats {
+        DatagramStats {
+            datagrams_sent: self.sent.load(Ordering::Relaxed),
+            datagrams_received: self.received.load(Ordering::Relaxed),
+            datagrams_dropped_send: self.dropped_send.load(Ordering::Relaxed),
+            datagrams_dropped_recv: self.dropped_recv.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -13,6 +13,23 @@ use qbase::{
     varint::VarInt,
 };
 
+use crate::stats::{DatagramCounters, DatagramStats};
+
+/// The order in which the queued datagrams are sent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SendOrder {
+    /// First in first out, the datagrams are sent in the order they are queued.
+    ///
+    /// The queue is unbounded, no datagram is dropped before it is sent.
+    #[default]
+    Fifo,
+    /// Last in first out, the most recently queued datagram is sent first.
+    ///
+    /// It suits the real-time applications that prefer the newest data to the stale one. At most
+    /// `capacity` datagrams are queued, the oldest one is dropped when the queue is full.
+    Lifo { capacity: usize },
+}
+
 /// The queue that caches the datagram frames to send.
 ///
 /// For application layer, this represents as the [`UnreliableWriter`], which is used to send the [datagram frames] to
@@ -32,13 +49,60 @@ use qbase::{
 pub struct DatagramFrameSink {
     /// The queue that stores the datagram frame to send.
     queue: VecDeque<Bytes>,
+    /// The order in which the datagrams in the queue are sent.
+    order: SendOrder,
+    /// The counters of the datagrams sent and dropped, shared with the [`DatagramFlow`].
+    ///
+    /// [`DatagramFlow`]: crate::DatagramFlow
+    pub(crate) counters: Arc<DatagramCounters>,
 }
 
 impl DatagramFrameSink {
     pub(crate) fn new() -> Self {
         Self {
             queue: Default::default(),
+            order: SendOrder::default(),
+            counters: Default::default(),
+        }
+    }
+
+    fn push(&mut self, datagram: Bytes) {
+        if let SendOrder::Lifo { capacity } = self.order {
+            // 队列已满，丢弃最旧的数据报
+            if self.queue.len() >= capacity {
+                let excess = self.queue.len() - capacity + 1;
+                self.queue.drain(..excess);
+                self.counters.on_dropped_send(excess);
+            }
         }
+        self.queue.push_back(datagram);
+    }
+
+    fn next(&self) -> Option<&Bytes> {
+        match self.order {
+            SendOrder::Fifo => self.queue.front(),
+            SendOrder::Lifo { .. } => self.queue.back(),
+        }
+    }
+
+    fn pop(&mut self) -> Option<Bytes> {
+        match self.order {
+            SendOrder::Fifo => self.queue.pop_front(),
+            SendOrder::Lifo { .. } => self.queue.pop_back(),
+        }
+    }
+
+    fn set_order(&mut self, order: SendOrder) {
+        if let SendOrder::Lifo { capacity } = order {
+            assert!(
+                capacity > 0,
+                "the capacity of the LIFO queue must be positive"
+            );
+            let excess = self.queue.len().saturating_sub(capacity);
+            self.queue.drain(..excess);
+            self.counters.on_dropped_send(excess);
+        }
+        self.order = order;
     }
 }
 
@@ -106,7 +170,7 @@ impl UnreliableOutgoing {
     pub fn try_read_datagram(&self, mut buf: &mut [u8]) -> Option<(DatagramFrame, usize)> {
         let mut guard = self.0.lock().unwrap();
         let writer = guard.as_mut().ok()?;
-        let datagram = writer.queue.front()?;
+        let datagram = writer.next()?;
 
         let available = buf.len();
 
@@ -115,7 +179,8 @@ impl UnreliableOutgoing {
             return None;
         }
 
-        let datagram = writer.queue.pop_front()?;
+        let datagram = writer.pop()?;
+        writer.counters.on_sent();
         let frame_without_len = DatagramFrame::new(None);
         let frame_with_len = DatagramFrame::new(Some(VarInt::try_from(datagram.len()).unwrap()));
         match max_encoding_size {
@@ -144,6 +209,30 @@ impl UnreliableOutgoing {
         todo!()
     }
 
+    /// Set the order in which the queued datagrams are sent, the default is [`SendOrder::Fifo`].
+    ///
+    /// Switching to [`SendOrder::Lifo`] drops the oldest datagrams exceeding its capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity of [`SendOrder::Lifo`] is 0.
+    pub fn set_send_order(&self, order: SendOrder) {
+        if let Ok(writer) = self.0.lock().unwrap().as_mut() {
+            writer.set_order(order);
+        }
+    }
+
+    /// Return the counters of the datagrams sent, received and dropped, which are shared with the incoming side of the
+    /// [`DatagramFlow`].
+    ///
+    /// Return `None` if the connection is closing or already closed.
+    ///
+    /// [`DatagramFlow`]: crate::DatagramFlow
+    pub fn stats(&self) -> Option<DatagramStats> {
+        let guard = self.0.lock().unwrap();
+        guard.as_ref().ok().map(|writer| writer.counters.snapshot())
+    }
+
     /// When a connection error occurs, set the internal state to an error state.
     ///
     /// Any subsequent calls to [`UnreliableWriter::send`] or [`UnreliableWriter::send_bytes`] will return an error.
@@ -185,17 +274,21 @@ impl UnreliableWriter {
     /// See [RFC](https://www.rfc-editor.org/rfc/rfc9221.html#name-transport-parameter) for more details about transport
     /// parameters.
     ///
-    /// If the size of the `data` exceeds the limit, the method will return an error.
+    /// If the size of the `data` exceeds the limit, the method will return an error, and the datagram is counted as
+    /// dropped, see [`DatagramStats::datagrams_dropped_send`].
     ///
     /// You can call [`UnreliableWriter::max_datagram_frame_size`] to know the maximum size of the datagram frame you can
     /// send, read its documentation for more details.
     ///
     /// If the connection is closing or already closed, the method will also return an error.
+    ///
+    /// [`DatagramStats::datagrams_dropped_send`]: crate::DatagramStats::datagrams_dropped_send
     pub fn send_bytes(&self, data: Bytes) -> io::Result<()> {
         match self.writer.lock().unwrap().deref_mut() {
             Ok(writer) => {
                 // Only consider the smallest encoding method: 1 byte
                 if (1 + data.len()) > self.max_datagram_frame_size {
+                    writer.counters.on_dropped_send(1);
                     return Err(io::Error::new(
                         io::ErrorKind::InvalidInput,
                         "datagram frame size exceeds the limit",
@@ -207,7 +300,7 @@ impl UnreliableWriter {
                         "peer does not support RFC 9221: An Unreliable Datagram Extension to QUIC, or it dont want to receive datagram frames",
                     ));
                 }
-                writer.queue.push_back(data.clone());
+                writer.push(data.clone());
                 Ok(())
             }
             Err(e) => Err(io::Error::from(e.clone())),
@@ -357,6 +450,46 @@ mod tests {
         assert_eq!(buffer, expected_buffer);
     }
 
+    #[test]
+    fn test_datagram_writer_lifo() {
+        let writer = Arc::new(Mutex::new(Ok(DatagramFrameSink::new())));
+        let outgoing = UnreliableOutgoing(writer);
+        let writer = outgoing.new_writer(1024).unwrap();
+
+        writer.send(&[0]).unwrap();
+        outgoing.set_send_order(SendOrder::Lifo { capacity: 3 });
+        for i in 1..6 {
+            writer.send(&[i]).unwrap();
+        }
+
+        // 队列已满时丢弃了最旧的0、1、2，最新的数据报先发送
+        let mut sent = Vec::new();
+        let mut buffer = [0; 1024];
+        while let Some((_frame, written)) = outgoing.try_read_datagram(&mut buffer) {
+            assert_eq!(written, 3);
+            sent.push(buffer[2]);
+        }
+        assert_eq!(sent, [5, 4, 3]);
+    }
+
+    #[test]
+    fn test_datagram_writer_fifo() {
+        let writer = Arc::new(Mutex::new(Ok(DatagramFrameSink::new())));
+        let outgoing = UnreliableOutgoing(writer);
+        let writer = outgoing.new_writer(1024).unwrap();
+
+        for i in 0..5 {
+            writer.send(&[i]).unwrap();
+        }
+
+        let mut sent = Vec::new();
+        let mut buffer = [0; 1024];
+        while let Some((_frame, _written)) = outgoing.try_read_datagram(&mut buffer) {
+            sent.push(buffer[2]);
+        }
+        assert_eq!(sent, [0, 1, 2, 3, 4]);
+    }
+
     #[test]
     fn test_datagram_writer_exceeds_limit() {
         let writer = Arc::new(Mutex::new(Ok(DatagramFrameSink::new())));